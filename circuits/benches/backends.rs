@@ -0,0 +1,85 @@
+//! Compare the BabyBear and Goldilocks backends on the balance withdrawal
+//!
+//! Run with `cargo bench --bench backends`. Reports mean prove time, mean
+//! verify time and proof size for each backend under the same FRI
+//! parameters.
+
+use std::time::{Duration, Instant};
+
+use p3_baby_bear::BabyBear;
+use p3_field::PrimeCharacteristicRing;
+use p3_goldilocks::Goldilocks;
+
+use noctis_circuits::balance_withdrawal::{path_indices, BalanceWithdrawalCircuit, BalanceWithdrawalWitness};
+use noctis_circuits::goldilocks::{self, GoldilocksConfig};
+use noctis_circuits::merkle::TREE_DEPTH;
+use noctis_circuits::prover::{self, FriParams, ProverConfig, StarkHash};
+
+const ITERATIONS: u32 = 10;
+
+fn witness<F: PrimeCharacteristicRing + Copy>() -> BalanceWithdrawalWitness<F> {
+    BalanceWithdrawalWitness {
+        domain: F::from_u32(8453),
+        spending_key: F::from_u32(12345),
+        asset: [0; 20],
+        balance: F::from_u32(10000),
+        randomness: F::from_u32(99999),
+        note_index: 5,
+        merkle_path: [F::ZERO; TREE_DEPTH],
+        path_indices: path_indices(5),
+        new_randomness: F::from_u32(88888),
+        association_path: None,
+        blocklist_proof: None,
+    }
+}
+
+fn time<T>(mut f: impl FnMut() -> T) -> (T, Duration) {
+    let mut out = f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        out = f();
+    }
+    (out, start.elapsed() / ITERATIONS)
+}
+
+fn report(name: &str, prove: Duration, verify: Duration, proof_bytes: usize) {
+    println!(
+        "{:<22} prove {:>10.2?}  verify {:>10.2?}  proof {:>7} bytes",
+        name, prove, verify, proof_bytes
+    );
+}
+
+fn bench_babybear(name: &str, hash: StarkHash, params: &FriParams) {
+    let witness = witness::<BabyBear>();
+    let circuit =
+        BalanceWithdrawalCircuit::from_witness(&witness, [0xab; 20], BabyBear::new(6000))
+            .unwrap();
+    let config = ProverConfig::new(hash, params);
+
+    let (proof, prove_time) =
+        time(|| prover::prove_balance_withdrawal(&config, &circuit, &witness).unwrap());
+    let (_, verify_time) = time(|| prover::verify_balance_withdrawal(&config, &proof).unwrap());
+    report(name, prove_time, verify_time, proof.proof_bytes.len());
+}
+
+fn bench_goldilocks(params: &FriParams) {
+    let witness = witness::<Goldilocks>();
+    let circuit =
+        BalanceWithdrawalCircuit::from_witness(&witness, [0xab; 20], Goldilocks::from_u64(6000))
+            .unwrap();
+    let config = GoldilocksConfig::new(params);
+
+    let (proof, prove_time) =
+        time(|| goldilocks::prove_balance_withdrawal(&config, &circuit, &witness).unwrap());
+    let (_, verify_time) = time(|| goldilocks::verify_balance_withdrawal(&config, &proof).unwrap());
+    report("Goldilocks/Keccak", prove_time, verify_time, proof.proof_bytes.len());
+}
+
+fn main() {
+    let params = FriParams::default();
+    println!("balance withdrawal, {:?}, mean of {} runs", params, ITERATIONS);
+
+    bench_babybear("BabyBear/Poseidon2", StarkHash::Poseidon2, &params);
+    bench_babybear("BabyBear/Keccak", StarkHash::Keccak, &params);
+    bench_goldilocks(&params);
+}
@@ -18,7 +18,7 @@ include "node_modules/circomlib/circuits/poseidon.circom";
  *   - spendingKey: Secret key known only to note owner
  *   - balance: Original note balance
  *   - randomness: Random value used in commitment
- *   - noteIndex: Position of note in Merkle tree
+ *   - noteIndex: Position of note in Merkle tree, bound to pathIndices
  *   - pathElements[20]: Merkle proof siblings
  *   - pathIndices[20]: Left/right indicators for Merkle path
  */
@@ -82,6 +82,14 @@ template Withdrawal(levels) {
 
     // 7. Verify computed root matches public merkleRoot
     computedRoot === merkleRoot;
+
+    // 8. noteIndex must be the leaf position the path proves, otherwise one
+    // note could be spent once per index (pathIndices[i] is bit i of it)
+    var position = 0;
+    for (var i = 0; i < levels; i++) {
+        position += pathIndices[i] * 2 ** i;
+    }
+    position === noteIndex;
 }
 
 // Merkle Tree verification using Poseidon
@@ -0,0 +1,217 @@
+//! Solidity ABI encoding for vault calls
+//!
+//! Only the shapes the vaults use are supported: a dynamic `bytes proof`
+//! followed by a fixed `uint256[N]` array of public inputs, its batched
+//! form `(bytes[], uint256[N][])`, and single-word admin calls.
+
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::groth16::U256;
+
+/// `BalanceVaultV4.withdraw(bytes proof, uint256[10] publicInputs)`
+pub const WITHDRAW_SIGNATURE: &str = "withdraw(bytes,uint256[10])";
+
+/// `BalanceVaultV4.withdrawBatch(bytes[] proofs, uint256[10][] publicInputs)`
+pub const WITHDRAW_BATCH_SIGNATURE: &str = "withdrawBatch(bytes[],uint256[10][])";
+
+/// `BalanceVaultV4.depositWithProof(bytes proof, uint256[3] publicInputs)`
+pub const DEPOSIT_WITH_PROOF_SIGNATURE: &str = "depositWithProof(bytes,uint256[3])";
+
+/// `BalanceVaultV4.publishAssociationRoot(uint256 root)`
+pub const PUBLISH_ASSOCIATION_ROOT_SIGNATURE: &str = "publishAssociationRoot(uint256)";
+
+/// keccak256 of arbitrary bytes
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut out = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut out);
+    out
+}
+
+/// First 4 bytes of keccak256(signature)
+pub fn function_selector(signature: &str) -> [u8; 4] {
+    let hash = keccak256(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// ABI-encode `(bytes, uint256[N])` arguments, without a selector
+pub fn encode_bytes_and_words(data: &[u8], words: &[U256]) -> Vec<u8> {
+    // Head: offset of the bytes payload, then the static array inline
+    let head_len = 32 * (1 + words.len());
+    let padded_len = data.len().div_ceil(32) * 32;
+
+    let mut out = Vec::with_capacity(head_len + 32 + padded_len);
+    out.extend_from_slice(&U256::from_u64(head_len as u64).to_be_bytes());
+    for word in words {
+        out.extend_from_slice(&word.to_be_bytes());
+    }
+
+    // Tail: length-prefixed bytes, right-padded to a word boundary
+    out.extend_from_slice(&U256::from_u64(data.len() as u64).to_be_bytes());
+    out.extend_from_slice(data);
+    out.resize(head_len + 32 + padded_len, 0);
+    out
+}
+
+fn push_word(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&U256::from_u64(value as u64).to_be_bytes());
+}
+
+/// Length-prefixed bytes, right-padded to a word boundary
+fn push_bytes(out: &mut Vec<u8>, data: &[u8]) {
+    push_word(out, data.len());
+    out.extend_from_slice(data);
+    out.resize(out.len() + data.len().next_multiple_of(32) - data.len(), 0);
+}
+
+/// ABI-encode `(bytes[], uint256[N][])` arguments, without a selector
+pub fn encode_bytes_list_and_word_rows<const N: usize>(data: &[Vec<u8>], rows: &[[U256; N]]) -> Vec<u8> {
+    // bytes[]: length, one offset per element (relative to the first
+    // offset), then the elements
+    let mut list = Vec::new();
+    push_word(&mut list, data.len());
+    let mut offset = 32 * data.len();
+    for d in data {
+        push_word(&mut list, offset);
+        offset += 32 + d.len().next_multiple_of(32);
+    }
+    for d in data {
+        push_bytes(&mut list, d);
+    }
+
+    let mut out = Vec::with_capacity(64 + list.len() + 32 * (1 + N * rows.len()));
+    push_word(&mut out, 64);
+    push_word(&mut out, 64 + list.len());
+    out.extend_from_slice(&list);
+
+    // uint256[N][]: length, then the static rows inline
+    push_word(&mut out, rows.len());
+    for word in rows.iter().flatten() {
+        out.extend_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+/// Full calldata for `BalanceVaultV4.withdraw`
+pub fn encode_withdraw_calldata(proof: &[u8], public_inputs: &[U256; 10]) -> Vec<u8> {
+    let mut calldata = function_selector(WITHDRAW_SIGNATURE).to_vec();
+    calldata.extend_from_slice(&encode_bytes_and_words(proof, public_inputs));
+    calldata
+}
+
+/// Full calldata for `BalanceVaultV4.withdrawBatch`
+pub fn encode_withdraw_batch_calldata(proofs: &[Vec<u8>], public_inputs: &[[U256; 10]]) -> Vec<u8> {
+    let mut calldata = function_selector(WITHDRAW_BATCH_SIGNATURE).to_vec();
+    calldata.extend_from_slice(&encode_bytes_list_and_word_rows(proofs, public_inputs));
+    calldata
+}
+
+/// Full calldata for `BalanceVaultV4.depositWithProof`
+///
+/// `public_inputs` is `[commitment, amount, asset]`.
+pub fn encode_deposit_calldata(proof: &[u8], public_inputs: &[U256; 3]) -> Vec<u8> {
+    let mut calldata = function_selector(DEPOSIT_WITH_PROOF_SIGNATURE).to_vec();
+    calldata.extend_from_slice(&encode_bytes_and_words(proof, public_inputs));
+    calldata
+}
+
+/// Full calldata for `BalanceVaultV4.publishAssociationRoot`
+pub fn encode_publish_association_root_calldata(root: U256) -> Vec<u8> {
+    let mut calldata = function_selector(PUBLISH_ASSOCIATION_ROOT_SIGNATURE).to_vec();
+    calldata.extend_from_slice(&root.to_be_bytes());
+    calldata
+}
+
+/// Parse a 0x-prefixed 20-byte Ethereum address
+pub fn parse_address(s: &str) -> Option<[u8; 20]> {
+    let s = s.trim();
+    let hex_str = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))?;
+    let bytes = hex::decode(hex_str).ok()?;
+    bytes.try_into().ok()
+}
+
+/// Address as a uint256 word, the way `address(uint160(x))` reads it back
+pub fn address_to_u256(address: &[u8; 20]) -> U256 {
+    let mut word = [0u8; 32];
+    word[12..].copy_from_slice(address);
+    U256::from_be_bytes(&word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_function_selector() {
+        assert_eq!(function_selector("transfer(address,uint256)"), [0xa9, 0x05, 0x9c, 0xbb]);
+    }
+
+    #[test]
+    fn test_encode_bytes_and_words() {
+        let words = [U256::from_u64(1), U256::from_u64(2)];
+        let encoded = encode_bytes_and_words(&[0xaa; 33], &words);
+
+        // offset, 2 words, length, 2 padded data words
+        assert_eq!(encoded.len(), 32 * 6);
+        assert_eq!(encoded[31], 0x60);
+        assert_eq!(encoded[63], 1);
+        assert_eq!(encoded[95], 2);
+        assert_eq!(encoded[127], 33);
+        assert_eq!(encoded[128..161], [0xaa; 33]);
+        assert!(encoded[161..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_encode_bytes_list_and_word_rows() {
+        let data = vec![vec![0xaa; 33], vec![0xbb; 1]];
+        let rows = [[U256::from_u64(1), U256::from_u64(2)], [U256::from_u64(3), U256::from_u64(4)]];
+        let encoded = encode_bytes_list_and_word_rows(&data, &rows);
+        let word = |i: usize| U256::from_be_bytes(encoded[32 * i..32 * (i + 1)].try_into().unwrap());
+
+        // Heads
+        assert_eq!(word(0), U256::from_u64(0x40));
+        assert_eq!(word(1), U256::from_u64(0x40 + 32 * 8));
+        // bytes[]: length, offsets, [33, 2 data words], [1, 1 data word]
+        assert_eq!(word(2), U256::from_u64(2));
+        assert_eq!(word(3), U256::from_u64(0x40));
+        assert_eq!(word(4), U256::from_u64(0x40 + 32 * 3));
+        assert_eq!(word(5), U256::from_u64(33));
+        assert_eq!(encoded[32 * 6..32 * 6 + 33], [0xaa; 33]);
+        assert_eq!(word(8), U256::from_u64(1));
+        assert_eq!(encoded[32 * 9], 0xbb);
+        // uint256[2][]: length, rows inline
+        assert_eq!(word(10), U256::from_u64(2));
+        assert_eq!(word(11), U256::from_u64(1));
+        assert_eq!(word(14), U256::from_u64(4));
+        assert_eq!(encoded.len(), 32 * 15);
+    }
+
+    #[test]
+    fn test_encode_deposit_calldata() {
+        let public_inputs = [U256::from_u64(7), U256::from_u64(10000), U256::from_u64(0)];
+        let calldata = encode_deposit_calldata(&[0xaa; 40], &public_inputs);
+
+        assert_eq!(calldata[..4], function_selector(DEPOSIT_WITH_PROOF_SIGNATURE));
+        assert_eq!(calldata[4..], encode_bytes_and_words(&[0xaa; 40], &public_inputs));
+        // selector, offset, 3 words, length, 2 padded data words
+        assert_eq!(calldata.len(), 4 + 32 * 7);
+    }
+
+    #[test]
+    fn test_encode_publish_association_root_calldata() {
+        let calldata = encode_publish_association_root_calldata(U256::from_u64(0x1234));
+        assert_eq!(calldata[..4], function_selector(PUBLISH_ASSOCIATION_ROOT_SIGNATURE));
+        assert_eq!(calldata[4..], U256::from_u64(0x1234).to_be_bytes());
+    }
+
+    #[test]
+    fn test_address_roundtrip() {
+        let address = parse_address("0x441F619ff56d516474b3e0c1608eeA44a3a6E486").unwrap();
+        let word = address_to_u256(&address);
+        assert_eq!(word.to_hex(), "0x000000000000000000000000441f619ff56d516474b3e0c1608eea44a3a6e486");
+
+        assert!(parse_address("0x1234").is_none());
+        assert!(parse_address("441F619ff56d516474b3e0c1608eeA44a3a6E486").is_none());
+    }
+}
@@ -0,0 +1,138 @@
+//! Ethereum addresses as STARK public values
+//!
+//! A 160-bit address does not fit in one BabyBear (or Goldilocks) element,
+//! and reducing it mod p would let two addresses share a proof. The STARK
+//! circuits instead take each address public input as `ADDRESS_LIMBS`
+//! public values of `ADDRESS_LIMB_BITS` bits, low limb first. Every limb is
+//! below both moduli, so the split is injective, and verifiers derive the
+//! limbs from the address themselves: the generated Solidity verifier
+//! rejects a `uint256` input of 2^160 or more before splitting it.
+//!
+//! A note commits to its asset through `asset_id`, the hash of the asset's
+//! limbs, which the circuits recompute from the asset public input.
+
+use p3_field::{Field, PrimeCharacteristicRing};
+
+use crate::poseidon::{poseidon_hash_slice, RATE};
+
+/// A 20-byte Ethereum address
+pub type Address = [u8; 20];
+
+/// Public values an address input expands to
+pub const ADDRESS_LIMBS: usize = 6;
+
+/// Bits per limb; `ADDRESS_LIMBS · ADDRESS_LIMB_BITS >= 160`
+pub const ADDRESS_LIMB_BITS: usize = 27;
+
+const _: () = assert!(ADDRESS_LIMBS * ADDRESS_LIMB_BITS >= 160);
+const _: () = assert!(ADDRESS_LIMBS <= RATE);
+
+/// Split an address into `ADDRESS_LIMBS` little-endian limbs
+///
+/// Limb `j` is `(address >> 27·j) mod 2^27`, reading the address as the
+/// big-endian integer `uint160(address)`.
+pub fn address_limbs(address: &Address) -> [u32; ADDRESS_LIMBS] {
+    let mut limbs = [0u32; ADDRESS_LIMBS];
+    for (i, &byte) in address.iter().rev().enumerate() {
+        for bit in 0..8 {
+            let pos = 8 * i + bit;
+            limbs[pos / ADDRESS_LIMB_BITS] |= (((byte >> bit) & 1) as u32) << (pos % ADDRESS_LIMB_BITS);
+        }
+    }
+    limbs
+}
+
+/// `address_limbs` as field elements, the public values of an address
+pub fn address_values<F: PrimeCharacteristicRing>(address: &Address) -> [F; ADDRESS_LIMBS] {
+    address_limbs(address).map(F::from_u32)
+}
+
+/// Asset id a note of `asset` commits to: hash(limbs of the address)
+///
+/// One permutation of the limbs, so the circuits check it on one row.
+pub fn asset_id<F: Field>(asset: &Address) -> F {
+    poseidon_hash_slice(&address_values::<F>(asset))
+}
+
+/// Position among a circuit's public values of its public input `input`,
+/// when each input in `address_inputs` expands to `ADDRESS_LIMBS` values
+pub const fn public_value_index(input: usize, address_inputs: &[usize]) -> usize {
+    let mut index = input;
+    let mut i = 0;
+    while i < address_inputs.len() {
+        if address_inputs[i] < input {
+            index += ADDRESS_LIMBS - 1;
+        }
+        i += 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p3_field::PrimeField64;
+
+    /// Reassemble the integer from its limbs
+    fn from_limbs(limbs: &[u32; ADDRESS_LIMBS]) -> [u8; 21] {
+        let mut value = [0u8; 21];
+        for (j, &limb) in limbs.iter().enumerate() {
+            for bit in 0..ADDRESS_LIMB_BITS {
+                if (limb >> bit) & 1 == 1 {
+                    let pos = ADDRESS_LIMB_BITS * j + bit;
+                    value[20 - pos / 8] |= 1 << (pos % 8);
+                }
+            }
+        }
+        value
+    }
+
+    #[test]
+    fn test_limbs_cover_the_address() {
+        // Every limb is canonical in both fields
+        let max_limb = (1u64 << ADDRESS_LIMB_BITS) - 1;
+        assert!(max_limb < p3_baby_bear::BabyBear::ORDER_U64);
+        assert!(max_limb < p3_goldilocks::Goldilocks::ORDER_U64);
+
+        let address: Address = core::array::from_fn(|i| (i as u8).wrapping_mul(37) ^ 0xa5);
+        let limbs = address_limbs(&address);
+        assert!(limbs.iter().all(|&l| l < 1 << ADDRESS_LIMB_BITS));
+        assert_eq!(from_limbs(&limbs)[1..], address);
+        assert_eq!(from_limbs(&limbs)[0], 0);
+
+        assert_eq!(address_limbs(&[0xff; 20])[..5], [(1 << ADDRESS_LIMB_BITS) - 1; 5]);
+        assert_eq!(address_limbs(&[0xff; 20])[5], (1 << (160 - 5 * ADDRESS_LIMB_BITS)) - 1);
+    }
+
+    #[test]
+    fn test_addresses_equal_mod_p_differ() {
+        // 2^31 - 2^27 + 1 apart: the same BabyBear element when reduced
+        let mut a = [0u8; 20];
+        a[19] = 1;
+        let mut b = a;
+        b[16] = 0x78;
+        b[19] = 2;
+        assert_ne!(address_limbs(&a), address_limbs(&b));
+    }
+
+    #[test]
+    fn test_asset_id_binds_the_address() {
+        let mut a = [0u8; 20];
+        a[19] = 1;
+        let mut b = a;
+        b[16] = 0x78;
+        b[19] = 2;
+        let id = asset_id::<p3_baby_bear::BabyBear>;
+        assert_ne!(id(&a), id(&b));
+        assert_ne!(id(&[0; 20]), id(&a));
+    }
+
+    #[test]
+    fn test_public_value_index() {
+        let addresses = [2, 5];
+        assert_eq!(public_value_index(1, &addresses), 1);
+        assert_eq!(public_value_index(2, &addresses), 2);
+        assert_eq!(public_value_index(3, &addresses), 3 + ADDRESS_LIMBS - 1);
+        assert_eq!(public_value_index(6, &addresses), 6 + 2 * (ADDRESS_LIMBS - 1));
+    }
+}
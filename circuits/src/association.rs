@@ -0,0 +1,244 @@
+//! Association sets (Privacy Pools)
+//!
+//! An association set is a list of deposit commitments an association set
+//! provider approves, e.g. deposits not linked to known stolen funds. The
+//! provider publishes the root of a Merkle tree over the set, and a
+//! withdrawal can additionally prove its note is a leaf of that tree without
+//! revealing which one (see `BalanceWithdrawalCircuit::with_association_root`).
+//!
+//! The tree has the same shape and hash as the note commitment tree: depth
+//! TREE_DEPTH, filled left to right, unfilled slots empty subtrees. A root of
+//! zero means "no association set" to the circuit and the vault, which an
+//! actual set never hashes to.
+//!
+//! `AssociationSet` is the tree over a Poseidon2 field, for
+//! `BalanceWithdrawalCircuit`. `VaultAssociationSet` is the same tree over
+//! BN254 vault commitments, hashed like BalanceVaultV4's note tree, for
+//! `r1cs::WithdrawalCircuit::with_association`; its root is the one
+//! `BalanceVaultV4.publishAssociationRoot` accepts.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+
+use p3_baby_bear::BabyBear;
+use p3_field::PrimeField64;
+
+use crate::groth16::U256;
+use crate::merkle::{filled_tree_proof, TREE_DEPTH};
+use crate::poseidon_bn254::{self, Bn254Field};
+
+type Val = BabyBear;
+
+/// Errors raised while building an association set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssociationSetError {
+    /// No commitments were approved
+    Empty,
+    /// More commitments than leaves in a TREE_DEPTH tree
+    TooManyCommitments,
+}
+
+impl fmt::Display for AssociationSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssociationSetError::Empty => write!(f, "Association set is empty"),
+            AssociationSetError::TooManyCommitments => {
+                write!(f, "Association set exceeds 2^{} commitments", TREE_DEPTH)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AssociationSetError {}
+
+/// Membership path of a commitment in an association set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AssociationPath<F = Val> {
+    pub path: [F; TREE_DEPTH],
+    pub indices: [bool; TREE_DEPTH],
+}
+
+/// Merkle tree over the approved deposit commitments
+pub struct AssociationSet<F = Val> {
+    commitments: Vec<F>,
+    /// Leaf index of each commitment, keyed by its canonical value
+    index: HashMap<u64, usize>,
+    root: F,
+}
+
+impl<F: PrimeField64> AssociationSet<F> {
+    /// Build the tree over `commitments`, in order, dropping repeats
+    pub fn new(commitments: &[F]) -> Result<Self, AssociationSetError> {
+        let mut unique: Vec<F> = Vec::with_capacity(commitments.len());
+        let mut index = HashMap::with_capacity(commitments.len());
+        for &c in commitments {
+            if let Entry::Vacant(slot) = index.entry(c.as_canonical_u64()) {
+                slot.insert(unique.len());
+                unique.push(c);
+            }
+        }
+
+        if unique.is_empty() {
+            return Err(AssociationSetError::Empty);
+        }
+        if unique.len() > 1 << TREE_DEPTH {
+            return Err(AssociationSetError::TooManyCommitments);
+        }
+
+        let (root, _, _) = filled_tree_proof(&unique, 0).expect("set is non-empty and fits the tree");
+        Ok(Self { commitments: unique, index, root })
+    }
+
+    /// Root to publish and to prove against
+    pub fn root(&self) -> F {
+        self.root
+    }
+
+    /// Number of distinct commitments in the set
+    pub fn len(&self) -> usize {
+        self.commitments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commitments.is_empty()
+    }
+
+    pub fn contains(&self, commitment: F) -> bool {
+        self.index.contains_key(&commitment.as_canonical_u64())
+    }
+
+    /// Membership path for `commitment`, or None if it is not approved
+    pub fn path(&self, commitment: F) -> Option<AssociationPath<F>> {
+        let &index = self.index.get(&commitment.as_canonical_u64())?;
+        let (_, path, indices) = filled_tree_proof(&self.commitments, index)?;
+        Some(AssociationPath { path, indices })
+    }
+}
+
+/// Merkle tree over approved BN254 vault commitments
+pub struct VaultAssociationSet {
+    commitments: Vec<Bn254Field>,
+    /// Leaf index of each commitment
+    index: HashMap<U256, usize>,
+    root: Bn254Field,
+}
+
+impl VaultAssociationSet {
+    /// Build the tree over `commitments`, in order, dropping repeats
+    pub fn new(commitments: &[Bn254Field]) -> Result<Self, AssociationSetError> {
+        let mut unique: Vec<Bn254Field> = Vec::with_capacity(commitments.len());
+        let mut index = HashMap::with_capacity(commitments.len());
+        for &c in commitments {
+            if let Entry::Vacant(slot) = index.entry(U256::from(c)) {
+                slot.insert(unique.len());
+                unique.push(c);
+            }
+        }
+
+        if unique.is_empty() {
+            return Err(AssociationSetError::Empty);
+        }
+        if unique.len() > 1 << TREE_DEPTH {
+            return Err(AssociationSetError::TooManyCommitments);
+        }
+
+        let (root, _, _) =
+            poseidon_bn254::filled_tree_proof(&unique, 0, TREE_DEPTH).expect("set is non-empty and fits the tree");
+        Ok(Self { commitments: unique, index, root })
+    }
+
+    /// Root to publish and to prove against
+    pub fn root(&self) -> Bn254Field {
+        self.root
+    }
+
+    /// Number of distinct commitments in the set
+    pub fn len(&self) -> usize {
+        self.commitments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commitments.is_empty()
+    }
+
+    pub fn contains(&self, commitment: Bn254Field) -> bool {
+        self.index.contains_key(&U256::from(commitment))
+    }
+
+    /// Membership path for `commitment` in the `compute_merkle_root`
+    /// convention, or None if it is not approved
+    pub fn path(&self, commitment: Bn254Field) -> Option<(Vec<Bn254Field>, Vec<bool>)> {
+        let &index = self.index.get(&U256::from(commitment))?;
+        let (_, path, indices) = poseidon_bn254::filled_tree_proof(&self.commitments, index, TREE_DEPTH)?;
+        Some((path, indices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::balance_withdrawal::compute_merkle_root_with_path;
+
+    fn commitments() -> Vec<Val> {
+        (1..=5).map(|i| Val::new(1000 + i)).collect()
+    }
+
+    #[test]
+    fn test_paths_open_to_root() {
+        let set = AssociationSet::new(&commitments()).unwrap();
+        assert_eq!(set.len(), 5);
+        assert_ne!(set.root(), Val::new(0));
+
+        for c in commitments() {
+            let path = set.path(c).unwrap();
+            assert_eq!(compute_merkle_root_with_path(c, &path.path, &path.indices), set.root());
+        }
+        assert!(set.path(Val::new(7)).is_none());
+    }
+
+    #[test]
+    fn test_repeats_dropped() {
+        let mut repeated = commitments();
+        repeated.extend(commitments());
+        let set = AssociationSet::new(&repeated).unwrap();
+        assert_eq!(set.len(), 5);
+        assert_eq!(set.root(), AssociationSet::new(&commitments()).unwrap().root());
+    }
+
+    #[test]
+    fn test_root_depends_on_set() {
+        let set = AssociationSet::new(&commitments()).unwrap();
+        let smaller = AssociationSet::new(&commitments()[..4]).unwrap();
+        assert_ne!(set.root(), smaller.root());
+        assert!(!smaller.contains(Val::new(1005)));
+    }
+
+    #[test]
+    fn test_empty_set_rejected() {
+        assert_eq!(
+            AssociationSet::<Val>::new(&[]).err(),
+            Some(AssociationSetError::Empty)
+        );
+        assert_eq!(VaultAssociationSet::new(&[]).err(), Some(AssociationSetError::Empty));
+    }
+
+    #[test]
+    fn test_vault_paths_open_to_root() {
+        let commitments: Vec<Bn254Field> = (1..=5).map(|i| Bn254Field::new(1000 + i)).collect();
+        let mut repeated = commitments.clone();
+        repeated.push(commitments[0]);
+        let set = VaultAssociationSet::new(&repeated).unwrap();
+        assert_eq!(set.len(), 5);
+
+        // The vault's tree over the same leaves
+        let (root, _, _) = poseidon_bn254::filled_tree_proof(&commitments, 0, TREE_DEPTH).unwrap();
+        assert_eq!(set.root(), root);
+        for c in commitments {
+            let (path, indices) = set.path(c).unwrap();
+            assert_eq!(poseidon_bn254::compute_merkle_root(c, &path, &indices), set.root());
+        }
+        assert!(set.path(Bn254Field::new(7)).is_none());
+        assert!(!set.contains(Bn254Field::new(7)));
+    }
+}
@@ -5,13 +5,41 @@
 //! 2. The note has balance >= withdrawal_amount (range proof)
 //! 3. Correct computation of change note commitment
 //! 4. Correct nullifier derivation
+//! 5. The relayer fee does not exceed the amount (range proof)
+//! 6. Optionally, the note is in an association set (see `association`)
+//! 7. Optionally, the note is not on a blocklist (see `blocklist::FieldBlocklist`)
+//!
+//! A note commitment is hash(domain, spending_key_hash, asset_id, balance,
+//! randomness), with asset_id = hash(limbs of the asset address) (see
+//! `address::asset_id`) and domain the deployment's (`poseidon::domain_tag`). The change note keeps the asset of the spent note, so notes
+//! of several tokens can share one tree without one being withdrawn as
+//! another.
 //!
 //! Public inputs:
 //!   - merkle_root: Root of the note commitment tree
-//!   - nullifier: hash(spending_key, note_index) - prevents double spend
+//!   - nullifier: hash(domain, nk, note_index), with nk = hash(spending_key,
+//!     NK_TAG) (see `keys::nullifier_key`) - prevents double spend
 //!   - recipient: Address receiving the withdrawal
-//!   - amount: Amount being withdrawn
+//!   - amount: Amount being withdrawn, fee included
 //!   - change_commitment: Commitment for remaining balance (0 if full withdrawal)
+//!   - relayer: Address paid the fee (0 if submitted directly)
+//!   - fee: Part of amount paid to the relayer (0 if submitted directly)
+//!   - asset: Token of the note, the zero address for ETH
+//!   - association_root: Root of the association set the note is proven to
+//!     belong to (0 if none)
+//!   - blocklist_root: Root of the blocklist the note is proven to be absent
+//!     from (0 if none)
+//!   - domain: Deployment domain of the note, the vault's DOMAIN mod p; the
+//!     vault passes its own, so a proof made for another deployment does not
+//!     verify
+//!
+//! The recipient, relayer and asset enter the AIR at full width, as
+//! `ADDRESS_LIMBS` public values each (see `address`), so a proof for one
+//! address cannot be replayed for another that agrees with it mod p.
+//!
+//! Binding the relayer and fee stops a front-runner from resubmitting a
+//! relayed proof and collecting the fee: the vault pays `fee` to `relayer`
+//! and `amount - fee` to `recipient`.
 //!
 //! Private inputs:
 //!   - spending_key: User's secret key
@@ -21,48 +49,239 @@
 //!   - merkle_path: Sibling hashes for Merkle proof
 //!   - path_indices: Left/right indicators for Merkle proof
 //!   - new_randomness: Randomness for change note (if partial withdrawal)
+//!   - association_path: Path of the note commitment in the association set
+//!   - blocklist_proof: Path to the commitment's empty leaf in the blocklist
+//!
+//! The circuit is generic over the field, BabyBear by default. Balances and
+//! amounts are single field elements, and the range proofs decompose the
+//! fee, `amount - fee` and `balance - amount` into `amount_bits` bits, the
+//! bound the transfer, merge and deposit circuits use too (27 with BabyBear,
+//! 60 with Goldilocks). Their sums cannot wrap, and the change note stays
+//! within the bound, so it can later be merged.
+//!
+//! Every hash is one row of the `poseidon_air` gadget, in this order: the
+//! asset id, hash(spending_key), nk, the note commitment, one row per Merkle
+//! level, the nullifier, the change commitment, one row per level of the association
+//! path, then one row per level of the blocklist path. The Merkle path bits
+//! also spell the note index the nullifier is derived from, the blocklist
+//! path bits spell the commitment, and the range proof bits run alongside on
+//! the first `amount_bits` rows. The association and blocklist paths are
+//! hashed on every proof; their roots are only checked when nonzero.
 
-use p3_air::{Air, AirBuilder, BaseAir};
+use std::fmt;
+
+use p3_air::{Air, AirBuilder, AirBuilderWithPublicValues, BaseAir};
 use p3_baby_bear::BabyBear;
-use p3_field::PrimeField32;
+use p3_field::{Field, PrimeCharacteristicRing, PrimeField64};
 use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::Matrix;
 
+use crate::abi::address_to_u256;
+use crate::address::{address_limbs, address_values, asset_id, public_value_index, Address, ADDRESS_LIMBS};
+use crate::association::AssociationPath;
+use crate::blocklist::{field_blocklist_depth, FieldNonMembershipProof};
+use crate::keys::{note_commitment, note_nullifier, nullifier_key, NK_TAG};
 use crate::merkle::TREE_DEPTH;
-use crate::poseidon::{poseidon_hash, poseidon_hash_2, poseidon_hash_3};
+use crate::transfer::amount_bits;
+use crate::groth16::U256;
+use crate::poseidon::{poseidon_hash, poseidon_hash_2};
+use crate::poseidon_air::{
+    assert_constant, assert_digest, assert_inputs, fill_bit_sum, fill_constant, Program, AUX_COL, CARRY_COL,
+};
+use crate::prover::{evm_proof_bytes, ProverError};
 
 type Val = BabyBear;
 
-/// Number of columns: public inputs (5) + range proof bits (64) + merkle path (20*2)
-const NUM_COLS: usize = 5 + 64 + TREE_DEPTH * 2;
+/// Number of public inputs, including the change commitment, relayer, fee,
+/// asset, association root, blocklist root and domain
+pub const NUM_PUBLIC_INPUTS: usize = 11;
+
+/// Public input of the domain, last as `IBalanceVerifier` takes it
+pub const DOMAIN_INPUT: usize = NUM_PUBLIC_INPUTS - 1;
+
+/// Public inputs that are addresses: the recipient, the relayer and the asset
+pub const ADDRESS_INPUTS: [usize; 3] = [2, 5, 7];
+
+/// Number of public values of the AIR, with each address split into limbs
+pub const NUM_PUBLIC_VALUES: usize = NUM_PUBLIC_INPUTS + ADDRESS_INPUTS.len() * (ADDRESS_LIMBS - 1);
+
+// Steps of the row program
+const ASSET_STEP: usize = 0;
+const SPENDING_KEY_HASH_STEP: usize = ASSET_STEP + 1;
+const NULLIFIER_KEY_STEP: usize = SPENDING_KEY_HASH_STEP + 1;
+const COMMITMENT_STEP: usize = NULLIFIER_KEY_STEP + 1;
+const MERKLE_STEP: usize = COMMITMENT_STEP + 1;
+const NULLIFIER_STEP: usize = MERKLE_STEP + TREE_DEPTH;
+const CHANGE_STEP: usize = NULLIFIER_STEP + 1;
+const ASSOCIATION_STEP: usize = CHANGE_STEP + 1;
+const BLOCKLIST_STEP: usize = ASSOCIATION_STEP + TREE_DEPTH;
+
+// Private inputs, equal on every row
+const ASSET_ID_COL: usize = AUX_COL;
+const SPENDING_KEY_COL: usize = ASSET_ID_COL + 1;
+const SPENDING_KEY_HASH_COL: usize = SPENDING_KEY_COL + 1;
+const NULLIFIER_KEY_COL: usize = SPENDING_KEY_HASH_COL + 1;
+const BALANCE_COL: usize = NULLIFIER_KEY_COL + 1;
+const RANDOMNESS_COL: usize = BALANCE_COL + 1;
+const NEW_RANDOMNESS_COL: usize = RANDOMNESS_COL + 1;
+/// 1 for a partial withdrawal, which publishes a change commitment
+const HAS_CHANGE_COL: usize = NEW_RANDOMNESS_COL + 1;
+/// Inverse of `balance - amount` for a partial withdrawal
+const CHANGE_INV_COL: usize = HAS_CHANGE_COL + 1;
+/// Note commitment, the leaf of the association path
+const COMMITMENT_COL: usize = CHANGE_INV_COL + 1;
+/// 1 when proving against an association root, and its inverse
+const HAS_ASSOCIATION_COL: usize = COMMITMENT_COL + 1;
+const ASSOCIATION_INV_COL: usize = HAS_ASSOCIATION_COL + 1;
+/// 1 when proving against a blocklist root, and its inverse
+const HAS_BLOCKLIST_COL: usize = ASSOCIATION_INV_COL + 1;
+const BLOCKLIST_INV_COL: usize = HAS_BLOCKLIST_COL + 1;
+/// Sibling and side of the running node on a Merkle, association or
+/// blocklist step
+const SIBLING_COL: usize = BLOCKLIST_INV_COL + 1;
+const IS_RIGHT_COL: usize = SIBLING_COL + 1;
+/// Running node of the association or blocklist path, hashed on the row's step
+const NODE_COL: usize = IS_RIGHT_COL + 1;
+/// Running sum of the path bits: the note index from the last Merkle step on
+const INDEX_COL: usize = NODE_COL + 1;
+/// Running sums of the low `key_split` and the remaining blocklist path
+/// bits: the commitment's key in two parts
+const LOW_KEY_COL: usize = INDEX_COL + 1;
+const HIGH_KEY_COL: usize = LOW_KEY_COL + 1;
+/// Inverse of the gap between the high key part and its maximum, and the
+/// gap times it, read on the last row
+const KEY_GAP_INV_COL: usize = HIGH_KEY_COL + 1;
+const KEY_GAP_PROD_COL: usize = KEY_GAP_INV_COL + 1;
+/// Range proof bits and their running sums, one bit per row
+const DIFF_BIT_COL: usize = KEY_GAP_PROD_COL + 1;
+const DIFF_COL: usize = DIFF_BIT_COL + 1;
+const FEE_MARGIN_BIT_COL: usize = DIFF_COL + 1;
+const FEE_MARGIN_COL: usize = FEE_MARGIN_BIT_COL + 1;
+const FEE_BIT_COL: usize = FEE_MARGIN_COL + 1;
+const FEE_COL: usize = FEE_BIT_COL + 1;
+const NUM_AUX_COLS: usize = FEE_COL + 1 - AUX_COL;
+
+const GLOBAL_COLS: [usize; 14] = [
+    ASSET_ID_COL,
+    SPENDING_KEY_COL,
+    SPENDING_KEY_HASH_COL,
+    NULLIFIER_KEY_COL,
+    BALANCE_COL,
+    RANDOMNESS_COL,
+    NEW_RANDOMNESS_COL,
+    HAS_CHANGE_COL,
+    CHANGE_INV_COL,
+    COMMITMENT_COL,
+    HAS_ASSOCIATION_COL,
+    ASSOCIATION_INV_COL,
+    HAS_BLOCKLIST_COL,
+    BLOCKLIST_INV_COL,
+];
+
+/// Width of the low part of a blocklist key over `F`, whose modulus is
+/// 2^n - 2^m + 1 with n the blocklist depth and m this (BabyBear: 31 and
+/// 27, Goldilocks: 64 and 32)
+fn key_split<F: PrimeField64>() -> usize {
+    let (n, m) = (field_blocklist_depth::<F>(), (F::ORDER_U64 - 1).trailing_zeros() as usize);
+    debug_assert_eq!((1u128 << n) - (1u128 << m) + 1, F::ORDER_U64 as u128);
+    m
+}
+
+/// Steps of the row program over `F`: one hash per row
+fn hash_steps<F: PrimeField64>() -> usize {
+    BLOCKLIST_STEP + field_blocklist_depth::<F>()
+}
+
+/// Row program of the circuit over `F`, long enough for the hashes and
+/// for one range proof bit per row
+fn program<F: PrimeField64>() -> Program {
+    Program::new(NUM_AUX_COLS, hash_steps::<F>().max(amount_bits::<F>()))
+}
 
 /// Balance withdrawal circuit with range proofs
-pub struct BalanceWithdrawalCircuit {
+pub struct BalanceWithdrawalCircuit<F = Val> {
     // Public inputs
-    pub merkle_root: Val,
-    pub nullifier: Val,
-    pub recipient: Val,
-    pub amount: Val,
-    pub change_commitment: Val,
+    pub merkle_root: F,
+    pub nullifier: F,
+    pub recipient: Address,
+    pub amount: F,
+    pub change_commitment: F,
+    pub relayer: Address,
+    pub fee: F,
+    pub asset: Address,
+    pub association_root: F,
+    pub blocklist_root: F,
+    /// Deployment domain, `poseidon::domain_tag`
+    pub domain: F,
+}
+
+/// Reasons a witness does not satisfy the withdrawal, transfer or merge statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitnessError {
+    InvalidMerkleProof,
+    InvalidNullifier,
+    InsufficientBalance,
+    InvalidChangeCommitment,
+    NonZeroChangeCommitment,
+    DuplicateNullifier,
+    InvalidOutputCommitment,
+    AmountOutOfRange,
+    ValueNotConserved,
+    InvalidNoteCount,
+    FeeExceedsAmount,
+    NotInAssociationSet,
+    Blocklisted,
 }
 
+impl fmt::Display for WitnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            WitnessError::InvalidMerkleProof => "Invalid Merkle proof",
+            WitnessError::InvalidNullifier => "Invalid nullifier",
+            WitnessError::InsufficientBalance => "Insufficient balance",
+            WitnessError::InvalidChangeCommitment => "Invalid change commitment",
+            WitnessError::NonZeroChangeCommitment => "Change commitment should be zero for full withdrawal",
+            WitnessError::DuplicateNullifier => "Both inputs have the same nullifier",
+            WitnessError::InvalidOutputCommitment => "Invalid output commitment",
+            WitnessError::AmountOutOfRange => "Amount exceeds the range-checked width",
+            WitnessError::ValueNotConserved => "Inputs do not equal outputs plus public amount",
+            WitnessError::InvalidNoteCount => "Wrong number of input notes",
+            WitnessError::FeeExceedsAmount => "Relayer fee exceeds amount",
+            WitnessError::NotInAssociationSet => "Note is not in the association set",
+            WitnessError::Blocklisted => "Note is on the blocklist",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::error::Error for WitnessError {}
+
 /// Private witness for the withdrawal
-pub struct BalanceWithdrawalWitness {
-    pub spending_key: Val,
-    pub balance: Val,
-    pub randomness: Val,
+pub struct BalanceWithdrawalWitness<F = Val> {
+    /// Deployment domain of the note
+    pub domain: F,
+    pub spending_key: F,
+    pub asset: Address,
+    pub balance: F,
+    pub randomness: F,
     pub note_index: u64,
-    pub merkle_path: [Val; TREE_DEPTH],
+    pub merkle_path: [F; TREE_DEPTH],
     pub path_indices: [bool; TREE_DEPTH],
-    pub new_randomness: Val,
+    pub new_randomness: F,
+    /// Only needed when proving against an association root
+    pub association_path: Option<AssociationPath<F>>,
+    /// Only needed when proving against a blocklist root
+    pub blocklist_proof: Option<FieldNonMembershipProof<F>>,
 }
 
-impl BalanceWithdrawalCircuit {
+impl<F: PrimeField64> BalanceWithdrawalCircuit<F> {
     pub fn new(
-        merkle_root: Val,
-        nullifier: Val,
-        recipient: Val,
-        amount: Val,
-        change_commitment: Val,
+        merkle_root: F,
+        nullifier: F,
+        recipient: Address,
+        amount: F,
+        change_commitment: F,
+        domain: F,
     ) -> Self {
         Self {
             merkle_root,
@@ -70,122 +289,537 @@ impl BalanceWithdrawalCircuit {
             recipient,
             amount,
             change_commitment,
+            relayer: [0; 20],
+            fee: F::ZERO,
+            asset: [0; 20],
+            association_root: F::ZERO,
+            blocklist_root: F::ZERO,
+            domain,
         }
     }
 
-    /// Generate the execution trace for proving
-    pub fn generate_trace(&self, witness: &BalanceWithdrawalWitness) -> RowMajorMatrix<Val> {
-        // ===== Verify all constraints =====
+    /// Withdraw a note of `asset` rather than the default, the zero address
+    pub fn with_asset(mut self, asset: Address) -> Self {
+        self.asset = asset;
+        self
+    }
+
+    /// Pay `fee` out of the amount to `relayer`
+    ///
+    /// Without it the relayer and fee are zero, for a withdrawal submitted
+    /// by the recipient.
+    pub fn with_relayer(mut self, relayer: Address, fee: F) -> Self {
+        self.relayer = relayer;
+        self.fee = fee;
+        self
+    }
+
+    /// Also prove the note is in the association set with root `root`
+    ///
+    /// Without it the root is zero and no association path is checked.
+    pub fn with_association_root(mut self, root: F) -> Self {
+        self.association_root = root;
+        self
+    }
+
+    /// Also prove the note is not in the blocklist with root `root`
+    ///
+    /// Without it the root is zero and no blocklist path is checked.
+    pub fn with_blocklist_root(mut self, root: F) -> Self {
+        self.blocklist_root = root;
+        self
+    }
+
+    /// Derive the public inputs from a witness
+    ///
+    /// Fails only if the note cannot cover `amount`; the Merkle root is
+    /// whatever the path hashes to, so callers holding an expected root
+    /// should compare it (or run `check_witness`) afterwards. The same holds
+    /// for the association and blocklist roots when the witness carries
+    /// their paths.
+    pub fn from_witness(
+        witness: &BalanceWithdrawalWitness<F>,
+        recipient: Address,
+        amount: F,
+    ) -> Result<Self, WitnessError> {
+        let spending_key_hash = poseidon_hash(witness.spending_key);
+        let asset_id = asset_id::<F>(&witness.asset);
+        let domain = witness.domain;
+        let commitment = note_commitment(domain, spending_key_hash, asset_id, witness.balance, witness.randomness);
+        let merkle_root = compute_merkle_root_with_path(
+            commitment,
+            &witness.merkle_path,
+            &witness.path_indices,
+        );
+        let nullifier = note_nullifier(domain, nullifier_key(witness.spending_key), witness.note_index);
+
+        let balance_u64 = field_to_u64(witness.balance);
+        let amount_u64 = field_to_u64(amount);
+        if balance_u64 < amount_u64 {
+            return Err(WitnessError::InsufficientBalance);
+        }
+
+        let change_balance = balance_u64 - amount_u64;
+        let change_commitment = if change_balance > 0 {
+            note_commitment(domain, spending_key_hash, asset_id, F::from_u64(change_balance), witness.new_randomness)
+        } else {
+            F::ZERO
+        };
+
+        let association_root = witness
+            .association_path
+            .map(|p| compute_merkle_root_with_path(commitment, &p.path, &p.indices))
+            .unwrap_or(F::ZERO);
+        let blocklist_root = witness
+            .blocklist_proof
+            .as_ref()
+            .map(|p| p.root(commitment))
+            .unwrap_or(F::ZERO);
+
+        Ok(Self::new(merkle_root, nullifier, recipient, amount, change_commitment, domain)
+            .with_asset(witness.asset)
+            .with_association_root(association_root)
+            .with_blocklist_root(blocklist_root))
+    }
+
+    /// Public inputs in verifier order, each address as its limbs:
+    /// [merkle_root, nullifier, recipient, amount, change_commitment, relayer,
+    ///  fee, asset, association_root, blocklist_root, domain]
+    pub fn public_values(&self) -> Vec<F> {
+        let mut values = Vec::with_capacity(NUM_PUBLIC_VALUES);
+        values.extend([self.merkle_root, self.nullifier]);
+        values.extend(address_values::<F>(&self.recipient));
+        values.extend([self.amount, self.change_commitment]);
+        values.extend(address_values::<F>(&self.relayer));
+        values.push(self.fee);
+        values.extend(address_values::<F>(&self.asset));
+        values.extend([self.association_root, self.blocklist_root, self.domain]);
+        values
+    }
 
+    /// Check that the witness satisfies the statement for these public inputs
+    pub fn check_witness(&self, witness: &BalanceWithdrawalWitness<F>) -> Result<(), WitnessError> {
         // 1. Compute spending_key_hash = hash(spending_key)
         let spending_key_hash = poseidon_hash(witness.spending_key);
 
         // 2. Compute original note commitment
-        // commitment = hash(spending_key_hash, balance, randomness)
-        let note_commitment = poseidon_hash_3(
+        // commitment = hash(domain, spending_key_hash, asset_id, balance, randomness)
+        let asset_id = asset_id::<F>(&self.asset);
+        let commitment = note_commitment(
+            self.domain,
             spending_key_hash,
+            asset_id,
             witness.balance,
             witness.randomness,
         );
 
         // 3. Verify Merkle proof
         let computed_root = compute_merkle_root_with_path(
-            note_commitment,
+            commitment,
             &witness.merkle_path,
             &witness.path_indices,
         );
-        assert_eq!(computed_root, self.merkle_root, "Invalid Merkle proof");
+        if computed_root != self.merkle_root {
+            return Err(WitnessError::InvalidMerkleProof);
+        }
 
-        // 4. Verify nullifier = hash(spending_key, note_index)
-        let note_index_field = Val::new(witness.note_index as u32);
-        let computed_nullifier = poseidon_hash_2(witness.spending_key, note_index_field);
-        assert_eq!(computed_nullifier, self.nullifier, "Invalid nullifier");
+        // 4. Verify nullifier = hash(domain, nk, note_index), where the circuit
+        // reads note_index off the Merkle path bits
+        if witness.note_index != path_position(&witness.path_indices) {
+            return Err(WitnessError::InvalidNullifier);
+        }
+        let computed_nullifier = note_nullifier(self.domain, nullifier_key(witness.spending_key), witness.note_index);
+        if computed_nullifier != self.nullifier {
+            return Err(WitnessError::InvalidNullifier);
+        }
 
         // 5. Verify balance >= amount (range proof)
         let balance_u64 = field_to_u64(witness.balance);
         let amount_u64 = field_to_u64(self.amount);
-        assert!(balance_u64 >= amount_u64, "Insufficient balance");
+        if balance_u64 < amount_u64 {
+            return Err(WitnessError::InsufficientBalance);
+        }
+
+        // The range proofs only cover amount_bits bits
+        let fee_u64 = field_to_u64(self.fee);
+        let bound = 1u64 << amount_bits::<F>();
+        if balance_u64 - amount_u64 >= bound || amount_u64.saturating_sub(fee_u64) >= bound || fee_u64 >= bound {
+            return Err(WitnessError::AmountOutOfRange);
+        }
 
         // 6. Verify change commitment
         let change_balance = balance_u64 - amount_u64;
         if change_balance > 0 {
-            // Partial withdrawal - verify change commitment
-            let change_balance_field = Val::new(change_balance as u32);
-            let expected_change = poseidon_hash_3(
+            // Partial withdrawal - verify change commitment, same asset
+            let change_balance_field = F::from_u64(change_balance);
+            let expected_change = note_commitment(
+                self.domain,
                 spending_key_hash,
+                asset_id,
                 change_balance_field,
                 witness.new_randomness,
             );
-            assert_eq!(expected_change, self.change_commitment, "Invalid change commitment");
-        } else {
+            if expected_change != self.change_commitment {
+                return Err(WitnessError::InvalidChangeCommitment);
+            }
+        } else if self.change_commitment != F::ZERO {
             // Full withdrawal - change commitment must be zero
-            assert_eq!(self.change_commitment, Val::new(0), "Change commitment should be zero for full withdrawal");
+            return Err(WitnessError::NonZeroChangeCommitment);
         }
 
-        // ===== Build trace matrix =====
-        let mut trace_values = Vec::with_capacity(NUM_COLS);
-
-        // Public inputs
-        trace_values.push(self.merkle_root);
-        trace_values.push(self.nullifier);
-        trace_values.push(self.recipient);
-        trace_values.push(self.amount);
-        trace_values.push(self.change_commitment);
-
-        // Range proof: balance - amount >= 0
-        // Decompose (balance - amount) into 64 bits
-        let diff = balance_u64 - amount_u64;
-        for i in 0..64 {
-            let bit = ((diff >> i) & 1) as u32;
-            trace_values.push(Val::new(bit));
+        // 7. Verify fee <= amount (range proof)
+        if fee_u64 > amount_u64 {
+            return Err(WitnessError::FeeExceedsAmount);
         }
 
-        // Merkle path
-        for i in 0..TREE_DEPTH {
-            trace_values.push(witness.merkle_path[i]);
+        // 8. Verify association set membership, if a set is given
+        if self.association_root != F::ZERO {
+            let in_set = witness.association_path.is_some_and(|p| {
+                compute_merkle_root_with_path(commitment, &p.path, &p.indices)
+                    == self.association_root
+            });
+            if !in_set {
+                return Err(WitnessError::NotInAssociationSet);
+            }
         }
 
-        // Path indices
-        for i in 0..TREE_DEPTH {
-            trace_values.push(if witness.path_indices[i] {
-                Val::new(1)
-            } else {
-                Val::new(0)
-            });
+        // 9. Verify blocklist non-membership, if a blocklist is given
+        if self.blocklist_root != F::ZERO {
+            let unblocked = witness
+                .blocklist_proof
+                .as_ref()
+                .is_some_and(|p| p.verify(commitment, self.blocklist_root));
+            if !unblocked {
+                return Err(WitnessError::Blocklisted);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generate the execution trace for proving
+    pub fn generate_trace(&self, witness: &BalanceWithdrawalWitness<F>) -> RowMajorMatrix<F> {
+        // ===== Verify all constraints =====
+        if let Err(e) = self.check_witness(witness) {
+            panic!("{}", e);
+        }
+
+        let spending_key_hash = poseidon_hash(witness.spending_key);
+        let nk = nullifier_key(witness.spending_key);
+        let balance_u64 = field_to_u64(witness.balance);
+        let amount_u64 = field_to_u64(self.amount);
+        let fee_u64 = field_to_u64(self.fee);
+        let change = witness.balance - self.amount;
+        let asset_id = asset_id::<F>(&self.asset);
+
+        // ===== One permutation per step, in the order `eval` wires them =====
+        let mut inputs = vec![
+            address_values::<F>(&self.asset).to_vec(),
+            vec![witness.spending_key],
+            vec![witness.spending_key, F::from_u64(NK_TAG)],
+            vec![self.domain, spending_key_hash, asset_id, witness.balance, witness.randomness],
+        ];
+        let commitment = note_commitment(self.domain, spending_key_hash, asset_id, witness.balance, witness.randomness);
+        let mut current = commitment;
+        for (&sibling, &is_right) in witness.merkle_path.iter().zip(&witness.path_indices) {
+            let pair = if is_right { [sibling, current] } else { [current, sibling] };
+            current = poseidon_hash_2(pair[0], pair[1]);
+            inputs.push(pair.to_vec());
+        }
+        inputs.push(vec![self.domain, nk, F::from_u64(witness.note_index)]);
+        inputs.push(vec![self.domain, spending_key_hash, asset_id, change, witness.new_randomness]);
+
+        // Without an association set the path is hashed all the same, over
+        // empty siblings, and its root left unchecked
+        let association = match (self.association_root != F::ZERO, witness.association_path) {
+            (true, Some(path)) => path,
+            _ => AssociationPath { path: [F::ZERO; TREE_DEPTH], indices: [false; TREE_DEPTH] },
+        };
+        let mut association_nodes = [F::ZERO; TREE_DEPTH];
+        let mut current = commitment;
+        for (level, node) in association_nodes.iter_mut().enumerate() {
+            *node = current;
+            let (sibling, is_right) = (association.path[level], association.indices[level]);
+            let pair = if is_right { [sibling, current] } else { [current, sibling] };
+            current = poseidon_hash_2(pair[0], pair[1]);
+            inputs.push(pair.to_vec());
+        }
+
+        // The blocklist path climbs from the commitment's empty leaf along
+        // its bits, likewise over empty siblings without a blocklist
+        let blocklist = match (self.blocklist_root != F::ZERO, &witness.blocklist_proof) {
+            (true, Some(proof)) => proof.clone(),
+            _ => FieldNonMembershipProof::blank(),
+        };
+        let key = commitment.as_canonical_u64();
+        let mut blocklist_nodes = Vec::with_capacity(blocklist.siblings.len());
+        let mut current = F::ZERO;
+        for (level, &sibling) in blocklist.siblings.iter().enumerate() {
+            blocklist_nodes.push(current);
+            let pair = if (key >> level) & 1 == 1 { [sibling, current] } else { [current, sibling] };
+            current = poseidon_hash_2(pair[0], pair[1]);
+            inputs.push(pair.to_vec());
+        }
+
+        let program = program::<F>();
+        let mut trace = program.trace(&inputs);
+
+        // ===== Private inputs =====
+        let has_change = change != F::ZERO;
+        fill_constant(&mut trace, ASSET_ID_COL, asset_id);
+        fill_constant(&mut trace, SPENDING_KEY_COL, witness.spending_key);
+        fill_constant(&mut trace, SPENDING_KEY_HASH_COL, spending_key_hash);
+        fill_constant(&mut trace, NULLIFIER_KEY_COL, nk);
+        fill_constant(&mut trace, BALANCE_COL, witness.balance);
+        fill_constant(&mut trace, RANDOMNESS_COL, witness.randomness);
+        fill_constant(&mut trace, NEW_RANDOMNESS_COL, witness.new_randomness);
+        fill_constant(&mut trace, HAS_CHANGE_COL, F::from_bool(has_change));
+        fill_constant(&mut trace, CHANGE_INV_COL, if has_change { change.inverse() } else { F::ZERO });
+        fill_constant(&mut trace, COMMITMENT_COL, commitment);
+        let has_association = self.association_root != F::ZERO;
+        fill_constant(&mut trace, HAS_ASSOCIATION_COL, F::from_bool(has_association));
+        let association_inv = if has_association { self.association_root.inverse() } else { F::ZERO };
+        fill_constant(&mut trace, ASSOCIATION_INV_COL, association_inv);
+        let has_blocklist = self.blocklist_root != F::ZERO;
+        fill_constant(&mut trace, HAS_BLOCKLIST_COL, F::from_bool(has_blocklist));
+        let blocklist_inv = if has_blocklist { self.blocklist_root.inverse() } else { F::ZERO };
+        fill_constant(&mut trace, BLOCKLIST_INV_COL, blocklist_inv);
+
+        // ===== Merkle path, and the note index its bits spell =====
+        let width = trace.width;
+        for (level, row) in trace.values.chunks_mut(width).skip(MERKLE_STEP).take(TREE_DEPTH).enumerate() {
+            row[SIBLING_COL] = witness.merkle_path[level];
+        }
+        fill_bit_sum(&mut trace, IS_RIGHT_COL, INDEX_COL, MERKLE_STEP, TREE_DEPTH, witness.note_index);
+
+        // ===== Association path =====
+        let rows = trace.values.chunks_mut(width).skip(ASSOCIATION_STEP).take(TREE_DEPTH);
+        for (level, row) in rows.enumerate() {
+            row[SIBLING_COL] = association.path[level];
+            row[IS_RIGHT_COL] = F::from_bool(association.indices[level]);
+            row[NODE_COL] = association_nodes[level];
         }
 
-        RowMajorMatrix::new(trace_values, NUM_COLS)
+        // ===== Blocklist path, and the commitment its bits spell =====
+        let (depth, split) = (field_blocklist_depth::<F>(), key_split::<F>());
+        let rows = trace.values.chunks_mut(width).skip(BLOCKLIST_STEP).take(depth);
+        for (level, row) in rows.enumerate() {
+            row[SIBLING_COL] = blocklist.siblings[level];
+            row[NODE_COL] = blocklist_nodes[level];
+        }
+        fill_bit_sum(&mut trace, IS_RIGHT_COL, LOW_KEY_COL, BLOCKLIST_STEP, split, key);
+        fill_bit_sum(&mut trace, IS_RIGHT_COL, HIGH_KEY_COL, BLOCKLIST_STEP + split, depth - split, key >> split);
+        let key_gap = F::from_u64((1 << (depth - split)) - 1 - (key >> split));
+        let key_gap_inv = key_gap.try_inverse().unwrap_or(F::ZERO);
+        fill_constant(&mut trace, KEY_GAP_INV_COL, key_gap_inv);
+        fill_constant(&mut trace, KEY_GAP_PROD_COL, key_gap * key_gap_inv);
+
+        // ===== Range proofs: balance - amount, amount - fee and fee =====
+        let bits = amount_bits::<F>();
+        fill_bit_sum(&mut trace, DIFF_BIT_COL, DIFF_COL, 0, bits, balance_u64 - amount_u64);
+        fill_bit_sum(&mut trace, FEE_MARGIN_BIT_COL, FEE_MARGIN_COL, 0, bits, amount_u64 - fee_u64);
+        fill_bit_sum(&mut trace, FEE_BIT_COL, FEE_COL, 0, bits, fee_u64);
+
+        trace
+    }
+
+    /// Height of the trace `generate_trace` produces
+    pub fn trace_height() -> usize {
+        program::<F>().height()
     }
 }
 
-impl BaseAir<Val> for BalanceWithdrawalCircuit {
+impl<F: PrimeField64> BaseAir<F> for BalanceWithdrawalCircuit<F> {
     fn width(&self) -> usize {
-        NUM_COLS
+        program::<F>().width()
     }
 }
 
-impl<AB: AirBuilder<F = Val>> Air<AB> for BalanceWithdrawalCircuit {
-    fn eval(&self, _builder: &mut AB) {
-        // Constraints are validated during trace generation
-        // Full AIR constraints would include:
-        // - Range proof: bits are binary, sum equals (balance - amount)
-        // - Poseidon permutation constraints for all hash computations
-        // - Merkle tree traversal constraints
-        // - Commitment derivation constraints
-        // - Nullifier computation constraints
-        // - Binary constraints for path indices
-        //
-        // For now, the trace generation validates all constraints
-        // and the prover ensures the trace satisfies them.
+impl<F: PrimeField64, AB: AirBuilderWithPublicValues<F = F>> Air<AB> for BalanceWithdrawalCircuit<F> {
+    fn eval(&self, builder: &mut AB) {
+        let main = builder.main();
+        let local = main.row_slice(0).expect("The matrix is empty?");
+        let next = main.row_slice(1).expect("The matrix has only one row?");
+        let local: Vec<AB::Expr> = local.iter().map(|&v| v.into()).collect();
+        let next: Vec<AB::Expr> = next.iter().map(|&v| v.into()).collect();
+        let pis: Vec<AB::Expr> = builder.public_values().iter().map(|&v| v.into()).collect();
+        let pi = |input: usize| pis[public_value_index(input, &ADDRESS_INPUTS)].clone();
+
+        let program = program::<F>();
+        program.eval(builder, &local, &next);
+        for col in GLOBAL_COLS {
+            assert_constant(builder, &local, &next, col);
+        }
+
+        let spending_key = local[SPENDING_KEY_COL].clone();
+        let spending_key_hash = local[SPENDING_KEY_HASH_COL].clone();
+        let nk = local[NULLIFIER_KEY_COL].clone();
+        let balance = local[BALANCE_COL].clone();
+        let has_change = local[HAS_CHANGE_COL].clone();
+        let (merkle_root, nullifier, amount) = (pi(0), pi(1), pi(3));
+        let (change_commitment, fee) = (pi(4), pi(6));
+        let (association_root, blocklist_root, domain) = (pi(8), pi(9), pi(DOMAIN_INPUT));
+        let asset_id = local[ASSET_ID_COL].clone();
+
+        // asset_id = hash(limbs of the asset)
+        let asset = public_value_index(7, &ADDRESS_INPUTS);
+        let step = program.flag(&local, ASSET_STEP);
+        assert_inputs(builder, step.clone(), &local, &pis[asset..asset + ADDRESS_LIMBS]);
+        assert_digest(builder, step, &local, asset_id.clone());
+
+        // spending_key_hash = hash(spending_key)
+        let step = program.flag(&local, SPENDING_KEY_HASH_STEP);
+        assert_inputs(builder, step.clone(), &local, std::slice::from_ref(&spending_key));
+        assert_digest(builder, step, &local, spending_key_hash.clone());
+
+        // nk = hash(spending_key, NK_TAG)
+        let step = program.flag(&local, NULLIFIER_KEY_STEP);
+        assert_inputs(builder, step.clone(), &local, &[spending_key, AB::Expr::from_u64(NK_TAG)]);
+        assert_digest(builder, step, &local, nk.clone());
+
+        // commitment = hash(domain, spending_key_hash, asset_id, balance,
+        // randomness), carried into the first Merkle step
+        let lanes = [
+            domain.clone(),
+            spending_key_hash.clone(),
+            asset_id.clone(),
+            balance.clone(),
+            local[RANDOMNESS_COL].clone(),
+        ];
+        let step = program.flag(&local, COMMITMENT_STEP);
+        assert_inputs(builder, step.clone(), &local, &lanes);
+        assert_digest(builder, step, &local, local[COMMITMENT_COL].clone());
+
+        // Each level hashes the carried node with the sibling, on the side
+        // the path bit selects, and the last one yields merkle_root
+        let merkle = program.phase(&local, MERKLE_STEP, TREE_DEPTH);
+        let (node, sibling, is_right) = (&local[CARRY_COL], &local[SIBLING_COL], &local[IS_RIGHT_COL]);
+        let left = node.clone() + is_right.clone() * (sibling.clone() - node.clone());
+        let right = sibling.clone() + is_right.clone() * (node.clone() - sibling.clone());
+        assert_inputs(builder, merkle, &local, &[left, right]);
+        assert_digest(builder, program.flag(&local, NULLIFIER_STEP - 1), &local, merkle_root);
+        program.eval_bit_sum(builder, &local, &next, IS_RIGHT_COL, INDEX_COL, MERKLE_STEP, TREE_DEPTH);
+
+        // nullifier = hash(domain, nk, note_index)
+        let step = program.flag(&local, NULLIFIER_STEP);
+        assert_inputs(builder, step.clone(), &local, &[domain.clone(), nk, local[INDEX_COL].clone()]);
+        assert_digest(builder, step, &local, nullifier);
+
+        // change_commitment = hash(domain, spending_key_hash, asset_id,
+        // balance - amount, new_randomness) when balance > amount, and 0 when
+        // they are equal
+        let change = balance - amount.clone();
+        let step = program.flag(&local, CHANGE_STEP);
+        let lanes = [domain, spending_key_hash, asset_id, change.clone(), local[NEW_RANDOMNESS_COL].clone()];
+        assert_inputs(builder, step.clone(), &local, &lanes);
+        assert_digest(builder, step * has_change.clone(), &local, change_commitment.clone());
+        builder.assert_bool(has_change.clone());
+        let no_change = AB::Expr::ONE - has_change.clone();
+        builder.when(no_change.clone()).assert_zero(change.clone());
+        builder.when(no_change).assert_zero(change_commitment);
+        builder
+            .when(has_change)
+            .assert_one(change.clone() * local[CHANGE_INV_COL].clone());
+
+        // Range proofs: balance - amount, amount - fee and fee all fit in
+        // amount_bits bits, so balance >= amount >= fee
+        let bits = amount_bits::<F>();
+        program.eval_bit_sum(builder, &local, &next, DIFF_BIT_COL, DIFF_COL, 0, bits);
+        program.eval_bit_sum(builder, &local, &next, FEE_MARGIN_BIT_COL, FEE_MARGIN_COL, 0, bits);
+        program.eval_bit_sum(builder, &local, &next, FEE_BIT_COL, FEE_COL, 0, bits);
+        let mut when_last_row = builder.when_last_row();
+        when_last_row.assert_eq(local[DIFF_COL].clone(), change);
+        when_last_row.assert_eq(local[FEE_MARGIN_COL].clone(), amount - fee.clone());
+        when_last_row.assert_eq(local[FEE_COL].clone(), fee);
+
+        // The association path starts at the commitment and goes on from
+        // the carried node; its last step yields association_root, if nonzero
+        let association = program.phase(&local, ASSOCIATION_STEP, TREE_DEPTH);
+        let (node, sibling, is_right) = (&local[NODE_COL], &local[SIBLING_COL], &local[IS_RIGHT_COL]);
+        builder
+            .when(program.flag(&local, ASSOCIATION_STEP))
+            .assert_eq(node.clone(), local[COMMITMENT_COL].clone());
+        builder
+            .when(program.phase(&local, ASSOCIATION_STEP + 1, TREE_DEPTH - 1))
+            .assert_eq(node.clone(), local[CARRY_COL].clone());
+        builder.when(association.clone()).assert_bool(is_right.clone());
+        let left = node.clone() + is_right.clone() * (sibling.clone() - node.clone());
+        let right = sibling.clone() + is_right.clone() * (node.clone() - sibling.clone());
+        assert_inputs(builder, association, &local, &[left, right]);
+
+        let has_association = local[HAS_ASSOCIATION_COL].clone();
+        let last = program.flag(&local, BLOCKLIST_STEP - 1);
+        assert_digest(builder, last * has_association.clone(), &local, association_root.clone());
+        builder.assert_bool(has_association.clone());
+        builder
+            .when(AB::Expr::ONE - has_association.clone())
+            .assert_zero(association_root.clone());
+        builder
+            .when(has_association)
+            .assert_one(association_root * local[ASSOCIATION_INV_COL].clone());
+
+        // The blocklist path starts at an empty leaf and climbs along the
+        // bits of the commitment, low bit first; its last step yields
+        // blocklist_root, if nonzero
+        let (depth, split) = (field_blocklist_depth::<F>(), key_split::<F>());
+        let blocklist = program.phase(&local, BLOCKLIST_STEP, depth);
+        let (node, sibling, is_right) = (&local[NODE_COL], &local[SIBLING_COL], &local[IS_RIGHT_COL]);
+        builder.when(program.flag(&local, BLOCKLIST_STEP)).assert_zero(node.clone());
+        builder
+            .when(program.phase(&local, BLOCKLIST_STEP + 1, depth - 1))
+            .assert_eq(node.clone(), local[CARRY_COL].clone());
+        let left = node.clone() + is_right.clone() * (sibling.clone() - node.clone());
+        let right = sibling.clone() + is_right.clone() * (node.clone() - sibling.clone());
+        assert_inputs(builder, blocklist, &local, &[left, right]);
+        program.eval_bit_sum(builder, &local, &next, IS_RIGHT_COL, LOW_KEY_COL, BLOCKLIST_STEP, split);
+        program.eval_bit_sum(builder, &local, &next, IS_RIGHT_COL, HIGH_KEY_COL, BLOCKLIST_STEP + split, depth - split);
+
+        // The bits spell the commitment below p, so the path is to its own
+        // leaf: low + 2^split * high, with low = 0 when high is at its
+        // maximum 2^(depth - split) - 1
+        let (low, high) = (local[LOW_KEY_COL].clone(), local[HIGH_KEY_COL].clone());
+        let key_gap = AB::Expr::from(F::from_u64((1 << (depth - split)) - 1)) - high.clone();
+        let key_gap_prod = local[KEY_GAP_PROD_COL].clone();
+        let mut when_last_row = builder.when_last_row();
+        when_last_row.assert_eq(
+            local[COMMITMENT_COL].clone(),
+            low.clone() + high * AB::Expr::from(F::from_u64(1 << split)),
+        );
+        when_last_row.assert_eq(key_gap_prod.clone(), key_gap * local[KEY_GAP_INV_COL].clone());
+        when_last_row.assert_zero(low * (AB::Expr::ONE - key_gap_prod));
+
+        let has_blocklist = local[HAS_BLOCKLIST_COL].clone();
+        let last = program.flag(&local, BLOCKLIST_STEP + depth - 1);
+        assert_digest(builder, last * has_blocklist.clone(), &local, blocklist_root.clone());
+        builder.assert_bool(has_blocklist.clone());
+        builder
+            .when(AB::Expr::ONE - has_blocklist.clone())
+            .assert_zero(blocklist_root.clone());
+        builder
+            .when(has_blocklist)
+            .assert_one(blocklist_root * local[BLOCKLIST_INV_COL].clone());
+
+        // recipient and relayer are bound as public values
     }
 }
 
+/// Note index whose path in the tree takes the sides in `indices`
+pub fn path_position(indices: &[bool; TREE_DEPTH]) -> u64 {
+    indices.iter().rev().fold(0, |index, &is_right| index << 1 | is_right as u64)
+}
+
+/// Sides of the path of the note at `index`: `true` where the running node
+/// is the right child, as `compute_merkle_root_with_path` expects
+pub fn path_indices(index: u64) -> [bool; TREE_DEPTH] {
+    core::array::from_fn(|level| (index >> level) & 1 == 1)
+}
+
 /// Compute Merkle root from leaf and path
-fn compute_merkle_root_with_path(
-    leaf: Val,
-    path: &[Val; TREE_DEPTH],
+pub fn compute_merkle_root_with_path<F: Field>(
+    leaf: F,
+    path: &[F; TREE_DEPTH],
     indices: &[bool; TREE_DEPTH],
-) -> Val {
+) -> F {
     let mut current = leaf;
     for i in 0..TREE_DEPTH {
         if indices[i] {
@@ -200,9 +834,8 @@ fn compute_merkle_root_with_path(
 }
 
 /// Convert field element to u64 (for range checks)
-fn field_to_u64(val: Val) -> u64 {
-    // BabyBear field element to canonical u32, then u64
-    val.as_canonical_u32() as u64
+fn field_to_u64<F: PrimeField64>(val: F) -> u64 {
+    val.as_canonical_u64()
 }
 
 /// Proof data for serialization
@@ -212,26 +845,99 @@ pub struct BalanceWithdrawalProof {
     pub public_inputs: PublicInputs,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct PublicInputs {
     pub merkle_root: u64,
     pub nullifier: u64,
-    pub recipient: u64,
+    pub recipient: Address,
     pub amount: u64,
     pub change_commitment: u64,
+    pub relayer: Address,
+    pub fee: u64,
+    pub asset: Address,
+    pub association_root: u64,
+    pub blocklist_root: u64,
+    pub domain: u64,
+}
+
+impl PublicInputs {
+    pub fn from_circuit<F: PrimeField64>(circuit: &BalanceWithdrawalCircuit<F>) -> Self {
+        Self {
+            merkle_root: field_to_u64(circuit.merkle_root),
+            nullifier: field_to_u64(circuit.nullifier),
+            recipient: circuit.recipient,
+            amount: field_to_u64(circuit.amount),
+            change_commitment: field_to_u64(circuit.change_commitment),
+            relayer: circuit.relayer,
+            fee: field_to_u64(circuit.fee),
+            asset: circuit.asset,
+            association_root: field_to_u64(circuit.association_root),
+            blocklist_root: field_to_u64(circuit.blocklist_root),
+            domain: field_to_u64(circuit.domain),
+        }
+    }
+
+    /// Public values in verifier order, each address as its limbs
+    pub fn to_array(&self) -> [u64; NUM_PUBLIC_VALUES] {
+        let limbs = |address: &Address| address_limbs(address).map(u64::from);
+        let mut values = Vec::with_capacity(NUM_PUBLIC_VALUES);
+        values.extend([self.merkle_root, self.nullifier]);
+        values.extend(limbs(&self.recipient));
+        values.extend([self.amount, self.change_commitment]);
+        values.extend(limbs(&self.relayer));
+        values.push(self.fee);
+        values.extend(limbs(&self.asset));
+        values.extend([self.association_root, self.blocklist_root, self.domain]);
+        values.try_into().expect("NUM_PUBLIC_VALUES values")
+    }
+
+    /// Public values as field elements, in verifier order
+    pub fn to_field_elements<F: PrimeCharacteristicRing>(&self) -> Vec<F> {
+        self.to_array().iter().map(|&v| F::from_u64(v)).collect()
+    }
+
+    /// Circuit for these public inputs
+    pub fn to_circuit<F: PrimeField64>(&self) -> BalanceWithdrawalCircuit<F> {
+        BalanceWithdrawalCircuit::new(
+            F::from_u64(self.merkle_root),
+            F::from_u64(self.nullifier),
+            self.recipient,
+            F::from_u64(self.amount),
+            F::from_u64(self.change_commitment),
+            F::from_u64(self.domain),
+        )
+        .with_relayer(self.relayer, F::from_u64(self.fee))
+        .with_asset(self.asset)
+        .with_association_root(F::from_u64(self.association_root))
+        .with_blocklist_root(F::from_u64(self.blocklist_root))
+    }
+
+    /// Public inputs as `IBalanceVerifier` takes them, addresses at full
+    /// width; the vault passes its DOMAIN, which the verifier reduces mod p
+    pub fn to_evm_inputs(&self) -> [U256; NUM_PUBLIC_INPUTS] {
+        [
+            U256::from_u64(self.merkle_root),
+            U256::from_u64(self.nullifier),
+            address_to_u256(&self.recipient),
+            U256::from_u64(self.amount),
+            U256::from_u64(self.change_commitment),
+            address_to_u256(&self.relayer),
+            U256::from_u64(self.fee),
+            address_to_u256(&self.asset),
+            U256::from_u64(self.association_root),
+            U256::from_u64(self.blocklist_root),
+            U256::from_u64(self.domain),
+        ]
+    }
 }
 
 impl BalanceWithdrawalProof {
-    /// Format for Solidity verifier
-    pub fn to_solidity_calldata(&self) -> (Vec<u8>, [u64; 5]) {
-        let inputs = [
-            self.public_inputs.merkle_root,
-            self.public_inputs.nullifier,
-            self.public_inputs.recipient,
-            self.public_inputs.amount,
-            self.public_inputs.change_commitment,
-        ];
-        (self.proof_bytes.clone(), inputs)
+    /// Format for the generated `IBalanceVerifier`: EVM-encoded proof and
+    /// all eleven public inputs, relayer, fee, asset, association root,
+    /// blocklist root and domain last
+    pub fn to_solidity_calldata(&self) -> Result<(Vec<u8>, [U256; NUM_PUBLIC_INPUTS]), ProverError> {
+        let proof = evm_proof_bytes(&self.proof_bytes)?;
+        Ok((proof, self.public_inputs.to_evm_inputs()))
     }
 }
 
@@ -239,6 +945,11 @@ impl BalanceWithdrawalProof {
 mod tests {
     use super::*;
 
+    const RECIPIENT: Address = [0xab; 20];
+    const RELAYER: Address = [0xbe; 20];
+    const ASSET: Address = [0xa5; 20];
+    const DOMAIN: Val = Val::new(8453);
+
     #[test]
     fn test_full_withdrawal() {
         // Setup
@@ -249,13 +960,13 @@ mod tests {
 
         // Compute derived values
         let spending_key_hash = poseidon_hash(spending_key);
-        let note_commitment = poseidon_hash_3(spending_key_hash, balance, randomness);
-        let nullifier = poseidon_hash_2(spending_key, Val::new(note_index as u32));
+        let commitment = note_commitment(DOMAIN, spending_key_hash, asset_id(&[0; 20]), balance, randomness);
+        let nullifier = note_nullifier(DOMAIN, nullifier_key(spending_key), note_index);
 
         // Simple merkle path (all zeros for testing)
         let merkle_path = [Val::new(0); TREE_DEPTH];
-        let path_indices = [false; TREE_DEPTH];
-        let merkle_root = compute_merkle_root_with_path(note_commitment, &merkle_path, &path_indices);
+        let path_indices = path_indices(note_index);
+        let merkle_root = compute_merkle_root_with_path(commitment, &merkle_path, &path_indices);
 
         // Full withdrawal - no change
         let amount = Val::new(10000);
@@ -264,19 +975,24 @@ mod tests {
         let circuit = BalanceWithdrawalCircuit::new(
             merkle_root,
             nullifier,
-            Val::new(0xABCD), // recipient
+            RECIPIENT,
             amount,
             change_commitment,
+            DOMAIN,
         );
 
         let witness = BalanceWithdrawalWitness {
             spending_key,
+            domain: DOMAIN,
+            asset: [0; 20],
             balance,
             randomness,
             note_index,
             merkle_path,
             path_indices,
             new_randomness: Val::new(0),
+            association_path: None,
+            blocklist_proof: None,
         };
 
         // Should not panic
@@ -293,36 +1009,41 @@ mod tests {
 
         // Compute derived values
         let spending_key_hash = poseidon_hash(spending_key);
-        let note_commitment = poseidon_hash_3(spending_key_hash, balance, randomness);
-        let nullifier = poseidon_hash_2(spending_key, Val::new(note_index as u32));
+        let commitment = note_commitment(DOMAIN, spending_key_hash, asset_id(&[0; 20]), balance, randomness);
+        let nullifier = note_nullifier(DOMAIN, nullifier_key(spending_key), note_index);
 
         // Simple merkle path
         let merkle_path = [Val::new(0); TREE_DEPTH];
-        let path_indices = [false; TREE_DEPTH];
-        let merkle_root = compute_merkle_root_with_path(note_commitment, &merkle_path, &path_indices);
+        let path_indices = path_indices(note_index);
+        let merkle_root = compute_merkle_root_with_path(commitment, &merkle_path, &path_indices);
 
         // Partial withdrawal - 6000 out of 10000
         let amount = Val::new(6000);
         let new_randomness = Val::new(88888);
         let change_balance = Val::new(4000);
-        let change_commitment = poseidon_hash_3(spending_key_hash, change_balance, new_randomness);
+        let change_commitment = note_commitment(DOMAIN, spending_key_hash, asset_id(&[0; 20]), change_balance, new_randomness);
 
         let circuit = BalanceWithdrawalCircuit::new(
             merkle_root,
             nullifier,
-            Val::new(0xABCD),
+            RECIPIENT,
             amount,
             change_commitment,
+            DOMAIN,
         );
 
         let witness = BalanceWithdrawalWitness {
             spending_key,
+            domain: DOMAIN,
+            asset: [0; 20],
             balance,
             randomness,
             note_index,
             merkle_path,
             path_indices,
             new_randomness,
+            association_path: None,
+            blocklist_proof: None,
         };
 
         // Should not panic
@@ -338,12 +1059,12 @@ mod tests {
         let note_index = 5u64;
 
         let spending_key_hash = poseidon_hash(spending_key);
-        let note_commitment = poseidon_hash_3(spending_key_hash, balance, randomness);
-        let nullifier = poseidon_hash_2(spending_key, Val::new(note_index as u32));
+        let commitment = note_commitment(DOMAIN, spending_key_hash, asset_id(&[0; 20]), balance, randomness);
+        let nullifier = note_nullifier(DOMAIN, nullifier_key(spending_key), note_index);
 
         let merkle_path = [Val::new(0); TREE_DEPTH];
-        let path_indices = [false; TREE_DEPTH];
-        let merkle_root = compute_merkle_root_with_path(note_commitment, &merkle_path, &path_indices);
+        let path_indices = path_indices(note_index);
+        let merkle_root = compute_merkle_root_with_path(commitment, &merkle_path, &path_indices);
 
         // Try to withdraw more than balance
         let amount = Val::new(15000); // More than 10000!
@@ -351,22 +1072,169 @@ mod tests {
         let circuit = BalanceWithdrawalCircuit::new(
             merkle_root,
             nullifier,
-            Val::new(0xABCD),
+            RECIPIENT,
             amount,
             Val::new(0),
+            DOMAIN,
         );
 
         let witness = BalanceWithdrawalWitness {
             spending_key,
+            domain: DOMAIN,
+            asset: [0; 20],
             balance,
             randomness,
             note_index,
             merkle_path,
             path_indices,
             new_randomness: Val::new(0),
+            association_path: None,
+            blocklist_proof: None,
         };
 
         // This should panic with "Insufficient balance"
         let _trace = circuit.generate_trace(&witness);
     }
+
+    #[test]
+    fn test_relayer_fee_bound_to_amount() {
+        let witness = BalanceWithdrawalWitness {
+            spending_key: Val::new(12345),
+            domain: DOMAIN,
+            asset: [0; 20],
+            balance: Val::new(10000),
+            randomness: Val::new(99999),
+            note_index: 5,
+            merkle_path: [Val::new(0); TREE_DEPTH],
+            path_indices: path_indices(5),
+            new_randomness: Val::new(88888),
+            association_path: None,
+            blocklist_proof: None,
+        };
+        let circuit = BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000))
+            .unwrap()
+            .with_relayer(RELAYER, Val::new(6000));
+        assert!(circuit.check_witness(&witness).is_ok());
+        let (relayer, fee) = (public_value_index(5, &ADDRESS_INPUTS), public_value_index(6, &ADDRESS_INPUTS));
+        assert_eq!(circuit.public_values()[relayer..fee], address_values::<Val>(&RELAYER));
+        assert_eq!(circuit.public_values()[fee], Val::new(6000));
+
+        let circuit = circuit.with_relayer(RELAYER, Val::new(6001));
+        assert_eq!(circuit.check_witness(&witness), Err(WitnessError::FeeExceedsAmount));
+    }
+
+    #[test]
+    fn test_asset_bound_to_commitment() {
+        let witness = BalanceWithdrawalWitness {
+            spending_key: Val::new(12345),
+            domain: DOMAIN,
+            asset: ASSET,
+            balance: Val::new(10000),
+            randomness: Val::new(99999),
+            note_index: 5,
+            merkle_path: [Val::new(0); TREE_DEPTH],
+            path_indices: path_indices(5),
+            new_randomness: Val::new(88888),
+            association_path: None,
+            blocklist_proof: None,
+        };
+        let circuit = BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+        let (asset, association_root) = (public_value_index(7, &ADDRESS_INPUTS), public_value_index(8, &ADDRESS_INPUTS));
+        assert_eq!(circuit.public_values()[asset..association_root], address_values::<Val>(&ASSET));
+        assert_eq!(
+            circuit.change_commitment,
+            note_commitment(DOMAIN, poseidon_hash(Val::new(12345)), asset_id(&ASSET), Val::new(4000), Val::new(88888))
+        );
+        assert!(circuit.check_witness(&witness).is_ok());
+
+        // The same note cannot be withdrawn as another asset, or in another
+        // deployment
+        let mut other_domain = BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+        other_domain.domain = Val::new(84532);
+        assert_eq!(other_domain.check_witness(&witness), Err(WitnessError::InvalidMerkleProof));
+        let circuit = circuit.with_asset([0; 20]);
+        assert_eq!(circuit.check_witness(&witness), Err(WitnessError::InvalidMerkleProof));
+    }
+
+    #[test]
+    fn test_association_set_membership() {
+        use crate::association::AssociationSet;
+
+        let mut witness = BalanceWithdrawalWitness {
+            spending_key: Val::new(12345),
+            domain: DOMAIN,
+            asset: [0; 20],
+            balance: Val::new(10000),
+            randomness: Val::new(99999),
+            note_index: 5,
+            merkle_path: [Val::new(0); TREE_DEPTH],
+            path_indices: path_indices(5),
+            new_randomness: Val::new(88888),
+            association_path: None,
+            blocklist_proof: None,
+        };
+        let commitment = note_commitment(DOMAIN, poseidon_hash(Val::new(12345)), asset_id(&[0; 20]), Val::new(10000), Val::new(99999));
+
+        // Without an association set the root is zero
+        let circuit = BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+        assert_eq!(circuit.public_values()[public_value_index(8, &ADDRESS_INPUTS)], Val::new(0));
+
+        let approved = AssociationSet::new(&[Val::new(1), commitment, Val::new(3)]).unwrap();
+        witness.association_path = approved.path(commitment);
+        let circuit = BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+        assert_eq!(circuit.association_root, approved.root());
+        assert!(circuit.check_witness(&witness).is_ok());
+        assert_eq!(circuit.generate_trace(&witness).width(), circuit.width());
+
+        // A set the note is not in has no path for it
+        let other = AssociationSet::new(&[Val::new(1), Val::new(3)]).unwrap();
+        let circuit = circuit.with_association_root(other.root());
+        assert_eq!(circuit.check_witness(&witness), Err(WitnessError::NotInAssociationSet));
+
+        witness.association_path = None;
+        let circuit = circuit.with_association_root(approved.root());
+        assert_eq!(circuit.check_witness(&witness), Err(WitnessError::NotInAssociationSet));
+    }
+
+    #[test]
+    fn test_blocklist_non_membership() {
+        use crate::blocklist::FieldBlocklist;
+
+        let mut witness = BalanceWithdrawalWitness {
+            spending_key: Val::new(12345),
+            domain: DOMAIN,
+            asset: [0; 20],
+            balance: Val::new(10000),
+            randomness: Val::new(99999),
+            note_index: 5,
+            merkle_path: [Val::new(0); TREE_DEPTH],
+            path_indices: path_indices(5),
+            new_randomness: Val::new(88888),
+            association_path: None,
+            blocklist_proof: None,
+        };
+        let commitment = note_commitment(DOMAIN, poseidon_hash(Val::new(12345)), asset_id(&[0; 20]), Val::new(10000), Val::new(99999));
+
+        // Without a blocklist the root is zero
+        let circuit = BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+        assert_eq!(circuit.public_values()[public_value_index(9, &ADDRESS_INPUTS)], Val::new(0));
+
+        let blocklist = FieldBlocklist::from_commitments(&[Val::new(1), Val::new(3)]);
+        witness.blocklist_proof = blocklist.non_membership_proof(commitment);
+        let circuit = BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+        assert_eq!(circuit.blocklist_root, blocklist.root());
+        assert!(circuit.check_witness(&witness).is_ok());
+        assert_eq!(circuit.generate_trace(&witness).width(), circuit.width());
+
+        // Once the note is blocked its old path no longer leads to the root
+        let mut blocked = blocklist.clone();
+        blocked.insert(commitment);
+        assert!(blocked.non_membership_proof(commitment).is_none());
+        let circuit = circuit.with_blocklist_root(blocked.root());
+        assert_eq!(circuit.check_witness(&witness), Err(WitnessError::Blocklisted));
+
+        witness.blocklist_proof = None;
+        let circuit = circuit.with_blocklist_root(blocklist.root());
+        assert_eq!(circuit.check_witness(&witness), Err(WitnessError::Blocklisted));
+    }
 }
@@ -0,0 +1,341 @@
+//! Batches of BalanceVaultV4 withdrawals
+//!
+//! A relayer collects 2 to 64 Groth16 withdrawal proofs of
+//! `r1cs::WithdrawalCircuit` against the same Merkle root and settles them in
+//! one `withdrawBatch` call. Each entry is a proof in the encoding the vault
+//! forwards to its `IBalanceVerifier`, with the ten public inputs
+//! `withdrawBatch` takes for it; the vault appends its DOMAIN as the
+//! eleventh. The batch exposes what the vault needs to apply every
+//! withdrawal: the root and the per-withdrawal nullifiers, recipients,
+//! amounts, change commitments, relayers, fees, assets and association
+//! roots.
+//!
+//! This is batching, not aggregation: a batch carries every inner proof and
+//! `withdrawBatch` verifies each one, so it saves the per-transaction
+//! overhead but not the per-proof verification cost.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use ark_bn254::Bn254;
+use ark_groth16::Proof;
+
+use crate::abi::encode_withdraw_batch_calldata;
+use crate::groth16::{Groth16Error, Groth16Proof, VerificationKey, U256};
+use crate::poseidon_bn254::Bn254Field;
+use crate::r1cs::{WithdrawalCircuit, NUM_PUBLIC_INPUTS};
+
+/// Fewest withdrawals in a batch
+pub const MIN_BATCH_SIZE: usize = 2;
+/// Most withdrawals in a batch
+pub const MAX_BATCH_SIZE: usize = 64;
+
+/// Public inputs per withdrawal in `withdrawBatch`: all but the DOMAIN
+const VAULT_INPUTS: usize = NUM_PUBLIC_INPUTS - 1;
+
+/// Errors from building or verifying a batch
+#[derive(Debug)]
+pub enum BatchError {
+    /// Batch size outside `MIN_BATCH_SIZE..=MAX_BATCH_SIZE`
+    BatchSize(usize),
+    /// Withdrawal `index` is against a different root than the first
+    RootMismatch { index: usize },
+    /// Withdrawal `index` repeats an earlier nullifier
+    DuplicateNullifier { index: usize },
+    /// Withdrawal `index` is of a different asset than the first, while the
+    /// vault holds one token
+    AssetMismatch { index: usize },
+    /// Withdrawal `index` does not verify
+    Proof { index: usize },
+    /// The verification key is not one of the withdrawal circuit
+    VerificationKey(Groth16Error),
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchError::BatchSize(n) => write!(
+                f,
+                "Batch of {} withdrawals, expected {} to {}",
+                n, MIN_BATCH_SIZE, MAX_BATCH_SIZE
+            ),
+            BatchError::RootMismatch { index } => {
+                write!(f, "Withdrawal {} is against a different Merkle root", index)
+            }
+            BatchError::DuplicateNullifier { index } => {
+                write!(f, "Withdrawal {} reuses a nullifier", index)
+            }
+            BatchError::AssetMismatch { index } => {
+                write!(f, "Withdrawal {} is of a different asset", index)
+            }
+            BatchError::Proof { index } => write!(f, "Withdrawal {} does not verify", index),
+            BatchError::VerificationKey(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// One withdrawal of a batch
+#[derive(Clone, Debug)]
+pub struct BatchEntry {
+    pub proof: Groth16Proof,
+    /// merkleRoot, nullifier, recipient, amount, changeCommitment, relayer,
+    /// fee, asset, associationRoot, blocklistRoot
+    pub public_inputs: [U256; VAULT_INPUTS],
+}
+
+impl BatchEntry {
+    /// Entry for `proof` of `circuit`, without the circuit's domain input
+    pub fn new(circuit: &WithdrawalCircuit, proof: &Proof<Bn254>) -> Self {
+        let inputs = circuit.evm_public_inputs();
+        Self {
+            proof: Groth16Proof::from(proof),
+            public_inputs: inputs[..VAULT_INPUTS].try_into().expect("vault inputs"),
+        }
+    }
+}
+
+/// Public outputs of a batch, one list entry per withdrawal
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchPublicOutputs {
+    pub merkle_root: U256,
+    pub nullifiers: Vec<U256>,
+    pub recipients: Vec<[u8; 20]>,
+    pub amounts: Vec<U256>,
+    pub change_commitments: Vec<U256>,
+    pub relayers: Vec<[u8; 20]>,
+    pub fees: Vec<U256>,
+    pub assets: Vec<[u8; 20]>,
+    /// Zero for withdrawals without an association set
+    pub association_roots: Vec<U256>,
+    /// Zero for withdrawals without a blocklist
+    pub blocklist_roots: Vec<U256>,
+}
+
+/// Address in the low 20 bytes of a public input
+fn address(word: U256) -> [u8; 20] {
+    word.to_be_bytes()[12..].try_into().expect("20 bytes")
+}
+
+/// Withdrawals against one root with distinct nullifiers
+pub struct WithdrawalBatch {
+    entries: Vec<BatchEntry>,
+}
+
+impl WithdrawalBatch {
+    /// Check the batch is well formed; proofs are checked by `verify`
+    pub fn new(entries: Vec<BatchEntry>) -> Result<Self, BatchError> {
+        if !(MIN_BATCH_SIZE..=MAX_BATCH_SIZE).contains(&entries.len()) {
+            return Err(BatchError::BatchSize(entries.len()));
+        }
+
+        let first = entries[0].public_inputs;
+        let mut nullifiers = HashSet::new();
+        for (index, entry) in entries.iter().enumerate() {
+            let inputs = &entry.public_inputs;
+            if inputs[0] != first[0] {
+                return Err(BatchError::RootMismatch { index });
+            }
+            if !nullifiers.insert(inputs[1]) {
+                return Err(BatchError::DuplicateNullifier { index });
+            }
+            if inputs[7] != first[7] {
+                return Err(BatchError::AssetMismatch { index });
+            }
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &[BatchEntry] {
+        &self.entries
+    }
+
+    /// Verify every withdrawal as the vault's verifier does, with the
+    /// vault's `domain` appended to its inputs
+    pub fn verify(&self, vk: &VerificationKey, domain: Bn254Field) -> Result<(), BatchError> {
+        for (index, entry) in self.entries.iter().enumerate() {
+            let mut inputs = entry.public_inputs.to_vec();
+            inputs.push(U256::from(domain));
+            if !vk.verify(&entry.proof, &inputs).map_err(BatchError::VerificationKey)? {
+                return Err(BatchError::Proof { index });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn public_outputs(&self) -> BatchPublicOutputs {
+        let column = |i: usize| self.entries.iter().map(|e| e.public_inputs[i]).collect();
+        let addresses = |i: usize| self.entries.iter().map(|e| address(e.public_inputs[i])).collect();
+        BatchPublicOutputs {
+            merkle_root: self.entries[0].public_inputs[0],
+            nullifiers: column(1),
+            recipients: addresses(2),
+            amounts: column(3),
+            change_commitments: column(4),
+            relayers: addresses(5),
+            fees: column(6),
+            assets: addresses(7),
+            association_roots: column(8),
+            blocklist_roots: column(9),
+        }
+    }
+
+    /// Public inputs of each withdrawal as `withdrawBatch` takes them
+    pub fn solidity_public_inputs(&self) -> Vec<[U256; VAULT_INPUTS]> {
+        self.entries.iter().map(|e| e.public_inputs).collect()
+    }
+
+    /// Calldata for `BalanceVaultV4.withdrawBatch`
+    pub fn to_solidity_calldata(&self) -> Vec<u8> {
+        let proofs: Vec<Vec<u8>> = self.entries.iter().map(|e| e.proof.to_solidity_calldata()).collect();
+        encode_withdraw_batch_calldata(&proofs, &self.solidity_public_inputs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::{address_to_u256, function_selector, WITHDRAW_BATCH_SIGNATURE};
+    use crate::groth16::PROOF_CALLDATA_LEN;
+    use crate::keys::SpendingKey;
+    use crate::poseidon_bn254::{address_to_field, compute_domain, compute_domain_commitment, filled_tree_proof};
+    use crate::r1cs::{prove, setup, WithdrawalWitness};
+    use ark_groth16::ProvingKey;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const LEVELS: usize = 4;
+    const RECIPIENT: [u8; 20] = [0x11; 20];
+    const RELAYER: [u8; 20] = [0x22; 20];
+    const ASSET: [u8; 20] = [0x33; 20];
+    const VAULT: [u8; 20] = [0x44; 20];
+
+    fn domain() -> Bn254Field {
+        compute_domain(31337, &VAULT)
+    }
+
+    /// Same note at indices 5 and 6, so both entries share one root
+    fn entry(pk: &ProvingKey<Bn254>, note_index: usize, amount: u64) -> BatchEntry {
+        let spending_key = SpendingKey::from_seed(Bn254Field::new(0x5eed));
+        let asset = address_to_field(&ASSET);
+        let (balance, randomness) = (Bn254Field::new(10000), Bn254Field::new(99999));
+        let commitment =
+            compute_domain_commitment(domain(), spending_key.spending_key_hash(), asset, balance, randomness);
+        let mut leaves = vec![Bn254Field::ZERO; 7];
+        leaves[5] = commitment;
+        leaves[6] = commitment;
+        let (_, path_elements, path_indices) = filled_tree_proof(&leaves, note_index, LEVELS).unwrap();
+
+        let witness = WithdrawalWitness {
+            domain: domain(),
+            spending_key,
+            asset,
+            balance,
+            randomness,
+            new_randomness: Bn254Field::new(88888),
+            note_index: Bn254Field::new(note_index as u64),
+            path_elements,
+            path_indices,
+        };
+        let circuit = WithdrawalCircuit::new(witness, address_to_field(&RECIPIENT), Bn254Field::new(amount))
+            .with_relayer(address_to_field(&RELAYER), Bn254Field::new(25));
+        let proof = prove(pk, circuit.clone(), &mut StdRng::seed_from_u64(note_index as u64)).unwrap();
+        BatchEntry::new(&circuit, &proof)
+    }
+
+    fn dummy(merkle_root: u64, nullifier: u64) -> BatchEntry {
+        let mut public_inputs = [U256::from_u64(0); VAULT_INPUTS];
+        public_inputs[0] = U256::from_u64(merkle_root);
+        public_inputs[1] = U256::from_u64(nullifier);
+        public_inputs[2] = address_to_u256(&RECIPIENT);
+        public_inputs[3] = U256::from_u64(1);
+        BatchEntry {
+            proof: Groth16Proof::from_solidity_calldata(&[0; PROOF_CALLDATA_LEN]).unwrap(),
+            public_inputs,
+        }
+    }
+
+    #[test]
+    fn test_batch_size_bounds() {
+        for n in [0, 1, MAX_BATCH_SIZE + 1] {
+            let entries = (0..n as u64).map(|i| dummy(7, i)).collect();
+            assert!(matches!(WithdrawalBatch::new(entries), Err(BatchError::BatchSize(m)) if m == n));
+        }
+        let entries = (0..MAX_BATCH_SIZE as u64).map(|i| dummy(7, i)).collect();
+        assert_eq!(WithdrawalBatch::new(entries).unwrap().len(), MAX_BATCH_SIZE);
+    }
+
+    #[test]
+    fn test_batch_rejects_inconsistent_entries() {
+        let batch = WithdrawalBatch::new(vec![dummy(7, 1), dummy(8, 2)]);
+        assert!(matches!(batch, Err(BatchError::RootMismatch { index: 1 })));
+
+        let batch = WithdrawalBatch::new(vec![dummy(7, 1), dummy(7, 2), dummy(7, 1)]);
+        assert!(matches!(batch, Err(BatchError::DuplicateNullifier { index: 2 })));
+
+        let mut wrong = dummy(7, 2);
+        wrong.public_inputs[7] = address_to_u256(&ASSET);
+        let batch = WithdrawalBatch::new(vec![dummy(7, 1), wrong]);
+        assert!(matches!(batch, Err(BatchError::AssetMismatch { index: 1 })));
+    }
+
+    #[test]
+    fn test_verify_and_encode_batch() {
+        let (pk, vk) = setup(LEVELS, &mut StdRng::seed_from_u64(11)).unwrap();
+        let vk = VerificationKey::from(&vk);
+        let batch = WithdrawalBatch::new(vec![entry(&pk, 5, 6000), entry(&pk, 6, 10000)]).unwrap();
+        batch.verify(&vk, domain()).unwrap();
+
+        // Proofs made for this vault do not verify in another
+        assert!(matches!(
+            batch.verify(&vk, compute_domain(31337, &[0x45; 20])),
+            Err(BatchError::Proof { index: 0 })
+        ));
+
+        let outputs = batch.public_outputs();
+        assert_eq!(outputs.nullifiers.len(), 2);
+        assert_ne!(outputs.nullifiers[0], outputs.nullifiers[1]);
+        assert_eq!(outputs.amounts, vec![U256::from_u64(6000), U256::from_u64(10000)]);
+        assert_eq!(outputs.recipients, vec![RECIPIENT; 2]);
+        assert_eq!(outputs.change_commitments[1], U256::from_u64(0));
+        assert_eq!(outputs.relayers, vec![RELAYER; 2]);
+        assert_eq!(outputs.fees, vec![U256::from_u64(25); 2]);
+        assert_eq!(outputs.assets, vec![ASSET; 2]);
+        assert_eq!(outputs.association_roots, vec![U256::from_u64(0); 2]);
+        assert_eq!(outputs.blocklist_roots, vec![U256::from_u64(0); 2]);
+
+        let calldata = batch.to_solidity_calldata();
+        assert_eq!(calldata[..4], function_selector(WITHDRAW_BATCH_SIGNATURE));
+        // Last row ends [.., recipient, amount, change commitment, relayer, fee,
+        // asset, association root, blocklist root]
+        let word = |i: usize| &calldata[calldata.len() - 32 * i..][..32];
+        assert_eq!(word(8), address_to_u256(&RECIPIENT).to_be_bytes());
+        assert_eq!(word(7), U256::from_u64(10000).to_be_bytes());
+        assert_eq!(word(6), [0u8; 32]);
+        assert_eq!(word(5), address_to_u256(&RELAYER).to_be_bytes());
+        assert_eq!(word(4), U256::from_u64(25).to_be_bytes());
+        assert_eq!(word(3), address_to_u256(&ASSET).to_be_bytes());
+        assert_eq!(word(2), [0u8; 32]);
+        assert_eq!(word(1), [0u8; 32]);
+
+        // Each proof is encoded as withdraw takes it
+        let proof = batch.entries()[1].proof.to_solidity_calldata();
+        assert!(calldata.windows(PROOF_CALLDATA_LEN).any(|w| w == proof.as_slice()));
+
+        // A failing withdrawal is reported by its index
+        let mut entries = batch.entries().to_vec();
+        entries[1].public_inputs[3] = U256::from_u64(9000);
+        let batch = WithdrawalBatch::new(entries).unwrap();
+        assert!(matches!(batch.verify(&vk, domain()), Err(BatchError::Proof { index: 1 })));
+    }
+}
@@ -0,0 +1,119 @@
+//! noctis-association-root: compute an association set root to publish
+//!
+//! Usage:
+//!   noctis-association-root --set <set.json> [--field bn254|babybear] [--out <file.json>]
+//!
+//! The set file lists the approved deposit commitments, as
+//! `{"commitments": [...]}` or a bare array, in the order the provider
+//! assigns them; repeats are dropped.
+//!
+//! By default the commitments are BalanceVaultV4 notes over BN254, and the
+//! root is that of `association::VaultAssociationSet`. Writes a JSON object
+//! with the root, the number of commitments in the set, and the ABI-encoded
+//! calldata for `BalanceVaultV4.publishAssociationRoot`, to be sent by the
+//! vault owner. Withdrawers prove against the published root by passing the
+//! same file to `noctis-prove --proving-key ... --association-set`.
+//!
+//! With `--field babybear`, the commitments are notes of the BabyBear
+//! circuit, for `noctis-prove --association-set` without `--proving-key`.
+//! The root is a public input of the STARK verifier, whose proofs the vault
+//! cannot check, so there is no calldata.
+//!
+//! Exit codes:
+//!   0  success
+//!   1  usage or I/O error
+//!   2  malformed set file, or an empty set
+//!   3  commitment does not fit in the field
+
+use std::collections::HashMap;
+use std::fs;
+use std::process;
+
+use p3_field::PrimeField32;
+
+use noctis_circuits::abi::encode_publish_association_root_calldata;
+use noctis_circuits::association::{AssociationSet, VaultAssociationSet};
+use noctis_circuits::groth16::U256;
+use noctis_circuits::note::{AssociationSetFile, NoteError};
+
+const EXIT_USAGE: i32 = 1;
+const EXIT_MALFORMED_INPUT: i32 = 2;
+const EXIT_OUT_OF_RANGE: i32 = 3;
+
+const USAGE: &str = "Usage: noctis-association-root --set <set.json> [--field bn254|babybear] [--out <file>]";
+
+fn fail(code: i32, msg: &str) -> ! {
+    eprintln!("error: {}", msg);
+    process::exit(code);
+}
+
+fn fail_commitments(e: NoteError) -> ! {
+    let code = match e {
+        NoteError::OutOfRange(_) | NoteError::NotInScalarField(_) => EXIT_OUT_OF_RANGE,
+        _ => EXIT_MALFORMED_INPUT,
+    };
+    fail(code, &e.to_string())
+}
+
+fn parse_args() -> HashMap<String, String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        process::exit(0);
+    }
+    if !args.len().is_multiple_of(2) {
+        fail(EXIT_USAGE, USAGE);
+    }
+
+    let mut opts = HashMap::new();
+    for pair in args.chunks(2) {
+        match pair[0].as_str() {
+            "--set" | "--field" | "--out" => {
+                opts.insert(pair[0].trim_start_matches("--").to_string(), pair[1].clone());
+            }
+            other => fail(EXIT_USAGE, &format!("unknown option {}\n{}", other, USAGE)),
+        }
+    }
+    opts
+}
+
+fn main() {
+    let opts = parse_args();
+    let path = opts
+        .get("set")
+        .unwrap_or_else(|| fail(EXIT_USAGE, &format!("missing --set\n{}", USAGE)));
+    let json = fs::read_to_string(path).unwrap_or_else(|e| fail(EXIT_USAGE, &format!("cannot read {}: {}", path, e)));
+
+    let file = AssociationSetFile::from_json(&json).unwrap_or_else(|e| fail(EXIT_MALFORMED_INPUT, &e.to_string()));
+
+    let output = match opts.get("field").map_or("bn254", String::as_str) {
+        "bn254" => {
+            let commitments = file.vault_commitments().unwrap_or_else(|e| fail_commitments(e));
+            let set =
+                VaultAssociationSet::new(&commitments).unwrap_or_else(|e| fail(EXIT_MALFORMED_INPUT, &e.to_string()));
+            let root = U256::from(set.root());
+            let calldata = encode_publish_association_root_calldata(root);
+            serde_json::json!({
+                "root": root.to_dec_string(),
+                "size": set.len(),
+                "calldata": format!("0x{}", hex::encode(&calldata)),
+            })
+        }
+        "babybear" => {
+            let commitments = file.commitments().unwrap_or_else(|e| fail_commitments(e));
+            let set = AssociationSet::new(&commitments).unwrap_or_else(|e| fail(EXIT_MALFORMED_INPUT, &e.to_string()));
+            serde_json::json!({
+                "root": set.root().as_canonical_u32().to_string(),
+                "size": set.len(),
+            })
+        }
+        other => fail(EXIT_USAGE, &format!("unknown field {}\n{}", other, USAGE)),
+    };
+    let output = serde_json::to_string_pretty(&output).expect("output serialization");
+
+    match opts.get("out") {
+        Some(path) => fs::write(path, output)
+            .unwrap_or_else(|e| fail(EXIT_USAGE, &format!("cannot write {}: {}", path, e))),
+        None => println!("{}", output),
+    }
+}
@@ -0,0 +1,159 @@
+//! generate-verifier: emit a Solidity verifier contract
+//!
+//! Usage:
+//!   generate-verifier --circuit withdrawal|balance [--name <ContractName>] [--out <file.sol>]
+//!   generate-verifier --vk <verification_key.json> [--name <ContractName>] [--out <file.sol>]
+//!   generate-verifier --setup <proving_key.bin> [--circuit withdrawal|deposit] [--name <ContractName>] [--out <file.sol>]
+//!
+//! With `--circuit`, emits a STARK verifier for a Plonky3 circuit.
+//! `withdrawal` produces an `IVerifier` implementation (four public inputs),
+//! deployed with the DOMAIN of the vault it serves; `balance` produces an
+//! `IBalanceVerifier`, which also binds the change commitment, relayer, fee,
+//! asset, association root, blocklist root and the DOMAIN the vault passes.
+//! Both reduce DOMAIN to the BabyBear domain the notes are derived in.
+//! BalanceVaultV4 takes Groth16 proofs over its BN254 tree instead.
+//! The verifier is pinned to the default STARK configuration used by
+//! noctis-prove, and reads the proof encoding noctis-prove outputs.
+//!
+//! With `--vk`, emits a Groth16 verifier for the circom circuit from a
+//! snarkjs verification key. The number of public inputs is taken from the
+//! key; with four the contract implements `IVerifier`, with eleven
+//! `IBalanceVerifier`. For example, to
+//! regenerate the deployed verifier after a circuit change:
+//!
+//!   generate-verifier --vk circuits/circom/build/verification_key.json \
+//!       --name WithdrawalVerifierV2 --out src/core/WithdrawalVerifierV2.sol
+//!
+//! With `--setup`, generates Groth16 keys for the Rust withdrawal circuit
+//! (`r1cs::WithdrawalCircuit`, over BalanceVaultV4's tree), writes the
+//! proving key noctis-prove needs to the given file and emits the matching
+//! `IBalanceVerifier`. The keys come from this run's randomness alone, and
+//! whoever ran it can forge proofs against the verifier: deployments should
+//! take their keys from a multi-party ceremony instead.
+//!
+//! `--setup` with `--circuit deposit` does the same for the deposit circuit
+//! (`r1cs::deposit::DepositCircuit`): the proving key is the one
+//! noctis-prove-deposit needs, and the contract an `IDepositVerifier`, which
+//! BalanceVaultV4 takes as its third constructor argument.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::process;
+
+use noctis_circuits::codegen::groth16::Groth16VerifierSpec;
+use noctis_circuits::codegen::stark::StarkVerifierSpec;
+use noctis_circuits::groth16::VerificationKey;
+use noctis_circuits::prover::FriParams;
+use noctis_circuits::r1cs;
+use noctis_circuits::merkle::TREE_DEPTH;
+use rand::rngs::OsRng;
+
+const USAGE: &str = "Usage: generate-verifier (--circuit withdrawal|balance | --vk <verification_key.json> \
+| --setup <proving_key.bin> [--circuit withdrawal|deposit]) \
+[--name <ContractName>] [--out <file.sol>]";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("error: {}", msg);
+    process::exit(1);
+}
+
+fn parse_args() -> HashMap<String, String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        process::exit(0);
+    }
+    if !args.len().is_multiple_of(2) {
+        fail(USAGE);
+    }
+
+    let mut opts = HashMap::new();
+    for pair in args.chunks(2) {
+        match pair[0].as_str() {
+            "--circuit" | "--vk" | "--setup" | "--name" | "--out" => {
+                opts.insert(pair[0].trim_start_matches("--").to_string(), pair[1].clone());
+            }
+            other => fail(&format!("unknown option {}\n{}", other, USAGE)),
+        }
+    }
+    opts
+}
+
+fn stark_verifier(circuit: &str, name: Option<&String>) -> String {
+    let fri = FriParams::default();
+    let mut spec = match circuit {
+        "withdrawal" => StarkVerifierSpec::withdrawal(fri),
+        "balance" => StarkVerifierSpec::balance_withdrawal(fri),
+        other => fail(&format!("unknown circuit '{}'\n{}", other, USAGE)),
+    };
+    if let Some(name) = name {
+        spec.contract_name = name.clone();
+    }
+
+    let layout = spec.layout();
+    eprintln!(
+        "{}: trace width {}, {} quotient chunk(s), proof {} bytes",
+        spec.contract_name,
+        spec.trace_width,
+        layout.quotient_chunks,
+        32 * layout.proof_words()
+    );
+    spec.to_solidity()
+}
+
+fn groth16_verifier(vk_path: &str, name: Option<&String>) -> String {
+    let json = fs::read_to_string(vk_path).unwrap_or_else(|e| fail(&format!("cannot read {}: {}", vk_path, e)));
+    let vk = VerificationKey::from_snarkjs_json(&json).unwrap_or_else(|e| fail(&e.to_string()));
+
+    let name = name.map_or("Groth16Verifier", |n| n.as_str());
+    let spec = Groth16VerifierSpec::new(name, vk.num_public_inputs());
+    eprintln!("{}: {} public inputs", spec.contract_name, spec.num_public_inputs());
+    spec.to_solidity(&vk)
+}
+
+fn setup_verifier(pk_path: &str, circuit: &str, name: Option<&String>) -> String {
+    let (pk, vk, spec) = match circuit {
+        "withdrawal" => {
+            eprintln!("generating keys for a depth-{} tree; this takes a while", TREE_DEPTH);
+            let (pk, vk) =
+                r1cs::setup(TREE_DEPTH, &mut OsRng).unwrap_or_else(|e| fail(&format!("setup failed: {}", e)));
+            let name = name.map_or("BalanceWithdrawalVerifier", |n| n.as_str());
+            (pk, vk, Groth16VerifierSpec::new(name, r1cs::NUM_PUBLIC_INPUTS))
+        }
+        "deposit" => {
+            let (pk, vk) =
+                r1cs::deposit::setup(&mut OsRng).unwrap_or_else(|e| fail(&format!("setup failed: {}", e)));
+            (pk, vk, Groth16VerifierSpec::deposit(name.map_or("DepositVerifier", |n| n.as_str())))
+        }
+        other => fail(&format!("unknown circuit '{}'\n{}", other, USAGE)),
+    };
+
+    let file = File::create(pk_path).unwrap_or_else(|e| fail(&format!("cannot write {}: {}", pk_path, e)));
+    r1cs::write_proving_key(&pk, BufWriter::new(file))
+        .unwrap_or_else(|e| fail(&format!("cannot write {}: {}", pk_path, e)));
+
+    let vk = VerificationKey::from(&vk);
+    eprintln!("{}: {} public inputs, proving key in {}", spec.contract_name, spec.num_public_inputs(), pk_path);
+    spec.to_solidity(&vk)
+}
+
+fn main() {
+    let opts = parse_args();
+
+    let source = match (opts.get("circuit"), opts.get("vk"), opts.get("setup")) {
+        (Some(circuit), None, None) => stark_verifier(circuit, opts.get("name")),
+        (None, Some(vk), None) => groth16_verifier(vk, opts.get("name")),
+        (circuit, None, Some(pk)) => {
+            setup_verifier(pk, circuit.map_or("withdrawal", |c| c.as_str()), opts.get("name"))
+        }
+        _ => fail(USAGE),
+    };
+
+    match opts.get("out") {
+        Some(path) => {
+            fs::write(path, source).unwrap_or_else(|e| fail(&format!("cannot write {}: {}", path, e)))
+        }
+        None => print!("{}", source),
+    }
+}
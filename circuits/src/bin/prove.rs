@@ -0,0 +1,451 @@
+//! noctis-prove: generate a BalanceVaultV4 withdrawal proof from a note
+//!
+//! Usage:
+//!   noctis-prove [--proving-key proving_key.bin] --chain-id <id> --vault 0x...
+//!                --note note.json (--merkle-path path.json | --tree tree.json)
+//!                --recipient 0x... --amount <amount> [--relayer 0x... --fee <fee>]
+//!                [--association-set set.json] [--blocklist blocked.json]
+//!                [--new-randomness <value>] [--out proof.json]
+//!
+//! Writes a JSON object with the proof, its `publicInputs` and the new
+//! randomness of the change note. `--chain-id` and `--vault` give the
+//! vault's DOMAIN, which the note's commitment and nullifier are derived in.
+//!
+//! Without `--proving-key`, the note is proven with the Plonky3 circuit over
+//! BabyBear, in the domain `poseidon::domain_tag` derives from the same
+//! DOMAIN. The proof is in the word encoding of the STARK verifier
+//! `generate-verifier --circuit balance` emits, and `publicInputs` are its
+//! eleven `IBalanceVerifier` inputs, DOMAIN last. BalanceVaultV4 cannot
+//! verify it: its tree is over BN254 and its verifier is Groth16, so there
+//! is no calldata.
+//!
+//! With `--proving-key`, the note is a BalanceVaultV4 note over BN254 and is
+//! proven with the Groth16 circuit (`r1cs::WithdrawalCircuit`), using a key
+//! from `generate-verifier --setup`. `publicInputs` are the ten the vault
+//! takes, and the output also has the ABI-encoded calldata for
+//! `BalanceVaultV4.withdraw`.
+//! The tree snapshot lists the vault's note commitments in insertion order,
+//! as its NoteCreated events report them. Amounts and the note's balance
+//! must fit in 64 bits.
+//!
+//! With `--association-set`, the proof also shows the note is one of the
+//! approved commitments in the file (the format noctis-association-root
+//! reads), against the root that tool computes for the same file: the one it
+//! publishes to the vault, or with `--field babybear` the root for the STARK
+//! verifier.
+//!
+//! With `--blocklist`, a file of blocked commitments in the same format, the
+//! proof also shows the note is not one of them, against the root of
+//! `blocklist::FieldBlocklist` over the file.
+//!
+//! Exit codes:
+//!   0  success
+//!   1  usage or I/O error
+//!   2  malformed note or Merkle path file
+//!   3  value out of range: not in the BabyBear field, or with --proving-key
+//!      not in the BN254 scalar field or an amount above 64 bits
+//!   4  note commitment does not match its secrets
+//!   5  note is not in the tree snapshot
+//!   6  note is not in the association set
+//!   7  unsupported note: a BN254 vault note without --proving-key
+//!   8  note is on the blocklist
+//!   10 Merkle path does not lead to the stated root
+//!   11 nullifier mismatch
+//!   12 amount exceeds note balance
+//!   13 change commitment mismatch
+//!   14 non-zero change commitment on a full withdrawal
+//!   15-19 transfer and merge witness errors (duplicate nullifier, output
+//!         commitment mismatch, amount out of range, value not conserved,
+//!         wrong number of input notes)
+//!   20 generated proof failed self-verification
+//!   21 relayer fee exceeds the withdrawal amount
+//!   22 association path does not lead to the association root
+//!   23 blocklist path does not lead to the blocklist root
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::process;
+
+use ark_bn254::Fr;
+use ark_ff::UniformRand;
+use p3_baby_bear::BabyBear;
+use p3_field::PrimeField32;
+use rand::rngs::OsRng;
+use rand::Rng;
+
+use noctis_circuits::abi::{address_to_u256, encode_withdraw_calldata, parse_address};
+use noctis_circuits::association::{AssociationSet, VaultAssociationSet};
+use noctis_circuits::balance_withdrawal::{BalanceWithdrawalCircuit, BalanceWithdrawalWitness, WitnessError};
+use noctis_circuits::blocklist::{Blocklist, FieldBlocklist};
+use noctis_circuits::groth16::{Groth16Proof, VerificationKey, U256};
+use noctis_circuits::note::{
+    parse_bn254, parse_field, AssociationSetFile, MerklePathFile, Note, NoteError, TreeSnapshot,
+};
+use noctis_circuits::poseidon::reduce_domain;
+use noctis_circuits::poseidon_bn254::{address_to_field, compute_domain, Bn254Field};
+use noctis_circuits::prover::{
+    default_config, evm_proof_bytes, prove_balance_withdrawal, verify_balance_withdrawal, ProverError,
+};
+use noctis_circuits::r1cs::{self, WithdrawalCircuit, WithdrawalWitness, AMOUNT_BITS};
+
+const EXIT_USAGE: i32 = 1;
+const EXIT_MALFORMED_INPUT: i32 = 2;
+const EXIT_OUT_OF_RANGE: i32 = 3;
+const EXIT_COMMITMENT_MISMATCH: i32 = 4;
+const EXIT_NOT_IN_TREE: i32 = 5;
+const EXIT_NOT_IN_ASSOCIATION_SET: i32 = 6;
+const EXIT_UNSUPPORTED_NOTE: i32 = 7;
+const EXIT_BLOCKLISTED: i32 = 8;
+const EXIT_PROOF_FAILED: i32 = 20;
+
+const USAGE: &str = "Usage: noctis-prove [--proving-key <proving_key.bin>] --chain-id <id> --vault <0x address> \
+--note <note.json> (--merkle-path <path.json> | --tree <tree.json>) \
+--recipient <0x address> --amount <amount> [--relayer <0x address>] [--fee <fee>] \
+[--association-set <set.json>] [--blocklist <blocked.json>] [--new-randomness <value>] [--out <file>]";
+
+type Opts = HashMap<String, String>;
+
+fn fail(code: i32, msg: &str) -> ! {
+    eprintln!("error: {}", msg);
+    process::exit(code);
+}
+
+fn note_error_code(e: &NoteError) -> i32 {
+    match e {
+        NoteError::InvalidJson(_)
+        | NoteError::InvalidNumber(_)
+        | NoteError::InvalidAsset
+        | NoteError::InvalidPathLength(_) => EXIT_MALFORMED_INPUT,
+        NoteError::OutOfRange(_) | NoteError::NotInScalarField(_) => EXIT_OUT_OF_RANGE,
+        NoteError::UnsupportedNote(_) => EXIT_UNSUPPORTED_NOTE,
+        NoteError::CommitmentMismatch => EXIT_COMMITMENT_MISMATCH,
+        NoteError::NoteNotInTree(_) => EXIT_NOT_IN_TREE,
+    }
+}
+
+fn witness_error_code(e: &WitnessError) -> i32 {
+    match e {
+        WitnessError::InvalidMerkleProof => 10,
+        WitnessError::InvalidNullifier => 11,
+        WitnessError::InsufficientBalance => 12,
+        WitnessError::InvalidChangeCommitment => 13,
+        WitnessError::NonZeroChangeCommitment => 14,
+        WitnessError::DuplicateNullifier => 15,
+        WitnessError::InvalidOutputCommitment => 16,
+        WitnessError::AmountOutOfRange => 17,
+        WitnessError::ValueNotConserved => 18,
+        WitnessError::InvalidNoteCount => 19,
+        WitnessError::FeeExceedsAmount => 21,
+        WitnessError::NotInAssociationSet => 22,
+        WitnessError::Blocklisted => 23,
+    }
+}
+
+fn parse_args() -> Opts {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        process::exit(0);
+    }
+    if !args.len().is_multiple_of(2) {
+        fail(EXIT_USAGE, USAGE);
+    }
+
+    let mut opts = HashMap::new();
+    for pair in args.chunks(2) {
+        match pair[0].as_str() {
+            "--note" | "--merkle-path" | "--tree" | "--recipient" | "--amount" | "--relayer" | "--fee"
+            | "--association-set" | "--blocklist" | "--new-randomness" | "--out" | "--proving-key"
+            | "--chain-id" | "--vault" => {
+                opts.insert(pair[0].trim_start_matches("--").to_string(), pair[1].clone());
+            }
+            other => fail(EXIT_USAGE, &format!("unknown option {}\n{}", other, USAGE)),
+        }
+    }
+    opts
+}
+
+fn required(opts: &Opts, key: &str) -> String {
+    opts.get(key)
+        .cloned()
+        .unwrap_or_else(|| fail(EXIT_USAGE, &format!("missing --{}\n{}", key, USAGE)))
+}
+
+fn read_file(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| fail(EXIT_USAGE, &format!("cannot read {}: {}", path, e)))
+}
+
+fn to_u256(v: BabyBear) -> U256 {
+    U256::from_u64(v.as_canonical_u32() as u64)
+}
+
+/// Parse an amount for the Groth16 circuit, which range-checks it to
+/// AMOUNT_BITS
+fn parse_amount(name: &'static str, value: &str) -> Bn254Field {
+    let v = parse_bn254(name, value).unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string()));
+    if !fits_amount(v) {
+        fail(EXIT_OUT_OF_RANGE, &format!("{} exceeds {} bits", name, AMOUNT_BITS));
+    }
+    v
+}
+
+/// Whether `v` is below 2^AMOUNT_BITS, i.e. fits in the low limb
+fn fits_amount(v: Bn254Field) -> bool {
+    const _: () = assert!(AMOUNT_BITS == 64);
+    U256::from(v).limbs()[1..] == [0; 3]
+}
+
+fn main() {
+    let opts = parse_args();
+
+    let output = match opts.get("proving-key") {
+        Some(pk) => prove_vault_note(&opts, pk),
+        None => prove_babybear_note(&opts),
+    };
+    let output = serde_json::to_string_pretty(&output).expect("output serialization");
+
+    match opts.get("out") {
+        Some(path) => fs::write(path, output)
+            .unwrap_or_else(|e| fail(EXIT_USAGE, &format!("cannot write {}: {}", path, e))),
+        None => println!("{}", output),
+    }
+}
+
+/// The vault's DOMAIN, from `--chain-id` and `--vault`
+fn vault_domain(opts: &Opts) -> Bn254Field {
+    let chain_id = required(opts, "chain-id")
+        .parse::<u64>()
+        .unwrap_or_else(|_| fail(EXIT_USAGE, "chain-id must be a decimal integer"));
+    let vault = parse_address(&required(opts, "vault"))
+        .unwrap_or_else(|| fail(EXIT_USAGE, "vault must be a 0x-prefixed 20-byte address"));
+    compute_domain(chain_id, &vault)
+}
+
+/// STARK proof of a note over BabyBear
+fn prove_babybear_note(opts: &Opts) -> serde_json::Value {
+    let vault_domain = U256::from(vault_domain(opts));
+    let domain = reduce_domain::<BabyBear>(&vault_domain);
+
+    // Note secrets, and the full asset address for the calldata
+    let (note, asset) = Note::from_json(&read_file(&required(opts, "note")))
+        .and_then(|n| Ok((n.secrets(domain)?, n.asset_address()?)))
+        .unwrap_or_else(|e| match e {
+            NoteError::UnsupportedNote(_) => fail(
+                EXIT_UNSUPPORTED_NOTE,
+                &format!("{}\nprove it with --proving-key, --chain-id and --vault", e),
+            ),
+            e => fail(note_error_code(&e), &e.to_string()),
+        });
+
+    // Merkle path, either given directly or rebuilt from all leaves
+    let merkle = match (opts.get("merkle-path"), opts.get("tree")) {
+        (Some(path), None) => MerklePathFile::from_json(&read_file(path)).and_then(|p| p.to_witness()),
+        (None, Some(tree)) => TreeSnapshot::from_json(&read_file(tree)).and_then(|t| t.to_witness(&note)),
+        _ => fail(EXIT_USAGE, &format!("pass exactly one of --merkle-path or --tree\n{}", USAGE)),
+    }
+    .unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string()));
+
+    let recipient = parse_address(&required(opts, "recipient"))
+        .unwrap_or_else(|| fail(EXIT_USAGE, "recipient must be a 0x-prefixed 20-byte address"));
+    let amount = parse_field("amount", &required(opts, "amount"))
+        .unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string()));
+    // Without a relayer the withdrawer submits the transaction and pays no fee
+    let relayer = match opts.get("relayer") {
+        Some(v) => parse_address(v).unwrap_or_else(|| fail(EXIT_USAGE, "relayer must be a 0x-prefixed 20-byte address")),
+        None => [0u8; 20],
+    };
+    let fee = match opts.get("fee") {
+        Some(v) => parse_field("fee", v).unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string())),
+        None => BabyBear::new(0),
+    };
+    // Without an association set the root is zero and no membership is shown
+    let association_path = opts.get("association-set").map(|file| {
+        let commitments = AssociationSetFile::from_json(&read_file(file))
+            .and_then(|f| f.commitments())
+            .unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string()));
+        let set = AssociationSet::new(&commitments).unwrap_or_else(|e| fail(EXIT_MALFORMED_INPUT, &e.to_string()));
+        set.path(note.commitment)
+            .unwrap_or_else(|| fail(EXIT_NOT_IN_ASSOCIATION_SET, "note is not in the association set"))
+    });
+    // Without a blocklist the root is zero and no non-membership is shown
+    let blocklist_proof = opts.get("blocklist").map(|file| {
+        let commitments = AssociationSetFile::from_json(&read_file(file))
+            .and_then(|f| f.commitments())
+            .unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string()));
+        FieldBlocklist::from_commitments(&commitments)
+            .non_membership_proof(note.commitment)
+            .unwrap_or_else(|| fail(EXIT_BLOCKLISTED, "note is on the blocklist"))
+    });
+    let new_randomness = match opts.get("new-randomness") {
+        Some(v) => parse_field("new-randomness", v).unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string())),
+        None => BabyBear::new(rand::thread_rng().gen_range(1..BabyBear::ORDER_U32)),
+    };
+
+    let witness = BalanceWithdrawalWitness {
+        domain,
+        spending_key: note.spending_key,
+        asset: note.asset,
+        balance: note.balance,
+        randomness: note.randomness,
+        note_index: note.note_index,
+        merkle_path: merkle.path,
+        path_indices: merkle.indices,
+        new_randomness,
+        association_path,
+        blocklist_proof,
+    };
+
+    let mut circuit = BalanceWithdrawalCircuit::from_witness(&witness, recipient, amount)
+        .unwrap_or_else(|e| fail(witness_error_code(&e), &e.to_string()))
+        .with_relayer(relayer, fee);
+    if let Some(root) = merkle.root {
+        circuit.merkle_root = root;
+    }
+
+    let config = default_config();
+    let proof = match prove_balance_withdrawal(&config, &circuit, &witness) {
+        Ok(proof) => proof,
+        Err(ProverError::Witness(e)) => fail(witness_error_code(&e), &e.to_string()),
+        Err(e) => fail(EXIT_PROOF_FAILED, &e.to_string()),
+    };
+    if let Err(e) = verify_balance_withdrawal(&config, &proof) {
+        fail(EXIT_PROOF_FAILED, &e.to_string());
+    }
+
+    // Addresses and DOMAIN at full width, as the STARK verifier splits the
+    // addresses into limbs and reduces DOMAIN
+    let public_inputs = [
+        to_u256(circuit.merkle_root),
+        to_u256(circuit.nullifier),
+        address_to_u256(&recipient),
+        to_u256(circuit.amount),
+        to_u256(circuit.change_commitment),
+        address_to_u256(&relayer),
+        to_u256(circuit.fee),
+        address_to_u256(&asset),
+        to_u256(circuit.association_root),
+        to_u256(circuit.blocklist_root),
+        vault_domain,
+    ];
+    let evm_proof = evm_proof_bytes(&proof.proof_bytes).unwrap_or_else(|e| fail(EXIT_PROOF_FAILED, &e.to_string()));
+
+    serde_json::json!({
+        "verifier": "BalanceWithdrawalStarkVerifier",
+        "proof": format!("0x{}", hex::encode(&evm_proof)),
+        "publicInputs": public_inputs.iter().map(|v| v.to_dec_string()).collect::<Vec<_>>(),
+        "newRandomness": new_randomness.as_canonical_u32().to_string(),
+    })
+}
+
+/// Groth16 proof of a BalanceVaultV4 note over the vault's BN254 tree
+fn prove_vault_note(opts: &Opts, pk_path: &str) -> serde_json::Value {
+    let domain = vault_domain(opts);
+
+    let note = Note::from_json(&read_file(&required(opts, "note")))
+        .and_then(|n| n.vault_secrets(domain))
+        .unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string()));
+    if !fits_amount(note.balance) {
+        fail(EXIT_OUT_OF_RANGE, &format!("note balance exceeds {} bits", AMOUNT_BITS));
+    }
+
+    let merkle = match (opts.get("merkle-path"), opts.get("tree")) {
+        (Some(path), None) => MerklePathFile::from_json(&read_file(path)).and_then(|p| p.to_vault_witness()),
+        (None, Some(tree)) => TreeSnapshot::from_json(&read_file(tree)).and_then(|t| t.to_vault_witness(&note)),
+        _ => fail(EXIT_USAGE, &format!("pass exactly one of --merkle-path or --tree\n{}", USAGE)),
+    }
+    .unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string()));
+
+    let recipient = parse_address(&required(opts, "recipient"))
+        .unwrap_or_else(|| fail(EXIT_USAGE, "recipient must be a 0x-prefixed 20-byte address"));
+    let amount = parse_amount("amount", &required(opts, "amount"));
+    // Without a relayer the withdrawer submits the transaction and pays no fee
+    let relayer = match opts.get("relayer") {
+        Some(v) => parse_address(v).unwrap_or_else(|| fail(EXIT_USAGE, "relayer must be a 0x-prefixed 20-byte address")),
+        None => [0u8; 20],
+    };
+    let fee = opts.get("fee").map_or(Bn254Field::ZERO, |v| parse_amount("fee", v));
+    if U256::from(amount) > U256::from(note.balance) {
+        fail(witness_error_code(&WitnessError::InsufficientBalance), "amount exceeds note balance");
+    }
+    if U256::from(fee) > U256::from(amount) {
+        fail(witness_error_code(&WitnessError::FeeExceedsAmount), "fee exceeds the withdrawal amount");
+    }
+    // Without an association set the root is zero and no membership is shown
+    let association = opts.get("association-set").map(|file| {
+        let commitments = AssociationSetFile::from_json(&read_file(file))
+            .and_then(|f| f.vault_commitments())
+            .unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string()));
+        let set =
+            VaultAssociationSet::new(&commitments).unwrap_or_else(|e| fail(EXIT_MALFORMED_INPUT, &e.to_string()));
+        let (path, indices) = set
+            .path(note.commitment)
+            .unwrap_or_else(|| fail(EXIT_NOT_IN_ASSOCIATION_SET, "note is not in the association set"));
+        (set.root(), path, indices)
+    });
+    // Without a blocklist the root is zero and no non-membership is shown
+    let blocklist = opts.get("blocklist").map(|file| {
+        let commitments = AssociationSetFile::from_json(&read_file(file))
+            .and_then(|f| f.vault_commitments())
+            .unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string()));
+        let blocklist = Blocklist::from_commitments(&commitments);
+        let proof = blocklist
+            .non_membership_proof(note.commitment)
+            .unwrap_or_else(|| fail(EXIT_BLOCKLISTED, "note is on the blocklist"));
+        (blocklist.root(), proof)
+    });
+    let new_randomness = match opts.get("new-randomness") {
+        Some(v) => parse_bn254("new-randomness", v).unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string())),
+        None => Bn254Field::from(Fr::rand(&mut OsRng)),
+    };
+
+    let witness = WithdrawalWitness {
+        domain,
+        spending_key: note.spending_key,
+        asset: note.asset,
+        balance: note.balance,
+        randomness: note.randomness,
+        new_randomness,
+        note_index: Bn254Field::new(note.note_index),
+        path_elements: merkle.path,
+        path_indices: merkle.indices,
+    };
+    if merkle.root.is_some_and(|root| root != witness.merkle_root()) {
+        fail(witness_error_code(&WitnessError::InvalidMerkleProof), "Merkle path does not lead to the stated root");
+    }
+
+    let mut circuit = WithdrawalCircuit::new(witness, address_to_field(&recipient), amount)
+        .with_relayer(address_to_field(&relayer), fee);
+    if let Some((root, path, indices)) = association {
+        circuit = circuit.with_association(root, path, indices);
+    }
+    if let Some((root, proof)) = blocklist {
+        circuit = circuit.with_blocklist(root, proof);
+    }
+    let inputs = circuit.evm_public_inputs();
+
+    let pk = File::open(pk_path)
+        .map_err(|e| e.to_string())
+        .and_then(|f| r1cs::read_proving_key(BufReader::new(f)).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| fail(EXIT_USAGE, &format!("cannot read proving key {}: {}", pk_path, e)));
+    let proof = r1cs::prove(&pk, circuit, &mut OsRng).unwrap_or_else(|e| fail(EXIT_PROOF_FAILED, &e.to_string()));
+
+    // Check the bytes the vault forwards as its verifier reads them, with the
+    // DOMAIN it appends
+    let evm_proof = Groth16Proof::from(&proof).to_solidity_calldata();
+    let verified = Groth16Proof::from_solidity_calldata(&evm_proof)
+        .and_then(|p| VerificationKey::from(&pk.vk).verify(&p, &inputs));
+    if !matches!(verified, Ok(true)) {
+        fail(EXIT_PROOF_FAILED, "generated proof failed verification");
+    }
+
+    let public_inputs: [U256; 10] = inputs[..10].try_into().expect("ten vault inputs");
+    let calldata = encode_withdraw_calldata(&evm_proof, &public_inputs);
+
+    serde_json::json!({
+        "proof": format!("0x{}", hex::encode(&evm_proof)),
+        "publicInputs": public_inputs.iter().map(|v| v.to_dec_string()).collect::<Vec<_>>(),
+        "newRandomness": U256::from(new_randomness).to_dec_string(),
+        "calldata": format!("0x{}", hex::encode(&calldata)),
+    })
+}
@@ -0,0 +1,153 @@
+//! noctis-prove-deposit: generate a BalanceVaultV4 deposit proof for a note
+//!
+//! Usage:
+//!   noctis-prove-deposit --proving-key deposit_key.bin --chain-id <id> --vault 0x...
+//!                        --note note.json [--out proof.json]
+//!
+//! Proves with the Groth16 deposit circuit (`r1cs::deposit::DepositCircuit`)
+//! that the note's commitment holds its balance of its asset, using a key
+//! from `generate-verifier --setup --circuit deposit`. `--chain-id` and
+//! `--vault` give the vault's DOMAIN, which the commitment is derived in.
+//! The whole balance is deposited, and must fit in 64 bits so the note can
+//! be withdrawn. The note's index is not used; it may still be null.
+//!
+//! Writes a JSON object with the proof, the three `publicInputs` of
+//! `depositWithProof` (commitment, amount, asset) and the ABI-encoded
+//! calldata for it.
+//!
+//! Exit codes:
+//!   0  success
+//!   1  usage or I/O error
+//!   2  malformed note
+//!   3  value not in the BN254 scalar field, or a balance above 64 bits
+//!   4  note commitment does not match its secrets in the vault's domain
+//!   20 generated proof failed self-verification
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::process;
+
+use rand::rngs::OsRng;
+
+use noctis_circuits::abi::{encode_deposit_calldata, parse_address};
+use noctis_circuits::groth16::{Groth16Proof, VerificationKey, U256};
+use noctis_circuits::note::{Note, NoteError};
+use noctis_circuits::poseidon_bn254::compute_domain;
+use noctis_circuits::r1cs::deposit::{self, DepositCircuit};
+use noctis_circuits::r1cs::{self, AMOUNT_BITS};
+
+const EXIT_USAGE: i32 = 1;
+const EXIT_MALFORMED_INPUT: i32 = 2;
+const EXIT_OUT_OF_RANGE: i32 = 3;
+const EXIT_COMMITMENT_MISMATCH: i32 = 4;
+const EXIT_PROOF_FAILED: i32 = 20;
+
+const USAGE: &str = "Usage: noctis-prove-deposit --proving-key <deposit_key.bin> --chain-id <id> \
+--vault <0x address> --note <note.json> [--out <file>]";
+
+type Opts = HashMap<String, String>;
+
+fn fail(code: i32, msg: &str) -> ! {
+    eprintln!("error: {}", msg);
+    process::exit(code);
+}
+
+fn note_error_code(e: &NoteError) -> i32 {
+    match e {
+        NoteError::OutOfRange(_) | NoteError::NotInScalarField(_) => EXIT_OUT_OF_RANGE,
+        NoteError::CommitmentMismatch => EXIT_COMMITMENT_MISMATCH,
+        _ => EXIT_MALFORMED_INPUT,
+    }
+}
+
+fn parse_args() -> Opts {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        process::exit(0);
+    }
+    if !args.len().is_multiple_of(2) {
+        fail(EXIT_USAGE, USAGE);
+    }
+
+    let mut opts = HashMap::new();
+    for pair in args.chunks(2) {
+        match pair[0].as_str() {
+            "--proving-key" | "--chain-id" | "--vault" | "--note" | "--out" => {
+                opts.insert(pair[0].trim_start_matches("--").to_string(), pair[1].clone());
+            }
+            other => fail(EXIT_USAGE, &format!("unknown option {}\n{}", other, USAGE)),
+        }
+    }
+    opts
+}
+
+fn required(opts: &Opts, key: &str) -> String {
+    opts.get(key)
+        .cloned()
+        .unwrap_or_else(|| fail(EXIT_USAGE, &format!("missing --{}\n{}", key, USAGE)))
+}
+
+fn main() {
+    let opts = parse_args();
+
+    let chain_id = required(&opts, "chain-id")
+        .parse::<u64>()
+        .unwrap_or_else(|_| fail(EXIT_USAGE, "chain-id must be a decimal integer"));
+    let vault = parse_address(&required(&opts, "vault"))
+        .unwrap_or_else(|| fail(EXIT_USAGE, "vault must be a 0x-prefixed 20-byte address"));
+    let domain = compute_domain(chain_id, &vault);
+
+    let note_path = required(&opts, "note");
+    let json = fs::read_to_string(&note_path)
+        .unwrap_or_else(|e| fail(EXIT_USAGE, &format!("cannot read {}: {}", note_path, e)));
+    let note = Note::from_json(&json)
+        .and_then(|n| n.vault_secrets(domain))
+        .unwrap_or_else(|e| fail(note_error_code(&e), &e.to_string()));
+    const _: () = assert!(AMOUNT_BITS == 64);
+    if U256::from(note.balance).limbs()[1..] != [0; 3] {
+        fail(EXIT_OUT_OF_RANGE, &format!("note balance exceeds {} bits", AMOUNT_BITS));
+    }
+
+    let circuit = DepositCircuit::new(
+        domain,
+        note.spending_key.spending_key_hash(),
+        note.asset,
+        note.balance,
+        note.randomness,
+    );
+    let inputs = circuit.evm_public_inputs();
+
+    let pk_path = required(&opts, "proving-key");
+    let pk = File::open(&pk_path)
+        .map_err(|e| e.to_string())
+        .and_then(|f| r1cs::read_proving_key(BufReader::new(f)).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| fail(EXIT_USAGE, &format!("cannot read proving key {}: {}", pk_path, e)));
+    let proof = deposit::prove(&pk, circuit, &mut OsRng).unwrap_or_else(|e| fail(EXIT_PROOF_FAILED, &e.to_string()));
+
+    // Check the bytes the vault forwards as its verifier reads them, with the
+    // DOMAIN it appends
+    let evm_proof = Groth16Proof::from(&proof).to_solidity_calldata();
+    let verified = Groth16Proof::from_solidity_calldata(&evm_proof)
+        .and_then(|p| VerificationKey::from(&pk.vk).verify(&p, &inputs));
+    if !matches!(verified, Ok(true)) {
+        fail(EXIT_PROOF_FAILED, "generated proof failed verification");
+    }
+
+    let public_inputs: [U256; 3] = inputs[..3].try_into().expect("three vault inputs");
+    let calldata = encode_deposit_calldata(&evm_proof, &public_inputs);
+
+    let output = serde_json::json!({
+        "proof": format!("0x{}", hex::encode(&evm_proof)),
+        "publicInputs": public_inputs.iter().map(|v| v.to_dec_string()).collect::<Vec<_>>(),
+        "calldata": format!("0x{}", hex::encode(&calldata)),
+    });
+    let output = serde_json::to_string_pretty(&output).expect("output serialization");
+
+    match opts.get("out") {
+        Some(path) => fs::write(path, output)
+            .unwrap_or_else(|e| fail(EXIT_USAGE, &format!("cannot write {}: {}", path, e))),
+        None => println!("{}", output),
+    }
+}
@@ -0,0 +1,404 @@
+//! Blocklist sparse Merkle tree over BN254
+//!
+//! The complement of association sets: rather than showing a note is among
+//! approved deposits, a withdrawal shows its commitment is not among blocked
+//! ones. The blocklist is a sparse Merkle tree keyed by commitment. The path
+//! to a commitment's leaf follows its `BLOCKLIST_DEPTH` canonical bits, least
+//! significant at the leaf, so every commitment has a leaf of its own. A
+//! blocked commitment's leaf is 1 and every other leaf 0; a non-membership
+//! proof is the sibling path showing the leaf is 0.
+//!
+//! Hashes are BN254 Poseidon (`poseidon_bn254::hash_pair`), so the tree is
+//! checked in the R1CS withdrawal circuit by `r1cs::gadgets::smt_non_membership`.
+//! Only non-empty nodes are stored.
+//!
+//! `FieldBlocklist` is the same tree over a Poseidon2 field, one level per
+//! bit of its modulus, for `BalanceWithdrawalCircuit`.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+use p3_baby_bear::BabyBear;
+use p3_field::PrimeField64;
+
+use crate::poseidon;
+use crate::poseidon_bn254::{compute_merkle_root, hash_pair, Bn254Field};
+
+/// Tree depth: one level per bit of a BN254 scalar
+pub const BLOCKLIST_DEPTH: usize = Fr::MODULUS_BIT_SIZE as usize;
+
+/// Node position: level above the leaves, and index within the level
+type NodeId = (usize, [u64; 4]);
+
+/// Roots of empty subtrees: `empty[0] = 0`, `empty[i + 1] = hash(empty[i], empty[i])`
+fn empty_hashes() -> &'static [Bn254Field] {
+    static EMPTY: OnceLock<Vec<Bn254Field>> = OnceLock::new();
+    EMPTY.get_or_init(|| {
+        let mut empty = vec![Bn254Field::ZERO; BLOCKLIST_DEPTH + 1];
+        for i in 0..BLOCKLIST_DEPTH {
+            empty[i + 1] = hash_pair(empty[i], empty[i]);
+        }
+        empty
+    })
+}
+
+/// Canonical little-endian limbs of a key
+fn key_limbs(key: Bn254Field) -> [u64; 4] {
+    Fr::from(key).into_bigint().0
+}
+
+fn key_bit(limbs: &[u64; 4], i: usize) -> bool {
+    (limbs[i / 64] >> (i % 64)) & 1 == 1
+}
+
+/// Index at `level` of the node above the leaf of `limbs`, i.e. `limbs >> level`
+fn node_index(limbs: &[u64; 4], level: usize) -> [u64; 4] {
+    let (words, bits) = (level / 64, level % 64);
+    let mut out = [0u64; 4];
+    for i in 0..4usize.saturating_sub(words) {
+        out[i] = limbs[i + words] >> bits;
+        if bits > 0 && i + words + 1 < 4 {
+            out[i] |= limbs[i + words + 1] << (64 - bits);
+        }
+    }
+    out
+}
+
+/// `is_left[i]` for `compute_merkle_root`: true if the node at level `i` on
+/// the path of `key` is a left child
+fn path_is_left(key: Bn254Field) -> Vec<bool> {
+    let limbs = key_limbs(key);
+    (0..BLOCKLIST_DEPTH).map(|i| !key_bit(&limbs, i)).collect()
+}
+
+/// Proof that a commitment's leaf is empty
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NonMembershipProof {
+    /// Siblings from the leaf up, `BLOCKLIST_DEPTH` of them
+    pub siblings: Vec<Bn254Field>,
+}
+
+impl NonMembershipProof {
+    /// All-zero proof, for key generation and circuits without a blocklist
+    pub fn blank() -> Self {
+        Self { siblings: vec![Bn254Field::ZERO; BLOCKLIST_DEPTH] }
+    }
+
+    /// Root of the tree the proof is for, if `commitment` is not blocked
+    pub fn root(&self, commitment: Bn254Field) -> Bn254Field {
+        compute_merkle_root(Bn254Field::ZERO, &self.siblings, &path_is_left(commitment))
+    }
+
+    pub fn verify(&self, commitment: Bn254Field, root: Bn254Field) -> bool {
+        self.siblings.len() == BLOCKLIST_DEPTH && self.root(commitment) == root
+    }
+}
+
+/// Sparse Merkle tree of blocked commitments
+#[derive(Clone, Debug, Default)]
+pub struct Blocklist {
+    nodes: HashMap<NodeId, Bn254Field>,
+}
+
+impl Blocklist {
+    /// Empty blocklist
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_commitments(commitments: &[Bn254Field]) -> Self {
+        let mut blocklist = Self::new();
+        for &c in commitments {
+            blocklist.insert(c);
+        }
+        blocklist
+    }
+
+    /// Root to publish and to prove against
+    pub fn root(&self) -> Bn254Field {
+        self.node(BLOCKLIST_DEPTH, [0; 4])
+    }
+
+    pub fn contains(&self, commitment: Bn254Field) -> bool {
+        self.node(0, key_limbs(commitment)) != Bn254Field::ZERO
+    }
+
+    pub fn insert(&mut self, commitment: Bn254Field) {
+        self.set_leaf(commitment, Bn254Field::new(1));
+    }
+
+    pub fn remove(&mut self, commitment: Bn254Field) {
+        self.set_leaf(commitment, Bn254Field::ZERO);
+    }
+
+    /// Non-membership proof for `commitment`, or None if it is blocked
+    pub fn non_membership_proof(&self, commitment: Bn254Field) -> Option<NonMembershipProof> {
+        if self.contains(commitment) {
+            return None;
+        }
+        let limbs = key_limbs(commitment);
+        let siblings = (0..BLOCKLIST_DEPTH)
+            .map(|level| {
+                let mut sibling = node_index(&limbs, level);
+                sibling[0] ^= 1;
+                self.node(level, sibling)
+            })
+            .collect();
+        Some(NonMembershipProof { siblings })
+    }
+
+    fn node(&self, level: usize, index: [u64; 4]) -> Bn254Field {
+        self.nodes.get(&(level, index)).copied().unwrap_or(empty_hashes()[level])
+    }
+
+    fn set_node(&mut self, level: usize, index: [u64; 4], value: Bn254Field) {
+        if value == empty_hashes()[level] {
+            self.nodes.remove(&(level, index));
+        } else {
+            self.nodes.insert((level, index), value);
+        }
+    }
+
+    /// Write a leaf and rehash its path to the root
+    fn set_leaf(&mut self, commitment: Bn254Field, value: Bn254Field) {
+        let limbs = key_limbs(commitment);
+        let mut current = value;
+        self.set_node(0, limbs, current);
+
+        for level in 0..BLOCKLIST_DEPTH {
+            let mut sibling_index = node_index(&limbs, level);
+            sibling_index[0] ^= 1;
+            let sibling = self.node(level, sibling_index);
+
+            current = if key_bit(&limbs, level) {
+                hash_pair(sibling, current)
+            } else {
+                hash_pair(current, sibling)
+            };
+            self.set_node(level + 1, node_index(&limbs, level + 1), current);
+        }
+    }
+}
+
+/// Depth of a `FieldBlocklist` over `F`: one level per bit of the modulus
+pub fn field_blocklist_depth<F: PrimeField64>() -> usize {
+    (u64::BITS - F::ORDER_U64.leading_zeros()) as usize
+}
+
+/// Index at `level` of the node above the leaf of `key`, i.e. `key >> level`
+fn field_node_index(key: u64, level: usize) -> u64 {
+    key.checked_shr(level as u32).unwrap_or(0)
+}
+
+/// Proof that a commitment's leaf is empty in a `FieldBlocklist`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldNonMembershipProof<F = BabyBear> {
+    /// Siblings from the leaf up, `field_blocklist_depth::<F>()` of them
+    pub siblings: Vec<F>,
+}
+
+impl<F: PrimeField64> FieldNonMembershipProof<F> {
+    /// All-zero proof, for withdrawals without a blocklist
+    pub fn blank() -> Self {
+        Self { siblings: vec![F::ZERO; field_blocklist_depth::<F>()] }
+    }
+
+    /// Root of the tree the proof is for, if `commitment` is not blocked
+    pub fn root(&self, commitment: F) -> F {
+        let key = commitment.as_canonical_u64();
+        self.siblings.iter().enumerate().fold(F::ZERO, |current, (level, &sibling)| {
+            if field_node_index(key, level) & 1 == 1 {
+                poseidon::hash_pair(sibling, current)
+            } else {
+                poseidon::hash_pair(current, sibling)
+            }
+        })
+    }
+
+    pub fn verify(&self, commitment: F, root: F) -> bool {
+        self.siblings.len() == field_blocklist_depth::<F>() && self.root(commitment) == root
+    }
+}
+
+/// Sparse Merkle tree of blocked commitments over a Poseidon2 field
+#[derive(Clone, Debug)]
+pub struct FieldBlocklist<F = BabyBear> {
+    nodes: HashMap<(usize, u64), F>,
+    /// Roots of empty subtrees, as `empty_hashes`
+    empty: Vec<F>,
+}
+
+impl<F: PrimeField64> Default for FieldBlocklist<F> {
+    fn default() -> Self {
+        let depth = field_blocklist_depth::<F>();
+        let mut empty = vec![F::ZERO; depth + 1];
+        for i in 0..depth {
+            empty[i + 1] = poseidon::hash_pair(empty[i], empty[i]);
+        }
+        Self { nodes: HashMap::new(), empty }
+    }
+}
+
+impl<F: PrimeField64> FieldBlocklist<F> {
+    /// Empty blocklist
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_commitments(commitments: &[F]) -> Self {
+        let mut blocklist = Self::new();
+        for &c in commitments {
+            blocklist.insert(c);
+        }
+        blocklist
+    }
+
+    /// Root to publish and to prove against
+    pub fn root(&self) -> F {
+        self.node(field_blocklist_depth::<F>(), 0)
+    }
+
+    pub fn contains(&self, commitment: F) -> bool {
+        self.node(0, commitment.as_canonical_u64()) != F::ZERO
+    }
+
+    pub fn insert(&mut self, commitment: F) {
+        self.set_leaf(commitment, F::ONE);
+    }
+
+    pub fn remove(&mut self, commitment: F) {
+        self.set_leaf(commitment, F::ZERO);
+    }
+
+    /// Non-membership proof for `commitment`, or None if it is blocked
+    pub fn non_membership_proof(&self, commitment: F) -> Option<FieldNonMembershipProof<F>> {
+        if self.contains(commitment) {
+            return None;
+        }
+        let key = commitment.as_canonical_u64();
+        let siblings = (0..field_blocklist_depth::<F>())
+            .map(|level| self.node(level, field_node_index(key, level) ^ 1))
+            .collect();
+        Some(FieldNonMembershipProof { siblings })
+    }
+
+    fn node(&self, level: usize, index: u64) -> F {
+        self.nodes.get(&(level, index)).copied().unwrap_or(self.empty[level])
+    }
+
+    fn set_node(&mut self, level: usize, index: u64, value: F) {
+        if value == self.empty[level] {
+            self.nodes.remove(&(level, index));
+        } else {
+            self.nodes.insert((level, index), value);
+        }
+    }
+
+    /// Write a leaf and rehash its path to the root
+    fn set_leaf(&mut self, commitment: F, value: F) {
+        let key = commitment.as_canonical_u64();
+        let mut current = value;
+        self.set_node(0, key, current);
+
+        for level in 0..field_blocklist_depth::<F>() {
+            let index = field_node_index(key, level);
+            let sibling = self.node(level, index ^ 1);
+            current = if index & 1 == 1 {
+                poseidon::hash_pair(sibling, current)
+            } else {
+                poseidon::hash_pair(current, sibling)
+            };
+            self.set_node(level + 1, field_node_index(key, level + 1), current);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p3_field::PrimeCharacteristicRing;
+
+    #[test]
+    fn test_empty_blocklist() {
+        let blocklist = Blocklist::new();
+        assert_eq!(blocklist.root(), empty_hashes()[BLOCKLIST_DEPTH]);
+
+        let proof = blocklist.non_membership_proof(Bn254Field::new(42)).unwrap();
+        assert!(proof.verify(Bn254Field::new(42), blocklist.root()));
+    }
+
+    #[test]
+    fn test_non_membership_proofs() {
+        let blocked = [Bn254Field::new(7), Bn254Field::new(8), Bn254Field::from_hex("0x1234abcd")];
+        let blocklist = Blocklist::from_commitments(&blocked);
+        let root = blocklist.root();
+
+        for c in blocked {
+            assert!(blocklist.contains(c));
+            assert!(blocklist.non_membership_proof(c).is_none());
+        }
+
+        // 6 and 9 share all but the lowest bits with blocked keys
+        for c in [Bn254Field::new(6), Bn254Field::new(9), Bn254Field::ZERO - Bn254Field::new(1)] {
+            let proof = blocklist.non_membership_proof(c).unwrap();
+            assert!(proof.verify(c, root));
+        }
+
+        // An empty-leaf path for a blocked key does not lead to the root
+        let proof = blocklist.non_membership_proof(Bn254Field::new(6)).unwrap();
+        assert!(!proof.verify(Bn254Field::new(7), root));
+    }
+
+    #[test]
+    fn test_stale_proof_rejected() {
+        let mut blocklist = Blocklist::from_commitments(&[Bn254Field::new(7)]);
+        let proof = blocklist.non_membership_proof(Bn254Field::new(8)).unwrap();
+
+        blocklist.insert(Bn254Field::new(8));
+        assert!(!proof.verify(Bn254Field::new(8), blocklist.root()));
+    }
+
+    #[test]
+    fn test_root_independent_of_order() {
+        let a = Blocklist::from_commitments(&[Bn254Field::new(1), Bn254Field::new(2), Bn254Field::new(3)]);
+        let b = Blocklist::from_commitments(&[Bn254Field::new(3), Bn254Field::new(1), Bn254Field::new(2)]);
+        assert_eq!(a.root(), b.root());
+
+        let mut c = a.clone();
+        c.remove(Bn254Field::new(3));
+        assert_eq!(c.root(), Blocklist::from_commitments(&[Bn254Field::new(1), Bn254Field::new(2)]).root());
+        c.remove(Bn254Field::new(1));
+        c.remove(Bn254Field::new(2));
+        assert_eq!(c.root(), Blocklist::new().root());
+        assert!(c.nodes.is_empty());
+    }
+
+    #[test]
+    fn test_field_blocklist() {
+        let blocked = [BabyBear::new(7), BabyBear::new(8), BabyBear::new(0x1234abc)];
+        let blocklist = FieldBlocklist::from_commitments(&blocked);
+        let root = blocklist.root();
+        assert_eq!(FieldNonMembershipProof::<BabyBear>::blank().siblings.len(), 31);
+
+        for c in blocked {
+            assert!(blocklist.non_membership_proof(c).is_none());
+        }
+        for c in [BabyBear::new(6), BabyBear::new(9), BabyBear::ZERO - BabyBear::new(1)] {
+            let proof = blocklist.non_membership_proof(c).unwrap();
+            assert!(proof.verify(c, root));
+            assert!(!proof.verify(c, FieldBlocklist::<BabyBear>::new().root()));
+        }
+
+        let proof = blocklist.non_membership_proof(BabyBear::new(6)).unwrap();
+        assert!(!proof.verify(BabyBear::new(7), root));
+
+        let mut c = blocklist.clone();
+        for b in blocked {
+            c.remove(b);
+        }
+        assert_eq!(c.root(), FieldBlocklist::<BabyBear>::new().root());
+        assert!(c.nodes.is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+//! Solidity Groth16 verifier from a snarkjs verification key
+//!
+//! Emits the same contract as `src/core/WithdrawalVerifierV2.sol`: the
+//! snarkjs pairing check behind an `IVerifier`-style `verifyProof(bytes,
+//! uint256[N])` that ABI-decodes `(uint256[2] pA, uint256[2][2] pB,
+//! uint256[2] pC)`, i.e. `Groth16Proof::to_solidity_calldata`.
+//!
+//! With four public inputs the contract implements `IVerifier`, with eleven
+//! (`r1cs::WithdrawalCircuit`) `IBalanceVerifier`; any other count gets the same entry point with a
+//! `uint256[N]` array and no interface. `Groth16VerifierSpec::deposit` is
+//! the exception: the four inputs of `r1cs::deposit::DepositCircuit` make an
+//! `IDepositVerifier`.
+
+use super::{render_template, verifier_interface};
+use crate::groth16::{G1Point, G2Point, VerificationKey};
+
+const TEMPLATE: &str = include_str!("groth16_verifier.sol.tmpl");
+
+/// Public input names of the withdrawal circuits, in signal order
+pub const WITHDRAWAL_INPUT_NAMES: [&str; 11] = [
+    "merkleRoot",
+    "nullifier",
+    "recipient",
+    "amount",
+    "changeCommitment",
+    "relayer",
+    "fee",
+    "asset",
+    "associationRoot",
+    "blocklistRoot",
+    "domain",
+];
+
+/// Public input names of the deposit circuit, in signal order
+pub const DEPOSIT_INPUT_NAMES: [&str; 4] = ["commitment", "amount", "asset", "domain"];
+
+/// Contract name and input documentation for a Groth16 verifier
+#[derive(Clone, Debug)]
+pub struct Groth16VerifierSpec {
+    pub contract_name: String,
+    /// One name per public input, used in the NatSpec comment
+    pub input_names: Vec<String>,
+    /// Solidity interface the contract implements, if any
+    pub interface: Option<&'static str>,
+}
+
+impl Groth16VerifierSpec {
+    /// Spec for `num_public_inputs` inputs named after the withdrawal signals
+    pub fn new(contract_name: &str, num_public_inputs: usize) -> Self {
+        let input_names = (0..num_public_inputs)
+            .map(|i| match WITHDRAWAL_INPUT_NAMES.get(i) {
+                Some(name) => name.to_string(),
+                None => format!("input{}", i),
+            })
+            .collect();
+        Self {
+            contract_name: contract_name.to_string(),
+            input_names,
+            interface: verifier_interface(num_public_inputs),
+        }
+    }
+
+    /// Spec for the deposit circuit, an `IDepositVerifier`
+    pub fn deposit(contract_name: &str) -> Self {
+        Self {
+            contract_name: contract_name.to_string(),
+            input_names: DEPOSIT_INPUT_NAMES.iter().map(|n| n.to_string()).collect(),
+            interface: Some("IDepositVerifier"),
+        }
+    }
+
+    pub fn num_public_inputs(&self) -> usize {
+        self.input_names.len()
+    }
+
+    /// Render the verifier for `vk`
+    ///
+    /// Panics if the key was built for a different number of public inputs.
+    pub fn to_solidity(&self, vk: &VerificationKey) -> String {
+        let n = self.num_public_inputs();
+        assert_eq!(
+            vk.num_public_inputs(),
+            n,
+            "verification key has {} public inputs, spec has {}",
+            vk.num_public_inputs(),
+            n
+        );
+        let interface = self.interface;
+
+        let ic_constants = vk
+            .ic
+            .iter()
+            .enumerate()
+            .flat_map(|(i, p)| {
+                [
+                    format!("    uint256 constant IC{}x = {};", i, p.x),
+                    format!("    uint256 constant IC{}y = {};", i, p.y),
+                ]
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mul_acc = (1..=n)
+            .map(|i| {
+                format!(
+                    "                g1_mulAccC(_pVk, IC{}x, IC{}y, calldataload(add(pubSignals, {})))",
+                    i,
+                    i,
+                    32 * (i - 1)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let check_fields = (0..n)
+            .map(|i| format!("            checkField(calldataload(add(_pubSignals, {})))", 32 * i))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut values: Vec<(String, String)> = [
+            ("CONTRACT_NAME", self.contract_name.clone()),
+            (
+                "IMPORTS",
+                interface.map_or(String::new(), |i| format!("import \"./{}.sol\";\n\n", i)),
+            ),
+            ("INHERITS", interface.map_or(String::new(), |i| format!(" is {}", i))),
+            ("OVERRIDE", interface.map_or(String::new(), |_| " override".to_string())),
+            ("NUM_PUBLIC", n.to_string()),
+            ("INPUT_NAMES", self.input_names.join(", ")),
+            ("IC_CONSTANTS", ic_constants),
+            ("MUL_ACC", mul_acc),
+            ("CHECK_FIELDS", check_fields),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        values.extend(g1_values("ALPHA", &vk.alpha));
+        values.extend(g2_values("BETA", &vk.beta));
+        values.extend(g2_values("GAMMA", &vk.gamma));
+        values.extend(g2_values("DELTA", &vk.delta));
+
+        render_template(TEMPLATE, &values)
+    }
+}
+
+fn g1_values(name: &str, p: &G1Point) -> Vec<(String, String)> {
+    vec![
+        (format!("{}X", name), p.x.to_dec_string()),
+        (format!("{}Y", name), p.y.to_dec_string()),
+    ]
+}
+
+/// G2 constants in EVM order: `x1` is the imaginary part of x
+fn g2_values(name: &str, p: &G2Point) -> Vec<(String, String)> {
+    let evm = p.to_evm_order();
+    vec![
+        (format!("{}X1", name), evm[0][0].to_dec_string()),
+        (format!("{}X2", name), evm[0][1].to_dec_string()),
+        (format!("{}Y1", name), evm[1][0].to_dec_string()),
+        (format!("{}Y2", name), evm[1][1].to_dec_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VK_JSON: &str = include_str!("../../circom/build/verification_key.json");
+
+    #[test]
+    fn test_regenerates_withdrawal_verifier_v2() {
+        let vk = VerificationKey::from_snarkjs_json(VK_JSON).unwrap();
+        let source = Groth16VerifierSpec::new("WithdrawalVerifierV2", 4).to_solidity(&vk);
+        assert_eq!(source, include_str!("../../../src/core/WithdrawalVerifierV2.sol"));
+    }
+
+    #[test]
+    fn test_balance_verifier() {
+        let mut vk = VerificationKey::from_snarkjs_json(VK_JSON).unwrap();
+        for _ in 0..7 {
+            vk.ic.push(vk.ic[1]);
+        }
+
+        let source = Groth16VerifierSpec::new("BalanceWithdrawalVerifier", 11).to_solidity(&vk);
+        assert!(source.contains("import \"./IBalanceVerifier.sol\";"));
+        assert!(source.contains("contract BalanceWithdrawalVerifier is IBalanceVerifier {"));
+        assert!(source.contains("uint256[11] calldata publicInputs"));
+        assert!(source.contains(") external view override returns (bool)"));
+        assert!(source.contains(
+            "@param publicInputs [merkleRoot, nullifier, recipient, amount, changeCommitment, relayer, fee, asset, associationRoot, blocklistRoot, domain]"
+        ));
+        assert!(source.contains("g1_mulAccC(_pVk, IC11x, IC11y, calldataload(add(pubSignals, 320)))"));
+        assert!(source.contains("checkField(calldataload(add(_pubSignals, 320)))"));
+
+        // Ten inputs no longer match the vault's interface
+        vk.ic.pop();
+        let source = Groth16VerifierSpec::new("Verifier", 10).to_solidity(&vk);
+        assert!(!source.contains("IBalanceVerifier"));
+    }
+
+    #[test]
+    fn test_deposit_verifier() {
+        let vk = VerificationKey::from_snarkjs_json(VK_JSON).unwrap();
+        let source = Groth16VerifierSpec::deposit("DepositVerifier").to_solidity(&vk);
+        assert!(source.contains("import \"./IDepositVerifier.sol\";"));
+        assert!(source.contains("contract DepositVerifier is IDepositVerifier {"));
+        assert!(source.contains("uint256[4] calldata publicInputs"));
+        assert!(source.contains("@param publicInputs [commitment, amount, asset, domain]"));
+        assert!(!source.contains("is IVerifier"));
+    }
+
+    #[test]
+    #[should_panic(expected = "verification key has 4 public inputs, spec has 5")]
+    fn test_rejects_input_count_mismatch() {
+        let vk = VerificationKey::from_snarkjs_json(VK_JSON).unwrap();
+        Groth16VerifierSpec::new("Verifier", 5).to_solidity(&vk);
+    }
+}
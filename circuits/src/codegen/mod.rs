@@ -0,0 +1,52 @@
+//! Solidity code generation for on-chain verifiers
+//!
+//! Templates use `{{KEY}}` placeholders, filled by `render_template`.
+
+pub mod groth16;
+pub mod stark;
+
+/// Solidity interface a verifier with `num_public_inputs` inputs implements
+///
+/// `IVerifier` fixes four inputs; `IBalanceVerifier` adds the change
+/// commitment, relayer, fee, asset, association root, blocklist root and
+/// deployment domain for eleven.
+pub fn verifier_interface(num_public_inputs: usize) -> Option<&'static str> {
+    match num_public_inputs {
+        4 => Some("IVerifier"),
+        11 => Some("IBalanceVerifier"),
+        _ => None,
+    }
+}
+
+/// Replace every `{{KEY}}` in `template` with its value
+///
+/// Panics if a placeholder is left unfilled, so a template/generator
+/// mismatch fails loudly instead of emitting broken Solidity.
+pub(crate) fn render_template<K: AsRef<str>>(template: &str, values: &[(K, String)]) -> String {
+    let mut out = template.to_string();
+    for (key, value) in values {
+        out = out.replace(&format!("{{{{{}}}}}", key.as_ref()), value);
+    }
+    if let Some(start) = out.find("{{") {
+        let end = out[start..].find("}}").map_or(out.len(), |e| start + e + 2);
+        panic!("Unfilled template placeholder {}", &out[start..end]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let out = render_template("a {{X}} b {{Y}} {{X}}", &[("X", "1".to_string()), ("Y", "2".to_string())]);
+        assert_eq!(out, "a 1 b 2 1");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unfilled template placeholder {{Z}}")]
+    fn test_render_template_missing_key() {
+        render_template::<&str>("{{Z}}", &[]);
+    }
+}
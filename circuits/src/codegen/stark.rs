@@ -0,0 +1,980 @@
+//! Solidity verifier for the Plonky3 circuits
+//!
+//! The generated contract replays the p3-uni-stark verifier for one AIR under
+//! `NoctisStarkConfig`: it rebuilds the Fiat-Shamir transcript, evaluates the
+//! AIR constraints at the out-of-domain point zeta, checks them against the
+//! recomposed quotient, then verifies every FRI query (Merkle openings of the
+//! trace and quotient rows, the reduced opening and each folding round).
+//!
+//! Bincode proofs are not practical to parse in the EVM, so the contract takes
+//! the flat encoding from `encode_evm_proof`: one BabyBear element per 32-byte
+//! word, in a fixed order determined by the trace width, trace height and FRI
+//! parameters. `ProofLayout` describes that order.
+//!
+//! Straight-line code for every constraint would not fit under the EIP-170
+//! limit, so the contract evaluates them from data: the Poseidon2 permutation
+//! every row of a `poseidon_air` program runs, which is most of the
+//! constraints, with a loop over the rounds (`_permutationConstraints`), and
+//! the rest as a `ConstraintProgram`.
+
+use std::collections::HashMap;
+
+use p3_air::{Air, BaseAir};
+use p3_baby_bear::{
+    BABYBEAR_RC16_EXTERNAL_FINAL, BABYBEAR_RC16_EXTERNAL_INITIAL, BABYBEAR_RC16_INTERNAL,
+};
+use p3_field::{BasedVectorSpace, Field, PrimeCharacteristicRing, PrimeField32, TwoAdicField};
+use p3_uni_stark::{
+    get_symbolic_constraints, Entry, SymbolicAirBuilder, SymbolicExpression,
+};
+
+use super::{render_template, verifier_interface};
+use crate::address::{ADDRESS_LIMBS, ADDRESS_LIMB_BITS};
+use crate::balance_withdrawal::{self, BalanceWithdrawalCircuit};
+use crate::poseidon::{is_external_round, MDS_MATRIX, ROUND_CONSTANTS, TOTAL_ROUNDS, WIDTH};
+use crate::poseidon_air::{permutation_residuals, PERM_COLS};
+use crate::prover::{FriParams, NoctisStarkConfig, ProofParts, Val, MIN_TRACE_HEIGHT};
+use crate::withdrawal::{self, WithdrawalCircuit};
+
+const TEMPLATE: &str = include_str!("stark_verifier.sol.tmpl");
+
+/// State of a verifier taking its domain in the constructor
+const DOMAIN_STATE: &str = "
+    /// Deployment domain: the DOMAIN of the vault this verifier serves, mod p
+    uint256 public immutable DOMAIN;
+
+    constructor(uint256 domain) {
+        DOMAIN = domain % P;
+    }
+";
+
+/// Digest size of the Poseidon2 Merkle tree, in field elements
+const DIGEST_ELEMS: usize = 8;
+/// Extension degree of the challenge field
+const EXT_DEGREE: usize = 4;
+
+/// Word layout of an EVM-encoded proof
+///
+/// ```text
+/// [degree_bits]
+/// [trace commit: 8] [quotient commit: 8]
+/// [trace local: 4W] [trace next: 4W] [quotient chunks: 16D]
+/// [FRI commits: 8L] [final poly: 4] [pow witness]
+/// per query:
+///   [trace row: W] [trace path: 8H]
+///   [quotient row: 4D] [quotient path: 8H]
+///   per round r < L: [sibling: 4] [path: 8(H - 1 - r)]
+/// ```
+///
+/// W is the trace width, D the number of quotient chunks, H the log height
+/// of the LDE and L the number of FRI folding rounds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProofLayout {
+    pub trace_width: usize,
+    pub quotient_chunks: usize,
+    pub degree_bits: usize,
+    pub log_blowup: usize,
+    pub num_queries: usize,
+}
+
+impl ProofLayout {
+    pub fn log_max_height(&self) -> usize {
+        self.degree_bits + self.log_blowup
+    }
+
+    pub fn num_fri_rounds(&self) -> usize {
+        self.degree_bits
+    }
+
+    pub fn off_trace_commit(&self) -> usize {
+        1
+    }
+
+    pub fn off_quotient_commit(&self) -> usize {
+        self.off_trace_commit() + DIGEST_ELEMS
+    }
+
+    pub fn off_trace_local(&self) -> usize {
+        self.off_quotient_commit() + DIGEST_ELEMS
+    }
+
+    pub fn off_trace_next(&self) -> usize {
+        self.off_trace_local() + EXT_DEGREE * self.trace_width
+    }
+
+    pub fn off_quotient_opened(&self) -> usize {
+        self.off_trace_next() + EXT_DEGREE * self.trace_width
+    }
+
+    pub fn off_fri_commits(&self) -> usize {
+        self.off_quotient_opened() + EXT_DEGREE * EXT_DEGREE * self.quotient_chunks
+    }
+
+    pub fn off_final_poly(&self) -> usize {
+        self.off_fri_commits() + DIGEST_ELEMS * self.num_fri_rounds()
+    }
+
+    pub fn off_pow(&self) -> usize {
+        self.off_final_poly() + EXT_DEGREE
+    }
+
+    pub fn off_queries(&self) -> usize {
+        self.off_pow() + 1
+    }
+
+    pub fn query_words(&self) -> usize {
+        let h = self.log_max_height();
+        let input = self.trace_width + EXT_DEGREE * self.quotient_chunks + 2 * DIGEST_ELEMS * h;
+        let fri: usize = (0..self.num_fri_rounds())
+            .map(|r| EXT_DEGREE + DIGEST_ELEMS * (h - 1 - r))
+            .sum();
+        input + fri
+    }
+
+    pub fn proof_words(&self) -> usize {
+        self.off_queries() + self.num_queries * self.query_words()
+    }
+}
+
+/// Where the verifier takes the deployment domain of a circuit from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DomainSource {
+    /// The AIR has no domain
+    None,
+    /// A public input, the vault's full-width DOMAIN, reduced mod p
+    Input(usize),
+    /// The constructor, for interfaces without a domain input; the contract
+    /// appends it as the last public value
+    Constructor,
+}
+
+/// Everything the generator needs to know about one circuit
+pub struct StarkVerifierSpec {
+    pub contract_name: String,
+    pub air_name: String,
+    pub trace_width: usize,
+    pub num_public_values: usize,
+    /// Public inputs the contract takes, before addresses are split
+    pub num_inputs: usize,
+    /// Public inputs that are addresses, each `ADDRESS_LIMBS` public values
+    pub address_inputs: Vec<usize>,
+    pub domain: DomainSource,
+    pub degree_bits: usize,
+    pub log_quotient_degree: usize,
+    pub fri: FriParams,
+    constraints: Vec<SymbolicExpression<Val>>,
+    /// Leading constraints that are the row permutation, folded by a loop
+    permutation_constraints: usize,
+}
+
+impl StarkVerifierSpec {
+    /// Describe an AIR proven with a trace of height `2^degree_bits`
+    pub fn new<A>(
+        contract_name: &str,
+        air_name: &str,
+        air: &A,
+        num_public_values: usize,
+        degree_bits: usize,
+        fri: FriParams,
+    ) -> Self
+    where
+        A: BaseAir<Val> + Air<SymbolicAirBuilder<Val>>,
+    {
+        assert_eq!(
+            fri.log_final_poly_len, 0,
+            "generate-verifier only supports log_final_poly_len = 0"
+        );
+        assert!(
+            air.preprocessed_trace().is_none(),
+            "generate-verifier does not support preprocessed columns"
+        );
+
+        let constraints = get_symbolic_constraints(air, 0, num_public_values);
+        let log_quotient_degree = log_quotient_degree(&constraints);
+        let permutation_constraints = permutation_prefix(&constraints, air.width(), num_public_values);
+
+        Self {
+            contract_name: contract_name.to_string(),
+            air_name: air_name.to_string(),
+            trace_width: air.width(),
+            num_public_values,
+            num_inputs: num_public_values,
+            address_inputs: Vec::new(),
+            domain: DomainSource::None,
+            degree_bits,
+            log_quotient_degree,
+            fri,
+            constraints,
+            permutation_constraints,
+        }
+    }
+
+    /// `WithdrawalCircuit`, implementing `IVerifier`; the four inputs of
+    /// `IVerifier` leave no room for the domain, so the contract is deployed
+    /// with the vault's DOMAIN
+    pub fn withdrawal(fri: FriParams) -> Self {
+        let air = WithdrawalCircuit::new(Val::ZERO, Val::ZERO, [0; 20], Val::ZERO, Val::ZERO);
+        let mut spec = Self::new(
+            "WithdrawalStarkVerifier",
+            "WithdrawalCircuit",
+            &air,
+            air.public_values().len(),
+            trace_degree_bits(WithdrawalCircuit::<Val>::trace_height()),
+            fri,
+        );
+        spec.num_inputs = withdrawal::NUM_PUBLIC_INPUTS;
+        spec.address_inputs = withdrawal::ADDRESS_INPUTS.to_vec();
+        spec.domain = DomainSource::Constructor;
+        spec
+    }
+
+    /// `BalanceWithdrawalCircuit`, implementing `IBalanceVerifier`, whose
+    /// last input is the vault's DOMAIN
+    pub fn balance_withdrawal(fri: FriParams) -> Self {
+        let air = BalanceWithdrawalCircuit::new(Val::ZERO, Val::ZERO, [0; 20], Val::ZERO, Val::ZERO, Val::ZERO);
+        let mut spec = Self::new(
+            "BalanceWithdrawalStarkVerifier",
+            "BalanceWithdrawalCircuit",
+            &air,
+            air.public_values().len(),
+            trace_degree_bits(BalanceWithdrawalCircuit::<Val>::trace_height()),
+            fri,
+        );
+        spec.num_inputs = balance_withdrawal::NUM_PUBLIC_INPUTS;
+        spec.address_inputs = balance_withdrawal::ADDRESS_INPUTS.to_vec();
+        spec.domain = DomainSource::Input(balance_withdrawal::DOMAIN_INPUT);
+        spec
+    }
+
+    pub fn quotient_chunks(&self) -> usize {
+        1 << self.log_quotient_degree
+    }
+
+    pub fn layout(&self) -> ProofLayout {
+        ProofLayout {
+            trace_width: self.trace_width,
+            quotient_chunks: self.quotient_chunks(),
+            degree_bits: self.degree_bits,
+            log_blowup: self.fri.log_blowup,
+            num_queries: self.fri.num_queries,
+        }
+    }
+
+    /// Render the Solidity verifier contract
+    pub fn to_solidity(&self) -> String {
+        let layout = self.layout();
+        let interface = verifier_interface(self.num_inputs);
+
+        let mask = |inputs: &[usize]| inputs.iter().fold(0u64, |mask, &i| mask | (1 << i));
+        let domain_inputs = match self.domain {
+            DomainSource::Input(i) => vec![i],
+            _ => Vec::new(),
+        };
+        let constructor = self.domain == DomainSource::Constructor;
+        let program = ConstraintProgram::new(&self.constraints[self.permutation_constraints..]);
+
+        let values = [
+            ("CONTRACT_NAME", self.contract_name.clone()),
+            ("AIR_NAME", self.air_name.clone()),
+            (
+                "IMPORTS",
+                interface.map_or(String::new(), |i| format!("\nimport {{{0}}} from \"./{0}.sol\";\n", i)),
+            ),
+            ("INHERITS", interface.map_or(String::new(), |i| format!(" is {}", i))),
+            ("OVERRIDE", interface.map_or(String::new(), |_| " override".to_string())),
+            ("MUTABILITY", if constructor { "view" } else { "pure" }.to_string()),
+            ("DOMAIN_STATE", if constructor { DOMAIN_STATE.to_string() } else { String::new() }),
+            (
+                "DOMAIN_VALUE",
+                if constructor { "\n        pis[k++] = DOMAIN;".to_string() } else { String::new() },
+            ),
+            ("TRACE_WIDTH", self.trace_width.to_string()),
+            ("NUM_INPUTS", self.num_inputs.to_string()),
+            ("NUM_PUBLIC", self.num_public_values.to_string()),
+            ("ADDRESS_INPUTS", format!("{:#x}", mask(&self.address_inputs))),
+            ("DOMAIN_INPUTS", format!("{:#x}", mask(&domain_inputs))),
+            ("ADDRESS_LIMBS", ADDRESS_LIMBS.to_string()),
+            ("ADDRESS_LIMB_BITS", ADDRESS_LIMB_BITS.to_string()),
+            ("DEGREE_BITS", self.degree_bits.to_string()),
+            ("LOG_BLOWUP", self.fri.log_blowup.to_string()),
+            ("QUOTIENT_CHUNKS", layout.quotient_chunks.to_string()),
+            ("LOG_MAX_HEIGHT", layout.log_max_height().to_string()),
+            ("NUM_FRI_ROUNDS", layout.num_fri_rounds().to_string()),
+            ("NUM_QUERIES", self.fri.num_queries.to_string()),
+            ("POW_BITS", self.fri.proof_of_work_bits.to_string()),
+            ("G_TRACE", canonical(Val::two_adic_generator(self.degree_bits))),
+            ("G_TRACE_INV", canonical(Val::two_adic_generator(self.degree_bits).inverse())),
+            ("G_MAX", canonical(Val::two_adic_generator(layout.log_max_height()))),
+            ("OFF_TRACE_COMMIT", layout.off_trace_commit().to_string()),
+            ("OFF_QUOTIENT_COMMIT", layout.off_quotient_commit().to_string()),
+            ("OFF_TRACE_LOCAL", layout.off_trace_local().to_string()),
+            ("OFF_TRACE_NEXT", layout.off_trace_next().to_string()),
+            ("OFF_QUOTIENT_OPENED", layout.off_quotient_opened().to_string()),
+            ("OFF_FRI_COMMITS", layout.off_fri_commits().to_string()),
+            ("OFF_FINAL_POLY", layout.off_final_poly().to_string()),
+            ("OFF_POW", layout.off_pow().to_string()),
+            ("OFF_QUERIES", layout.off_queries().to_string()),
+            ("QUERY_WORDS", layout.query_words().to_string()),
+            ("PROOF_WORDS", layout.proof_words().to_string()),
+            ("AIR_ROUNDS", TOTAL_ROUNDS.to_string()),
+            (
+                "AIR_EXTERNAL_ROUNDS",
+                format!("{:#x}", (0..TOTAL_ROUNDS).filter(|&r| is_external_round(r)).fold(0u64, |m, r| m | (1 << r))),
+            ),
+            ("AIR_MDS", render_air_mds()),
+            (
+                "AIR_ROUND_CONSTANTS",
+                packed_words(ROUND_CONSTANTS.iter().flatten().map(|&c| Val::from_u32(c))),
+            ),
+            ("AIR_PERMUTATION", (self.permutation_constraints > 0).to_string()),
+            ("CONSTRAINT_TEMPS", program.temps.to_string()),
+            ("CONSTRAINT_CONSTANTS", packed_words(program.constants.iter().copied())),
+            ("CONSTRAINT_PROGRAM", hex::encode(&program.code)),
+            ("QUOTIENT", self.render_quotient()),
+            ("TWO_ADIC_GENERATORS", render_two_adic_generators(layout.log_max_height())),
+            ("ROUND_CONSTANTS", packed_words(poseidon2_round_constants())),
+        ];
+
+        render_template(TEMPLATE, &values)
+    }
+
+    /// Quotient recomposition: sum_i zps_i(zeta) * chunk_i(zeta)
+    ///
+    /// Chunk i lives on the coset `GENERATOR * g^i * H` of the quotient
+    /// domain, where g generates the quotient domain and H the trace domain.
+    /// zps_i is the product of the other chunks' vanishing polynomials,
+    /// normalized to 1 at the first point of chunk i.
+    fn render_quotient(&self) -> String {
+        let chunks = self.quotient_chunks();
+        let g = Val::two_adic_generator(self.degree_bits + self.log_quotient_degree);
+        let shifts: Vec<Val> = (0..chunks).map(|j| Val::GENERATOR * g.exp_u64(j as u64)).collect();
+
+        let mut lines = vec!["        uint256 zps;".to_string()];
+        for i in 0..chunks {
+            let norm = (0..chunks)
+                .filter(|&j| j != i)
+                .map(|j| (shifts[i] * shifts[j].inverse()).exp_power_of_2(self.degree_bits) - Val::ONE)
+                .product::<Val>()
+                .inverse();
+
+            lines.push(format!("        zps = {};", canonical(norm)));
+            for (j, shift) in shifts.iter().enumerate() {
+                if j != i {
+                    lines.push(format!(
+                        "        zps = _emul(zps, _esub(_epow2(_escale(zeta, {}), DEGREE_BITS), 1));",
+                        canonical(shift.inverse())
+                    ));
+                }
+            }
+            lines.push(format!(
+                "        quotient = _eadd(quotient, _emul(zps, _chunkAt(proof, OFF_QUOTIENT_OPENED + {})));",
+                EXT_DEGREE * EXT_DEGREE * i
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Log height of a trace of `height` rows once the prover pads it
+pub fn trace_degree_bits(height: usize) -> usize {
+    height.max(MIN_TRACE_HEIGHT).next_power_of_two().trailing_zeros() as usize
+}
+
+/// log2 of the number of quotient chunks for `constraints`, as p3-uni-stark
+/// computes it
+pub fn log_quotient_degree(constraints: &[SymbolicExpression<Val>]) -> usize {
+    let max_degree = constraints
+        .iter()
+        .map(|c| c.degree_multiple())
+        .max()
+        .unwrap_or(0)
+        .max(2);
+    (max_degree - 1).next_power_of_two().trailing_zeros() as usize
+}
+
+fn canonical(v: Val) -> String {
+    v.as_canonical_u32().to_string()
+}
+
+/// Number of leading constraints that are the Poseidon2 permutation of
+/// `poseidon_air` on the first `PERM_COLS` columns, or 0 if the AIR does
+/// not start with it
+///
+/// Compares the constraints with `permutation_residuals` at a point where
+/// every column differs, so a different layout or order cannot match.
+fn permutation_prefix(constraints: &[SymbolicExpression<Val>], width: usize, num_public_values: usize) -> usize {
+    let count = PERM_COLS - WIDTH;
+    if width < PERM_COLS || constraints.len() < count {
+        return 0;
+    }
+
+    let row = Row::sample(width, num_public_values);
+    let expected = permutation_residuals::<Val, Val>(&row.local[..PERM_COLS]);
+    let matches = constraints[..count]
+        .iter()
+        .zip(&expected)
+        .all(|(c, e)| row.eval(c) == *e);
+    if matches {
+        count
+    } else {
+        0
+    }
+}
+
+/// Values of one row pair, to evaluate constraints at
+struct Row {
+    local: Vec<Val>,
+    next: Vec<Val>,
+    public: Vec<Val>,
+    /// [isFirstRow, isLastRow, isTransition]
+    selectors: [Val; 3],
+}
+
+impl Row {
+    /// Distinct powers of the generator in every position
+    fn sample(width: usize, num_public_values: usize) -> Self {
+        let point = |n: usize, salt: u64| -> Vec<Val> {
+            (0..n).map(|i| Val::GENERATOR.exp_u64(3 * i as u64 + salt)).collect()
+        };
+        Self {
+            local: point(width, 1),
+            next: point(width, 2),
+            public: point(num_public_values, 3),
+            selectors: [Val::new(5), Val::new(6), Val::new(7)],
+        }
+    }
+
+    fn eval(&self, expr: &SymbolicExpression<Val>) -> Val {
+        match expr {
+            SymbolicExpression::Variable(v) => match v.entry {
+                Entry::Main { offset: 0 } => self.local[v.index],
+                Entry::Main { offset: 1 } => self.next[v.index],
+                Entry::Public => self.public[v.index],
+                _ => panic!("Unsupported constraint variable {:?}", v.entry),
+            },
+            SymbolicExpression::IsFirstRow => self.selectors[0],
+            SymbolicExpression::IsLastRow => self.selectors[1],
+            SymbolicExpression::IsTransition => self.selectors[2],
+            SymbolicExpression::Constant(c) => *c,
+            SymbolicExpression::Add { x, y, .. } => self.eval(x) + self.eval(y),
+            SymbolicExpression::Sub { x, y, .. } => self.eval(x) - self.eval(y),
+            SymbolicExpression::Neg { x, .. } => -self.eval(x),
+            SymbolicExpression::Mul { x, y, .. } => self.eval(x) * self.eval(y),
+        }
+    }
+}
+
+/// The MDS matrix of `poseidon.rs` repeats with period 4 in both indices;
+/// byte `4 * i + j` is entry `(i, j)` of the 4x4 block
+fn render_air_mds() -> String {
+    let mut packed = 0u128;
+    for (i, row) in MDS_MATRIX.iter().enumerate() {
+        for (j, &m) in row.iter().enumerate() {
+            assert_eq!(m, MDS_MATRIX[i % 4][j % 4], "MDS matrix is not 4-periodic");
+            assert!(m < 256, "MDS entry does not fit a byte");
+        }
+    }
+    for (i, row) in MDS_MATRIX.iter().take(4).enumerate() {
+        for (j, &m) in row.iter().take(4).enumerate() {
+            packed |= (m as u128) << (8 * (4 * i + j));
+        }
+    }
+    format!("{:#x}", packed)
+}
+
+/// Field elements as four big-endian bytes each, the encoding
+/// `_unpackWords` reads
+fn packed_words(values: impl IntoIterator<Item = Val>) -> String {
+    values.into_iter().map(|v| format!("{:08x}", v.as_canonical_u32())).collect()
+}
+
+/// Operand kinds of a `ConstraintProgram`, in the order `_evalConstraints`
+/// lays out its value arrays
+const OPERAND_TEMP: u16 = 0;
+const OPERAND_LOCAL: u16 = 1;
+const OPERAND_NEXT: u16 = 2;
+const OPERAND_PUBLIC: u16 = 3;
+const OPERAND_SELECTOR: u16 = 4;
+const OPERAND_CONSTANT: u16 = 5;
+/// Bits of an operand holding its index; the kind is the three above them
+const OPERAND_INDEX_BITS: u32 = 12;
+
+const OP_ADD: u8 = 0;
+const OP_SUB: u8 = 1;
+const OP_MUL: u8 = 2;
+const OP_FOLD: u8 = 3;
+
+/// Constraints lowered to the byte program `_evalConstraints` interprets
+///
+/// Straight-line Solidity costs tens of bytes of code per operation; an
+/// instruction is four big-endian bytes of data (a 2-bit opcode, then two
+/// 15-bit operands), which keeps the contract size nearly independent of
+/// the AIR. Every instruction but `OP_FOLD` writes the next temporary, and
+/// `OP_FOLD` folds its first operand into the accumulator with alpha.
+/// Operands are a kind (`OPERAND_*`) and an index; shared subexpressions
+/// are computed once.
+#[derive(Default)]
+struct ConstraintProgram {
+    code: Vec<u8>,
+    constants: Vec<Val>,
+    temps: usize,
+    cache: HashMap<*const SymbolicExpression<Val>, u16>,
+    constant_index: HashMap<u32, u16>,
+}
+
+impl ConstraintProgram {
+    fn new(constraints: &[SymbolicExpression<Val>]) -> Self {
+        let mut program = Self::default();
+        for c in constraints {
+            let operand = program.operand(c);
+            program.push(OP_FOLD, operand, 0);
+        }
+        program
+    }
+
+    fn operand(&mut self, expr: &SymbolicExpression<Val>) -> u16 {
+        let key = expr as *const _;
+        if let Some(&operand) = self.cache.get(&key) {
+            return operand;
+        }
+
+        let (op, x, y) = match expr {
+            SymbolicExpression::Variable(v) => {
+                let kind = match v.entry {
+                    Entry::Main { offset: 0 } => OPERAND_LOCAL,
+                    Entry::Main { offset: 1 } => OPERAND_NEXT,
+                    Entry::Public => OPERAND_PUBLIC,
+                    _ => panic!("Unsupported constraint variable {:?}", v.entry),
+                };
+                return encode_operand(kind, v.index);
+            }
+            SymbolicExpression::IsFirstRow => return encode_operand(OPERAND_SELECTOR, 0),
+            SymbolicExpression::IsLastRow => return encode_operand(OPERAND_SELECTOR, 1),
+            SymbolicExpression::IsTransition => return encode_operand(OPERAND_SELECTOR, 2),
+            SymbolicExpression::Constant(c) => return self.constant(*c),
+            SymbolicExpression::Add { x, y, .. } => (OP_ADD, self.operand(x), self.operand(y)),
+            SymbolicExpression::Sub { x, y, .. } => (OP_SUB, self.operand(x), self.operand(y)),
+            SymbolicExpression::Neg { x, .. } => {
+                let x = self.operand(x);
+                (OP_SUB, self.constant(Val::ZERO), x)
+            }
+            SymbolicExpression::Mul { x, y, .. } => (OP_MUL, self.operand(x), self.operand(y)),
+        };
+
+        let operand = encode_operand(OPERAND_TEMP, self.temps);
+        self.temps += 1;
+        self.push(op, x, y);
+        self.cache.insert(key, operand);
+        operand
+    }
+
+    fn constant(&mut self, c: Val) -> u16 {
+        let constants = &mut self.constants;
+        *self.constant_index.entry(c.as_canonical_u32()).or_insert_with(|| {
+            constants.push(c);
+            encode_operand(OPERAND_CONSTANT, constants.len() - 1)
+        })
+    }
+
+    fn push(&mut self, op: u8, x: u16, y: u16) {
+        let instruction = (op as u32) << 30 | (x as u32) << 15 | y as u32;
+        self.code.extend(instruction.to_be_bytes());
+    }
+}
+
+fn encode_operand(kind: u16, index: usize) -> u16 {
+    assert!(index < 1 << OPERAND_INDEX_BITS, "constraint operand index {} out of range", index);
+    (kind << OPERAND_INDEX_BITS) | index as u16
+}
+
+fn render_two_adic_generators(max_bits: usize) -> String {
+    (1..=max_bits)
+        .map(|bits| {
+            format!(
+                "        if (bits == {}) return {};",
+                bits,
+                canonical(Val::two_adic_generator(bits))
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Poseidon2 constants in the order `_permute` reads them
+pub fn poseidon2_round_constants() -> Vec<Val> {
+    let mut rc = Vec::with_capacity(157);
+    rc.extend(BABYBEAR_RC16_EXTERNAL_INITIAL.iter().flatten());
+    rc.extend(BABYBEAR_RC16_INTERNAL.iter());
+    rc.extend(BABYBEAR_RC16_EXTERNAL_FINAL.iter().flatten());
+    rc.extend(internal_diagonal());
+    rc
+}
+
+/// Diagonal of the width-16 internal matrix, minus the all-ones part:
+/// [-2, 1, 2, 1/2, 3, 4, -1/2, -3, -4, 1/2^8, 1/4, 1/8, 1/2^27, -1/2^8, -1/16, -1/2^27]
+fn internal_diagonal() -> [Val; 16] {
+    let inv_pow2 = |k: usize| Val::TWO.exp_u64(k as u64).inverse();
+    let int = |n: u32| Val::from_u32(n);
+    [
+        -int(2),
+        int(1),
+        int(2),
+        inv_pow2(1),
+        int(3),
+        int(4),
+        -inv_pow2(1),
+        -int(3),
+        -int(4),
+        inv_pow2(8),
+        inv_pow2(2),
+        inv_pow2(3),
+        inv_pow2(27),
+        -inv_pow2(8),
+        -inv_pow2(4),
+        -inv_pow2(27),
+    ]
+}
+
+/// Flatten a proof into field elements in `ProofLayout` order
+pub fn evm_proof_words(proof: &ProofParts<NoctisStarkConfig>) -> Vec<Val> {
+    fn push_ext<E: BasedVectorSpace<Val>>(words: &mut Vec<Val>, values: &[E]) {
+        for v in values {
+            words.extend_from_slice(v.as_basis_coefficients_slice());
+        }
+    }
+
+    let fri = &proof.opening_proof;
+    let mut words = vec![Val::from_usize(proof.degree_bits)];
+
+    let trace_commit: [Val; DIGEST_ELEMS] = proof.commitments.trace.into();
+    let quotient_commit: [Val; DIGEST_ELEMS] = proof.commitments.quotient_chunks.into();
+    words.extend(trace_commit);
+    words.extend(quotient_commit);
+
+    push_ext(&mut words, &proof.opened_values.trace_local);
+    push_ext(&mut words, &proof.opened_values.trace_next);
+    for chunk in &proof.opened_values.quotient_chunks {
+        push_ext(&mut words, chunk);
+    }
+
+    for commit in &fri.commit_phase_commits {
+        let digest: [Val; DIGEST_ELEMS] = (*commit).into();
+        words.extend(digest);
+    }
+    push_ext(&mut words, &fri.final_poly);
+    words.push(fri.pow_witness);
+
+    for query in &fri.query_proofs {
+        // Batch 0 is the trace, batch 1 the quotient chunks
+        for batch in &query.input_proof {
+            for row in &batch.opened_values {
+                words.extend_from_slice(row);
+            }
+            for sibling in &batch.opening_proof {
+                words.extend_from_slice(sibling);
+            }
+        }
+        for step in &query.commit_phase_openings {
+            push_ext(&mut words, &[step.sibling_value]);
+            for sibling in &step.opening_proof {
+                words.extend_from_slice(sibling);
+            }
+        }
+    }
+
+    words
+}
+
+/// Encode a proof for the generated verifier: one big-endian word per element
+pub fn encode_evm_proof(proof: &ProofParts<NoctisStarkConfig>) -> Vec<u8> {
+    let words = evm_proof_words(proof);
+    let mut out = Vec::with_capacity(32 * words.len());
+    for w in words {
+        out.extend_from_slice(&[0u8; 28]);
+        out.extend_from_slice(&w.as_canonical_u32().to_be_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::public_value_index;
+    use crate::balance_withdrawal::{path_indices, BalanceWithdrawalWitness};
+    use crate::merkle::{compute_merkle_root, TREE_DEPTH};
+    use crate::groth16::U256;
+    use crate::poseidon::{domain_tag, hash_commitment, hash_nullifier};
+    use crate::poseidon_bn254::compute_domain;
+    use crate::prover::{
+        default_config, evm_proof_bytes, prove_balance_withdrawal, prove_withdrawal, verify_withdrawal,
+        ProverConfig, StarkHash,
+    };
+    use crate::withdrawal::WithdrawalWitness;
+
+    #[test]
+    fn test_layout_offsets() {
+        let layout = ProofLayout {
+            trace_width: 3,
+            quotient_chunks: 2,
+            degree_bits: 2,
+            log_blowup: 1,
+            num_queries: 5,
+        };
+        assert_eq!(layout.off_trace_local(), 17);
+        assert_eq!(layout.off_quotient_opened(), 17 + 24);
+        assert_eq!(layout.off_fri_commits(), 17 + 24 + 32);
+        assert_eq!(layout.off_queries(), 17 + 24 + 32 + 16 + 4 + 1);
+        // rows 3 + 8, paths 2 * 8 * 3, rounds (4 + 16) + (4 + 8)
+        assert_eq!(layout.query_words(), 11 + 48 + 32);
+        assert_eq!(layout.proof_words(), layout.off_queries() + 5 * 91);
+    }
+
+    #[test]
+    fn test_round_constants() {
+        let rc = poseidon2_round_constants();
+        assert_eq!(rc.len(), 157);
+        assert_eq!(rc[141] + Val::TWO, Val::ZERO);
+        assert_eq!(rc[144] * Val::TWO, Val::ONE);
+    }
+
+    fn unpack_words(packed: &str) -> Vec<Val> {
+        hex::decode(packed)
+            .unwrap()
+            .chunks(4)
+            .map(|w| Val::new(u32::from_be_bytes(w.try_into().unwrap())))
+            .collect()
+    }
+
+    /// `_evalConstraints` of the generated contract, over the base field
+    fn eval_contract(spec: &StarkVerifierSpec, row: &Row, alpha: Val) -> Val {
+        let mut acc = Val::ZERO;
+        if spec.permutation_constraints > 0 {
+            let rcs = unpack_words(&packed_words(ROUND_CONSTANTS.iter().flatten().map(|&c| Val::from_u32(c))));
+            let mds = u128::from_str_radix(render_air_mds().trim_start_matches("0x"), 16).unwrap();
+            let mut state = row.local[..WIDTH].to_vec();
+            let mut col = WIDTH;
+            for r in 0..TOTAL_ROUNDS {
+                let lanes = if is_external_round(r) { WIDTH } else { 1 };
+                let mut sums = [Val::ZERO; 4];
+                for j in 0..WIDTH {
+                    let mut u = state[j] + rcs[WIDTH * r + j];
+                    if j < lanes {
+                        let cube = row.local[col];
+                        col += 1;
+                        acc = acc * alpha + (cube - u * u * u);
+                        u = cube * cube * u;
+                    }
+                    sums[j % 4] += u;
+                }
+                for (i, lane) in state.iter_mut().enumerate() {
+                    let mixed: Val = (0..4)
+                        .map(|j| sums[j] * Val::new(((mds >> (8 * (4 * (i % 4) + j))) & 0xff) as u32))
+                        .sum();
+                    *lane = row.local[col + i];
+                    acc = acc * alpha + (*lane - mixed);
+                }
+                col += WIDTH;
+            }
+        }
+
+        let program = ConstraintProgram::new(&spec.constraints[spec.permutation_constraints..]);
+        let constants = unpack_words(&packed_words(program.constants.iter().copied()));
+        let mut temps = Vec::with_capacity(program.temps);
+        for ins in program.code.chunks(4) {
+            let ins = u32::from_be_bytes(ins.try_into().unwrap());
+            let operand = |x: u32, temps: &[Val]| {
+                let i = (x & 0xfff) as usize;
+                match x >> 12 {
+                    0 => temps[i],
+                    1 => row.local[i],
+                    2 => row.next[i],
+                    3 => row.public[i],
+                    4 => row.selectors[i],
+                    5 => constants[i],
+                    kind => panic!("operand kind {}", kind),
+                }
+            };
+            let a = operand((ins >> 15) & 0x7fff, &temps);
+            let b = operand(ins & 0x7fff, &temps);
+            match (ins >> 30) as u8 {
+                OP_ADD => temps.push(a + b),
+                OP_SUB => temps.push(a - b),
+                OP_MUL => temps.push(a * b),
+                OP_FOLD => acc = acc * alpha + a,
+                op => panic!("opcode {}", op),
+            }
+        }
+        assert_eq!(temps.len(), program.temps);
+        acc
+    }
+
+    #[test]
+    fn test_contract_evaluates_constraints() {
+        let alpha = Val::new(987654321);
+        for spec in [
+            StarkVerifierSpec::withdrawal(FriParams::default()),
+            StarkVerifierSpec::balance_withdrawal(FriParams::default()),
+        ] {
+            // Both are row programs, so the permutation is folded by the loop
+            assert_eq!(spec.permutation_constraints, PERM_COLS - WIDTH);
+            assert_eq!(permutation_prefix(&spec.constraints[1..], spec.trace_width, spec.num_public_values), 0);
+
+            let row = Row::sample(spec.trace_width, spec.num_public_values);
+            let expected = spec.constraints.iter().fold(Val::ZERO, |acc, c| acc * alpha + row.eval(c));
+            assert_eq!(eval_contract(&spec, &row, alpha), expected, "{}", spec.air_name);
+        }
+    }
+
+    #[test]
+    fn test_withdrawal_verifier_source() {
+        let spec = StarkVerifierSpec::withdrawal(FriParams::default());
+        let source = spec.to_solidity();
+
+        assert!(source.contains("contract WithdrawalStarkVerifier is IVerifier"));
+        assert!(source.contains("uint256[4] calldata publicInputs"));
+        assert!(source.contains(") external view override returns (bool)"));
+        assert!(source.contains(&format!("PROOF_WORDS = {};", spec.layout().proof_words())));
+        assert!(source.contains("ADDRESS_INPUTS = 0x4;"));
+        // The domain comes from the constructor, after the inputs
+        assert!(source.contains("NUM_PUBLIC = 10;"));
+        assert!(source.contains("DOMAIN_INPUTS = 0x0;"));
+        assert!(source.contains("constructor(uint256 domain)"));
+        assert!(source.contains("pis[k++] = DOMAIN;"));
+        // Rounds 0..4 and 17..21 are external
+        assert!(source.contains("AIR_PERMUTATION = true;"));
+        assert!(source.contains("AIR_EXTERNAL_ROUNDS = 0x1e000f;"));
+        assert!(!source.contains("{{"));
+    }
+
+    #[test]
+    fn test_balance_verifier_source() {
+        let spec = StarkVerifierSpec::balance_withdrawal(FriParams::default());
+        let source = spec.to_solidity();
+        assert!(source.contains("contract BalanceWithdrawalStarkVerifier is IBalanceVerifier"));
+        assert!(source.contains("uint256[11] calldata publicInputs"));
+        assert!(source.contains(") external pure override returns (bool)"));
+        assert!(source.contains("ADDRESS_INPUTS = 0xa4;"));
+        assert!(source.contains("DOMAIN_INPUTS = 0x400;"));
+        assert!(!source.contains("constructor"));
+        // The root, nullifier, amount, change commitment, fee, asset,
+        // association root, blocklist root and domain enter the constraints
+        let program = ConstraintProgram::new(&spec.constraints[spec.permutation_constraints..]);
+        let public_operands: Vec<u16> = program
+            .code
+            .chunks(4)
+            .flat_map(|ins| {
+                let ins = u32::from_be_bytes(ins.try_into().unwrap());
+                [(ins >> 15) as u16 & 0x7fff, ins as u16 & 0x7fff]
+            })
+            .collect();
+        for i in [0, 1, 3, 4, 6, 7, 8, 9, 10] {
+            let index = public_value_index(i, &balance_withdrawal::ADDRESS_INPUTS);
+            let operand = encode_operand(OPERAND_PUBLIC, index);
+            assert!(public_operands.contains(&operand), "public input {}", i);
+        }
+        assert!(source.contains("DEGREE_BITS = 7;"));
+    }
+
+    #[test]
+    fn test_evm_proof_matches_layout() {
+        let witness = WithdrawalWitness {
+            secret: Val::new(12345),
+            nullifier_preimage: Val::new(67890),
+            merkle_path: [Val::ZERO; TREE_DEPTH],
+            path_indices: [false; TREE_DEPTH],
+        };
+        let domain = Val::new(8453);
+        let commitment = hash_commitment(domain, witness.secret, witness.nullifier_preimage);
+        let circuit = WithdrawalCircuit::new(
+            compute_merkle_root(commitment, &witness.merkle_path, &witness.path_indices),
+            hash_nullifier(domain, witness.nullifier_preimage),
+            [0xab; 20],
+            Val::new(1000),
+            domain,
+        );
+        let config = default_config();
+        let proof = prove_withdrawal(&config, &circuit, &witness).unwrap();
+        let stark_proof: ProofParts<NoctisStarkConfig> = bincode::deserialize(&proof.proof_bytes).unwrap();
+
+        let spec = StarkVerifierSpec::withdrawal(FriParams::default());
+        let encoded = encode_evm_proof(&stark_proof);
+        assert_eq!(encoded.len(), 32 * spec.layout().proof_words());
+        assert_eq!(encoded[31] as usize, spec.degree_bits);
+    }
+
+    #[test]
+    fn test_withdrawal_verifier_fixture() {
+        // Two queries keep the proof small enough to check in; the contract
+        // only differs from the default one in its constants
+        let fri = FriParams { num_queries: 2, ..FriParams::default() };
+        let witness = WithdrawalWitness {
+            secret: Val::new(12345),
+            nullifier_preimage: Val::new(67890),
+            merkle_path: [Val::ZERO; TREE_DEPTH],
+            path_indices: [false; TREE_DEPTH],
+        };
+        // The verifier is deployed with the full-width DOMAIN of the vault
+        let (chain_id, vault) = (8453, [0x44; 20]);
+        let vault_domain = U256::from(compute_domain(chain_id, &vault));
+        let domain = domain_tag(chain_id, &vault);
+        let commitment = hash_commitment(domain, witness.secret, witness.nullifier_preimage);
+        let circuit = WithdrawalCircuit::new(
+            compute_merkle_root(commitment, &witness.merkle_path, &witness.path_indices),
+            hash_nullifier(domain, witness.nullifier_preimage),
+            [0xab; 20],
+            Val::new(1000),
+            domain,
+        );
+
+        let mut spec = StarkVerifierSpec::withdrawal(fri);
+        spec.contract_name = "WithdrawalStarkVerifierFixture".to_string();
+        let fixture = include_str!("../../../test/fixtures/WithdrawalStarkVerifierFixture.sol");
+        assert_eq!(
+            fixture,
+            spec.to_solidity().replace("from \"./IVerifier.sol\"", "from \"../../src/core/IVerifier.sol\"")
+        );
+
+        // Proving is deterministic, so the checked-in proof is the one the
+        // current prover makes
+        let config = ProverConfig::new(StarkHash::Poseidon2, &fri).with_min_security_bits(0.0);
+        let proof = prove_withdrawal(&config, &circuit, &witness).unwrap();
+        verify_withdrawal(&config, &proof).unwrap();
+        let evm_proof = evm_proof_bytes(&proof.proof_bytes).unwrap();
+        assert_eq!(evm_proof.len(), 32 * spec.layout().proof_words());
+        let proof_hex = include_str!("../../../test/fixtures/WithdrawalStarkProof.hex");
+        assert_eq!(proof_hex, format!("0x{}", hex::encode(&evm_proof)));
+
+        let forge_test = include_str!("../../../test/WithdrawalStarkVerifier.t.sol");
+        let inputs = proof.public_inputs.to_evm_inputs();
+        assert!(forge_test.contains(&format!("[uint256({}), {}, ", inputs[0], inputs[1])));
+        assert!(forge_test.contains(&format!("hex\"{}\"", hex::encode([0xab; 20]))));
+        assert!(forge_test.contains(&format!("{}]", inputs[3])));
+        assert!(forge_test.contains(&format!("DOMAIN = {};", vault_domain.to_dec_string())));
+        assert!(forge_test.contains(&format!("{} * 32", spec.layout().proof_words())));
+    }
+
+    #[test]
+    fn test_balance_evm_proof_matches_layout() {
+        let witness = BalanceWithdrawalWitness {
+            domain: Val::new(8453),
+            spending_key: Val::new(12345),
+            asset: [0; 20],
+            balance: Val::new(10000),
+            randomness: Val::new(99999),
+            note_index: 5,
+            merkle_path: [Val::ZERO; TREE_DEPTH],
+            path_indices: path_indices(5),
+            new_randomness: Val::new(88888),
+            association_path: None,
+            blocklist_proof: None,
+        };
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, [0xab; 20], Val::new(6000)).unwrap();
+        let config = default_config();
+        let proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        let stark_proof: ProofParts<NoctisStarkConfig> = bincode::deserialize(&proof.proof_bytes).unwrap();
+
+        let layout = StarkVerifierSpec::balance_withdrawal(FriParams::default()).layout();
+        assert_eq!(evm_proof_words(&stark_proof).len(), layout.proof_words());
+    }
+}
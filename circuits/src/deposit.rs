@@ -0,0 +1,389 @@
+//! Deposit circuit for Noctis Privacy Vault
+//!
+//! A deposit that took the commitment on trust would let a depositor commit
+//! to a larger balance than they pay in. This circuit proves the commitment
+//! opens to exactly the deposited amount of the deposited asset:
+//! 1. commitment = hash(domain, spending_key_hash, asset_id, amount,
+//!    randomness), with asset_id = hash(limbs of the asset address)
+//! 2. amount fits in `amount_bits::<F>()` bits
+//!
+//! This is the deposit statement for notes of the BabyBear circuits.
+//! BalanceVaultV4 notes are BN254 commitments in the vault's domain, and are
+//! deposited with a Groth16 proof of `r1cs::deposit` instead.
+//!
+//! Only the spending_key_hash is needed, so a note can be deposited on behalf
+//! of a recipient who keeps their spending key.
+//!
+//! Public inputs:
+//!   - commitment: The new note commitment
+//!   - amount: Tokens transferred in by the deposit
+//!   - asset: Token transferred in, the zero address for ETH, as
+//!     `ADDRESS_LIMBS` public values (see `address`)
+//!   - domain: Deployment domain of the note, `poseidon::domain_tag`
+//!
+//! Private inputs:
+//!   - spending_key_hash: hash(spending_key) of the note owner
+//!   - randomness: Note blinding factor
+//!
+//! The asset id and the commitment are one row each of the `poseidon_air`
+//! gadget, on steps 0 and 1; the amount's bits run alongside on the first
+//! `amount_bits` rows.
+
+use p3_air::{Air, AirBuilder, AirBuilderWithPublicValues, BaseAir};
+use p3_baby_bear::BabyBear;
+use p3_field::{PrimeCharacteristicRing, PrimeField64};
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::Matrix;
+
+use crate::abi::address_to_u256;
+use crate::address::{address_limbs, address_values, asset_id, Address, ADDRESS_LIMBS};
+use crate::balance_withdrawal::WitnessError;
+use crate::groth16::U256;
+use crate::keys::note_commitment;
+use crate::poseidon_air::{assert_constant, assert_digest, assert_inputs, fill_bit_sum, fill_constant, Program, AUX_COL};
+use crate::transfer::amount_bits;
+
+type Val = BabyBear;
+
+/// Number of public inputs
+pub const NUM_PUBLIC_INPUTS: usize = 4;
+
+/// Public inputs that are addresses: the asset
+pub const ADDRESS_INPUTS: [usize; 1] = [2];
+
+/// Number of public values of the AIR, with the asset split into limbs
+pub const NUM_PUBLIC_VALUES: usize = NUM_PUBLIC_INPUTS + ADDRESS_INPUTS.len() * (ADDRESS_LIMBS - 1);
+
+/// Public value of the domain, last
+const DOMAIN_VALUE: usize = NUM_PUBLIC_VALUES - 1;
+
+/// Steps hashing the asset id and the commitment
+const ASSET_STEP: usize = 0;
+const COMMITMENT_STEP: usize = ASSET_STEP + 1;
+
+/// Private inputs, equal on every row
+const ASSET_ID_COL: usize = AUX_COL;
+const SPENDING_KEY_HASH_COL: usize = ASSET_ID_COL + 1;
+const RANDOMNESS_COL: usize = SPENDING_KEY_HASH_COL + 1;
+/// Range proof of the amount: one bit per row, and their running sum
+const AMOUNT_BIT_COL: usize = RANDOMNESS_COL + 1;
+const AMOUNT_COL: usize = AMOUNT_BIT_COL + 1;
+const NUM_AUX_COLS: usize = AMOUNT_COL + 1 - AUX_COL;
+
+/// One step per amount bit, the first two of which also hash the asset id
+/// and the commitment
+fn program<F: PrimeField64>() -> Program {
+    Program::new(NUM_AUX_COLS, amount_bits::<F>().max(COMMITMENT_STEP + 1))
+}
+
+/// Private witness for a deposit
+pub struct DepositWitness<F = Val> {
+    /// Deployment domain of the note
+    pub domain: F,
+    pub spending_key_hash: F,
+    pub asset: Address,
+    pub randomness: F,
+}
+
+/// Deposit circuit AIR
+pub struct DepositCircuit<F = Val> {
+    // Public inputs
+    pub commitment: F,
+    pub amount: F,
+    pub asset: Address,
+    pub domain: F,
+}
+
+impl<F: PrimeField64> DepositCircuit<F> {
+    pub fn new(commitment: F, amount: F, asset: Address, domain: F) -> Self {
+        Self {
+            commitment,
+            amount,
+            asset,
+            domain,
+        }
+    }
+
+    /// Derive the commitment from a witness and check it
+    pub fn from_witness(witness: &DepositWitness<F>, amount: F) -> Result<Self, WitnessError> {
+        let commitment = note_commitment(
+            witness.domain,
+            witness.spending_key_hash,
+            asset_id(&witness.asset),
+            amount,
+            witness.randomness,
+        );
+        let circuit = Self::new(commitment, amount, witness.asset, witness.domain);
+        circuit.check_witness(witness)?;
+        Ok(circuit)
+    }
+
+    /// Public inputs in verifier order, the asset as its limbs:
+    /// [commitment, amount, asset, domain]
+    pub fn public_values(&self) -> Vec<F> {
+        let mut values = vec![self.commitment, self.amount];
+        values.extend(address_values::<F>(&self.asset));
+        values.push(self.domain);
+        values
+    }
+
+    /// Check that the witness satisfies the statement for these public inputs
+    pub fn check_witness(&self, witness: &DepositWitness<F>) -> Result<(), WitnessError> {
+        // 1. The commitment opens to the public amount, asset and domain
+        let commitment = note_commitment(
+            self.domain,
+            witness.spending_key_hash,
+            asset_id(&self.asset),
+            self.amount,
+            witness.randomness,
+        );
+        if commitment != self.commitment {
+            return Err(WitnessError::InvalidOutputCommitment);
+        }
+
+        // 2. Range check
+        if self.amount.as_canonical_u64() >> amount_bits::<F>() != 0 {
+            return Err(WitnessError::AmountOutOfRange);
+        }
+
+        Ok(())
+    }
+
+    /// Generate the execution trace for proving
+    pub fn generate_trace(&self, witness: &DepositWitness<F>) -> RowMajorMatrix<F> {
+        if let Err(e) = self.check_witness(witness) {
+            panic!("{}", e);
+        }
+
+        let asset_id = asset_id::<F>(&self.asset);
+        let lanes = vec![self.domain, witness.spending_key_hash, asset_id, self.amount, witness.randomness];
+        let mut trace = program::<F>().trace(&[address_values::<F>(&self.asset).to_vec(), lanes]);
+        fill_constant(&mut trace, ASSET_ID_COL, asset_id);
+        fill_constant(&mut trace, SPENDING_KEY_HASH_COL, witness.spending_key_hash);
+        fill_constant(&mut trace, RANDOMNESS_COL, witness.randomness);
+        let amount = self.amount.as_canonical_u64();
+        fill_bit_sum(&mut trace, AMOUNT_BIT_COL, AMOUNT_COL, 0, amount_bits::<F>(), amount);
+
+        trace
+    }
+
+    /// Height of the trace `generate_trace` produces
+    pub fn trace_height() -> usize {
+        program::<F>().height()
+    }
+}
+
+impl<F: PrimeField64> BaseAir<F> for DepositCircuit<F> {
+    fn width(&self) -> usize {
+        program::<F>().width()
+    }
+}
+
+impl<F: PrimeField64, AB: AirBuilderWithPublicValues<F = F>> Air<AB> for DepositCircuit<F> {
+    fn eval(&self, builder: &mut AB) {
+        let main = builder.main();
+        let local = main.row_slice(0).expect("The matrix is empty?");
+        let next = main.row_slice(1).expect("The matrix has only one row?");
+        let local: Vec<AB::Expr> = local.iter().map(|&v| v.into()).collect();
+        let next: Vec<AB::Expr> = next.iter().map(|&v| v.into()).collect();
+        let pis: Vec<AB::Expr> = builder.public_values().iter().map(|&v| v.into()).collect();
+        let (commitment, amount) = (pis[0].clone(), pis[1].clone());
+        let asset_id = local[ASSET_ID_COL].clone();
+
+        let program = program::<F>();
+        program.eval(builder, &local, &next);
+        assert_constant(builder, &local, &next, ASSET_ID_COL);
+        assert_constant(builder, &local, &next, SPENDING_KEY_HASH_COL);
+        assert_constant(builder, &local, &next, RANDOMNESS_COL);
+
+        // asset_id = hash(limbs of the asset)
+        let step = program.flag(&local, ASSET_STEP);
+        assert_inputs(builder, step.clone(), &local, &pis[2..2 + ADDRESS_LIMBS]);
+        assert_digest(builder, step, &local, asset_id.clone());
+
+        // commitment = hash(domain, spending_key_hash, asset_id, amount, randomness)
+        let lanes = [
+            pis[DOMAIN_VALUE].clone(),
+            local[SPENDING_KEY_HASH_COL].clone(),
+            asset_id,
+            amount.clone(),
+            local[RANDOMNESS_COL].clone(),
+        ];
+        let step = program.flag(&local, COMMITMENT_STEP);
+        assert_inputs(builder, step.clone(), &local, &lanes);
+        assert_digest(builder, step, &local, commitment);
+
+        // The amount is the sum of its amount_bits range-check bits
+        program.eval_bit_sum(builder, &local, &next, AMOUNT_BIT_COL, AMOUNT_COL, 0, amount_bits::<F>());
+        builder.when_last_row().assert_eq(local[AMOUNT_COL].clone(), amount);
+    }
+}
+
+/// Proof data for serialization
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct DepositProof {
+    pub proof_bytes: Vec<u8>,
+    pub public_inputs: DepositPublicInputs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DepositPublicInputs {
+    pub commitment: u64,
+    pub amount: u64,
+    pub asset: Address,
+    pub domain: u64,
+}
+
+impl DepositPublicInputs {
+    pub fn from_circuit<F: PrimeField64>(circuit: &DepositCircuit<F>) -> Self {
+        Self {
+            commitment: circuit.commitment.as_canonical_u64(),
+            amount: circuit.amount.as_canonical_u64(),
+            asset: circuit.asset,
+            domain: circuit.domain.as_canonical_u64(),
+        }
+    }
+
+    /// Public values in verifier order, the asset as its limbs
+    pub fn to_array(&self) -> [u64; NUM_PUBLIC_VALUES] {
+        let mut values = vec![self.commitment, self.amount];
+        values.extend(address_limbs(&self.asset).map(u64::from));
+        values.push(self.domain);
+        values.try_into().expect("NUM_PUBLIC_VALUES values")
+    }
+
+    /// Public values as field elements, in verifier order
+    pub fn to_field_elements<F: PrimeCharacteristicRing>(&self) -> Vec<F> {
+        self.to_array().iter().map(|&v| F::from_u64(v)).collect()
+    }
+
+    /// Circuit for these public inputs
+    pub fn to_circuit<F: PrimeField64>(&self) -> DepositCircuit<F> {
+        DepositCircuit::new(
+            F::from_u64(self.commitment),
+            F::from_u64(self.amount),
+            self.asset,
+            F::from_u64(self.domain),
+        )
+    }
+
+    /// Public inputs in the `IDepositVerifier` layout, the asset at full
+    /// width and the domain reduced, as a verifier reduces the vault's DOMAIN
+    pub fn to_evm_inputs(&self) -> [U256; NUM_PUBLIC_INPUTS] {
+        [
+            U256::from_u64(self.commitment),
+            U256::from_u64(self.amount),
+            address_to_u256(&self.asset),
+            U256::from_u64(self.domain),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poseidon::poseidon_hash;
+    use crate::poseidon_air::OUTPUT_COL;
+    use crate::prover::forged_trace_verifies;
+
+    const ASSET: Address = [0xa5; 20];
+    const DOMAIN: Val = Val::new(8453);
+
+    fn sample_witness() -> DepositWitness {
+        DepositWitness {
+            domain: DOMAIN,
+            spending_key_hash: poseidon_hash(Val::new(12345)),
+            asset: ASSET,
+            randomness: Val::new(99999),
+        }
+    }
+
+    #[test]
+    fn test_deposit_commitment() {
+        let witness = sample_witness();
+        let circuit = DepositCircuit::from_witness(&witness, Val::new(10000)).unwrap();
+        assert_eq!(
+            circuit.commitment,
+            note_commitment(DOMAIN, poseidon_hash(Val::new(12345)), asset_id(&ASSET), Val::new(10000), Val::new(99999))
+        );
+
+        let trace = circuit.generate_trace(&witness);
+        assert_eq!(trace.width(), circuit.width());
+        assert_eq!(trace.height(), DepositCircuit::<Val>::trace_height());
+        let rows: Vec<&[Val]> = trace.values.chunks(trace.width).collect();
+        assert_eq!(rows[COMMITMENT_STEP][OUTPUT_COL], circuit.commitment);
+        assert_eq!(rows[rows.len() - 1][AMOUNT_COL], Val::new(10000));
+    }
+
+    #[test]
+    fn test_commitment_to_larger_balance_rejected() {
+        // Commit to 10000 but deposit 1
+        let witness = sample_witness();
+        let commitment = DepositCircuit::from_witness(&witness, Val::new(10000)).unwrap().commitment;
+        let circuit = DepositCircuit::new(commitment, Val::new(1), ASSET, DOMAIN);
+        assert!(matches!(
+            circuit.check_witness(&witness),
+            Err(WitnessError::InvalidOutputCommitment)
+        ));
+
+        // Or commit to a valuable asset but deposit a cheap one
+        let circuit = DepositCircuit::new(commitment, Val::new(10000), [1; 20], DOMAIN);
+        assert!(matches!(
+            circuit.check_witness(&witness),
+            Err(WitnessError::InvalidOutputCommitment)
+        ));
+
+        // Or in another deployment
+        let circuit = DepositCircuit::new(commitment, Val::new(10000), ASSET, Val::new(84532));
+        assert!(matches!(
+            circuit.check_witness(&witness),
+            Err(WitnessError::InvalidOutputCommitment)
+        ));
+    }
+
+    #[test]
+    fn test_forged_amount_trace_rejected() {
+        let witness = sample_witness();
+        let honest = DepositCircuit::from_witness(&witness, Val::new(10000)).unwrap();
+        assert!(forged_trace_verifies(&honest, honest.generate_trace(&witness), &honest.public_values()));
+
+        // The amount bits of an honest trace, re-filled to sum to 1, against
+        // the commitment to 10000
+        let mut trace = honest.generate_trace(&witness);
+        fill_bit_sum(&mut trace, AMOUNT_BIT_COL, AMOUNT_COL, 0, amount_bits::<Val>(), 1);
+        let circuit = DepositCircuit::new(honest.commitment, Val::new(1), ASSET, DOMAIN);
+        assert!(!forged_trace_verifies(&circuit, trace, &circuit.public_values()));
+
+        // Or an honest opening of a different commitment
+        let trace = honest.generate_trace(&witness);
+        let circuit = DepositCircuit::new(honest.commitment + Val::new(1), Val::new(10000), ASSET, DOMAIN);
+        assert!(!forged_trace_verifies(&circuit, trace, &circuit.public_values()));
+
+        // Or claimed for another asset
+        let trace = honest.generate_trace(&witness);
+        let circuit = DepositCircuit::new(honest.commitment, Val::new(10000), [1; 20], DOMAIN);
+        assert!(!forged_trace_verifies(&circuit, trace, &circuit.public_values()));
+
+        // Or for another deployment
+        let trace = honest.generate_trace(&witness);
+        let circuit = DepositCircuit::new(honest.commitment, Val::new(10000), ASSET, Val::new(84532));
+        assert!(!forged_trace_verifies(&circuit, trace, &circuit.public_values()));
+    }
+
+    #[test]
+    fn test_amount_out_of_range_rejected() {
+        let amount = Val::new(1 << amount_bits::<Val>());
+        assert!(matches!(
+            DepositCircuit::from_witness(&sample_witness(), amount),
+            Err(WitnessError::AmountOutOfRange)
+        ));
+    }
+
+    #[test]
+    fn test_public_inputs_roundtrip() {
+        let circuit = DepositCircuit::from_witness(&sample_witness(), Val::new(10000)).unwrap();
+        let public_inputs = DepositPublicInputs::from_circuit(&circuit);
+        assert_eq!(public_inputs.to_field_elements::<Val>(), circuit.public_values());
+        assert_eq!(public_inputs.to_circuit::<Val>().public_values(), circuit.public_values());
+    }
+}
@@ -0,0 +1,293 @@
+//! Goldilocks backend for the withdrawal circuits
+//!
+//! Both circuits, the Poseidon2 hasher and the Merkle code are generic over
+//! the field; this module instantiates them over Goldilocks
+//! (p = 2^64 - 2^32 + 1):
+//! - Degree-2 binomial extension for FRI challenges (128 bits)
+//! - Radix-2 DIT DFT
+//! - Keccak-256 Merkle commitments and a serializing hash challenger
+//!
+//! Commitments use Keccak because it needs no field-specific constants.
+//! Proofs share the `WithdrawalProof` and `BalanceWithdrawalProof` types of
+//! the BabyBear backend; their public inputs are canonical Goldilocks values
+//! and only verify here. There is no EVM encoding or Solidity verifier for
+//! Goldilocks proofs.
+
+use p3_challenger::{HashChallenger, SerializingChallenger64};
+use p3_commit::ExtensionMmcs;
+use p3_dft::Radix2DitParallel;
+use p3_field::extension::BinomialExtensionField;
+use p3_fri::TwoAdicFriPcs;
+use p3_goldilocks::Goldilocks;
+use p3_keccak::Keccak256Hash;
+use p3_matrix::Matrix;
+use p3_merkle_tree::MerkleTreeMmcs;
+use p3_symmetric::{CompressionFunctionFromHasher, SerializingHasher};
+use p3_uni_stark::{prove, verify, Proof, StarkConfig};
+
+use crate::balance_withdrawal::{
+    BalanceWithdrawalCircuit, BalanceWithdrawalProof, BalanceWithdrawalWitness, PublicInputs,
+};
+use crate::prover::{
+    decode_proof, fri_config, pad_trace, FriParams, ProofParts, ProverError, StarkHash, MIN_SECURITY_BITS,
+};
+use crate::security::{security_level_over, ChallengeField, FriPreset, SecurityLevel};
+use crate::withdrawal::{WithdrawalCircuit, WithdrawalProof, WithdrawalPublicInputs, WithdrawalWitness};
+
+pub type Val = Goldilocks;
+pub type Challenge = BinomialExtensionField<Val, 2>;
+
+type ByteHash = Keccak256Hash;
+type FieldHash = SerializingHasher<ByteHash>;
+type Compress = CompressionFunctionFromHasher<ByteHash, 2, 32>;
+type ValMmcs = MerkleTreeMmcs<Val, u8, FieldHash, Compress, 32>;
+type ChallengeMmcs = ExtensionMmcs<Val, Challenge, ValMmcs>;
+type Challenger = SerializingChallenger64<Val, HashChallenger<u8, ByteHash, 32>>;
+type Dft = Radix2DitParallel<Val>;
+type Pcs = TwoAdicFriPcs<Val, Dft, ValMmcs, ChallengeMmcs>;
+
+/// STARK configuration for the Goldilocks instantiation
+pub type GoldilocksStarkConfig = StarkConfig<Pcs, Challenge, Challenger>;
+
+/// Goldilocks counterpart of `ProverConfig`
+///
+/// As with BabyBear, proving refuses to run, and verification rejects the
+/// proof, when the conjectured security for the trace falls below
+/// `min_security_bits`.
+pub struct GoldilocksConfig {
+    config: GoldilocksStarkConfig,
+    params: FriParams,
+    min_security_bits: f64,
+}
+
+impl GoldilocksConfig {
+    pub fn new(params: &FriParams) -> Self {
+        Self {
+            config: config_with_params(params),
+            params: *params,
+            min_security_bits: MIN_SECURITY_BITS,
+        }
+    }
+
+    /// Configuration for a named FRI preset
+    pub fn from_preset(preset: FriPreset) -> Self {
+        Self::new(&preset.params())
+    }
+
+    /// Change the security floor enforced by proving and verifying
+    pub fn with_min_security_bits(mut self, bits: f64) -> Self {
+        self.min_security_bits = bits;
+        self
+    }
+
+    pub fn params(&self) -> &FriParams {
+        &self.params
+    }
+
+    /// Security for a trace of `2^log_trace_height` rows
+    pub fn security(&self, log_trace_height: usize) -> SecurityLevel {
+        security_level_over(ChallengeField::Goldilocks, &self.params, StarkHash::Keccak, log_trace_height)
+    }
+
+    fn check_security(&self, trace_height: usize) -> Result<(), ProverError> {
+        let bits = self.security(trace_height.ilog2() as usize).conjectured_bits;
+        if bits < self.min_security_bits {
+            return Err(ProverError::InsufficientSecurity {
+                bits,
+                required: self.min_security_bits,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for GoldilocksConfig {
+    fn default() -> Self {
+        Self::new(&FriParams::default())
+    }
+}
+
+/// Build a Goldilocks STARK configuration with the given FRI parameters
+///
+/// Field elements are hashed as their little-endian u64 encodings; inner
+/// Merkle nodes are `keccak256(left || right)`.
+pub fn config_with_params(params: &FriParams) -> GoldilocksStarkConfig {
+    let byte_hash = ByteHash {};
+    let field_hash = FieldHash::new(byte_hash);
+    let compress = Compress::new(byte_hash);
+    let val_mmcs = ValMmcs::new(field_hash, compress);
+    let challenge_mmcs = ChallengeMmcs::new(val_mmcs.clone());
+
+    let pcs = Pcs::new(Dft::default(), val_mmcs, fri_config(params, challenge_mmcs));
+    let challenger = Challenger::from_hasher(vec![], byte_hash);
+
+    GoldilocksStarkConfig::new(pcs, challenger)
+}
+
+/// Prove a balance withdrawal over Goldilocks
+pub fn prove_balance_withdrawal(
+    config: &GoldilocksConfig,
+    circuit: &BalanceWithdrawalCircuit<Val>,
+    witness: &BalanceWithdrawalWitness<Val>,
+) -> Result<BalanceWithdrawalProof, ProverError> {
+    circuit.check_witness(witness)?;
+
+    let trace = pad_trace(circuit.generate_trace(witness));
+    config.check_security(trace.height())?;
+    let public_values = circuit.public_values();
+    let proof = prove(&config.config, circuit, trace, &public_values);
+
+    Ok(BalanceWithdrawalProof {
+        proof_bytes: bincode::serialize(&proof).expect("proof serialization"),
+        public_inputs: PublicInputs::from_circuit(circuit),
+    })
+}
+
+/// Verify a Goldilocks balance withdrawal proof against its public inputs
+pub fn verify_balance_withdrawal(
+    config: &GoldilocksConfig,
+    proof: &BalanceWithdrawalProof,
+) -> Result<(), ProverError> {
+    let circuit: BalanceWithdrawalCircuit<Val> = proof.public_inputs.to_circuit();
+    let public_values = circuit.public_values();
+
+    let parts: ProofParts<GoldilocksStarkConfig> = decode_proof(&proof.proof_bytes)?;
+    config.check_security(1 << parts.degree_bits)?;
+    let stark_proof: Proof<GoldilocksStarkConfig> = decode_proof(&proof.proof_bytes)?;
+    verify(&config.config, &circuit, &stark_proof, &public_values)
+        .map_err(|e| ProverError::Verification(format!("{:?}", e)))
+}
+
+/// Prove a fixed-denomination withdrawal over Goldilocks
+pub fn prove_withdrawal(
+    config: &GoldilocksConfig,
+    circuit: &WithdrawalCircuit<Val>,
+    witness: &WithdrawalWitness<Val>,
+) -> Result<WithdrawalProof, ProverError> {
+    let trace = pad_trace(circuit.generate_trace(witness));
+    config.check_security(trace.height())?;
+    let public_values = circuit.public_values();
+    let proof = prove(&config.config, circuit, trace, &public_values);
+
+    let public_inputs = WithdrawalPublicInputs::from_circuit(circuit);
+
+    Ok(WithdrawalProof {
+        proof_bytes: bincode::serialize(&proof).expect("proof serialization"),
+        public_inputs,
+    })
+}
+
+/// Verify a Goldilocks fixed-denomination withdrawal proof
+pub fn verify_withdrawal(
+    config: &GoldilocksConfig,
+    proof: &WithdrawalProof,
+) -> Result<(), ProverError> {
+    let circuit: WithdrawalCircuit<Val> = proof.public_inputs.to_circuit();
+    let public_values = circuit.public_values();
+
+    let parts: ProofParts<GoldilocksStarkConfig> = decode_proof(&proof.proof_bytes)?;
+    config.check_security(1 << parts.degree_bits)?;
+    let stark_proof: Proof<GoldilocksStarkConfig> = decode_proof(&proof.proof_bytes)?;
+    verify(&config.config, &circuit, &stark_proof, &public_values)
+        .map_err(|e| ProverError::Verification(format!("{:?}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::balance_withdrawal::path_indices;
+    use crate::merkle::{compute_merkle_root, TREE_DEPTH};
+    use crate::poseidon::{hash_commitment, hash_nullifier};
+    use crate::prover;
+    use p3_field::PrimeCharacteristicRing;
+
+    fn test_config() -> GoldilocksConfig {
+        GoldilocksConfig::from_preset(FriPreset::FastTest).with_min_security_bits(0.0)
+    }
+
+    fn sample_witness() -> BalanceWithdrawalWitness<Val> {
+        BalanceWithdrawalWitness {
+            domain: Val::from_u64(8453),
+            spending_key: Val::from_u64(12345),
+            asset: [0; 20],
+            // Above the BabyBear modulus
+            balance: Val::from_u64(5_000_000_000),
+            randomness: Val::from_u64(99999),
+            note_index: 5,
+            merkle_path: [Val::ZERO; TREE_DEPTH],
+            path_indices: path_indices(5),
+            new_randomness: Val::from_u64(88888),
+            association_path: None,
+            blocklist_proof: None,
+        }
+    }
+
+    #[test]
+    fn test_prove_and_verify_balance_withdrawal() {
+        let witness = sample_witness();
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, [0xab; 20], Val::from_u64(3_000_000_000))
+                .unwrap();
+
+        let config = test_config();
+        let proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        verify_balance_withdrawal(&config, &proof).unwrap();
+
+        let mut tampered = proof;
+        tampered.public_inputs.amount += 1;
+        assert!(matches!(
+            verify_balance_withdrawal(&config, &tampered),
+            Err(ProverError::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn test_prove_and_verify_withdrawal() {
+        let secret = Val::from_u64(12345);
+        let nullifier_preimage = Val::from_u64(67890);
+        let merkle_path = [Val::ZERO; TREE_DEPTH];
+        let path_indices = [false; TREE_DEPTH];
+        let domain = Val::from_u64(8453);
+        let root = compute_merkle_root(
+            hash_commitment(domain, secret, nullifier_preimage),
+            &merkle_path,
+            &path_indices,
+        );
+
+        let circuit = WithdrawalCircuit::new(
+            root,
+            hash_nullifier(domain, nullifier_preimage),
+            [0xab; 20],
+            Val::from_u64(10000),
+            domain,
+        );
+        let witness = WithdrawalWitness {
+            secret,
+            nullifier_preimage,
+            merkle_path,
+            path_indices,
+        };
+
+        let config = test_config();
+        let proof = prove_withdrawal(&config, &circuit, &witness).unwrap();
+        verify_withdrawal(&config, &proof).unwrap();
+
+        // Not a BabyBear proof
+        let babybear = prover::ProverConfig::new(StarkHash::Keccak, config.params());
+        assert!(prover::verify_withdrawal(&babybear, &proof).is_err());
+    }
+
+    #[test]
+    fn test_prove_refuses_insecure_config() {
+        let witness = sample_witness();
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, [0xab; 20], Val::from_u64(3_000_000_000))
+                .unwrap();
+
+        let config = GoldilocksConfig::from_preset(FriPreset::FastTest);
+        assert!(matches!(
+            prove_balance_withdrawal(&config, &circuit, &witness),
+            Err(ProverError::InsufficientSecurity { .. })
+        ));
+    }
+}
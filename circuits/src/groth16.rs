@@ -0,0 +1,797 @@
+//! Groth16 proof encoding compatible with snarkjs and the Solidity verifiers
+//!
+//! snarkjs writes a proof as `proof.json` (`pi_a`, `pi_b`, `pi_c` as decimal
+//! strings in projective form) and its public signals as `public.json`
+//! (an array of decimal strings). The on-chain verifiers take the proof as
+//! `abi.encode(uint256[2] pA, uint256[2][2] pB, uint256[2] pC)`.
+//!
+//! The only non-obvious step is the G2 point `pi_b`: snarkjs stores each
+//! Fq2 coordinate as `[c0, c1]`, while the EVM pairing precompile expects
+//! `[c1, c0]`. `snarkjs zkey export soliditycalldata` swaps them, and so does
+//! everything in this module that produces calldata.
+
+use std::fmt;
+
+use ark_bn254::{Bn254, Fq, Fq2, Fr, G1Affine, G2Affine};
+use ark_ec::pairing::Pairing;
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::{BigInt, PrimeField, Zero};
+
+/// BN254 base field modulus q (curve coordinates live in this field)
+pub const BN254_BASE_MODULUS: &str =
+    "21888242871839275222246405745257275088696311157297823662689037894645226208583";
+
+/// BN254 scalar field modulus r (public signals live in this field)
+pub const BN254_SCALAR_MODULUS: &str =
+    "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+
+/// Size in bytes of an ABI-encoded proof: 8 words (pA, pB, pC)
+pub const PROOF_CALLDATA_LEN: usize = 8 * 32;
+
+/// Errors raised while decoding snarkjs JSON or ABI-encoded proofs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Groth16Error {
+    /// The input is not valid JSON or is missing a field
+    InvalidJson(String),
+    /// A value is not a decimal (or 0x-prefixed hex) integer below 2^256
+    InvalidNumber(String),
+    /// A coordinate is not reduced modulo the BN254 base field
+    CoordinateOutOfRange(String),
+    /// A public signal is not reduced modulo the BN254 scalar field
+    SignalOutOfRange(String),
+    /// The point is not in affine form (z != 1)
+    NotAffine,
+    /// ABI-encoded proof has the wrong length
+    InvalidCalldataLength(usize),
+    /// The number of public inputs does not match the verification key
+    InputCountMismatch { expected: usize, actual: usize },
+}
+
+impl fmt::Display for Groth16Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Groth16Error::InvalidJson(e) => write!(f, "Invalid snarkjs JSON: {}", e),
+            Groth16Error::InvalidNumber(s) => write!(f, "Invalid number: {}", s),
+            Groth16Error::CoordinateOutOfRange(s) => write!(f, "Coordinate not in base field: {}", s),
+            Groth16Error::SignalOutOfRange(s) => write!(f, "Public signal not in scalar field: {}", s),
+            Groth16Error::NotAffine => write!(f, "Point is not in affine form"),
+            Groth16Error::InvalidCalldataLength(len) => {
+                write!(f, "Invalid proof calldata length: {} (expected {})", len, PROOF_CALLDATA_LEN)
+            }
+            Groth16Error::InputCountMismatch { expected, actual } => {
+                write!(f, "Got {} public inputs, the verification key takes {}", actual, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Groth16Error {}
+
+/// Unsigned 256-bit integer (4 x u64 limbs, little-endian)
+///
+/// Unlike `Bn254Field` this type does not reduce modulo anything, so it can
+/// carry base field coordinates, which are larger than the scalar modulus.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct U256([u64; 4]);
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // Most significant limb first
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl U256 {
+    pub const ZERO: Self = Self([0, 0, 0, 0]);
+    pub const ONE: Self = Self([1, 0, 0, 0]);
+
+    pub fn from_u64(value: u64) -> Self {
+        Self([value, 0, 0, 0])
+    }
+
+    pub fn from_limbs(limbs: [u64; 4]) -> Self {
+        Self(limbs)
+    }
+
+    pub fn limbs(&self) -> [u64; 4] {
+        self.0
+    }
+
+    /// Parse a decimal string, or a hex string with a `0x` prefix
+    pub fn parse(s: &str) -> Result<Self, Groth16Error> {
+        let s = s.trim();
+        if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Self::from_hex(hex)
+        } else {
+            Self::from_dec_str(s)
+        }
+    }
+
+    pub fn from_dec_str(s: &str) -> Result<Self, Groth16Error> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(Groth16Error::InvalidNumber(s.to_string()));
+        }
+
+        let mut result = Self::ZERO;
+        for b in s.bytes() {
+            result = result
+                .mul_small(10)
+                .and_then(|r| r.add_small((b - b'0') as u64))
+                .ok_or_else(|| Groth16Error::InvalidNumber(s.to_string()))?;
+        }
+        Ok(result)
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Self, Groth16Error> {
+        let hex = hex.trim_start_matches("0x");
+        if hex.is_empty() || hex.len() > 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(Groth16Error::InvalidNumber(hex.to_string()));
+        }
+
+        let padded = format!("{:0>64}", hex);
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let start = 64 - (i + 1) * 16;
+            let end = 64 - i * 16;
+            *limb = u64::from_str_radix(&padded[start..end], 16)
+                .map_err(|_| Groth16Error::InvalidNumber(hex.to_string()))?;
+        }
+        Ok(Self(limbs))
+    }
+
+    /// Lowercase hex, zero-padded to 64 digits, with `0x` prefix
+    pub fn to_hex(&self) -> String {
+        format!("0x{:016x}{:016x}{:016x}{:016x}", self.0[3], self.0[2], self.0[1], self.0[0])
+    }
+
+    pub fn to_dec_string(&self) -> String {
+        if *self == Self::ZERO {
+            return "0".to_string();
+        }
+
+        let mut digits = Vec::new();
+        let mut temp = *self;
+        while temp != Self::ZERO {
+            let (q, r) = temp.div_rem_small(10);
+            digits.push((r as u8 + b'0') as char);
+            temp = q;
+        }
+        digits.iter().rev().collect()
+    }
+
+    /// Big-endian 32-byte encoding (one ABI word)
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        for i in 0..4 {
+            out[(3 - i) * 8..(4 - i) * 8].copy_from_slice(&self.0[i].to_be_bytes());
+        }
+        out
+    }
+
+    pub fn from_be_bytes(bytes: &[u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&bytes[(3 - i) * 8..(4 - i) * 8]);
+            *limb = u64::from_be_bytes(word);
+        }
+        Self(limbs)
+    }
+
+    fn mul_small(&self, m: u64) -> Option<Self> {
+        let mut out = [0u64; 4];
+        let mut carry = 0u128;
+        for (o, &limb) in out.iter_mut().zip(self.0.iter()) {
+            let p = limb as u128 * m as u128 + carry;
+            *o = p as u64;
+            carry = p >> 64;
+        }
+        if carry != 0 {
+            None
+        } else {
+            Some(Self(out))
+        }
+    }
+
+    fn add_small(&self, a: u64) -> Option<Self> {
+        let mut out = self.0;
+        let mut carry = a;
+        for limb in out.iter_mut() {
+            let (sum, overflow) = limb.overflowing_add(carry);
+            *limb = sum;
+            carry = overflow as u64;
+            if carry == 0 {
+                break;
+            }
+        }
+        if carry != 0 {
+            None
+        } else {
+            Some(Self(out))
+        }
+    }
+
+    fn div_rem_small(&self, d: u64) -> (Self, u64) {
+        let mut quotient = [0u64; 4];
+        let mut rem = 0u128;
+        for i in (0..4).rev() {
+            let cur = (rem << 64) | self.0[i] as u128;
+            quotient[i] = (cur / d as u128) as u64;
+            rem = cur % d as u128;
+        }
+        (Self(quotient), rem as u64)
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_dec_string())
+    }
+}
+
+fn base_modulus() -> U256 {
+    U256::from_dec_str(BN254_BASE_MODULUS).expect("valid modulus")
+}
+
+fn scalar_modulus() -> U256 {
+    U256::from_dec_str(BN254_SCALAR_MODULUS).expect("valid modulus")
+}
+
+fn parse_coordinate(s: &str) -> Result<U256, Groth16Error> {
+    let value = U256::parse(s)?;
+    if value >= base_modulus() {
+        return Err(Groth16Error::CoordinateOutOfRange(s.to_string()));
+    }
+    Ok(value)
+}
+
+/// Affine point on the BN254 G1 curve
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct G1Point {
+    pub x: U256,
+    pub y: U256,
+}
+
+/// Affine point on the BN254 G2 curve
+///
+/// Coordinates are stored in snarkjs order: `x = x[0] + x[1] * u`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct G2Point {
+    pub x: [U256; 2],
+    pub y: [U256; 2],
+}
+
+/// Groth16 proof over BN254
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Groth16Proof {
+    pub a: G1Point,
+    pub b: G2Point,
+    pub c: G1Point,
+}
+
+/// Raw `proof.json` layout written by snarkjs
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SnarkjsProofJson {
+    pi_a: Vec<String>,
+    pi_b: Vec<Vec<String>>,
+    pi_c: Vec<String>,
+    #[serde(default = "default_protocol")]
+    protocol: String,
+    #[serde(default = "default_curve")]
+    curve: String,
+}
+
+fn default_protocol() -> String {
+    "groth16".to_string()
+}
+
+fn default_curve() -> String {
+    "bn128".to_string()
+}
+
+impl G1Point {
+    /// Parse `[x, y]` or projective `[x, y, "1"]`
+    fn from_snarkjs(coords: &[String]) -> Result<Self, Groth16Error> {
+        match coords.len() {
+            2 => {}
+            3 if U256::parse(&coords[2])? == U256::ONE => {}
+            3 => return Err(Groth16Error::NotAffine),
+            n => return Err(Groth16Error::InvalidJson(format!("G1 point has {} coordinates", n))),
+        }
+        Ok(Self {
+            x: parse_coordinate(&coords[0])?,
+            y: parse_coordinate(&coords[1])?,
+        })
+    }
+
+    fn to_snarkjs(self) -> Vec<String> {
+        vec![self.x.to_dec_string(), self.y.to_dec_string(), "1".to_string()]
+    }
+}
+
+impl G2Point {
+    /// Parse `[[x0, x1], [y0, y1]]` or projective `[..., ["1", "0"]]`
+    fn from_snarkjs(coords: &[Vec<String>]) -> Result<Self, Groth16Error> {
+        match coords.len() {
+            2 => {}
+            3 => {
+                let z = &coords[2];
+                if z.len() != 2 || U256::parse(&z[0])? != U256::ONE || U256::parse(&z[1])? != U256::ZERO {
+                    return Err(Groth16Error::NotAffine);
+                }
+            }
+            n => return Err(Groth16Error::InvalidJson(format!("G2 point has {} coordinates", n))),
+        }
+        if coords[0].len() != 2 || coords[1].len() != 2 {
+            return Err(Groth16Error::InvalidJson("G2 coordinate must have 2 components".to_string()));
+        }
+        Ok(Self {
+            x: [parse_coordinate(&coords[0][0])?, parse_coordinate(&coords[0][1])?],
+            y: [parse_coordinate(&coords[1][0])?, parse_coordinate(&coords[1][1])?],
+        })
+    }
+
+    fn to_snarkjs(self) -> Vec<Vec<String>> {
+        vec![
+            vec![self.x[0].to_dec_string(), self.x[1].to_dec_string()],
+            vec![self.y[0].to_dec_string(), self.y[1].to_dec_string()],
+            vec!["1".to_string(), "0".to_string()],
+        ]
+    }
+
+    /// Coordinates in EVM precompile order: `[[x1, x0], [y1, y0]]`
+    pub fn to_evm_order(&self) -> [[U256; 2]; 2] {
+        [[self.x[1], self.x[0]], [self.y[1], self.y[0]]]
+    }
+
+    /// Inverse of `to_evm_order`
+    pub fn from_evm_order(words: [[U256; 2]; 2]) -> Self {
+        Self {
+            x: [words[0][1], words[0][0]],
+            y: [words[1][1], words[1][0]],
+        }
+    }
+}
+
+fn fq_to_u256(x: Fq) -> U256 {
+    U256::from_limbs(x.into_bigint().0)
+}
+
+fn u256_to_fq(x: U256) -> Option<Fq> {
+    Fq::from_bigint(BigInt(x.limbs()))
+}
+
+/// The point at infinity is `(0, 0)`, as for the EVM precompiles
+impl From<&G1Affine> for G1Point {
+    fn from(p: &G1Affine) -> Self {
+        match p.xy() {
+            Some((x, y)) => Self { x: fq_to_u256(x), y: fq_to_u256(y) },
+            None => Self { x: U256::ZERO, y: U256::ZERO },
+        }
+    }
+}
+
+impl From<&G2Affine> for G2Point {
+    fn from(p: &G2Affine) -> Self {
+        match p.xy() {
+            Some((x, y)) => Self {
+                x: [fq_to_u256(x.c0), fq_to_u256(x.c1)],
+                y: [fq_to_u256(y.c0), fq_to_u256(y.c1)],
+            },
+            None => Self { x: [U256::ZERO; 2], y: [U256::ZERO; 2] },
+        }
+    }
+}
+
+impl G1Point {
+    /// The curve point, or `None` where the precompiles would fail
+    fn to_ark(self) -> Option<G1Affine> {
+        if self.x == U256::ZERO && self.y == U256::ZERO {
+            return Some(G1Affine::zero());
+        }
+        let p = G1Affine::new_unchecked(u256_to_fq(self.x)?, u256_to_fq(self.y)?);
+        p.is_on_curve().then_some(p)
+    }
+}
+
+impl G2Point {
+    /// The curve point, or `None` where the pairing precompile would fail
+    fn to_ark(self) -> Option<G2Affine> {
+        if self.x == [U256::ZERO; 2] && self.y == [U256::ZERO; 2] {
+            return Some(G2Affine::zero());
+        }
+        let p = G2Affine::new_unchecked(
+            Fq2::new(u256_to_fq(self.x[0])?, u256_to_fq(self.x[1])?),
+            Fq2::new(u256_to_fq(self.y[0])?, u256_to_fq(self.y[1])?),
+        );
+        (p.is_on_curve() && p.is_in_correct_subgroup_assuming_on_curve()).then_some(p)
+    }
+}
+
+impl Groth16Proof {
+    /// Parse a snarkjs `proof.json`
+    pub fn from_snarkjs_json(json: &str) -> Result<Self, Groth16Error> {
+        let raw: SnarkjsProofJson =
+            serde_json::from_str(json).map_err(|e| Groth16Error::InvalidJson(e.to_string()))?;
+
+        if raw.protocol != "groth16" {
+            return Err(Groth16Error::InvalidJson(format!("Unsupported protocol: {}", raw.protocol)));
+        }
+
+        Ok(Self {
+            a: G1Point::from_snarkjs(&raw.pi_a)?,
+            b: G2Point::from_snarkjs(&raw.pi_b)?,
+            c: G1Point::from_snarkjs(&raw.pi_c)?,
+        })
+    }
+
+    /// Write the proof in snarkjs `proof.json` form
+    pub fn to_snarkjs_json(&self) -> String {
+        let raw = SnarkjsProofJson {
+            pi_a: self.a.to_snarkjs(),
+            pi_b: self.b.to_snarkjs(),
+            pi_c: self.c.to_snarkjs(),
+            protocol: default_protocol(),
+            curve: default_curve(),
+        };
+        serde_json::to_string_pretty(&raw).expect("proof JSON serialization")
+    }
+
+    /// Proof words in the order the Solidity verifier reads them
+    pub fn to_words(&self) -> [U256; 8] {
+        let b = self.b.to_evm_order();
+        [
+            self.a.x, self.a.y,
+            b[0][0], b[0][1], b[1][0], b[1][1],
+            self.c.x, self.c.y,
+        ]
+    }
+
+    /// `bytes proof` for `IVerifier.verifyProof`:
+    /// `abi.encode(uint256[2] pA, uint256[2][2] pB, uint256[2] pC)`
+    pub fn to_solidity_calldata(&self) -> Vec<u8> {
+        let mut calldata = Vec::with_capacity(PROOF_CALLDATA_LEN);
+        for word in self.to_words() {
+            calldata.extend_from_slice(&word.to_be_bytes());
+        }
+        calldata
+    }
+
+    /// Decode the `bytes proof` produced by `to_solidity_calldata`
+    pub fn from_solidity_calldata(calldata: &[u8]) -> Result<Self, Groth16Error> {
+        if calldata.len() != PROOF_CALLDATA_LEN {
+            return Err(Groth16Error::InvalidCalldataLength(calldata.len()));
+        }
+
+        let mut words = [U256::ZERO; 8];
+        for (i, word) in words.iter_mut().enumerate() {
+            let mut buf = [0u8; 32];
+            buf.copy_from_slice(&calldata[i * 32..(i + 1) * 32]);
+            *word = U256::from_be_bytes(&buf);
+            if *word >= base_modulus() {
+                return Err(Groth16Error::CoordinateOutOfRange(word.to_dec_string()));
+            }
+        }
+
+        Ok(Self {
+            a: G1Point { x: words[0], y: words[1] },
+            b: G2Point::from_evm_order([[words[2], words[3]], [words[4], words[5]]]),
+            c: G1Point { x: words[6], y: words[7] },
+        })
+    }
+}
+
+/// Groth16 verification key over BN254
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerificationKey {
+    pub alpha: G1Point,
+    pub beta: G2Point,
+    pub gamma: G2Point,
+    pub delta: G2Point,
+    /// `IC[0]` plus one point per public input
+    pub ic: Vec<G1Point>,
+}
+
+/// Raw `verification_key.json` layout written by snarkjs
+#[derive(serde::Deserialize)]
+struct SnarkjsVerificationKeyJson {
+    #[serde(default = "default_protocol")]
+    protocol: String,
+    #[serde(rename = "nPublic")]
+    n_public: usize,
+    vk_alpha_1: Vec<String>,
+    vk_beta_2: Vec<Vec<String>>,
+    vk_gamma_2: Vec<Vec<String>>,
+    vk_delta_2: Vec<Vec<String>>,
+    #[serde(rename = "IC")]
+    ic: Vec<Vec<String>>,
+}
+
+impl VerificationKey {
+    /// Parse a snarkjs `verification_key.json`
+    pub fn from_snarkjs_json(json: &str) -> Result<Self, Groth16Error> {
+        let raw: SnarkjsVerificationKeyJson =
+            serde_json::from_str(json).map_err(|e| Groth16Error::InvalidJson(e.to_string()))?;
+
+        if raw.protocol != "groth16" {
+            return Err(Groth16Error::InvalidJson(format!("Unsupported protocol: {}", raw.protocol)));
+        }
+        if raw.ic.len() != raw.n_public + 1 {
+            return Err(Groth16Error::InvalidJson(format!(
+                "IC has {} points for {} public inputs",
+                raw.ic.len(),
+                raw.n_public
+            )));
+        }
+
+        Ok(Self {
+            alpha: G1Point::from_snarkjs(&raw.vk_alpha_1)?,
+            beta: G2Point::from_snarkjs(&raw.vk_beta_2)?,
+            gamma: G2Point::from_snarkjs(&raw.vk_gamma_2)?,
+            delta: G2Point::from_snarkjs(&raw.vk_delta_2)?,
+            ic: raw.ic.iter().map(|p| G1Point::from_snarkjs(p)).collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn num_public_inputs(&self) -> usize {
+        self.ic.len() - 1
+    }
+
+    /// Run the pairing check of the generated Solidity verifier
+    ///
+    /// Like the contract, a public input outside the scalar field or a point
+    /// the precompiles refuse makes the proof invalid rather than an error,
+    /// so the result is what `verifyProof` returns for the same arguments.
+    pub fn verify(&self, proof: &Groth16Proof, public_inputs: &[U256]) -> Result<bool, Groth16Error> {
+        if public_inputs.len() != self.num_public_inputs() {
+            return Err(Groth16Error::InputCountMismatch {
+                expected: self.num_public_inputs(),
+                actual: public_inputs.len(),
+            });
+        }
+
+        let Some(inputs) = public_inputs
+            .iter()
+            .map(|v| Fr::from_bigint(BigInt(v.limbs())))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(false);
+        };
+        let (Some(a), Some(b), Some(c)) = (proof.a.to_ark(), proof.b.to_ark(), proof.c.to_ark()) else {
+            return Ok(false);
+        };
+        let (Some(alpha), Some(beta), Some(gamma), Some(delta)) =
+            (self.alpha.to_ark(), self.beta.to_ark(), self.gamma.to_ark(), self.delta.to_ark())
+        else {
+            return Ok(false);
+        };
+        let Some(ic) = self.ic.iter().copied().map(G1Point::to_ark).collect::<Option<Vec<_>>>() else {
+            return Ok(false);
+        };
+
+        let vk_x = ic[1..]
+            .iter()
+            .zip(&inputs)
+            .fold(ic[0].into_group(), |acc, (point, input)| acc + *point * input);
+
+        // e(-A, B) * e(alpha, beta) * e(vk_x, gamma) * e(C, delta) == 1
+        let product = Bn254::multi_pairing([-a, alpha, vk_x.into_affine(), c], [b, beta, gamma, delta]);
+        Ok(product.is_zero())
+    }
+}
+
+/// Verification key in snarkjs form, as `Groth16VerifierSpec` renders it
+impl From<&ark_groth16::VerifyingKey<Bn254>> for VerificationKey {
+    fn from(vk: &ark_groth16::VerifyingKey<Bn254>) -> Self {
+        Self {
+            alpha: G1Point::from(&vk.alpha_g1),
+            beta: G2Point::from(&vk.beta_g2),
+            gamma: G2Point::from(&vk.gamma_g2),
+            delta: G2Point::from(&vk.delta_g2),
+            ic: vk.gamma_abc_g1.iter().map(G1Point::from).collect(),
+        }
+    }
+}
+
+impl From<&ark_groth16::Proof<Bn254>> for Groth16Proof {
+    fn from(proof: &ark_groth16::Proof<Bn254>) -> Self {
+        Self {
+            a: G1Point::from(&proof.a),
+            b: G2Point::from(&proof.b),
+            c: G1Point::from(&proof.c),
+        }
+    }
+}
+
+/// Parse a snarkjs `public.json` (array of decimal strings)
+pub fn parse_public_signals(json: &str) -> Result<Vec<U256>, Groth16Error> {
+    let raw: Vec<String> =
+        serde_json::from_str(json).map_err(|e| Groth16Error::InvalidJson(e.to_string()))?;
+
+    let r = scalar_modulus();
+    raw.iter()
+        .map(|s| {
+            let value = U256::parse(s)?;
+            if value >= r {
+                return Err(Groth16Error::SignalOutOfRange(s.clone()));
+            }
+            Ok(value)
+        })
+        .collect()
+}
+
+/// Write public signals in snarkjs `public.json` form
+pub fn public_signals_to_json(signals: &[U256]) -> String {
+    let raw: Vec<String> = signals.iter().map(|s| s.to_dec_string()).collect();
+    serde_json::to_string_pretty(&raw).expect("public signals serialization")
+}
+
+/// Reproduce the output of `snarkjs zkey export soliditycalldata`
+///
+/// The string can be pasted into a contract call (Remix, cast) as
+/// `pA, pB, pC, pubSignals`.
+pub fn solidity_calldata_string(proof: &Groth16Proof, signals: &[U256]) -> String {
+    let p256 = |v: &U256| format!("\"{}\"", v.to_hex());
+    let b = proof.b.to_evm_order();
+    let inputs: Vec<String> = signals.iter().map(p256).collect();
+
+    format!(
+        "[{}, {}],[[{}, {}],[{}, {}]],[{}, {}],[{}]",
+        p256(&proof.a.x), p256(&proof.a.y),
+        p256(&b[0][0]), p256(&b[0][1]), p256(&b[1][0]), p256(&b[1][1]),
+        p256(&proof.c.x), p256(&proof.c.y),
+        inputs.join(","),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROOF_JSON: &str = r#"{
+        "pi_a": ["1", "2", "1"],
+        "pi_b": [["3", "4"], ["5", "6"], ["1", "0"]],
+        "pi_c": ["7", "8", "1"],
+        "protocol": "groth16",
+        "curve": "bn128"
+    }"#;
+
+    #[test]
+    fn test_u256_decimal_roundtrip() {
+        let value = U256::from_dec_str(BN254_BASE_MODULUS).unwrap();
+        assert_eq!(value.to_dec_string(), BN254_BASE_MODULUS);
+        assert_eq!(
+            value.to_hex(),
+            "0x30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd47"
+        );
+        assert_eq!(U256::parse(&value.to_hex()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_u256_ordering() {
+        assert!(U256::from_limbs([0, 0, 0, 1]) > U256::from_limbs([u64::MAX, u64::MAX, u64::MAX, 0]));
+        assert!(U256::from_u64(2) > U256::ONE);
+    }
+
+    #[test]
+    fn test_u256_rejects_overflow() {
+        let too_big = "1".repeat(80);
+        assert!(U256::from_dec_str(&too_big).is_err());
+        assert!(U256::from_dec_str("12a").is_err());
+    }
+
+    #[test]
+    fn test_proof_json_roundtrip() {
+        let proof = Groth16Proof::from_snarkjs_json(PROOF_JSON).unwrap();
+        assert_eq!(proof.b.x, [U256::from_u64(3), U256::from_u64(4)]);
+
+        let json = proof.to_snarkjs_json();
+        assert_eq!(Groth16Proof::from_snarkjs_json(&json).unwrap(), proof);
+    }
+
+    #[test]
+    fn test_calldata_swaps_g2_coordinates() {
+        let proof = Groth16Proof::from_snarkjs_json(PROOF_JSON).unwrap();
+        let calldata = proof.to_solidity_calldata();
+        assert_eq!(calldata.len(), PROOF_CALLDATA_LEN);
+
+        let words: Vec<u8> = calldata.chunks(32).map(|w| w[31]).collect();
+        assert_eq!(words, vec![1, 2, 4, 3, 6, 5, 7, 8]);
+
+        assert_eq!(Groth16Proof::from_solidity_calldata(&calldata).unwrap(), proof);
+    }
+
+    #[test]
+    fn test_solidity_calldata_string() {
+        let proof = Groth16Proof::from_snarkjs_json(PROOF_JSON).unwrap();
+        let signals = parse_public_signals(r#"["9", "10"]"#).unwrap();
+        let s = solidity_calldata_string(&proof, &signals);
+
+        let w = |n: u64| format!("\"{}\"", U256::from_u64(n).to_hex());
+        let expected = format!(
+            "[{}, {}],[[{}, {}],[{}, {}]],[{}, {}],[{},{}]",
+            w(1), w(2), w(4), w(3), w(6), w(5), w(7), w(8), w(9), w(10)
+        );
+        assert_eq!(s, expected);
+    }
+
+    #[test]
+    fn test_rejects_out_of_range_values() {
+        let json = PROOF_JSON.replace("\"7\"", &format!("\"{}\"", BN254_BASE_MODULUS));
+        assert!(matches!(
+            Groth16Proof::from_snarkjs_json(&json),
+            Err(Groth16Error::CoordinateOutOfRange(_))
+        ));
+
+        let signals = format!("[\"{}\"]", BN254_SCALAR_MODULUS);
+        assert!(matches!(
+            parse_public_signals(&signals),
+            Err(Groth16Error::SignalOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_range_checks_compare_high_limb_first() {
+        // Low limbs below the moduli's, high limb above: only a most
+        // significant first comparison rejects these
+        let high = U256::from_limbs([0, 0, 0, u64::MAX]);
+
+        let json = PROOF_JSON.replace("\"7\"", &format!("\"{}\"", high.to_dec_string()));
+        assert!(matches!(
+            Groth16Proof::from_snarkjs_json(&json),
+            Err(Groth16Error::CoordinateOutOfRange(_))
+        ));
+
+        let mut calldata = Groth16Proof::from_snarkjs_json(PROOF_JSON).unwrap().to_solidity_calldata();
+        calldata[..32].copy_from_slice(&high.to_be_bytes());
+        assert!(matches!(
+            Groth16Proof::from_solidity_calldata(&calldata),
+            Err(Groth16Error::CoordinateOutOfRange(_))
+        ));
+
+        let signals = format!("[\"{}\"]", high.to_dec_string());
+        assert!(matches!(
+            parse_public_signals(&signals),
+            Err(Groth16Error::SignalOutOfRange(_))
+        ));
+
+        // And the largest in-range values are still accepted
+        let r_minus_one = U256::from_limbs({
+            let mut limbs = scalar_modulus().limbs();
+            limbs[0] -= 1;
+            limbs
+        });
+        let signals = format!("[\"{}\"]", r_minus_one.to_dec_string());
+        assert_eq!(parse_public_signals(&signals).unwrap(), vec![r_minus_one]);
+    }
+
+    #[test]
+    fn test_verification_key_json() {
+        let vk = VerificationKey::from_snarkjs_json(include_str!("../circom/build/verification_key.json")).unwrap();
+        assert_eq!(vk.num_public_inputs(), 4);
+        assert_eq!(
+            vk.beta.to_evm_order()[0][0].to_dec_string(),
+            "4252822878758300859123897981450591353533073413197771768651442665752259397132"
+        );
+
+        let truncated = r#"{"protocol": "groth16", "nPublic": 2, "vk_alpha_1": ["1", "2", "1"],
+            "vk_beta_2": [["1", "2"], ["3", "4"]], "vk_gamma_2": [["1", "2"], ["3", "4"]],
+            "vk_delta_2": [["1", "2"], ["3", "4"]], "IC": [["1", "2"]]}"#;
+        assert!(matches!(
+            VerificationKey::from_snarkjs_json(truncated),
+            Err(Groth16Error::InvalidJson(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_projective_points() {
+        let json = PROOF_JSON.replace("[\"7\", \"8\", \"1\"]", "[\"7\", \"8\", \"2\"]");
+        assert_eq!(Groth16Proof::from_snarkjs_json(&json), Err(Groth16Error::NotAffine));
+    }
+}
@@ -0,0 +1,373 @@
+//! Keccak-256 Merkle membership circuit
+//!
+//! Proves that a public leaf is in the `keccak_merkle` tree with a public
+//! root. The path stays private.
+//!
+//! The trace stacks one Keccak-f[1600] permutation per tree level, laid out
+//! by p3-keccak-air (`NUM_ROUNDS` rows each), followed by padding
+//! permutations. Each real permutation hashes the 64-byte message
+//! `left || right`, so its preimage is fixed apart from the first eight
+//! lanes: lane 8 holds the `0x01` pad byte, lane 16 the final `0x80`, and
+//! all other lanes are zero. Digests enter the trace as 16 limbs of 16 bits,
+//! little-endian, which is also how they appear in the public values.
+//!
+//! Columns after the Keccak columns wire the permutations into a path:
+//!
+//! - `IS_RIGHT_COL`: the path index of the row's level, constant over the
+//!   permutation
+//! - `LEVEL_COL..`: one-hot level of the row's permutation, shifted along
+//!   at every last round row; the first padding permutation carries level
+//!   TREE_DEPTH and later ones none
+//! - `LINK_COL`: set on the last round row of levels `0..TREE_DEPTH - 1`,
+//!   where the output must reappear in the next preimage
+//!
+//! `LINK_COL` exists only to keep the linking constraint at degree 3, the
+//! degree of the Keccak AIR itself, so the circuit proves with
+//! `log_blowup = 1`.
+//!
+//! Public values: the leaf limbs, then the root limbs.
+
+use std::borrow::Borrow;
+
+use p3_air::{Air, AirBuilder, AirBuilderWithPublicValues, BaseAir};
+use p3_baby_bear::BabyBear;
+use p3_field::PrimeCharacteristicRing;
+use p3_keccak_air::{
+    generate_trace_rows, KeccakAir, KeccakCols, NUM_KECCAK_COLS, NUM_ROUNDS, U64_LIMBS,
+};
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::horizontally_truncated::HorizontallyTruncated;
+use p3_matrix::Matrix;
+
+use crate::balance_withdrawal::WitnessError;
+use crate::keccak_merkle::{compute_merkle_root, hash_pair, Digest};
+use crate::merkle::TREE_DEPTH;
+
+type Val = BabyBear;
+
+/// Limbs per digest
+pub const DIGEST_LIMBS: usize = 16;
+
+/// Bits per limb
+const LIMB_BITS: usize = 16;
+
+/// Number of public values: leaf and root limbs
+pub const NUM_PUBLIC_VALUES: usize = 2 * DIGEST_LIMBS;
+
+pub const IS_RIGHT_COL: usize = NUM_KECCAK_COLS;
+/// First level selector column
+pub const LEVEL_COL: usize = IS_RIGHT_COL + 1;
+pub const LINK_COL: usize = LEVEL_COL + TREE_DEPTH + 1;
+/// Number of columns
+pub const NUM_COLS: usize = LINK_COL + 1;
+
+/// Lanes of a digest
+const DIGEST_LANES: usize = 4;
+
+/// Split a digest into 16-bit limbs, little-endian
+pub fn digest_to_limbs(digest: &Digest) -> [u16; DIGEST_LIMBS] {
+    core::array::from_fn(|i| u16::from_le_bytes([digest[2 * i], digest[2 * i + 1]]))
+}
+
+/// Inverse of `digest_to_limbs`
+pub fn limbs_to_digest(limbs: &[u16; DIGEST_LIMBS]) -> Digest {
+    let mut digest = [0u8; 32];
+    for (i, limb) in limbs.iter().enumerate() {
+        digest[2 * i..2 * i + 2].copy_from_slice(&limb.to_le_bytes());
+    }
+    digest
+}
+
+/// Lanes of a digest as Keccak reads them
+fn digest_lanes(digest: &Digest) -> [u64; DIGEST_LANES] {
+    core::array::from_fn(|i| u64::from_le_bytes(digest[8 * i..8 * i + 8].try_into().unwrap()))
+}
+
+/// Padded Keccak-256 state for the message `left || right`
+fn pair_state(left: &Digest, right: &Digest) -> [u64; 25] {
+    let mut state = [0u64; 25];
+    state[..DIGEST_LANES].copy_from_slice(&digest_lanes(left));
+    state[DIGEST_LANES..2 * DIGEST_LANES].copy_from_slice(&digest_lanes(right));
+    state[2 * DIGEST_LANES] = 0x01;
+    state[16] = 0x80 << 56;
+    state
+}
+
+/// Reorder a state for `generate_trace_rows`, which reads lane `(x, y)` at
+/// index `5x + y` rather than the `x + 5y` of the Keccak spec
+fn generator_order(state: &[u64; 25]) -> [u64; 25] {
+    core::array::from_fn(|i| state[i / 5 + 5 * (i % 5)])
+}
+
+/// 16-bit limb `limb` of lane `lane` in the padded preimage of a pair
+fn padding_limb(lane: usize, limb: usize) -> u16 {
+    let value = pair_state(&[0u8; 32], &[0u8; 32])[lane];
+    (value >> (LIMB_BITS * limb)) as u16
+}
+
+/// Keccak membership circuit: public leaf and root
+pub struct KeccakMembershipCircuit {
+    pub leaf: Digest,
+    pub root: Digest,
+}
+
+/// Witness: the authentication path
+pub struct KeccakMembershipWitness {
+    pub merkle_path: [Digest; TREE_DEPTH],
+    /// `true` when the current node is the right child
+    pub path_indices: [bool; TREE_DEPTH],
+}
+
+impl KeccakMembershipCircuit {
+    pub fn new(leaf: Digest, root: Digest) -> Self {
+        Self { leaf, root }
+    }
+
+    /// Public values: leaf limbs, then root limbs
+    pub fn public_values(&self) -> Vec<Val> {
+        digest_to_limbs(&self.leaf)
+            .into_iter()
+            .chain(digest_to_limbs(&self.root))
+            .map(|limb| Val::from_u32(limb as u32))
+            .collect()
+    }
+
+    /// Check that the witness opens `leaf` to `root`
+    pub fn check_witness(&self, witness: &KeccakMembershipWitness) -> Result<(), WitnessError> {
+        if compute_merkle_root(&self.leaf, &witness.merkle_path, &witness.path_indices) != self.root {
+            return Err(WitnessError::InvalidMerkleProof);
+        }
+        Ok(())
+    }
+
+    /// Generate the trace for proving
+    pub fn generate_trace(&self, witness: &KeccakMembershipWitness) -> RowMajorMatrix<Val> {
+        let mut inputs = Vec::with_capacity(TREE_DEPTH);
+        let mut current = self.leaf;
+        for (sibling, &is_right) in witness.merkle_path.iter().zip(&witness.path_indices) {
+            let (left, right) = if is_right { (sibling, &current) } else { (&current, sibling) };
+            inputs.push(generator_order(&pair_state(left, right)));
+            current = hash_pair(left, right);
+        }
+
+        let keccak = generate_trace_rows::<Val>(inputs, 0);
+        let height = keccak.height();
+        let mut values = Vec::with_capacity(height * NUM_COLS);
+        for (row, keccak_row) in keccak.values.chunks(NUM_KECCAK_COLS).enumerate() {
+            let perm = row / NUM_ROUNDS;
+            values.extend_from_slice(keccak_row);
+            values.push(Val::from_bool(perm < TREE_DEPTH && witness.path_indices[perm]));
+            values.extend((0..=TREE_DEPTH).map(|level| Val::from_bool(level == perm)));
+            let last_round = row % NUM_ROUNDS == NUM_ROUNDS - 1;
+            values.push(Val::from_bool(last_round && perm + 1 < TREE_DEPTH));
+        }
+
+        RowMajorMatrix::new(values, NUM_COLS)
+    }
+}
+
+/// `builder` restricted to the Keccak columns, which lead every row
+struct KeccakColumns<'a, AB> {
+    inner: &'a mut AB,
+}
+
+impl<AB: AirBuilder> AirBuilder for KeccakColumns<'_, AB> {
+    type F = AB::F;
+    type Expr = AB::Expr;
+    type Var = AB::Var;
+    type M = HorizontallyTruncated<AB::Var, AB::M>;
+
+    fn main(&self) -> Self::M {
+        HorizontallyTruncated::new(self.inner.main(), NUM_KECCAK_COLS)
+            .expect("trace narrower than the Keccak columns")
+    }
+
+    fn is_first_row(&self) -> Self::Expr {
+        self.inner.is_first_row()
+    }
+
+    fn is_last_row(&self) -> Self::Expr {
+        self.inner.is_last_row()
+    }
+
+    fn is_transition_window(&self, size: usize) -> Self::Expr {
+        self.inner.is_transition_window(size)
+    }
+
+    fn assert_zero<I: Into<Self::Expr>>(&mut self, x: I) {
+        self.inner.assert_zero(x);
+    }
+}
+
+impl BaseAir<Val> for KeccakMembershipCircuit {
+    fn width(&self) -> usize {
+        NUM_COLS
+    }
+}
+
+impl<AB: AirBuilderWithPublicValues<F = Val>> Air<AB> for KeccakMembershipCircuit {
+    fn eval(&self, builder: &mut AB) {
+        KeccakAir {}.eval(&mut KeccakColumns { inner: builder });
+
+        let main = builder.main();
+        let local = main.row_slice(0).expect("The matrix is empty?");
+        let next = main.row_slice(1).expect("The matrix has only one row?");
+        let (local, next): (&[AB::Var], &[AB::Var]) = (&local, &next);
+        let public_values = builder.public_values().to_vec();
+        let (leaf, root) = public_values.split_at(DIGEST_LIMBS);
+
+        let local_keccak: &KeccakCols<AB::Var> = local[..NUM_KECCAK_COLS].borrow();
+        let next_keccak: &KeccakCols<AB::Var> = next[..NUM_KECCAK_COLS].borrow();
+        let last_round: AB::Expr = local_keccak.step_flags[NUM_ROUNDS - 1].into();
+
+        // Digest limb `i` of the preimage halves and of the output
+        let limb = |i: usize| (i / U64_LIMBS, i % U64_LIMBS);
+        let left = |cols: &KeccakCols<AB::Var>, i: usize| -> AB::Expr {
+            let (lane, l) = limb(i);
+            cols.preimage[0][lane][l].into()
+        };
+        let right = |cols: &KeccakCols<AB::Var>, i: usize| -> AB::Expr {
+            let (lane, l) = limb(i);
+            let lane = lane + DIGEST_LANES;
+            cols.preimage[lane / 5][lane % 5][l].into()
+        };
+        let output = |cols: &KeccakCols<AB::Var>, i: usize| -> AB::Expr {
+            let (lane, l) = limb(i);
+            cols.a_prime_prime_prime(0, lane, l).into()
+        };
+        // The half of the preimage holding the current node
+        let current = |cols: &KeccakCols<AB::Var>, is_right: AB::Expr, i: usize| {
+            let l = left(cols, i);
+            l.clone() + is_right * (right(cols, i) - l)
+        };
+
+        let is_right: AB::Expr = local[IS_RIGHT_COL].into();
+        let next_is_right: AB::Expr = next[IS_RIGHT_COL].into();
+        let level = |row: &[AB::Var], j: usize| -> AB::Expr { row[LEVEL_COL + j].into() };
+        let real: AB::Expr = (0..TREE_DEPTH).map(|j| level(local, j)).sum();
+        let linked: AB::Expr = (0..TREE_DEPTH - 1).map(|j| level(local, j)).sum();
+
+        builder.assert_bool(is_right.clone());
+        builder.assert_eq(local[LINK_COL], last_round.clone() * linked);
+
+        // Real levels hash a padded 64-byte message
+        for lane in 2 * DIGEST_LANES..25 {
+            for l in 0..U64_LIMBS {
+                let expected = AB::Expr::from(Val::from_u32(padding_limb(lane, l) as u32));
+                builder
+                    .when(real.clone())
+                    .assert_eq(local_keccak.preimage[lane / 5][lane % 5][l], expected);
+            }
+        }
+
+        // Level one-hot starts at 0 and shifts after every last round
+        let mut when_first_row = builder.when_first_row();
+        for j in 0..=TREE_DEPTH {
+            let expected = if j == 0 { AB::Expr::ONE } else { AB::Expr::ZERO };
+            when_first_row.assert_eq(local[LEVEL_COL + j], expected);
+        }
+        for (i, &leaf_limb) in leaf.iter().enumerate() {
+            when_first_row.assert_eq(current(local_keccak, is_right.clone(), i), leaf_limb);
+        }
+
+        let mut when_transition = builder.when_transition();
+        let stay = AB::Expr::ONE - last_round.clone();
+        for j in 0..=TREE_DEPTH {
+            let shifted_in = if j == 0 { AB::Expr::ZERO } else { level(local, j - 1) * last_round.clone() };
+            when_transition.assert_eq(level(next, j), level(local, j) * stay.clone() + shifted_in);
+        }
+        when_transition
+            .when(stay)
+            .assert_eq(next_is_right.clone(), is_right.clone());
+
+        // Each level's output is the current node of the next level
+        let link: AB::Expr = local[LINK_COL].into();
+        for i in 0..DIGEST_LIMBS {
+            when_transition.when(link.clone()).assert_eq(
+                current(next_keccak, next_is_right.clone(), i),
+                output(local_keccak, i),
+            );
+        }
+
+        // The last level's output is the root
+        let is_root = last_round * level(local, TREE_DEPTH - 1);
+        for (i, &root_limb) in root.iter().enumerate() {
+            builder.when(is_root.clone()).assert_eq(output(local_keccak, i), root_limb);
+        }
+    }
+}
+
+/// Proof data structure for serialization
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct KeccakMembershipProof {
+    /// Serialized proof bytes
+    pub proof_bytes: Vec<u8>,
+    pub leaf: Digest,
+    pub root: Digest,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::keccak256;
+    use crate::balance_withdrawal::WitnessError;
+    use crate::keccak_merkle::KeccakMerkleTree;
+
+    fn sample() -> (KeccakMembershipCircuit, KeccakMembershipWitness) {
+        let leaves: Vec<Digest> = (0u8..5).map(|i| keccak256(&[i])).collect();
+        let tree = KeccakMerkleTree::new(leaves.clone()).unwrap();
+        let (merkle_path, path_indices) = tree.get_proof(3).unwrap();
+        (
+            KeccakMembershipCircuit::new(leaves[3], tree.root()),
+            KeccakMembershipWitness { merkle_path, path_indices },
+        )
+    }
+
+    #[test]
+    fn test_limb_roundtrip() {
+        let digest = keccak256(b"noctis");
+        assert_eq!(limbs_to_digest(&digest_to_limbs(&digest)), digest);
+        assert_eq!(digest_to_limbs(&digest)[0], u16::from_le_bytes([digest[0], digest[1]]));
+    }
+
+    #[test]
+    fn test_padding_limbs() {
+        assert_eq!(padding_limb(8, 0), 0x0001);
+        assert_eq!(padding_limb(16, 3), 0x8000);
+        assert_eq!(padding_limb(16, 0), 0);
+        assert_eq!(padding_limb(24, 2), 0);
+    }
+
+    #[test]
+    fn test_trace_layout() {
+        let (circuit, witness) = sample();
+        circuit.check_witness(&witness).unwrap();
+
+        let trace = circuit.generate_trace(&witness);
+        assert_eq!(trace.width(), NUM_COLS);
+        assert_eq!(trace.height(), (TREE_DEPTH * NUM_ROUNDS).next_power_of_two());
+
+        let row = |r: usize| &trace.values[r * NUM_COLS..(r + 1) * NUM_COLS];
+
+        // Leaf 3 is a right child at level 0, a left child at level 2
+        assert_eq!(row(0)[IS_RIGHT_COL], Val::ONE);
+        assert_eq!(row(2 * NUM_ROUNDS)[IS_RIGHT_COL], Val::ZERO);
+
+        // Level selectors and links
+        assert_eq!(row(NUM_ROUNDS - 1)[LEVEL_COL], Val::ONE);
+        assert_eq!(row(NUM_ROUNDS - 1)[LINK_COL], Val::ONE);
+        assert_eq!(row(NUM_ROUNDS)[LEVEL_COL + 1], Val::ONE);
+        assert_eq!(row(TREE_DEPTH * NUM_ROUNDS - 1)[LINK_COL], Val::ZERO);
+        assert_eq!(row(TREE_DEPTH * NUM_ROUNDS)[LEVEL_COL + TREE_DEPTH], Val::ONE);
+    }
+
+    #[test]
+    fn test_wrong_root_fails_witness_check() {
+        let (mut circuit, witness) = sample();
+        circuit.root[0] ^= 1;
+        assert!(matches!(
+            circuit.check_witness(&witness),
+            Err(WitnessError::InvalidMerkleProof)
+        ));
+    }
+}
@@ -0,0 +1,179 @@
+//! Keccak-256 Merkle tree
+//!
+//! Nodes are 32-byte digests and inner nodes are `keccak256(left || right)`,
+//! i.e. `keccak256(abi.encodePacked(left, right))` over `bytes32` in
+//! Solidity. Like the on-chain incremental trees, the tree always has
+//! TREE_DEPTH levels, is filled left to right, and every unfilled slot is
+//! an empty subtree rooted at `zero_hashes()[level]`.
+//!
+//! `path_indices[i] = true` means the current node is the RIGHT child at
+//! level `i`, the convention `filled_tree_proof` and the withdrawal
+//! circuits use.
+
+use crate::abi::keccak256;
+use crate::merkle::TREE_DEPTH;
+
+/// A Keccak-256 digest
+pub type Digest = [u8; 32];
+
+/// `keccak256(left || right)`
+pub fn hash_pair(left: &Digest, right: &Digest) -> Digest {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left);
+    data[32..].copy_from_slice(right);
+    keccak256(&data)
+}
+
+/// Compute the Merkle root from a leaf and its path
+pub fn compute_merkle_root(
+    leaf: &Digest,
+    path: &[Digest; TREE_DEPTH],
+    path_indices: &[bool; TREE_DEPTH],
+) -> Digest {
+    let mut current = *leaf;
+    for (sibling, &is_right) in path.iter().zip(path_indices) {
+        current = if is_right {
+            hash_pair(sibling, &current)
+        } else {
+            hash_pair(&current, sibling)
+        };
+    }
+    current
+}
+
+/// Verify a Merkle proof against `root`
+pub fn verify_merkle_proof(
+    leaf: &Digest,
+    path: &[Digest; TREE_DEPTH],
+    path_indices: &[bool; TREE_DEPTH],
+    root: &Digest,
+) -> bool {
+    compute_merkle_root(leaf, path, path_indices) == *root
+}
+
+/// Roots of empty subtrees: `zeros[0] = 0`, `zeros[i + 1] = hash(zeros[i], zeros[i])`
+pub fn zero_hashes() -> [Digest; TREE_DEPTH + 1] {
+    let mut zeros = [[0u8; 32]; TREE_DEPTH + 1];
+    for i in 0..TREE_DEPTH {
+        zeros[i + 1] = hash_pair(&zeros[i], &zeros[i]);
+    }
+    zeros
+}
+
+/// Keccak-256 Merkle tree over a left-filled prefix of leaves
+pub struct KeccakMerkleTree {
+    /// `layers[0]` holds the leaves, `layers[TREE_DEPTH]` the root. Each
+    /// layer stores only its filled prefix.
+    layers: Vec<Vec<Digest>>,
+}
+
+impl KeccakMerkleTree {
+    /// Build the tree, or `None` if there are more than `2^TREE_DEPTH` leaves
+    pub fn new(leaves: Vec<Digest>) -> Option<Self> {
+        if leaves.len() > 1 << TREE_DEPTH {
+            return None;
+        }
+
+        let zeros = zero_hashes();
+        let mut layers = Vec::with_capacity(TREE_DEPTH + 1);
+        layers.push(leaves);
+        for level in 0..TREE_DEPTH {
+            let next = layers[level]
+                .chunks(2)
+                .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&zeros[level])))
+                .collect();
+            layers.push(next);
+        }
+        Some(Self { layers })
+    }
+
+    /// Number of leaves
+    pub fn len(&self) -> usize {
+        self.layers[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers[0].is_empty()
+    }
+
+    /// Root of the tree; the empty tree has root `zero_hashes()[TREE_DEPTH]`
+    pub fn root(&self) -> Digest {
+        self.layers[TREE_DEPTH]
+            .first()
+            .copied()
+            .unwrap_or_else(|| zero_hashes()[TREE_DEPTH])
+    }
+
+    /// Merkle proof for the leaf at `index`
+    pub fn get_proof(&self, index: usize) -> Option<([Digest; TREE_DEPTH], [bool; TREE_DEPTH])> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let zeros = zero_hashes();
+        let mut path = [[0u8; 32]; TREE_DEPTH];
+        let mut path_indices = [false; TREE_DEPTH];
+        let mut current_index = index;
+
+        for level in 0..TREE_DEPTH {
+            let is_right = current_index % 2 == 1;
+            let sibling_index = if is_right { current_index - 1 } else { current_index + 1 };
+
+            path_indices[level] = is_right;
+            path[level] = self.layers[level].get(sibling_index).copied().unwrap_or(zeros[level]);
+            current_index /= 2;
+        }
+
+        Some((path, path_indices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(i: u8) -> Digest {
+        keccak256(&[i])
+    }
+
+    #[test]
+    fn test_hash_pair_is_packed_keccak() {
+        // keccak256(abi.encodePacked(bytes32(0), bytes32(0)))
+        assert_eq!(
+            hex::encode(hash_pair(&[0u8; 32], &[0u8; 32])),
+            "ad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5"
+        );
+    }
+
+    #[test]
+    fn test_empty_tree_root() {
+        let tree = KeccakMerkleTree::new(vec![]).unwrap();
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), zero_hashes()[TREE_DEPTH]);
+    }
+
+    #[test]
+    fn test_every_leaf_proves_against_root() {
+        let leaves: Vec<Digest> = (0..5).map(leaf).collect();
+        let tree = KeccakMerkleTree::new(leaves.clone()).unwrap();
+        let root = tree.root();
+
+        for (i, l) in leaves.iter().enumerate() {
+            let (path, indices) = tree.get_proof(i).unwrap();
+            assert!(verify_merkle_proof(l, &path, &indices, &root));
+        }
+        assert!(tree.get_proof(5).is_none());
+
+        // Leaf 4 is a left child whose sibling slot is empty
+        let (path, indices) = tree.get_proof(4).unwrap();
+        assert!(!indices[0]);
+        assert_eq!(path[0], zero_hashes()[0]);
+    }
+
+    #[test]
+    fn test_wrong_leaf_fails() {
+        let tree = KeccakMerkleTree::new((0..3).map(leaf).collect()).unwrap();
+        let (path, indices) = tree.get_proof(1).unwrap();
+        assert!(!verify_merkle_proof(&leaf(9), &path, &indices, &tree.root()));
+    }
+}
@@ -0,0 +1,193 @@
+//! Note key hierarchy over BN254
+//!
+//! A wallet's keys are derived from a master seed, after Zcash Sapling:
+//!
+//! - ask = Poseidon(seed, ASK_TAG): spend authorizing key
+//! - nk = Poseidon(seed, NK_TAG): nullifier deriving key
+//! - ak = Poseidon(ask, 0): spend validating key
+//! - ivk = Poseidon(IVK_TAG, ak, nk): incoming viewing key
+//! - spendingKeyHash = Poseidon(ivk, 0): owner tag committed to in notes
+//!
+//! Nullifiers are Poseidon(domain, nk, noteIndex), so the full viewing key
+//! (ak, nk) detects spends and the incoming viewing key recognizes notes sent
+//! to the owner tag, but only a holder of ask can prove a withdrawal: the
+//! R1CS circuit opens the owner tag from ask and nk.
+//!
+//! The STARK circuits' BabyBear notes have a single spending key, owner tag
+//! hash(spendingKey). Their nullifier key is split off the same way,
+//! nk = hash(spendingKey, NK_TAG) (`nullifier_key`), and nullifiers are
+//! hash(domain, nk, noteIndex): each circuit derives both nk and the owner
+//! tag from the spending key, so a wallet given nk and the owner tag can
+//! watch spends but not make them. Their commitments mix in the same domain
+//! (`note_commitment`), the vault's DOMAIN reduced into the field
+//! (`poseidon::domain_tag`).
+
+use p3_field::Field;
+
+use crate::poseidon::{poseidon_hash_2, poseidon_hash_3, poseidon_hash_slice};
+use crate::poseidon_bn254::{compute_domain_nullifier, hash_3, hash_pair, Bn254Field};
+
+/// Derivation tags, distinct so no two keys of one seed coincide
+pub const ASK_TAG: u64 = 1;
+pub const NK_TAG: u64 = 2;
+pub const IVK_TAG: u64 = 3;
+
+/// Keys that can spend: the spend authorizing and nullifier deriving keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpendingKey {
+    pub ask: Bn254Field,
+    pub nk: Bn254Field,
+}
+
+/// Watch-only keys: can detect incoming notes and their spends
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FullViewingKey {
+    pub ak: Bn254Field,
+    pub nk: Bn254Field,
+}
+
+/// Can recognize notes sent to the owner tag, but not their spends
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IncomingViewingKey(pub Bn254Field);
+
+impl SpendingKey {
+    pub fn from_seed(seed: Bn254Field) -> Self {
+        Self {
+            ask: hash_pair(seed, Bn254Field::new(ASK_TAG)),
+            nk: hash_pair(seed, Bn254Field::new(NK_TAG)),
+        }
+    }
+
+    pub fn full_viewing_key(&self) -> FullViewingKey {
+        FullViewingKey { ak: hash_pair(self.ask, Bn254Field::ZERO), nk: self.nk }
+    }
+
+    pub fn spending_key_hash(&self) -> Bn254Field {
+        self.full_viewing_key().spending_key_hash()
+    }
+}
+
+impl FullViewingKey {
+    pub fn incoming_viewing_key(&self) -> IncomingViewingKey {
+        IncomingViewingKey(hash_3(Bn254Field::new(IVK_TAG), self.ak, self.nk))
+    }
+
+    pub fn spending_key_hash(&self) -> Bn254Field {
+        self.incoming_viewing_key().spending_key_hash()
+    }
+
+    /// Nullifier of the note at `note_index` in the deployment with `domain`
+    pub fn nullifier(&self, domain: Bn254Field, note_index: Bn254Field) -> Bn254Field {
+        compute_domain_nullifier(domain, self.nk, note_index)
+    }
+}
+
+impl IncomingViewingKey {
+    /// Owner tag that notes to this wallet commit to
+    pub fn spending_key_hash(&self) -> Bn254Field {
+        hash_pair(self.0, Bn254Field::ZERO)
+    }
+}
+
+/// Nullifier deriving key of a STARK note's spending key
+pub fn nullifier_key<F: Field>(spending_key: F) -> F {
+    poseidon_hash_2(spending_key, F::from_u64(NK_TAG))
+}
+
+/// Nullifier of the STARK note at `note_index` in the deployment with
+/// `domain`: hash(domain, nk, note_index)
+pub fn note_nullifier<F: Field>(domain: F, nk: F, note_index: u64) -> F {
+    poseidon_hash_3(domain, nk, F::from_u64(note_index))
+}
+
+/// Commitment to a STARK note in the deployment with `domain`:
+/// hash(domain, spending_key_hash, asset_id, balance, randomness)
+pub fn note_commitment<F: Field>(domain: F, spending_key_hash: F, asset_id: F, balance: F, randomness: F) -> F {
+    poseidon_hash_slice(&[domain, spending_key_hash, asset_id, balance, randomness])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poseidon::{domain_tag, poseidon_hash};
+    use crate::poseidon_bn254::{address_to_field, compute_domain, compute_domain_commitment};
+    use p3_baby_bear::BabyBear;
+
+    #[test]
+    fn test_keys_distinct() {
+        let sk = SpendingKey::from_seed(Bn254Field::new(0x5eed));
+        let fvk = sk.full_viewing_key();
+        let ivk = fvk.incoming_viewing_key();
+        let keys = [sk.ask, sk.nk, fvk.ak, ivk.0, sk.spending_key_hash()];
+        for (i, a) in keys.iter().enumerate() {
+            for b in &keys[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        assert_ne!(SpendingKey::from_seed(Bn254Field::new(0x5eee)), sk);
+    }
+
+    #[test]
+    fn test_viewing_keys_agree() {
+        let sk = SpendingKey::from_seed(Bn254Field::new(0x5eed));
+        let fvk = sk.full_viewing_key();
+        assert_eq!(fvk.spending_key_hash(), sk.spending_key_hash());
+        assert_eq!(fvk.incoming_viewing_key().spending_key_hash(), sk.spending_key_hash());
+
+        // A watch-only wallet derives the same nullifier the spender reveals
+        let domain = compute_domain(8453, &[0x44; 20]);
+        let index = Bn254Field::new(45);
+        assert_eq!(fvk.nullifier(domain, index), compute_domain_nullifier(domain, sk.nk, index));
+        assert_ne!(fvk.nullifier(domain, index), compute_domain_nullifier(domain, sk.ask, index));
+    }
+
+    #[test]
+    fn test_viewing_key_cannot_nullify() {
+        // The incoming viewing key and the owner tag recognize a note, but
+        // standing in for nk they do not give its nullifier
+        let sk = SpendingKey::from_seed(Bn254Field::new(0x5eed));
+        let fvk = sk.full_viewing_key();
+        let domain = compute_domain(8453, &[0x44; 20]);
+        let index = Bn254Field::new(45);
+        let nullifier = fvk.nullifier(domain, index);
+        for key in [fvk.incoming_viewing_key().0, sk.spending_key_hash(), fvk.ak] {
+            assert_ne!(compute_domain_nullifier(domain, key, index), nullifier);
+        }
+
+        // Likewise for STARK notes, whose owner tag is hash(spending_key)
+        let spending_key = BabyBear::new(12345);
+        let domain = domain_tag(8453, &[0x44; 20]);
+        let nullifier = note_nullifier(domain, nullifier_key(spending_key), 45);
+        assert_ne!(note_nullifier(domain, poseidon_hash(spending_key), 45), nullifier);
+        assert_ne!(note_nullifier(domain, spending_key, 45), nullifier);
+    }
+
+    #[test]
+    fn test_sdk_note_vectors() {
+        // sdk/test-sdk.js checks computeCommitment and computeNullifier
+        // against these values, the note of test/BalanceVaultV4Groth16.t.sol
+        let domain = compute_domain(31337, &[0x44; 20]);
+        assert_eq!(
+            domain.to_decimal_string(),
+            "14662978245061976630882248176688412036630854214599193360401297926632920049201"
+        );
+        let sk = SpendingKey::from_seed(Bn254Field::new(0x5eed));
+        let commitment = compute_domain_commitment(
+            domain,
+            sk.spending_key_hash(),
+            address_to_field(&[0x77; 20]),
+            Bn254Field::new(1000),
+            Bn254Field::new(0xabcdef),
+        );
+        assert_eq!(
+            commitment.to_decimal_string(),
+            "17692367850651603564815588957784872272511635620798776289052078333477056220622"
+        );
+
+        // and computeNullifier of the note at index 45
+        assert_eq!(
+            sk.full_viewing_key().nullifier(domain, Bn254Field::new(45)).to_decimal_string(),
+            "84676919971569883818273205275550777194632016565442236224169311278813426698"
+        );
+    }
+}
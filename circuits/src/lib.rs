@@ -2,23 +2,54 @@
 //!
 //! This library implements the ZK circuits for the Noctis privacy vault.
 //!
-//! Two circuit types:
+//! Five circuit types:
 //! 1. WithdrawalCircuit - Original Tornado-style fixed denomination
 //! 2. BalanceWithdrawalCircuit - Flexible amounts with range proofs
+//! 3. TransferCircuit - 2-in-2-out join-split for in-pool transfers
+//! 4. MergeCircuit - Consolidates up to 8 notes of one owner
+//! 5. DepositCircuit - Binds a deposit commitment to the deposited amount
 //!
 //! The balance-based circuit proves:
 //! 1. Knowledge of spending_key for a note in the Merkle tree
 //! 2. Note balance >= withdrawal amount (range proof)
 //! 3. Correct change commitment derivation
 //! 4. Valid nullifier to prevent double-spend
+//! 5. Optionally, membership in a published association set (association module)
+//! 6. Optionally, absence from a blocklist (blocklist module)
 //!
 //! V4 uses BN254 Poseidon for EVM compatibility (poseidon_bn254 module)
+//! Groth16 proofs from the circom circuit are encoded by the groth16 module
+//! The same withdrawal statement, with a sound amount range check, is
+//! available as a native arkworks circuit in the r1cs module, with the
+//! blocklist as a BN254 sparse Merkle tree
+//! Notes of the r1cs circuit are owned through the key hierarchy of the keys
+//! module, which separates spend authority from spend detection
 
 pub mod poseidon;
+pub mod poseidon_air;
 pub mod poseidon_bn254;
+pub mod keys;
 pub mod merkle;
+pub mod association;
+pub mod blocklist;
+pub mod keccak_merkle;
+pub mod keccak_membership;
 pub mod withdrawal;
 pub mod balance_withdrawal;
+pub mod transfer;
+pub mod merge;
+pub mod deposit;
+pub mod groth16;
+pub mod abi;
+pub mod address;
+pub mod note;
+pub mod prover;
+pub mod goldilocks;
+pub mod verifier;
+pub mod batch;
+pub mod security;
+pub mod codegen;
+pub mod r1cs;
 
 #[cfg(target_arch = "wasm32")]
 pub mod wasm;
@@ -28,3 +59,7 @@ pub mod wasm_bn254;
 
 pub use withdrawal::WithdrawalCircuit;
 pub use balance_withdrawal::{BalanceWithdrawalCircuit, BalanceWithdrawalWitness, BalanceWithdrawalProof};
+pub use transfer::{TransferCircuit, TransferWitness, TransferProof};
+pub use merge::{MergeCircuit, MergeWitness, MergeProof};
+pub use deposit::{DepositCircuit, DepositWitness, DepositProof};
+pub use groth16::Groth16Proof;
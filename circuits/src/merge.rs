@@ -0,0 +1,778 @@
+//! Note consolidation circuit for Noctis Privacy Vault
+//!
+//! Merges up to MAX_MERGE_INPUTS notes of one owner into a single note, so
+//! wallets can sweep up the change notes partial withdrawals leave behind.
+//! This circuit proves:
+//! 1. Every input note is in the Merkle tree under the same spending_key
+//!    and asset_id
+//! 2. Correct, pairwise distinct nullifiers for every input
+//! 3. The output commitment holds the sum of the input balances, under the
+//!    same spending_key, asset_id and domain
+//! 4. Every balance and the sum fit in `amount_bits::<F>()` bits, so the
+//!    sum of the balances is their integer sum: MAX_MERGE_INPUTS of them
+//!    stay below p
+//!
+//! Public inputs:
+//!   - merkle_root: Root of the note commitment tree
+//!   - nullifier_0 .. nullifier_7: hash(domain, nk, note_index) per input,
+//!     with nk = hash(spending_key, NK_TAG) (see `keys::nullifier_key`),
+//!     zero for unused slots
+//!   - output_commitment: hash(domain, hash(spending_key), asset_id, total,
+//!     output_randomness)
+//!   - domain: Deployment domain of every note, `poseidon::domain_tag`
+//!
+//! Private inputs:
+//!   - spending_key: Shared by all inputs and the output
+//!   - asset_id: Shared by all inputs and the output; merging never reveals
+//!     which asset was consolidated
+//!   - per input: balance, randomness, note_index, merkle_path, path_indices
+//!   - output_randomness: Fresh randomness for the merged note
+//!
+//! `plan_merge` picks the notes to merge from a wallet's unspent notes and
+//! builds the witness.
+//!
+//! Every hash is one row of the `poseidon_air` gadget: hash(spending_key)
+//! and nk, then per slot the note commitment, one row per Merkle level and the
+//! nullifier, then the output commitment. The Merkle path bits of a slot
+//! also spell the note index its nullifier is derived from, and the range
+//! proof bits run alongside on the first `amount_bits` rows. Unused slots
+//! are hashed over zeros, and neither their root nor their nullifier is
+//! checked.
+
+use p3_air::{Air, AirBuilder, AirBuilderWithPublicValues, BaseAir};
+use p3_baby_bear::BabyBear;
+use p3_field::{PrimeCharacteristicRing, PrimeField64};
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::Matrix;
+
+use crate::address::{asset_id, Address};
+use crate::balance_withdrawal::{compute_merkle_root_with_path, path_position, WitnessError};
+use crate::keys::{note_commitment, note_nullifier, nullifier_key, NK_TAG};
+use crate::merkle::{filled_tree_proof, TREE_DEPTH};
+use crate::note::NoteSecrets;
+use crate::poseidon::{poseidon_hash, poseidon_hash_2};
+use crate::poseidon_air::{
+    assert_constant, assert_digest, assert_inputs, fill_bit_sum, fill_constant, Program, AUX_COL, CARRY_COL,
+};
+use crate::transfer::amount_bits;
+
+type Val = BabyBear;
+
+/// Most notes one merge can spend
+pub const MAX_MERGE_INPUTS: usize = 8;
+
+/// Number of public inputs: merkle_root, nullifiers, output_commitment,
+/// domain
+pub const NUM_PUBLIC_INPUTS: usize = MAX_MERGE_INPUTS + 3;
+
+/// Public input of the domain, last
+pub const DOMAIN_INPUT: usize = NUM_PUBLIC_INPUTS - 1;
+
+/// Pairs of slots whose nullifiers must differ
+const NUM_PAIRS: usize = MAX_MERGE_INPUTS * (MAX_MERGE_INPUTS - 1) / 2;
+
+// Steps of the row program; each slot's, from `slot_step(slot, 0)`, are a
+// commitment, its Merkle path and its nullifier
+const SPENDING_KEY_HASH_STEP: usize = 0;
+const NULLIFIER_KEY_STEP: usize = SPENDING_KEY_HASH_STEP + 1;
+const COMMITMENT_STEP: usize = 0;
+const MERKLE_STEP: usize = COMMITMENT_STEP + 1;
+const NULLIFIER_STEP: usize = MERKLE_STEP + TREE_DEPTH;
+const SLOT_STEPS: usize = NULLIFIER_STEP + 1;
+const OUTPUT_STEP: usize = NULLIFIER_KEY_STEP + 1 + MAX_MERGE_INPUTS * SLOT_STEPS;
+const NUM_STEPS: usize = OUTPUT_STEP + 1;
+
+// Private inputs, equal on every row
+const SPENDING_KEY_COL: usize = AUX_COL;
+const SPENDING_KEY_HASH_COL: usize = SPENDING_KEY_COL + 1;
+const NULLIFIER_KEY_COL: usize = SPENDING_KEY_HASH_COL + 1;
+const ASSET_COL: usize = NULLIFIER_KEY_COL + 1;
+const OUTPUT_RANDOMNESS_COL: usize = ASSET_COL + 1;
+/// Sum of the input balances
+const TOTAL_COL: usize = OUTPUT_RANDOMNESS_COL + 1;
+
+// Columns of one slot, from `slot_col(slot, 0)`; all but the index are
+// equal on every row
+/// 1 for a slot in use
+const ACTIVE_COL: usize = 0;
+const BALANCE_COL: usize = ACTIVE_COL + 1;
+const RANDOMNESS_COL: usize = BALANCE_COL + 1;
+/// Running sum of the path bits: the note index from its last Merkle step on
+const INDEX_COL: usize = RANDOMNESS_COL + 1;
+const SLOT_COLS: usize = INDEX_COL + 1;
+
+const SLOTS_COL: usize = TOTAL_COL + 1;
+/// Inverse of the difference of each pair of nullifiers, equal on every row
+const NULLIFIER_GAP_INV_COL: usize = SLOTS_COL + MAX_MERGE_INPUTS * SLOT_COLS;
+/// Sibling and side of the running node on a Merkle step
+const SIBLING_COL: usize = NULLIFIER_GAP_INV_COL + NUM_PAIRS;
+const IS_RIGHT_COL: usize = SIBLING_COL + 1;
+/// Range proof bit and running sum of every balance and of the total, one
+/// bit per row
+const RANGE_COL: usize = IS_RIGHT_COL + 1;
+const NUM_AUX_COLS: usize = RANGE_COL + 2 * (MAX_MERGE_INPUTS + 1) - AUX_COL;
+
+const fn slot_step(slot: usize, step: usize) -> usize {
+    NULLIFIER_KEY_STEP + 1 + slot * SLOT_STEPS + step
+}
+
+const fn slot_col(slot: usize, col: usize) -> usize {
+    SLOTS_COL + slot * SLOT_COLS + col
+}
+
+/// Range proof bit column of amount `k`, followed by its running sum
+const fn range_col(k: usize) -> usize {
+    RANGE_COL + 2 * k
+}
+
+/// Pairs of slots `(i, j)` with `i < j`, in the order of their
+/// `NULLIFIER_GAP_INV_COL` columns
+fn slot_pairs() -> impl Iterator<Item = (usize, usize)> {
+    (0..MAX_MERGE_INPUTS).flat_map(|i| (i + 1..MAX_MERGE_INPUTS).map(move |j| (i, j)))
+}
+
+/// Row program of the circuit over `F`, long enough for the hashes and
+/// for one range proof bit per row
+fn program<F: PrimeField64>() -> Program {
+    Program::new(NUM_AUX_COLS, NUM_STEPS.max(amount_bits::<F>()))
+}
+
+/// A note being merged; its owner is `MergeWitness::spending_key`
+#[derive(Clone)]
+pub struct MergeInput<F = Val> {
+    pub balance: F,
+    pub randomness: F,
+    pub note_index: u64,
+    pub merkle_path: [F; TREE_DEPTH],
+    pub path_indices: [bool; TREE_DEPTH],
+}
+
+/// Private witness for a merge
+#[derive(Clone)]
+pub struct MergeWitness<F = Val> {
+    /// Deployment domain of every note
+    pub domain: F,
+    pub spending_key: F,
+    pub asset_id: F,
+    /// Between 1 and MAX_MERGE_INPUTS notes
+    pub inputs: Vec<MergeInput<F>>,
+    pub output_randomness: F,
+}
+
+impl<F: PrimeField64> MergeWitness<F> {
+    pub fn commitment(&self, input: &MergeInput<F>) -> F {
+        note_commitment(self.domain, poseidon_hash(self.spending_key), self.asset_id, input.balance, input.randomness)
+    }
+
+    pub fn nullifier(&self, input: &MergeInput<F>) -> F {
+        note_nullifier(self.domain, nullifier_key(self.spending_key), input.note_index)
+    }
+
+    /// Sum of the input balances, saturating at `u64::MAX`
+    pub fn total(&self) -> u64 {
+        self.inputs
+            .iter()
+            .fold(0u64, |acc, input| acc.saturating_add(input.balance.as_canonical_u64()))
+    }
+
+    /// Commitment of the merged note
+    pub fn output_commitment(&self) -> F {
+        note_commitment(
+            self.domain,
+            poseidon_hash(self.spending_key),
+            self.asset_id,
+            F::from_u64(self.total()),
+            self.output_randomness,
+        )
+    }
+}
+
+/// Merge circuit AIR
+pub struct MergeCircuit<F = Val> {
+    // Public inputs
+    pub merkle_root: F,
+    pub nullifiers: [F; MAX_MERGE_INPUTS],
+    pub output_commitment: F,
+    pub domain: F,
+}
+
+impl<F: PrimeField64> MergeCircuit<F> {
+    pub fn new(merkle_root: F, nullifiers: [F; MAX_MERGE_INPUTS], output_commitment: F, domain: F) -> Self {
+        Self {
+            merkle_root,
+            nullifiers,
+            output_commitment,
+            domain,
+        }
+    }
+
+    /// Derive the public inputs from a witness and check it
+    pub fn from_witness(witness: &MergeWitness<F>) -> Result<Self, WitnessError> {
+        let first = witness.inputs.first().ok_or(WitnessError::InvalidNoteCount)?;
+        let merkle_root = compute_merkle_root_with_path(
+            witness.commitment(first),
+            &first.merkle_path,
+            &first.path_indices,
+        );
+
+        let mut nullifiers = [F::ZERO; MAX_MERGE_INPUTS];
+        for (nullifier, input) in nullifiers.iter_mut().zip(&witness.inputs) {
+            *nullifier = witness.nullifier(input);
+        }
+
+        let circuit = Self::new(merkle_root, nullifiers, witness.output_commitment(), witness.domain);
+        circuit.check_witness(witness)?;
+        Ok(circuit)
+    }
+
+    /// Public inputs in verifier order:
+    /// [merkle_root, nullifier_0, ..., nullifier_7, output_commitment, domain]
+    pub fn public_values(&self) -> Vec<F> {
+        let mut values = Vec::with_capacity(NUM_PUBLIC_INPUTS);
+        values.push(self.merkle_root);
+        values.extend_from_slice(&self.nullifiers);
+        values.push(self.output_commitment);
+        values.push(self.domain);
+        values
+    }
+
+    /// Check that the witness satisfies the statement for these public inputs
+    pub fn check_witness(&self, witness: &MergeWitness<F>) -> Result<(), WitnessError> {
+        let count = witness.inputs.len();
+        if count == 0 || count > MAX_MERGE_INPUTS {
+            return Err(WitnessError::InvalidNoteCount);
+        }
+
+        // 1. Membership and nullifiers of the inputs
+        for (input, &nullifier) in witness.inputs.iter().zip(&self.nullifiers) {
+            let root = compute_merkle_root_with_path(
+                witness.commitment(input),
+                &input.merkle_path,
+                &input.path_indices,
+            );
+            if root != self.merkle_root || witness.domain != self.domain {
+                return Err(WitnessError::InvalidMerkleProof);
+            }
+            // The circuit reads note_index off the Merkle path bits
+            if input.note_index != path_position(&input.path_indices) || witness.nullifier(input) != nullifier {
+                return Err(WitnessError::InvalidNullifier);
+            }
+        }
+
+        // 2. Unused slots publish a zero nullifier
+        if self.nullifiers[count..].iter().any(|&n| n != F::ZERO) {
+            return Err(WitnessError::InvalidNullifier);
+        }
+
+        // 3. No note is spent twice
+        let used = &self.nullifiers[..count];
+        for (i, nullifier) in used.iter().enumerate() {
+            if used[i + 1..].contains(nullifier) {
+                return Err(WitnessError::DuplicateNullifier);
+            }
+        }
+
+        // 4. Range checks, including the total
+        let bits = amount_bits::<F>();
+        let in_range = |amount: u64| amount >> bits == 0;
+        if !witness.inputs.iter().all(|input| in_range(input.balance.as_canonical_u64()))
+            || !in_range(witness.total())
+        {
+            return Err(WitnessError::AmountOutOfRange);
+        }
+
+        // 5. Output commitment holds the total
+        if witness.output_commitment() != self.output_commitment {
+            return Err(WitnessError::InvalidOutputCommitment);
+        }
+
+        Ok(())
+    }
+
+    /// Generate the execution trace for proving
+    pub fn generate_trace(&self, witness: &MergeWitness<F>) -> RowMajorMatrix<F> {
+        if let Err(e) = self.check_witness(witness) {
+            panic!("{}", e);
+        }
+
+        // Unused slots hold a zero note at index 0
+        let blank = MergeInput {
+            balance: F::ZERO,
+            randomness: F::ZERO,
+            note_index: 0,
+            merkle_path: [F::ZERO; TREE_DEPTH],
+            path_indices: [false; TREE_DEPTH],
+        };
+        let count = witness.inputs.len();
+        let slots: Vec<&MergeInput<F>> =
+            witness.inputs.iter().chain(std::iter::repeat_n(&blank, MAX_MERGE_INPUTS - count)).collect();
+        let spending_key_hash = poseidon_hash(witness.spending_key);
+        let nk = nullifier_key(witness.spending_key);
+        let total = F::from_u64(witness.total());
+
+        // ===== One permutation per step, in the order `eval` wires them =====
+        let mut inputs = Vec::with_capacity(NUM_STEPS);
+        inputs.push(vec![witness.spending_key]);
+        inputs.push(vec![witness.spending_key, F::from_u64(NK_TAG)]);
+        for input in &slots {
+            inputs.push(vec![witness.domain, spending_key_hash, witness.asset_id, input.balance, input.randomness]);
+            let mut current = witness.commitment(input);
+            for (&sibling, &is_right) in input.merkle_path.iter().zip(&input.path_indices) {
+                let pair = if is_right { [sibling, current] } else { [current, sibling] };
+                current = poseidon_hash_2(pair[0], pair[1]);
+                inputs.push(pair.to_vec());
+            }
+            inputs.push(vec![witness.domain, nk, F::from_u64(input.note_index)]);
+        }
+        inputs.push(vec![witness.domain, spending_key_hash, witness.asset_id, total, witness.output_randomness]);
+
+        let mut trace = program::<F>().trace(&inputs);
+
+        // ===== Private inputs =====
+        fill_constant(&mut trace, SPENDING_KEY_COL, witness.spending_key);
+        fill_constant(&mut trace, SPENDING_KEY_HASH_COL, spending_key_hash);
+        fill_constant(&mut trace, NULLIFIER_KEY_COL, nk);
+        fill_constant(&mut trace, ASSET_COL, witness.asset_id);
+        fill_constant(&mut trace, OUTPUT_RANDOMNESS_COL, witness.output_randomness);
+        fill_constant(&mut trace, TOTAL_COL, total);
+
+        // ===== Slots: private columns, Merkle path and note index =====
+        let width = trace.width;
+        for (slot, input) in slots.iter().enumerate() {
+            fill_constant(&mut trace, slot_col(slot, ACTIVE_COL), F::from_bool(slot < count));
+            fill_constant(&mut trace, slot_col(slot, BALANCE_COL), input.balance);
+            fill_constant(&mut trace, slot_col(slot, RANDOMNESS_COL), input.randomness);
+
+            let first = slot_step(slot, MERKLE_STEP);
+            for (level, row) in trace.values.chunks_mut(width).skip(first).take(TREE_DEPTH).enumerate() {
+                row[SIBLING_COL] = input.merkle_path[level];
+            }
+            fill_bit_sum(&mut trace, IS_RIGHT_COL, slot_col(slot, INDEX_COL), first, TREE_DEPTH, input.note_index);
+        }
+
+        // ===== Nullifiers of the slots in use pairwise distinct =====
+        for (k, (i, j)) in slot_pairs().enumerate() {
+            let gap = self.nullifiers[i] - self.nullifiers[j];
+            fill_constant(&mut trace, NULLIFIER_GAP_INV_COL + k, gap.try_inverse().unwrap_or(F::ZERO));
+        }
+
+        // ===== Range proofs: every balance and the total =====
+        let amounts = slots.iter().map(|input| input.balance.as_canonical_u64()).chain([witness.total()]);
+        for (k, amount) in amounts.enumerate() {
+            fill_bit_sum(&mut trace, range_col(k), range_col(k) + 1, 0, amount_bits::<F>(), amount);
+        }
+
+        trace
+    }
+
+    /// Height of the trace `generate_trace` produces
+    pub fn trace_height() -> usize {
+        program::<F>().height()
+    }
+}
+
+impl<F: PrimeField64> BaseAir<F> for MergeCircuit<F> {
+    fn width(&self) -> usize {
+        program::<F>().width()
+    }
+}
+
+impl<F: PrimeField64, AB: AirBuilderWithPublicValues<F = F>> Air<AB> for MergeCircuit<F> {
+    fn eval(&self, builder: &mut AB) {
+        let main = builder.main();
+        let local = main.row_slice(0).expect("The matrix is empty?");
+        let next = main.row_slice(1).expect("The matrix has only one row?");
+        let local: Vec<AB::Expr> = local.iter().map(|&v| v.into()).collect();
+        let next: Vec<AB::Expr> = next.iter().map(|&v| v.into()).collect();
+        let pis: Vec<AB::Expr> = builder.public_values().iter().map(|&v| v.into()).collect();
+        let merkle_root = pis[0].clone();
+        let nullifiers = &pis[1..=MAX_MERGE_INPUTS];
+        let output_commitment = pis[MAX_MERGE_INPUTS + 1].clone();
+        let domain = pis[DOMAIN_INPUT].clone();
+
+        let program = program::<F>();
+        program.eval(builder, &local, &next);
+        for col in [SPENDING_KEY_COL, SPENDING_KEY_HASH_COL, NULLIFIER_KEY_COL, ASSET_COL, OUTPUT_RANDOMNESS_COL, TOTAL_COL] {
+            assert_constant(builder, &local, &next, col);
+        }
+        for slot in 0..MAX_MERGE_INPUTS {
+            for col in [ACTIVE_COL, BALANCE_COL, RANDOMNESS_COL] {
+                assert_constant(builder, &local, &next, slot_col(slot, col));
+            }
+        }
+        for k in 0..NUM_PAIRS {
+            assert_constant(builder, &local, &next, NULLIFIER_GAP_INV_COL + k);
+        }
+
+        let spending_key = local[SPENDING_KEY_COL].clone();
+        let spending_key_hash = local[SPENDING_KEY_HASH_COL].clone();
+        let nk = local[NULLIFIER_KEY_COL].clone();
+        let asset_id = local[ASSET_COL].clone();
+
+        // spending_key_hash = hash(spending_key)
+        let step = program.flag(&local, SPENDING_KEY_HASH_STEP);
+        assert_inputs(builder, step.clone(), &local, std::slice::from_ref(&spending_key));
+        assert_digest(builder, step, &local, spending_key_hash.clone());
+
+        // nk = hash(spending_key, NK_TAG)
+        let step = program.flag(&local, NULLIFIER_KEY_STEP);
+        assert_inputs(builder, step.clone(), &local, &[spending_key, AB::Expr::from_u64(NK_TAG)]);
+        assert_digest(builder, step, &local, nk.clone());
+
+        // Slots are used as a prefix of at least one note. An unused slot
+        // has a zero balance and publishes a zero nullifier.
+        builder.assert_one(local[slot_col(0, ACTIVE_COL)].clone());
+        for (slot, nullifier) in nullifiers.iter().enumerate() {
+            let col = |c: usize| local[slot_col(slot, c)].clone();
+            let step = |s: usize| slot_step(slot, s);
+
+            let active = col(ACTIVE_COL);
+            builder.assert_bool(active.clone());
+            let unused = AB::Expr::ONE - active.clone();
+            builder.when(unused.clone()).assert_zero(col(BALANCE_COL));
+            builder.when(unused.clone()).assert_zero(nullifier.clone());
+            if slot + 1 < MAX_MERGE_INPUTS {
+                builder.when(unused).assert_zero(local[slot_col(slot + 1, ACTIVE_COL)].clone());
+            }
+
+            // commitment = hash(domain, spending_key_hash, asset_id, balance,
+            // randomness), carried into the first Merkle step
+            let lanes =
+                [domain.clone(), spending_key_hash.clone(), asset_id.clone(), col(BALANCE_COL), col(RANDOMNESS_COL)];
+            assert_inputs(builder, program.flag(&local, step(COMMITMENT_STEP)), &local, &lanes);
+
+            // Each level hashes the carried node with the sibling, on the
+            // side the path bit selects; a used slot's last one yields
+            // merkle_root
+            let merkle = program.phase(&local, step(MERKLE_STEP), TREE_DEPTH);
+            let (node, sibling, is_right) = (&local[CARRY_COL], &local[SIBLING_COL], &local[IS_RIGHT_COL]);
+            let left = node.clone() + is_right.clone() * (sibling.clone() - node.clone());
+            let right = sibling.clone() + is_right.clone() * (node.clone() - sibling.clone());
+            assert_inputs(builder, merkle, &local, &[left, right]);
+            let last = program.flag(&local, step(NULLIFIER_STEP) - 1);
+            assert_digest(builder, last * active.clone(), &local, merkle_root.clone());
+            let index = slot_col(slot, INDEX_COL);
+            program.eval_bit_sum(builder, &local, &next, IS_RIGHT_COL, index, step(MERKLE_STEP), TREE_DEPTH);
+
+            // nullifier = hash(domain, nk, note_index) for a used slot
+            let flag = program.flag(&local, step(NULLIFIER_STEP));
+            assert_inputs(builder, flag.clone(), &local, &[domain.clone(), nk.clone(), col(INDEX_COL)]);
+            assert_digest(builder, flag * active, &local, nullifier.clone());
+        }
+
+        // No note is spent twice: the nullifiers of used slots differ
+        for (k, (i, j)) in slot_pairs().enumerate() {
+            let gap = nullifiers[i].clone() - nullifiers[j].clone();
+            builder
+                .when(local[slot_col(j, ACTIVE_COL)].clone())
+                .assert_one(gap * local[NULLIFIER_GAP_INV_COL + k].clone());
+        }
+
+        // Every balance and the total are the sum of their amount_bits
+        // range-check bits, and the total is the sum of the balances, which
+        // cannot wrap around the field at that width
+        let balances: Vec<AB::Expr> = (0..MAX_MERGE_INPUTS).map(|slot| local[slot_col(slot, BALANCE_COL)].clone()).collect();
+        let total = local[TOTAL_COL].clone();
+        for (k, amount) in balances.iter().chain([&total]).enumerate() {
+            program.eval_bit_sum(builder, &local, &next, range_col(k), range_col(k) + 1, 0, amount_bits::<F>());
+            builder.when_last_row().assert_eq(local[range_col(k) + 1].clone(), amount.clone());
+        }
+        builder.assert_eq(total.clone(), balances.into_iter().sum::<AB::Expr>());
+
+        // output_commitment = hash(domain, spending_key_hash, asset_id, total, output_randomness)
+        let lanes = [domain, spending_key_hash, asset_id, total, local[OUTPUT_RANDOMNESS_COL].clone()];
+        let step = program.flag(&local, OUTPUT_STEP);
+        assert_inputs(builder, step.clone(), &local, &lanes);
+        assert_digest(builder, step, &local, output_commitment);
+    }
+}
+
+/// Pick notes to merge and build the witness
+///
+/// Takes the unspent `asset` notes of `spending_key` from `notes` that are
+/// in the tree `leaves` of the deployment `domain`, smallest balance first, up to MAX_MERGE_INPUTS of them and
+/// as long as the total stays in range. Returns `None` unless at least two
+/// notes can be merged. Run it again on the remaining notes to sweep more.
+pub fn plan_merge(
+    spending_key: Val,
+    asset: &Address,
+    domain: Val,
+    notes: &[NoteSecrets],
+    leaves: &[Val],
+    output_randomness: Val,
+) -> Option<MergeWitness> {
+    let mut candidates: Vec<&NoteSecrets> = notes
+        .iter()
+        .filter(|note| note.spending_key == spending_key && note.asset == *asset)
+        .filter(|note| leaves.get(note.note_index as usize) == Some(&note.commitment))
+        .collect();
+    candidates.sort_by_key(|note| note.note_index);
+    candidates.dedup_by_key(|note| note.note_index);
+    candidates.sort_by_key(|note| note.balance.as_canonical_u64());
+
+    let limit = 1u64 << amount_bits::<Val>();
+    let mut total = 0u64;
+    let mut inputs = Vec::with_capacity(MAX_MERGE_INPUTS);
+    for note in candidates {
+        let balance = note.balance.as_canonical_u64();
+        if inputs.len() == MAX_MERGE_INPUTS || total + balance >= limit {
+            break;
+        }
+        let (_, merkle_path, path_indices) = filled_tree_proof(leaves, note.note_index as usize)?;
+        total += balance;
+        inputs.push(MergeInput {
+            balance: note.balance,
+            randomness: note.randomness,
+            note_index: note.note_index,
+            merkle_path,
+            path_indices,
+        });
+    }
+
+    if inputs.len() < 2 {
+        return None;
+    }
+    Some(MergeWitness {
+        domain,
+        spending_key,
+        asset_id: asset_id(asset),
+        inputs,
+        output_randomness,
+    })
+}
+
+/// Proof data for serialization
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct MergeProof {
+    pub proof_bytes: Vec<u8>,
+    pub public_inputs: MergePublicInputs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MergePublicInputs {
+    pub merkle_root: u64,
+    pub nullifiers: [u64; MAX_MERGE_INPUTS],
+    pub output_commitment: u64,
+    pub domain: u64,
+}
+
+impl MergePublicInputs {
+    pub fn from_circuit<F: PrimeField64>(circuit: &MergeCircuit<F>) -> Self {
+        Self {
+            merkle_root: circuit.merkle_root.as_canonical_u64(),
+            nullifiers: circuit.nullifiers.map(|n| n.as_canonical_u64()),
+            output_commitment: circuit.output_commitment.as_canonical_u64(),
+            domain: circuit.domain.as_canonical_u64(),
+        }
+    }
+
+    /// Public inputs in verifier order
+    pub fn to_array(&self) -> [u64; NUM_PUBLIC_INPUTS] {
+        let mut values = [0u64; NUM_PUBLIC_INPUTS];
+        values[0] = self.merkle_root;
+        values[1..=MAX_MERGE_INPUTS].copy_from_slice(&self.nullifiers);
+        values[MAX_MERGE_INPUTS + 1] = self.output_commitment;
+        values[DOMAIN_INPUT] = self.domain;
+        values
+    }
+
+    /// Public values as field elements, in verifier order
+    pub fn to_field_elements<F: PrimeCharacteristicRing>(&self) -> Vec<F> {
+        self.to_array().iter().map(|&v| F::from_u64(v)).collect()
+    }
+
+    /// Circuit for these public inputs
+    pub fn to_circuit<F: PrimeField64>(&self) -> MergeCircuit<F> {
+        MergeCircuit::new(
+            F::from_u64(self.merkle_root),
+            self.nullifiers.map(F::from_u64),
+            F::from_u64(self.output_commitment),
+            F::from_u64(self.domain),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prover::forged_trace_verifies;
+    use p3_goldilocks::Goldilocks;
+
+    const SPENDING_KEY: u32 = 12345;
+    const ASSET: Address = [0xa5; 20];
+    const DOMAIN: Val = Val::new(8453);
+
+    fn note(spending_key: u32, asset: Address, balance: u32, randomness: u32, note_index: u64) -> NoteSecrets {
+        let spending_key = Val::new(spending_key);
+        let balance = Val::new(balance);
+        let randomness = Val::new(randomness);
+        NoteSecrets {
+            spending_key,
+            asset,
+            balance,
+            randomness,
+            commitment: note_commitment(DOMAIN, poseidon_hash(spending_key), asset_id(&asset), balance, randomness),
+            note_index,
+        }
+    }
+
+    /// A wallet with dust notes of SPENDING_KEY in ASSET, one note of
+    /// SPENDING_KEY in another asset and one note of someone else
+    fn wallet(balances: &[u32]) -> (Vec<NoteSecrets>, Vec<Val>) {
+        let mut notes: Vec<NoteSecrets> = balances
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| note(SPENDING_KEY, ASSET, b, 1000 + i as u32, i as u64))
+            .collect();
+        notes.push(note(SPENDING_KEY, [0; 20], 1, 8, balances.len() as u64));
+        notes.push(note(999, ASSET, 5, 7, balances.len() as u64 + 1));
+        let leaves = notes.iter().map(|n| n.commitment).collect();
+        (notes, leaves)
+    }
+
+    #[test]
+    fn test_plan_and_merge() {
+        let (notes, leaves) = wallet(&[300, 100, 200]);
+        let witness = plan_merge(Val::new(SPENDING_KEY), &ASSET, DOMAIN, &notes, &leaves, Val::new(42)).unwrap();
+
+        // The other asset and owner are left alone, smallest balances come first
+        assert_eq!(witness.inputs.len(), 3);
+        assert_eq!(witness.inputs[0].balance, Val::new(100));
+        assert_eq!(witness.total(), 600);
+
+        let circuit = MergeCircuit::from_witness(&witness).unwrap();
+        assert_eq!(circuit.nullifiers[3..], [Val::new(0); MAX_MERGE_INPUTS - 3]);
+        assert_eq!(
+            circuit.output_commitment,
+            note_commitment(DOMAIN, poseidon_hash(Val::new(SPENDING_KEY)), asset_id(&ASSET), Val::new(600), Val::new(42))
+        );
+
+        assert_eq!(circuit.public_values()[DOMAIN_INPUT], DOMAIN);
+
+        let trace = circuit.generate_trace(&witness);
+        assert_eq!(trace.width(), circuit.width());
+        assert_eq!(trace.height(), MergeCircuit::<Val>::trace_height());
+
+        // The notes cannot be merged in another deployment
+        let mut other = circuit;
+        other.domain = Val::new(84532);
+        assert!(matches!(
+            other.check_witness(&witness),
+            Err(WitnessError::InvalidMerkleProof)
+        ));
+    }
+
+    #[test]
+    fn test_forged_trace_rejected() {
+        let (notes, leaves) = wallet(&[300, 100, 200]);
+        let witness = plan_merge(Val::new(SPENDING_KEY), &ASSET, DOMAIN, &notes, &leaves, Val::new(42)).unwrap();
+        let circuit = MergeCircuit::from_witness(&witness).unwrap();
+        assert!(forged_trace_verifies(&circuit, circuit.generate_trace(&witness), &circuit.public_values()));
+
+        // A merged note worth more than the inputs, its total re-filled
+        let mut trace = circuit.generate_trace(&witness);
+        fill_constant(&mut trace, TOTAL_COL, Val::new(700));
+        fill_bit_sum(&mut trace, range_col(MAX_MERGE_INPUTS), range_col(MAX_MERGE_INPUTS) + 1, 0, amount_bits::<Val>(), 700);
+        let mut inflated = MergeCircuit::from_witness(&witness).unwrap();
+        inflated.output_commitment =
+            note_commitment(DOMAIN, poseidon_hash(Val::new(SPENDING_KEY)), asset_id(&ASSET), Val::new(700), Val::new(42));
+        assert!(!forged_trace_verifies(&inflated, trace, &inflated.public_values()));
+
+        // A used slot passed off as unused, hiding its nullifier
+        let mut trace = circuit.generate_trace(&witness);
+        fill_constant(&mut trace, slot_col(2, ACTIVE_COL), Val::new(0));
+        let mut hidden = MergeCircuit::from_witness(&witness).unwrap();
+        hidden.nullifiers[2] = Val::new(0);
+        assert!(!forged_trace_verifies(&hidden, trace, &hidden.public_values()));
+
+        // Notes minted outside the pool, on paths to their own root
+        let (minted, minted_leaves) = wallet(&[5000, 5000]);
+        let forged = plan_merge(Val::new(SPENDING_KEY), &ASSET, DOMAIN, &minted, &minted_leaves, Val::new(42)).unwrap();
+        let mut in_pool = MergeCircuit::from_witness(&forged).unwrap();
+        let trace = in_pool.generate_trace(&forged);
+        in_pool.merkle_root = circuit.merkle_root;
+        assert!(!forged_trace_verifies(&in_pool, trace, &in_pool.public_values()));
+    }
+
+    #[test]
+    fn test_plan_caps_inputs() {
+        let (notes, leaves) = wallet(&[10; 11]);
+        let witness = plan_merge(Val::new(SPENDING_KEY), &ASSET, DOMAIN, &notes, &leaves, Val::new(42)).unwrap();
+        assert_eq!(witness.inputs.len(), MAX_MERGE_INPUTS);
+        assert!(MergeCircuit::from_witness(&witness).is_ok());
+    }
+
+    #[test]
+    fn test_plan_needs_two_notes() {
+        let (notes, leaves) = wallet(&[10]);
+        assert!(plan_merge(Val::new(SPENDING_KEY), &ASSET, DOMAIN, &notes, &leaves, Val::new(42)).is_none());
+
+        // Notes missing from the tree snapshot are not planned
+        let (notes, _) = wallet(&[10, 20]);
+        assert!(plan_merge(Val::new(SPENDING_KEY), &ASSET, DOMAIN, &notes, &[], Val::new(42)).is_none());
+    }
+
+    #[test]
+    fn test_wrong_total_rejected() {
+        let (notes, leaves) = wallet(&[300, 100]);
+        let witness = plan_merge(Val::new(SPENDING_KEY), &ASSET, DOMAIN, &notes, &leaves, Val::new(42)).unwrap();
+        let mut circuit = MergeCircuit::from_witness(&witness).unwrap();
+
+        // Claim a merged note worth more than the inputs
+        circuit.output_commitment =
+            note_commitment(DOMAIN, poseidon_hash(Val::new(SPENDING_KEY)), asset_id(&ASSET), Val::new(401), Val::new(42));
+        assert!(matches!(
+            circuit.check_witness(&witness),
+            Err(WitnessError::InvalidOutputCommitment)
+        ));
+    }
+
+    #[test]
+    fn test_same_note_twice_rejected() {
+        let (notes, leaves) = wallet(&[300, 100]);
+        let mut witness = plan_merge(Val::new(SPENDING_KEY), &ASSET, DOMAIN, &notes, &leaves, Val::new(42)).unwrap();
+        witness.inputs[1] = witness.inputs[0].clone();
+        assert!(matches!(
+            MergeCircuit::from_witness(&witness),
+            Err(WitnessError::DuplicateNullifier)
+        ));
+    }
+
+    #[test]
+    fn test_note_count_checked() {
+        let (notes, leaves) = wallet(&[10; 9]);
+        let mut witness = plan_merge(Val::new(SPENDING_KEY), &ASSET, DOMAIN, &notes, &leaves, Val::new(42)).unwrap();
+        let circuit = MergeCircuit::from_witness(&witness).unwrap();
+
+        witness.inputs.push(witness.inputs[0].clone());
+        assert!(matches!(
+            circuit.check_witness(&witness),
+            Err(WitnessError::InvalidNoteCount)
+        ));
+
+        witness.inputs.clear();
+        assert!(matches!(
+            MergeCircuit::from_witness(&witness),
+            Err(WitnessError::InvalidNoteCount)
+        ));
+    }
+
+    #[test]
+    fn test_full_slots_sum_below_modulus() {
+        // Eight balances just under the bound, as change and transfer notes can hold
+        let max = (1u64 << amount_bits::<Val>()) - 1;
+        assert!(MAX_MERGE_INPUTS as u64 * max < Val::ORDER_U64);
+        let max = (1u64 << amount_bits::<Goldilocks>()) - 1;
+        assert!(MAX_MERGE_INPUTS as u64 * max < Goldilocks::ORDER_U64);
+    }
+
+    #[test]
+    fn test_public_inputs_roundtrip() {
+        let (notes, leaves) = wallet(&[300, 100]);
+        let witness = plan_merge(Val::new(SPENDING_KEY), &ASSET, DOMAIN, &notes, &leaves, Val::new(42)).unwrap();
+        let circuit = MergeCircuit::from_witness(&witness).unwrap();
+        let public_inputs = MergePublicInputs::from_circuit(&circuit);
+        assert_eq!(public_inputs.to_field_elements::<Val>(), circuit.public_values());
+        assert_eq!(public_inputs.to_circuit::<Val>().public_values(), circuit.public_values());
+    }
+}
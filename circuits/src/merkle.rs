@@ -1,6 +1,9 @@
 //! Merkle tree proof verification for Plonky3
+//!
+//! Generic over the field of the Poseidon2 hasher, BabyBear by default.
 
 use p3_baby_bear::BabyBear;
+use p3_field::Field;
 use crate::poseidon::hash_pair;
 
 /// Tree depth for the commitment Merkle tree
@@ -17,31 +20,31 @@ pub const TREE_DEPTH: usize = 20;
 ///
 /// # Returns
 /// True if the proof is valid
-pub fn verify_merkle_proof(
-    leaf: BabyBear,
-    path: &[BabyBear; TREE_DEPTH],
+pub fn verify_merkle_proof<F: Field>(
+    leaf: F,
+    path: &[F; TREE_DEPTH],
     path_indices: &[bool; TREE_DEPTH],
-    root: BabyBear,
+    root: F,
 ) -> bool {
     let computed = compute_merkle_root(leaf, path, path_indices);
     computed == root
 }
 
 /// Compute Merkle root from leaf and path (fixed-size arrays)
-pub fn compute_merkle_root(
-    leaf: BabyBear,
-    path: &[BabyBear; TREE_DEPTH],
+pub fn compute_merkle_root<F: Field>(
+    leaf: F,
+    path: &[F; TREE_DEPTH],
     path_indices: &[bool; TREE_DEPTH],
-) -> BabyBear {
+) -> F {
     compute_merkle_root_slice(leaf, path.as_slice(), path_indices.as_slice())
 }
 
 /// Compute Merkle root from leaf and path (slices - for WASM)
-pub fn compute_merkle_root_slice(
-    leaf: BabyBear,
-    path: &[BabyBear],
+pub fn compute_merkle_root_slice<F: Field>(
+    leaf: F,
+    path: &[F],
     path_indices: &[bool],
-) -> BabyBear {
+) -> F {
     let mut current = leaf;
 
     for i in 0..path.len().min(path_indices.len()) {
@@ -60,14 +63,14 @@ pub fn compute_merkle_root_slice(
 }
 
 /// Merkle tree builder for creating proofs
-pub struct MerkleTree {
-    leaves: Vec<BabyBear>,
-    layers: Vec<Vec<BabyBear>>,
+pub struct MerkleTree<F = BabyBear> {
+    leaves: Vec<F>,
+    layers: Vec<Vec<F>>,
 }
 
-impl MerkleTree {
+impl<F: Field> MerkleTree<F> {
     /// Create a new Merkle tree from leaves
-    pub fn new(leaves: Vec<BabyBear>) -> Self {
+    pub fn new(leaves: Vec<F>) -> Self {
         let mut tree = Self {
             leaves: leaves.clone(),
             layers: vec![leaves],
@@ -87,7 +90,7 @@ impl MerkleTree {
                     next.push(hash_pair(chunk[0], chunk[1]));
                 } else {
                     // Odd number of nodes - hash with zero
-                    next.push(hash_pair(chunk[0], BabyBear::new(0)));
+                    next.push(hash_pair(chunk[0], F::ZERO));
                 }
             }
 
@@ -96,21 +99,21 @@ impl MerkleTree {
     }
 
     /// Get the root of the tree
-    pub fn root(&self) -> BabyBear {
+    pub fn root(&self) -> F {
         if self.layers.is_empty() || self.layers.last().unwrap().is_empty() {
-            BabyBear::new(0)
+            F::ZERO
         } else {
             self.layers.last().unwrap()[0]
         }
     }
 
     /// Get Merkle proof for a leaf at given index
-    pub fn get_proof(&self, index: usize) -> Option<([BabyBear; TREE_DEPTH], [bool; TREE_DEPTH])> {
+    pub fn get_proof(&self, index: usize) -> Option<([F; TREE_DEPTH], [bool; TREE_DEPTH])> {
         if index >= self.leaves.len() {
             return None;
         }
 
-        let mut path = [BabyBear::new(0); TREE_DEPTH];
+        let mut path = [F::ZERO; TREE_DEPTH];
         let mut path_indices = [true; TREE_DEPTH];
         let mut current_index = index;
 
@@ -119,7 +122,7 @@ impl MerkleTree {
                 break;
             }
 
-            let is_left = current_index % 2 == 0;
+            let is_left = current_index.is_multiple_of(2);
             // path_indices[i]=true means current is RIGHT child in verification
             // So if we're on the left, we set path_indices to false
             path_indices[level] = !is_left;
@@ -133,7 +136,7 @@ impl MerkleTree {
             path[level] = if sibling_index < layer.len() {
                 layer[sibling_index]
             } else {
-                BabyBear::new(0)
+                F::ZERO
             };
 
             current_index /= 2;
@@ -143,6 +146,54 @@ impl MerkleTree {
     }
 }
 
+/// Roots of empty subtrees: `zeros[0] = 0`, `zeros[i + 1] = hash(zeros[i], zeros[i])`
+///
+/// Matches the `ZEROS` table of the on-chain incremental tree.
+pub fn zero_hashes<F: Field>() -> [F; TREE_DEPTH + 1] {
+    let mut zeros = [F::ZERO; TREE_DEPTH + 1];
+    for i in 0..TREE_DEPTH {
+        zeros[i + 1] = hash_pair(zeros[i], zeros[i]);
+    }
+    zeros
+}
+
+/// Merkle proof for `index` in a TREE_DEPTH tree filled left to right with
+/// `leaves`, where every unfilled slot is an empty subtree (as on-chain).
+///
+/// Returns `(root, path, path_indices)` with `path_indices[i] = true` when the
+/// current node is the RIGHT child, the convention `BalanceWithdrawalCircuit`
+/// verifies with.
+pub fn filled_tree_proof<F: Field>(
+    leaves: &[F],
+    index: usize,
+) -> Option<(F, [F; TREE_DEPTH], [bool; TREE_DEPTH])> {
+    if index >= leaves.len() || leaves.len() > 1 << TREE_DEPTH {
+        return None;
+    }
+
+    let zeros = zero_hashes::<F>();
+    let mut path = [F::ZERO; TREE_DEPTH];
+    let mut path_indices = [false; TREE_DEPTH];
+    let mut layer = leaves.to_vec();
+    let mut current_index = index;
+
+    for level in 0..TREE_DEPTH {
+        let is_right = current_index % 2 == 1;
+        let sibling_index = if is_right { current_index - 1 } else { current_index + 1 };
+
+        path_indices[level] = is_right;
+        path[level] = layer.get(sibling_index).copied().unwrap_or(zeros[level]);
+
+        layer = layer
+            .chunks(2)
+            .map(|pair| hash_pair(pair[0], pair.get(1).copied().unwrap_or(zeros[level])))
+            .collect();
+        current_index /= 2;
+    }
+
+    Some((layer[0], path, path_indices))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -197,10 +248,38 @@ mod tests {
         let root = tree.root();
 
         // Verify all leaves
-        for i in 0..4 {
+        for (i, &leaf) in leaves.iter().enumerate() {
             let (path, indices) = tree.get_proof(i).unwrap();
-            assert!(verify_merkle_proof(leaves[i], &path, &indices, root));
+            assert!(verify_merkle_proof(leaf, &path, &indices, root));
+        }
+    }
+
+    #[test]
+    fn test_filled_tree_proof() {
+        let leaves: Vec<BabyBear> = (1..=5).map(BabyBear::new).collect();
+        let zeros = zero_hashes::<BabyBear>();
+
+        let (root, path, indices) = filled_tree_proof(&leaves, 4).unwrap();
+
+        // Leaf 4 is a left child whose sibling slot is empty
+        assert!(!indices[0]);
+        assert_eq!(path[0], zeros[0]);
+
+        let mut current = leaves[4];
+        for i in 0..TREE_DEPTH {
+            current = if indices[i] {
+                hash_pair(path[i], current)
+            } else {
+                hash_pair(current, path[i])
+            };
+        }
+        assert_eq!(current, root);
+
+        // Every leaf proves against the same root
+        for i in 0..leaves.len() {
+            assert_eq!(filled_tree_proof(&leaves, i).unwrap().0, root);
         }
+        assert!(filled_tree_proof(&leaves, 5).is_none());
     }
 
     #[test]
@@ -220,7 +299,7 @@ mod tests {
     #[ignore = "MerkleTree builder needs path_indices fix"]
     fn test_merkle_tree_many_leaves() {
         let leaves: Vec<BabyBear> = (0..100)
-            .map(|i| BabyBear::new(i))
+            .map(BabyBear::new)
             .collect();
 
         let tree = MerkleTree::new(leaves.clone());
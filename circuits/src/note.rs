@@ -0,0 +1,549 @@
+//! Note and Merkle path files used by the CLI tools
+//!
+//! A note is the JSON object the web app exports (see docs/backup.md):
+//!
+//! ```json
+//! {
+//!   "spendingKey": "123456...",
+//!   "asset": "0x0000000000000000000000000000000000000000",
+//!   "randomness": "789012...",
+//!   "balance": "1000000000000000000",
+//!   "commitment": "345678...",
+//!   "noteIndex": 42
+//! }
+//! ```
+//!
+//! `asset` is the token contract address, the zero address for ETH. Other
+//! values are decimal (or 0x-hex) strings. The Plonky3 circuits work over
+//! BabyBear, so every value must be below the BabyBear modulus to be proven.
+//!
+//! Notes of the BN254 vault carry full BN254 scalars (the format in
+//! docs/backup.md allows values up to the BN254 modulus). Their commitments
+//! are BN254 Poseidon hashes, which the BabyBear circuits cannot open, so
+//! `Note::secrets` rejects them with `NoteError::UnsupportedNote` rather than
+//! a generic range error. Such notes are withdrawn through the Groth16
+//! circuit (`r1cs::WithdrawalCircuit`) and read with `Note::vault_secrets`:
+//! there `spendingKey` is the wallet seed of `keys::SpendingKey::from_seed`,
+//! and the commitment is that of `compute_domain_commitment` in the vault's
+//! domain.
+//!
+//! The BabyBear commitment is hash(domain, hash(spendingKey), assetId,
+//! balance, randomness), with `assetId` the hash of the asset address's limbs
+//! (`address::asset_id`), so it binds all 160 bits of the address, and
+//! `domain` the deployment's `poseidon::domain_tag`.
+
+use std::fmt;
+
+use p3_baby_bear::BabyBear;
+use p3_field::PrimeField32;
+
+use crate::abi::parse_address;
+use crate::address::{asset_id, Address};
+use crate::groth16::{U256, BN254_SCALAR_MODULUS};
+use crate::keys::{note_commitment, SpendingKey};
+use crate::merkle::{filled_tree_proof, TREE_DEPTH};
+use crate::poseidon::poseidon_hash;
+use crate::poseidon_bn254::{self, address_to_field, compute_domain_commitment, Bn254Field};
+
+type Val = BabyBear;
+
+/// Errors raised while loading notes and Merkle paths
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NoteError {
+    /// The input is not valid JSON or is missing a field
+    InvalidJson(String),
+    /// A field is not a decimal or 0x-hex integer
+    InvalidNumber(&'static str),
+    /// The asset is not a 0x-prefixed 20-byte address
+    InvalidAsset,
+    /// A field does not fit in the BabyBear field
+    OutOfRange(&'static str),
+    /// A note field holds a BN254 scalar: the note belongs to the BN254 vault
+    UnsupportedNote(&'static str),
+    /// A field is not below the BN254 scalar modulus
+    NotInScalarField(&'static str),
+    /// The stored commitment does not match the note secrets
+    CommitmentMismatch,
+    /// The Merkle path has the wrong depth
+    InvalidPathLength(usize),
+    /// The tree snapshot does not contain the note
+    NoteNotInTree(u64),
+}
+
+impl fmt::Display for NoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoteError::InvalidJson(e) => write!(f, "Invalid JSON: {}", e),
+            NoteError::InvalidNumber(field) => write!(f, "Invalid number in field '{}'", field),
+            NoteError::InvalidAsset => write!(f, "Asset must be a 0x-prefixed 20-byte address"),
+            NoteError::OutOfRange(field) => {
+                write!(f, "Field '{}' does not fit in the BabyBear field", field)
+            }
+            NoteError::UnsupportedNote(field) => write!(
+                f,
+                "Unsupported note: field '{}' is a BN254 value, and BN254 notes cannot be proven by the BabyBear circuits",
+                field
+            ),
+            NoteError::NotInScalarField(field) => {
+                write!(f, "Field '{}' is not below the BN254 scalar field modulus", field)
+            }
+            NoteError::CommitmentMismatch => write!(f, "Note commitment does not match its secrets"),
+            NoteError::InvalidPathLength(len) => {
+                write!(f, "Merkle path has {} levels (expected {})", len, TREE_DEPTH)
+            }
+            NoteError::NoteNotInTree(index) => write!(f, "Note index {} is not in the tree", index),
+        }
+    }
+}
+
+impl std::error::Error for NoteError {}
+
+/// Parse a decimal or 0x-hex string into a BabyBear element
+pub fn parse_field(name: &'static str, value: &str) -> Result<Val, NoteError> {
+    let value = U256::parse(value).map_err(|_| NoteError::InvalidNumber(name))?;
+    let limbs = value.limbs();
+    if limbs[1] != 0 || limbs[2] != 0 || limbs[3] != 0 || limbs[0] >= Val::ORDER_U32 as u64 {
+        return Err(NoteError::OutOfRange(name));
+    }
+    Ok(Val::new(limbs[0] as u32))
+}
+
+/// Parse a note field, telling BN254 notes apart from malformed ones
+fn parse_note_field(name: &'static str, value: &str) -> Result<Val, NoteError> {
+    parse_field(name, value).map_err(|e| match e {
+        NoteError::OutOfRange(_) if is_bn254_scalar(value) => NoteError::UnsupportedNote(name),
+        e => e,
+    })
+}
+
+fn is_bn254_scalar(value: &str) -> bool {
+    let r = U256::from_dec_str(BN254_SCALAR_MODULUS).expect("valid modulus");
+    U256::parse(value).is_ok_and(|v| v < r)
+}
+
+/// Parse a decimal or 0x-hex string into a BN254 scalar
+///
+/// Values at or above the modulus are rejected rather than reduced, so each
+/// scalar has one spelling.
+pub fn parse_bn254(name: &'static str, value: &str) -> Result<Bn254Field, NoteError> {
+    if !is_bn254_scalar(value) {
+        U256::parse(value).map_err(|_| NoteError::InvalidNumber(name))?;
+        return Err(NoteError::NotInScalarField(name));
+    }
+    Ok(Bn254Field::from_limbs(U256::parse(value).expect("parsed above").limbs()))
+}
+
+/// Note as exported by the web app
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Note {
+    pub spending_key: String,
+    pub asset: String,
+    pub randomness: String,
+    pub balance: String,
+    pub commitment: String,
+    /// Null in a note that has not been deposited yet, read as 0
+    #[serde(deserialize_with = "index_or_null")]
+    pub note_index: u64,
+}
+
+fn index_or_null<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    <Option<u64> as serde::Deserialize>::deserialize(deserializer).map(Option::unwrap_or_default)
+}
+
+/// Note secrets as field elements
+#[derive(Clone, Copy, Debug)]
+pub struct NoteSecrets {
+    pub spending_key: Val,
+    pub asset: Address,
+    pub balance: Val,
+    pub randomness: Val,
+    pub commitment: Val,
+    pub note_index: u64,
+}
+
+impl Note {
+    pub fn from_json(json: &str) -> Result<Self, NoteError> {
+        serde_json::from_str(json).map_err(|e| NoteError::InvalidJson(e.to_string()))
+    }
+
+    /// Full asset address, as the vault takes it
+    pub fn asset_address(&self) -> Result<[u8; 20], NoteError> {
+        parse_address(&self.asset).ok_or(NoteError::InvalidAsset)
+    }
+
+    /// Convert to field elements and check the stored commitment in the
+    /// deployment's `domain` (`poseidon::domain_tag`)
+    pub fn secrets(&self, domain: Val) -> Result<NoteSecrets, NoteError> {
+        let spending_key = parse_note_field("spendingKey", &self.spending_key)?;
+        let asset = self.asset_address()?;
+        let balance = parse_note_field("balance", &self.balance)?;
+        let randomness = parse_note_field("randomness", &self.randomness)?;
+        let commitment = parse_note_field("commitment", &self.commitment)?;
+
+        let computed = note_commitment(domain, poseidon_hash(spending_key), asset_id(&asset), balance, randomness);
+        if computed != commitment {
+            return Err(NoteError::CommitmentMismatch);
+        }
+
+        Ok(NoteSecrets {
+            spending_key,
+            asset,
+            balance,
+            randomness,
+            commitment,
+            note_index: self.note_index,
+        })
+    }
+}
+
+/// Secrets of a BalanceVaultV4 note as BN254 scalars
+#[derive(Clone, Copy, Debug)]
+pub struct VaultNoteSecrets {
+    pub spending_key: SpendingKey,
+    pub asset: Bn254Field,
+    pub balance: Bn254Field,
+    pub randomness: Bn254Field,
+    pub commitment: Bn254Field,
+    pub note_index: u64,
+}
+
+impl Note {
+    /// Convert a BalanceVaultV4 note and check the stored commitment in the
+    /// vault's `domain` (`poseidon_bn254::compute_domain`)
+    pub fn vault_secrets(&self, domain: Bn254Field) -> Result<VaultNoteSecrets, NoteError> {
+        let spending_key = SpendingKey::from_seed(parse_bn254("spendingKey", &self.spending_key)?);
+        let asset = address_to_field(&self.asset_address()?);
+        let balance = parse_bn254("balance", &self.balance)?;
+        let randomness = parse_bn254("randomness", &self.randomness)?;
+        let commitment = parse_bn254("commitment", &self.commitment)?;
+
+        let computed =
+            compute_domain_commitment(domain, spending_key.spending_key_hash(), asset, balance, randomness);
+        if computed != commitment {
+            return Err(NoteError::CommitmentMismatch);
+        }
+
+        Ok(VaultNoteSecrets { spending_key, asset, balance, randomness, commitment, note_index: self.note_index })
+    }
+}
+
+/// Merkle path file, using the circom input names
+///
+/// `pathIndices[i]` is 1 when the current node is the right child.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MerklePathFile {
+    #[serde(default)]
+    pub root: Option<String>,
+    pub path_elements: Vec<String>,
+    pub path_indices: Vec<u8>,
+}
+
+/// Snapshot of all leaves in insertion order
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct TreeSnapshot {
+    pub leaves: Vec<String>,
+}
+
+/// Deposit commitments an association set provider approves
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct AssociationSetFile {
+    pub commitments: Vec<String>,
+}
+
+/// Merkle proof in the form `BalanceWithdrawalWitness` expects
+#[derive(Clone, Debug)]
+pub struct MerkleWitness {
+    /// Root the path was read from, if the source states one
+    pub root: Option<Val>,
+    pub path: [Val; TREE_DEPTH],
+    pub indices: [bool; TREE_DEPTH],
+}
+
+/// Merkle proof in the vault's BN254 tree, in the form
+/// `r1cs::WithdrawalWitness` expects
+#[derive(Clone, Debug)]
+pub struct VaultMerkleWitness {
+    /// Root the path was read from, if the source states one
+    pub root: Option<Bn254Field>,
+    pub path: Vec<Bn254Field>,
+    /// `true` if the current node is the left child
+    pub indices: Vec<bool>,
+}
+
+impl MerklePathFile {
+    pub fn from_json(json: &str) -> Result<Self, NoteError> {
+        serde_json::from_str(json).map_err(|e| NoteError::InvalidJson(e.to_string()))
+    }
+
+    pub fn to_witness(&self) -> Result<MerkleWitness, NoteError> {
+        if self.path_elements.len() != TREE_DEPTH {
+            return Err(NoteError::InvalidPathLength(self.path_elements.len()));
+        }
+        if self.path_indices.len() != TREE_DEPTH {
+            return Err(NoteError::InvalidPathLength(self.path_indices.len()));
+        }
+
+        let mut path = [Val::new(0); TREE_DEPTH];
+        let mut indices = [false; TREE_DEPTH];
+        for i in 0..TREE_DEPTH {
+            path[i] = parse_field("pathElements", &self.path_elements[i])?;
+            indices[i] = match self.path_indices[i] {
+                0 => false,
+                1 => true,
+                _ => return Err(NoteError::InvalidNumber("pathIndices")),
+            };
+        }
+
+        let root = match &self.root {
+            Some(root) => Some(parse_field("root", root)?),
+            None => None,
+        };
+
+        Ok(MerkleWitness { root, path, indices })
+    }
+
+    /// Read a path in the vault's BN254 tree
+    pub fn to_vault_witness(&self) -> Result<VaultMerkleWitness, NoteError> {
+        if self.path_elements.len() != TREE_DEPTH {
+            return Err(NoteError::InvalidPathLength(self.path_elements.len()));
+        }
+        if self.path_indices.len() != TREE_DEPTH {
+            return Err(NoteError::InvalidPathLength(self.path_indices.len()));
+        }
+
+        let path = self
+            .path_elements
+            .iter()
+            .map(|e| parse_bn254("pathElements", e))
+            .collect::<Result<Vec<_>, _>>()?;
+        let indices = self
+            .path_indices
+            .iter()
+            .map(|&i| match i {
+                0 => Ok(true),
+                1 => Ok(false),
+                _ => Err(NoteError::InvalidNumber("pathIndices")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let root = match &self.root {
+            Some(root) => Some(parse_bn254("root", root)?),
+            None => None,
+        };
+
+        Ok(VaultMerkleWitness { root, path, indices })
+    }
+}
+
+impl TreeSnapshot {
+    /// Accepts `{"leaves": [...]}` or a bare array of commitments
+    pub fn from_json(json: &str) -> Result<Self, NoteError> {
+        if let Ok(leaves) = serde_json::from_str::<Vec<String>>(json) {
+            return Ok(Self { leaves });
+        }
+        serde_json::from_str(json).map_err(|e| NoteError::InvalidJson(e.to_string()))
+    }
+
+    pub fn to_witness(&self, note: &NoteSecrets) -> Result<MerkleWitness, NoteError> {
+        let leaves = self
+            .leaves
+            .iter()
+            .map(|leaf| parse_field("leaves", leaf))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let index = note.note_index as usize;
+        if leaves.get(index) != Some(&note.commitment) {
+            return Err(NoteError::NoteNotInTree(note.note_index));
+        }
+
+        let (root, path, indices) =
+            filled_tree_proof(&leaves, index).ok_or(NoteError::NoteNotInTree(note.note_index))?;
+
+        Ok(MerkleWitness { root: Some(root), path, indices })
+    }
+
+    /// Rebuild the vault's BN254 tree and take the note's path in it
+    pub fn to_vault_witness(&self, note: &VaultNoteSecrets) -> Result<VaultMerkleWitness, NoteError> {
+        let leaves = self
+            .leaves
+            .iter()
+            .map(|leaf| parse_bn254("leaves", leaf))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let index = note.note_index as usize;
+        if leaves.get(index) != Some(&note.commitment) {
+            return Err(NoteError::NoteNotInTree(note.note_index));
+        }
+
+        let (root, path, indices) = poseidon_bn254::filled_tree_proof(&leaves, index, TREE_DEPTH)
+            .ok_or(NoteError::NoteNotInTree(note.note_index))?;
+
+        Ok(VaultMerkleWitness { root: Some(root), path, indices })
+    }
+}
+
+impl AssociationSetFile {
+    /// Accepts `{"commitments": [...]}` or a bare array of commitments
+    pub fn from_json(json: &str) -> Result<Self, NoteError> {
+        if let Ok(commitments) = serde_json::from_str::<Vec<String>>(json) {
+            return Ok(Self { commitments });
+        }
+        serde_json::from_str(json).map_err(|e| NoteError::InvalidJson(e.to_string()))
+    }
+
+    pub fn commitments(&self) -> Result<Vec<Val>, NoteError> {
+        self.commitments
+            .iter()
+            .map(|c| parse_field("commitments", c))
+            .collect()
+    }
+
+    /// The commitments as BN254 scalars, for vault notes
+    pub fn vault_commitments(&self) -> Result<Vec<Bn254Field>, NoteError> {
+        self.commitments
+            .iter()
+            .map(|c| parse_bn254("commitments", c))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASSET: &str = "0x000000000000000000000000000000000000abcd";
+    const DOMAIN: Val = Val::new(8453);
+
+    fn sample_note() -> Note {
+        let asset = parse_address(ASSET).unwrap();
+        let commitment =
+            note_commitment(DOMAIN, poseidon_hash(Val::new(12345)), asset_id(&asset), Val::new(10000), Val::new(99999));
+        Note {
+            spending_key: "12345".to_string(),
+            asset: ASSET.to_string(),
+            randomness: "0x1869f".to_string(),
+            balance: "10000".to_string(),
+            commitment: commitment.as_canonical_u32().to_string(),
+            note_index: 2,
+        }
+    }
+
+    #[test]
+    fn test_note_json() {
+        let json = serde_json::to_string(&sample_note()).unwrap();
+        assert!(json.contains("\"spendingKey\""));
+        assert!(json.contains("\"noteIndex\":2"));
+
+        let secrets = Note::from_json(&json).unwrap().secrets(DOMAIN).unwrap();
+        assert_eq!(secrets.randomness, Val::new(99999));
+        assert_eq!(secrets.asset, parse_address(ASSET).unwrap());
+
+        // A note exported before its deposit is mined has no index yet
+        let undeposited = Note::from_json(&json.replace("\"noteIndex\":2", "\"noteIndex\":null")).unwrap();
+        assert_eq!(undeposited.note_index, 0);
+    }
+
+    #[test]
+    fn test_note_rejects_bn254_sized_values() {
+        let mut note = sample_note();
+        note.spending_key = "739687949354288662934120678802664238479213643757161742868491514386759180214".to_string();
+        assert_eq!(note.secrets(DOMAIN).unwrap_err(), NoteError::UnsupportedNote("spendingKey"));
+        assert!(note.secrets(DOMAIN).unwrap_err().to_string().starts_with("Unsupported note"));
+
+        // Not a BN254 scalar either: a malformed note, not a BN254 one
+        note.spending_key = format!("0x{}", "f".repeat(64));
+        assert_eq!(note.secrets(DOMAIN).unwrap_err(), NoteError::OutOfRange("spendingKey"));
+    }
+
+    #[test]
+    fn test_note_rejects_wrong_commitment() {
+        let mut note = sample_note();
+        note.balance = "10001".to_string();
+        assert_eq!(note.secrets(DOMAIN).unwrap_err(), NoteError::CommitmentMismatch);
+
+        // Same secrets in another deployment
+        assert_eq!(sample_note().secrets(Val::new(84532)).unwrap_err(), NoteError::CommitmentMismatch);
+
+        // Same secrets under another asset
+        let mut note = sample_note();
+        note.asset = "0x000000000000000000000000000000000000abce".to_string();
+        assert_eq!(note.secrets(DOMAIN).unwrap_err(), NoteError::CommitmentMismatch);
+
+        note.asset = "0xabce".to_string();
+        assert_eq!(note.secrets(DOMAIN).unwrap_err(), NoteError::InvalidAsset);
+    }
+
+    #[test]
+    fn test_tree_snapshot_witness() {
+        let secrets = sample_note().secrets(DOMAIN).unwrap();
+        let leaves = vec!["1".to_string(), "2".to_string(), secrets.commitment.as_canonical_u32().to_string()];
+
+        let snapshot = TreeSnapshot::from_json(&serde_json::to_string(&leaves).unwrap()).unwrap();
+        let witness = snapshot.to_witness(&secrets).unwrap();
+        assert!(witness.root.is_some());
+        assert!(!witness.indices[0]);
+        assert!(witness.indices[1]);
+
+        let mut wrong = secrets;
+        wrong.note_index = 1;
+        assert_eq!(snapshot.to_witness(&wrong).unwrap_err(), NoteError::NoteNotInTree(1));
+    }
+
+    #[test]
+    fn test_association_set_file() {
+        let file = AssociationSetFile::from_json(r#"{"commitments": ["1", "0x2"]}"#).unwrap();
+        assert_eq!(file.commitments().unwrap(), vec![Val::new(1), Val::new(2)]);
+
+        let file = AssociationSetFile::from_json(r#"["3"]"#).unwrap();
+        assert_eq!(file.commitments().unwrap(), vec![Val::new(3)]);
+
+        let file = AssociationSetFile::from_json(r#"["0xffffffff"]"#).unwrap();
+        assert_eq!(file.commitments().unwrap_err(), NoteError::OutOfRange("commitments"));
+    }
+
+    #[test]
+    fn test_vault_note() {
+        let domain = poseidon_bn254::compute_domain(8453, &[0x44; 20]);
+        let spending_key = SpendingKey::from_seed(Bn254Field::new(12345));
+        let commitment = compute_domain_commitment(
+            domain,
+            spending_key.spending_key_hash(),
+            Bn254Field::new(0xABCD),
+            Bn254Field::new(10000),
+            Bn254Field::new(99999),
+        );
+        let mut note = sample_note();
+        note.commitment = commitment.to_decimal_string();
+
+        let secrets = note.vault_secrets(domain).unwrap();
+        assert_eq!(secrets.spending_key, spending_key);
+        assert_eq!(secrets.commitment, commitment);
+
+        // Another vault's domain does not open the commitment
+        let other = poseidon_bn254::compute_domain(8453, &[0x45; 20]);
+        assert_eq!(note.vault_secrets(other).unwrap_err(), NoteError::CommitmentMismatch);
+
+        // The modulus is not read as zero
+        note.randomness = BN254_SCALAR_MODULUS.to_string();
+        assert_eq!(note.vault_secrets(domain).unwrap_err(), NoteError::NotInScalarField("randomness"));
+
+        let leaves = vec!["1".to_string(), "2".to_string(), "3".to_string(), commitment.to_decimal_string()];
+        let snapshot = TreeSnapshot::from_json(&serde_json::to_string(&leaves).unwrap()).unwrap();
+        let mut secrets = secrets;
+        secrets.note_index = 3;
+        let witness = snapshot.to_vault_witness(&secrets).unwrap();
+        assert_eq!(witness.indices[..2], [false, false]);
+        assert!(witness.indices[2]);
+
+        // The same path in the circom layout, where 1 marks a right child
+        let file = MerklePathFile {
+            root: Some(witness.root.unwrap().to_decimal_string()),
+            path_elements: witness.path.iter().map(|e| e.to_decimal_string()).collect(),
+            path_indices: witness.indices.iter().map(|&left| u8::from(!left)).collect(),
+        };
+        let read = file.to_vault_witness().unwrap();
+        assert_eq!((read.root, read.path, read.indices), (witness.root, witness.path, witness.indices));
+    }
+}
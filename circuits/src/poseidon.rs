@@ -1,9 +1,18 @@
 //! Poseidon2 hash implementation for Plonky3
 //!
-//! Implements Poseidon2 with BabyBear field for efficient ZK proving.
+//! Implements Poseidon2 over any Plonky3 field, BabyBear by default.
 //! Configuration: width=16, 8 external rounds, 13 internal rounds
+//!
+//! Round constants and the MDS matrix are 32-bit integers embedded in the
+//! field: BabyBear reduces them mod p, Goldilocks takes them as they are.
+//! The S-box x^7 is a permutation of both fields.
 
 use p3_baby_bear::BabyBear;
+use p3_field::Field;
+
+use crate::address::Address;
+use crate::groth16::U256;
+use crate::poseidon_bn254::compute_domain;
 
 /// Poseidon2 configuration constants
 pub const WIDTH: usize = 16;
@@ -84,21 +93,37 @@ pub const MDS_MATRIX: [[u32; WIDTH]; WIDTH] = [
     [7, 1, 3, 5, 7, 1, 3, 5, 7, 1, 3, 5, 7, 1, 3, 5],
 ];
 
+/// Whether round `r` is external (full S-box layer)
+pub const fn is_external_round(r: usize) -> bool {
+    r < EXTERNAL_ROUNDS / 2 || r >= EXTERNAL_ROUNDS / 2 + INTERNAL_ROUNDS
+}
+
 /// Poseidon2 state
-pub struct Poseidon2State {
-    state: [BabyBear; WIDTH],
+pub struct Poseidon2State<F = BabyBear> {
+    state: [F; WIDTH],
+}
+
+impl<F: Field> Default for Poseidon2State<F> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl Poseidon2State {
+impl<F: Field> Poseidon2State<F> {
     /// Create a new state with all zeros
     pub fn new() -> Self {
         Self {
-            state: [BabyBear::new(0); WIDTH],
+            state: [F::ZERO; WIDTH],
         }
     }
 
-    /// Apply S-box (x^7 for BabyBear)
-    fn sbox(x: BabyBear) -> BabyBear {
+    /// Start from an arbitrary state
+    pub fn from_state(state: [F; WIDTH]) -> Self {
+        Self { state }
+    }
+
+    /// Apply S-box (x^7)
+    fn sbox(x: F) -> F {
         let x2 = x * x;
         let x4 = x2 * x2;
         let x6 = x4 * x2;
@@ -119,12 +144,11 @@ impl Poseidon2State {
 
     /// Apply MDS matrix
     fn mds_layer(&mut self) {
-        let mut result = [BabyBear::new(0); WIDTH];
+        let mut result = [F::ZERO; WIDTH];
 
-        for i in 0..WIDTH {
-            for j in 0..WIDTH {
-                let mds_val = BabyBear::new(MDS_MATRIX[i][j]);
-                result[i] = result[i] + mds_val * self.state[j];
+        for (out, row) in result.iter_mut().zip(MDS_MATRIX.iter()) {
+            for (&mds_val, &x) in row.iter().zip(self.state.iter()) {
+                *out += F::from_u32(mds_val) * x;
             }
         }
 
@@ -133,100 +157,120 @@ impl Poseidon2State {
 
     /// Add round constants
     fn add_constants(&mut self, round: usize) {
-        for i in 0..WIDTH {
-            // Reduce constant modulo BabyBear modulus
-            let c = ROUND_CONSTANTS[round][i] % 2013265921;
-            self.state[i] = self.state[i] + BabyBear::new(c);
+        for (x, &c) in self.state.iter_mut().zip(ROUND_CONSTANTS[round].iter()) {
+            *x += F::from_u32(c);
         }
     }
 
-    /// Run the full Poseidon2 permutation
-    pub fn permute(&mut self) {
-        // First half of external rounds
-        for r in 0..EXTERNAL_ROUNDS / 2 {
-            self.add_constants(r);
+    /// Apply round `r`: constants, S-box layer, MDS
+    pub fn apply_round(&mut self, r: usize) {
+        self.add_constants(r);
+        if is_external_round(r) {
             self.full_sbox_layer();
-            self.mds_layer();
-        }
-
-        // Internal rounds
-        for r in 0..INTERNAL_ROUNDS {
-            self.add_constants(EXTERNAL_ROUNDS / 2 + r);
+        } else {
             self.partial_sbox_layer();
-            self.mds_layer();
         }
+        self.mds_layer();
+    }
 
-        // Second half of external rounds
-        for r in 0..EXTERNAL_ROUNDS / 2 {
-            self.add_constants(EXTERNAL_ROUNDS / 2 + INTERNAL_ROUNDS + r);
-            self.full_sbox_layer();
-            self.mds_layer();
+    /// Run the full Poseidon2 permutation
+    pub fn permute(&mut self) {
+        for r in 0..TOTAL_ROUNDS {
+            self.apply_round(r);
         }
     }
 
+    /// Current state
+    pub fn state(&self) -> [F; WIDTH] {
+        self.state
+    }
+
     /// Absorb input into state
-    pub fn absorb(&mut self, input: &[BabyBear]) {
+    pub fn absorb(&mut self, input: &[F]) {
         for (i, &val) in input.iter().enumerate().take(RATE) {
-            self.state[i] = self.state[i] + val;
+            self.state[i] += val;
         }
     }
 
     /// Squeeze output from state
-    pub fn squeeze(&self) -> BabyBear {
+    pub fn squeeze(&self) -> F {
         self.state[0]
     }
 }
 
 /// Hash two field elements together (for Merkle tree)
-pub fn hash_pair(left: BabyBear, right: BabyBear) -> BabyBear {
-    let mut state = Poseidon2State::new();
+pub fn hash_pair<F: Field>(left: F, right: F) -> F {
+    let mut state = Poseidon2State::<F>::new();
     state.absorb(&[left, right]);
     state.permute();
     state.squeeze()
 }
 
-/// Hash secret and nullifier preimage to create commitment
-pub fn hash_commitment(secret: BabyBear, nullifier_preimage: BabyBear) -> BabyBear {
-    hash_pair(secret, nullifier_preimage)
+/// Deployment domain of the notes of `vault` on `chain_id`
+///
+/// The vault's own DOMAIN (`poseidon_bn254::compute_domain`, which carries
+/// the derivation version) reduced mod p, so the STARK and Groth16 notes of
+/// one deployment share a domain and a STARK verifier can take the DOMAIN
+/// the vault passes it.
+pub fn domain_tag<F: Field>(chain_id: u64, vault: &Address) -> F {
+    reduce_domain(&U256::from(compute_domain(chain_id, vault)))
 }
 
-/// Hash nullifier preimage to create nullifier
-pub fn hash_nullifier(nullifier_preimage: BabyBear) -> BabyBear {
-    let mut state = Poseidon2State::new();
-    state.absorb(&[nullifier_preimage]);
-    state.permute();
-    state.squeeze()
+/// A vault DOMAIN reduced into `F`, as the generated STARK verifiers reduce it
+pub fn reduce_domain<F: Field>(domain: &U256) -> F {
+    let base = F::from_u32(256);
+    domain.to_be_bytes().iter().fold(F::ZERO, |acc, &b| acc * base + F::from_u8(b))
+}
+
+/// Commitment to a note of the deployment with `domain`:
+/// hash(domain, secret, nullifier_preimage)
+pub fn hash_commitment<F: Field>(domain: F, secret: F, nullifier_preimage: F) -> F {
+    poseidon_hash_3(domain, secret, nullifier_preimage)
+}
+
+/// Nullifier of a note of the deployment with `domain`:
+/// hash(domain, nullifier_preimage)
+pub fn hash_nullifier<F: Field>(domain: F, nullifier_preimage: F) -> F {
+    hash_pair(domain, nullifier_preimage)
 }
 
 /// Hash arbitrary field elements (for WASM bindings)
-pub fn poseidon_hash_slice(input: &[BabyBear]) -> BabyBear {
-    let mut state = Poseidon2State::new();
+pub fn poseidon_hash_slice<F: Field>(input: &[F]) -> F {
+    let mut state = Poseidon2State::<F>::new();
     state.absorb(input);
     state.permute();
     state.squeeze()
 }
 
 /// Hash a single field element
-pub fn poseidon_hash(input: BabyBear) -> BabyBear {
-    let mut state = Poseidon2State::new();
+pub fn poseidon_hash<F: Field>(input: F) -> F {
+    let mut state = Poseidon2State::<F>::new();
     state.absorb(&[input]);
     state.permute();
     state.squeeze()
 }
 
 /// Hash two field elements
-pub fn poseidon_hash_2(a: BabyBear, b: BabyBear) -> BabyBear {
+pub fn poseidon_hash_2<F: Field>(a: F, b: F) -> F {
     hash_pair(a, b)
 }
 
 /// Hash three field elements
-pub fn poseidon_hash_3(a: BabyBear, b: BabyBear, c: BabyBear) -> BabyBear {
-    let mut state = Poseidon2State::new();
+pub fn poseidon_hash_3<F: Field>(a: F, b: F, c: F) -> F {
+    let mut state = Poseidon2State::<F>::new();
     state.absorb(&[a, b, c]);
     state.permute();
     state.squeeze()
 }
 
+/// Hash four field elements
+pub fn poseidon_hash_4<F: Field>(a: F, b: F, c: F, d: F) -> F {
+    let mut state = Poseidon2State::<F>::new();
+    state.absorb(&[a, b, c, d]);
+    state.permute();
+    state.squeeze()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -267,20 +311,42 @@ mod tests {
         let secret = BabyBear::new(12345);
         let nullifier_preimage = BabyBear::new(67890);
 
-        let commitment = hash_commitment(secret, nullifier_preimage);
+        let commitment = hash_commitment(BabyBear::new(1), secret, nullifier_preimage);
         assert_ne!(commitment, BabyBear::new(0));
     }
 
     #[test]
     fn test_hash_nullifier() {
         let preimage = BabyBear::new(12345);
-        let nullifier = hash_nullifier(preimage);
+        let nullifier = hash_nullifier(BabyBear::new(1), preimage);
         assert_ne!(nullifier, BabyBear::new(0));
     }
 
+    #[test]
+    fn test_domain_separation() {
+        use p3_field::PrimeField32;
+
+        let vault = [0x44; 20];
+        let base: BabyBear = domain_tag(8453, &vault);
+        let base_sepolia: BabyBear = domain_tag(84532, &vault);
+        let other_vault: BabyBear = domain_tag(8453, &[0x45; 20]);
+        assert_ne!(base, base_sepolia);
+        assert_ne!(base, other_vault);
+
+        // The vault's DOMAIN mod p, reduced here from its decimal digits
+        let p = BabyBear::ORDER_U32 as u64;
+        let domain = U256::from(compute_domain(8453, &vault)).to_dec_string();
+        let reduced = domain.bytes().fold(0u64, |acc, d| (acc * 10 + (d - b'0') as u64) % p);
+        assert_eq!(base.as_canonical_u32() as u64, reduced);
+
+        let (secret, preimage) = (BabyBear::new(12345), BabyBear::new(67890));
+        assert_ne!(hash_commitment(base, secret, preimage), hash_commitment(base_sepolia, secret, preimage));
+        assert_ne!(hash_nullifier(base, preimage), hash_nullifier(other_vault, preimage));
+    }
+
     #[test]
     fn test_permutation_changes_state() {
-        let mut state = Poseidon2State::new();
+        let mut state = Poseidon2State::<BabyBear>::new();
         state.state[0] = BabyBear::new(1);
 
         let before = state.state[0];
@@ -289,4 +355,19 @@ mod tests {
 
         assert_ne!(before, after);
     }
+
+    #[test]
+    fn test_hash_over_goldilocks() {
+        use p3_field::{PrimeCharacteristicRing, PrimeField64};
+        use p3_goldilocks::Goldilocks;
+
+        let a = Goldilocks::from_u64(123);
+        let b = Goldilocks::from_u64(456);
+        assert_eq!(hash_pair(a, b), hash_pair(a, b));
+        assert_ne!(hash_pair(a, b), hash_pair(b, a));
+
+        // Same inputs, different field, different digest
+        let babybear = hash_pair(BabyBear::new(123), BabyBear::new(456));
+        assert_ne!(hash_pair(a, b).as_canonical_u64(), babybear.as_canonical_u64());
+    }
 }
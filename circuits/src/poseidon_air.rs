@@ -0,0 +1,400 @@
+//! Poseidon2 permutation gadget and the row programs built from it
+//!
+//! One row holds one full permutation of `poseidon.rs`, laid out so every
+//! constraint has degree at most 3 and no preprocessed columns are needed:
+//!
+//! - the 16 input lanes
+//! - per round, a cube column `c_j = u_j^3` for every S-boxed lane
+//!   (`u = prev + rc`), followed by the 16 lanes after the round, which
+//!   must equal `MDS·s` with `s_j = c_j^2·u_j` (= `u_j^7`) on S-boxed lanes
+//!   and `s_j = u_j` elsewhere
+//!
+//! The last 16 of these columns are the permutation output, and every
+//! hash in the crate reads lane 0 of it.
+//!
+//! Circuits are row programs (`Program`): row `k` of the trace runs step
+//! `k`, a permutation whose input lanes the circuit wires to public values,
+//! its own columns and the previous row's digest (`CARRY_COL`). One-hot
+//! step flags select which wiring applies to a row. Rows past the last step
+//! hash the zero state with every flag cleared, so the permutation
+//! constraints hold on all rows without selectors.
+//!
+//! There are no preprocessed columns, and the circuits do not support them:
+//! p3-uni-stark at the pinned version commits the main trace only, and the
+//! generated Solidity verifiers open no second commitment. Round constants
+//! need no column, since every row runs the whole permutation and they are
+//! coefficients of its constraints, the same on every row. The step flags
+//! live in the main trace, and `Program::eval` pins them to the one
+//! schedule from the first row on, so the prover has no choice in them.
+//! Moving constants and selectors into a committed preprocessed trace needs
+//! a p3-uni-stark that commits one, plus a verifier that opens it.
+
+use std::ops::{Add, Mul, Sub};
+
+use p3_air::AirBuilder;
+use p3_field::Field;
+use p3_matrix::dense::RowMajorMatrix;
+
+use crate::poseidon::{is_external_round, Poseidon2State, MDS_MATRIX, ROUND_CONSTANTS, TOTAL_ROUNDS, WIDTH};
+
+/// Lanes the S-box is applied to in round `r`
+const fn sbox_lanes(r: usize) -> usize {
+    if is_external_round(r) {
+        WIDTH
+    } else {
+        1
+    }
+}
+
+/// Columns of one permutation
+pub const PERM_COLS: usize = {
+    let mut cols = WIDTH;
+    let mut r = 0;
+    while r < TOTAL_ROUNDS {
+        cols += sbox_lanes(r) + WIDTH;
+        r += 1;
+    }
+    cols
+};
+
+/// First output lane of the permutation; `OUTPUT_COL` is the digest
+pub const OUTPUT_COL: usize = PERM_COLS - WIDTH;
+
+/// Digest of the previous row
+pub const CARRY_COL: usize = PERM_COLS;
+
+/// First of the columns a circuit adds to the program
+pub const AUX_COL: usize = CARRY_COL + 1;
+
+/// Fill `row[..PERM_COLS]` with the permutation of `input` and return
+/// the output state
+pub fn generate_permutation<F: Field>(input: [F; WIDTH], row: &mut [F]) -> [F; WIDTH] {
+    row[..WIDTH].copy_from_slice(&input);
+    let mut col = WIDTH;
+    let mut state = Poseidon2State::from_state(input);
+
+    for (r, rc) in ROUND_CONSTANTS.iter().enumerate() {
+        let before = state.state();
+        for j in 0..sbox_lanes(r) {
+            let u = before[j] + F::from_u32(rc[j]);
+            row[col] = u * u * u;
+            col += 1;
+        }
+        state.apply_round(r);
+        row[col..col + WIDTH].copy_from_slice(&state.state());
+        col += WIDTH;
+    }
+
+    state.state()
+}
+
+/// Residuals of the permutation constraints on one row's first
+/// `PERM_COLS` columns; all vanish iff the output lanes are the
+/// permutation of the input lanes
+pub fn permutation_residuals<F, E>(cols: &[E]) -> Vec<E>
+where
+    F: Field,
+    E: Clone + From<F> + Add<Output = E> + Sub<Output = E> + Mul<Output = E>,
+{
+    let mut residuals = Vec::with_capacity(PERM_COLS - WIDTH);
+    let mut prev = 0;
+    let mut col = WIDTH;
+
+    for (r, rc) in ROUND_CONSTANTS.iter().enumerate() {
+        let u: Vec<E> = (0..WIDTH)
+            .map(|j| cols[prev + j].clone() + E::from(F::from_u32(rc[j])))
+            .collect();
+
+        let mut s = u.clone();
+        for j in 0..sbox_lanes(r) {
+            let cube = cols[col].clone();
+            residuals.push(cube.clone() - u[j].clone() * u[j].clone() * u[j].clone());
+            s[j] = cube.clone() * cube * u[j].clone();
+            col += 1;
+        }
+
+        for i in 0..WIDTH {
+            let mixed = (0..WIDTH)
+                .map(|j| E::from(F::from_u32(MDS_MATRIX[i][j])) * s[j].clone())
+                .reduce(|a, b| a + b)
+                .expect("WIDTH > 0");
+            residuals.push(cols[col + i].clone() - mixed);
+        }
+        prev = col;
+        col += WIDTH;
+    }
+
+    residuals
+}
+
+/// Constrain the permutation in `row[..PERM_COLS]`
+pub fn eval_permutation<AB: AirBuilder>(builder: &mut AB, row: &[AB::Expr]) {
+    for residual in permutation_residuals::<AB::F, AB::Expr>(&row[..PERM_COLS]) {
+        builder.assert_zero(residual);
+    }
+}
+
+/// Shape of a row program: the permutation and carry, `aux_cols`
+/// circuit columns from `AUX_COL`, then one flag per step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Program {
+    pub aux_cols: usize,
+    pub num_steps: usize,
+}
+
+impl Program {
+    pub const fn new(aux_cols: usize, num_steps: usize) -> Self {
+        Self { aux_cols, num_steps }
+    }
+
+    /// Column of the flag of step `step`
+    pub const fn step_col(&self, step: usize) -> usize {
+        AUX_COL + self.aux_cols + step
+    }
+
+    pub const fn width(&self) -> usize {
+        self.step_col(self.num_steps)
+    }
+
+    /// Trace height: one row per step, rounded up to a power of two
+    pub fn height(&self) -> usize {
+        self.num_steps.next_power_of_two().max(4)
+    }
+
+    /// Trace whose step `k` hashes `inputs[k]`, zero padded to `WIDTH`
+    /// lanes
+    ///
+    /// Fills the permutations, carries and step flags; steps past
+    /// `inputs.len()` and rows past the last step hash the zero state. The
+    /// circuit fills its own columns afterwards.
+    pub fn trace<F: Field>(&self, inputs: &[Vec<F>]) -> RowMajorMatrix<F> {
+        assert!(inputs.len() <= self.num_steps, "more hashes than steps");
+        let width = self.width();
+        let mut values = vec![F::ZERO; self.height() * width];
+        let mut carry = F::ZERO;
+
+        for (k, row) in values.chunks_mut(width).enumerate() {
+            let mut input = [F::ZERO; WIDTH];
+            if let Some(lanes) = inputs.get(k) {
+                assert!(lanes.len() <= WIDTH, "step {} has more than {} inputs", k, WIDTH);
+                input[..lanes.len()].copy_from_slice(lanes);
+            }
+            let output = generate_permutation(input, row);
+            row[CARRY_COL] = carry;
+            if k < self.num_steps {
+                row[self.step_col(k)] = F::ONE;
+            }
+            carry = output[0];
+        }
+
+        RowMajorMatrix::new(values, width)
+    }
+
+    /// Constrain the shared columns: the permutation of every row, the
+    /// carried digest, and the step flags walking one row per step
+    pub fn eval<AB: AirBuilder>(&self, builder: &mut AB, local: &[AB::Expr], next: &[AB::Expr]) {
+        eval_permutation(builder, local);
+
+        let mut when_first_row = builder.when_first_row();
+        when_first_row.assert_one(local[self.step_col(0)].clone());
+        for k in 1..self.num_steps {
+            when_first_row.assert_zero(local[self.step_col(k)].clone());
+        }
+
+        let mut when_transition = builder.when_transition();
+        when_transition.assert_eq(next[CARRY_COL].clone(), local[OUTPUT_COL].clone());
+        when_transition.assert_zero(next[self.step_col(0)].clone());
+        for k in 1..self.num_steps {
+            when_transition.assert_eq(next[self.step_col(k)].clone(), local[self.step_col(k - 1)].clone());
+        }
+    }
+
+    /// Flag of step `step`: one on its row, zero elsewhere
+    pub fn flag<E: Clone>(&self, row: &[E], step: usize) -> E {
+        row[self.step_col(step)].clone()
+    }
+
+    /// Sum of the flags of steps `first..first + count`
+    pub fn phase<E>(&self, row: &[E], first: usize, count: usize) -> E
+    where
+        E: Clone + Add<Output = E>,
+    {
+        row[self.step_col(first)..self.step_col(first + count)]
+            .iter()
+            .cloned()
+            .reduce(|a, b| a + b)
+            .expect("empty phase")
+    }
+
+    /// `Σ 2^i · flag(first + i)`: the weight of the bit a row adds to a
+    /// number accumulated over steps `first..first + count`
+    pub fn bit_weight<F, E>(&self, row: &[E], first: usize, count: usize) -> E
+    where
+        F: Field,
+        E: Clone + From<F> + Add<Output = E> + Mul<Output = E>,
+    {
+        let mut weight = F::ONE;
+        let mut terms = Vec::with_capacity(count);
+        for k in first..first + count {
+            terms.push(E::from(weight) * self.flag(row, k));
+            weight = weight.double();
+        }
+        terms.into_iter().reduce(|a, b| a + b).expect("empty phase")
+    }
+
+    /// Constrain `acc` to the running sum of `bit · 2^i` over the rows of
+    /// steps `first + i`, with `bit` boolean on those rows; from the last
+    /// of them on, `acc` holds the number those bits spell
+    #[allow(clippy::too_many_arguments)]
+    pub fn eval_bit_sum<AB: AirBuilder>(
+        &self,
+        builder: &mut AB,
+        local: &[AB::Expr],
+        next: &[AB::Expr],
+        bit: usize,
+        acc: usize,
+        first: usize,
+        count: usize,
+    ) {
+        let local_weight = self.bit_weight::<AB::F, AB::Expr>(local, first, count);
+        let next_weight = self.bit_weight::<AB::F, AB::Expr>(next, first, count);
+
+        builder.when(self.phase(local, first, count)).assert_bool(local[bit].clone());
+        builder
+            .when_first_row()
+            .assert_eq(local[acc].clone(), local[bit].clone() * local_weight);
+        builder
+            .when_transition()
+            .assert_eq(next[acc].clone(), local[acc].clone() + next[bit].clone() * next_weight);
+    }
+}
+
+/// Constrain the input lanes of the rows `flag` selects to `lanes`,
+/// zero padded to `WIDTH`
+pub fn assert_inputs<AB: AirBuilder>(builder: &mut AB, flag: AB::Expr, row: &[AB::Expr], lanes: &[AB::Expr]) {
+    let mut when = builder.when(flag);
+    for (j, input) in row[..WIDTH].iter().enumerate() {
+        match lanes.get(j) {
+            Some(lane) => when.assert_eq(input.clone(), lane.clone()),
+            None => when.assert_zero(input.clone()),
+        }
+    }
+}
+
+/// Constrain the digest of the rows `flag` selects to `value`
+pub fn assert_digest<AB: AirBuilder>(builder: &mut AB, flag: AB::Expr, row: &[AB::Expr], value: AB::Expr) {
+    builder.when(flag).assert_eq(row[OUTPUT_COL].clone(), value);
+}
+
+/// Fill the columns of `Program::eval_bit_sum` with the low `count` bits
+/// of `value` on the rows of steps `first..first + count`
+pub fn fill_bit_sum<F: Field>(trace: &mut RowMajorMatrix<F>, bit: usize, acc: usize, first: usize, count: usize, value: u64) {
+    let width = trace.width;
+    let mut sum = F::ZERO;
+    let mut weight = F::ONE;
+    for (k, row) in trace.values.chunks_mut(width).enumerate() {
+        if (first..first + count).contains(&k) {
+            let b = (value >> (k - first)) & 1 == 1;
+            row[bit] = F::from_bool(b);
+            if b {
+                sum += weight;
+            }
+            weight = weight.double();
+        }
+        row[acc] = sum;
+    }
+}
+
+/// Constrain column `col` to one value on all rows
+pub fn assert_constant<AB: AirBuilder>(builder: &mut AB, local: &[AB::Expr], next: &[AB::Expr], col: usize) {
+    builder.when_transition().assert_eq(next[col].clone(), local[col].clone());
+}
+
+/// Set column `col` to `value` on all rows
+pub fn fill_constant<F: Field>(trace: &mut RowMajorMatrix<F>, col: usize, value: F) {
+    let width = trace.width;
+    for row in trace.values.chunks_mut(width) {
+        row[col] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poseidon::{poseidon_hash_2, poseidon_hash_4};
+    use p3_baby_bear::BabyBear;
+
+    type Val = BabyBear;
+
+    #[test]
+    fn test_layout() {
+        // 8 external rounds of 16 cubes, 13 internal rounds of one
+        assert_eq!(PERM_COLS, WIDTH + 8 * (WIDTH + WIDTH) + 13 * (1 + WIDTH));
+    }
+
+    #[test]
+    fn test_output_matches_permutation() {
+        let input: [Val; WIDTH] = core::array::from_fn(|i| Val::new(i as u32 * 7 + 1));
+        let mut row = vec![Val::new(0); PERM_COLS];
+        let output = generate_permutation(input, &mut row);
+
+        let mut state = Poseidon2State::from_state(input);
+        state.permute();
+        assert_eq!(output, state.state());
+        assert_eq!(row[OUTPUT_COL..], state.state());
+    }
+
+    #[test]
+    fn test_residuals_vanish_on_valid_row() {
+        let input: [Val; WIDTH] = core::array::from_fn(|i| Val::new(1000 + i as u32));
+        let mut row = vec![Val::new(0); PERM_COLS];
+        generate_permutation(input, &mut row);
+
+        let residuals = permutation_residuals::<Val, Val>(&row);
+        assert!(residuals.iter().all(|&x| x == Val::new(0)));
+    }
+
+    #[test]
+    fn test_residuals_catch_tampering() {
+        let mut row = vec![Val::new(0); PERM_COLS];
+        generate_permutation([Val::new(3); WIDTH], &mut row);
+
+        // Any cube, intermediate lane or output lane
+        for col in [WIDTH, WIDTH + 40, OUTPUT_COL - 5, OUTPUT_COL] {
+            let mut tampered = row.clone();
+            tampered[col] += Val::new(1);
+            let residuals = permutation_residuals::<Val, Val>(&tampered);
+            assert!(residuals.iter().any(|&x| x != Val::new(0)), "column {}", col);
+        }
+    }
+
+    #[test]
+    fn test_fill_bit_sum() {
+        let program = Program::new(2, 5);
+        let mut trace = program.trace::<Val>(&[]);
+        fill_bit_sum(&mut trace, AUX_COL, AUX_COL + 1, 1, 3, 0b101);
+        let rows: Vec<&[Val]> = trace.values.chunks(program.width()).collect();
+
+        let bits: Vec<Val> = rows.iter().map(|r| r[AUX_COL]).collect();
+        assert_eq!(bits[1..4], [Val::new(1), Val::new(0), Val::new(1)]);
+        assert_eq!(rows[0][AUX_COL + 1], Val::new(0));
+        assert_eq!(rows[7][AUX_COL + 1], Val::new(5));
+    }
+
+    #[test]
+    fn test_program_trace_chains_digests() {
+        let (a, b) = (Val::new(5), Val::new(6));
+        let first = poseidon_hash_2(a, b);
+        let inputs = vec![vec![a, b], vec![first, Val::new(1), Val::new(2), Val::new(3)]];
+        let program = Program::new(2, 3);
+        let trace = program.trace(&inputs);
+        let rows: Vec<&[Val]> = trace.values.chunks(program.width()).collect();
+
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0][OUTPUT_COL], first);
+        assert_eq!(rows[1][CARRY_COL], first);
+        assert_eq!(rows[1][OUTPUT_COL], poseidon_hash_4(first, Val::new(1), Val::new(2), Val::new(3)));
+        assert_eq!(rows[2][program.step_col(2)], Val::new(1));
+        assert!(rows[3][program.step_col(0)..].iter().all(|&f| f == Val::new(0)));
+    }
+}
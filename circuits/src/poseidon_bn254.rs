@@ -1,12 +1,20 @@
 //! BN254 Poseidon hash implementation for V4 vault compatibility
 //!
 //! This implements Poseidon hash over the BN254 scalar field to match
-//! the poseidon-solidity library used in BalanceVaultV4.
+//! the poseidon-solidity library used in BalanceVaultV4 and circomlib's
+//! `Poseidon` template used by `circom/withdrawal.circom`.
 //!
 //! Field: BN254 (alt_bn128)
 //! p = 21888242871839275222246405745257275088548364400416034343698204186575808495617
 
+use std::collections::VecDeque;
 use std::ops::{Add, Mul, Sub};
+use std::sync::OnceLock;
+
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, BigInt, BigInteger, Field, PrimeField};
+
+use crate::groth16::U256;
 
 /// BN254 scalar field modulus
 pub const BN254_MODULUS: &str = "21888242871839275222246405745257275088548364400416034343698204186575808495617";
@@ -44,10 +52,10 @@ impl Bn254Field {
         let padded = format!("{:0>64}", hex);
 
         let mut limbs = [0u64; 4];
-        for i in 0..4 {
+        for (i, limb) in limbs.iter_mut().enumerate() {
             let start = 64 - (i + 1) * 16;
             let end = 64 - i * 16;
-            limbs[i] = u64::from_str_radix(&padded[start..end], 16).unwrap_or(0);
+            *limb = u64::from_str_radix(&padded[start..end], 16).unwrap_or(0);
         }
 
         Self::from_limbs(limbs)
@@ -60,7 +68,6 @@ impl Bn254Field {
 
     pub fn to_decimal_string(&self) -> String {
         // Convert to decimal for display
-        let mut result = String::new();
         let mut temp = *self;
 
         if temp == Self::ZERO {
@@ -168,10 +175,10 @@ impl Add for Bn254Field {
         let mut result = [0u64; 4];
         let mut carry = 0u64;
 
-        for i in 0..4 {
+        for (i, out) in result.iter_mut().enumerate() {
             let (sum, c1) = self.limbs[i].overflowing_add(rhs.limbs[i]);
             let (sum, c2) = sum.overflowing_add(carry);
-            result[i] = sum;
+            *out = sum;
             carry = (c1 as u64) + (c2 as u64);
         }
 
@@ -212,215 +219,201 @@ impl Mul for Bn254Field {
     type Output = Self;
 
     fn mul(self, rhs: Self) -> Self {
-        // Full 512-bit product
-        let mut product = [0u128; 8];
-
-        for i in 0..4 {
-            let mut carry = 0u128;
-            for j in 0..4 {
-                let p = (self.limbs[i] as u128) * (rhs.limbs[j] as u128) + product[i + j] + carry;
-                product[i + j] = p & 0xFFFFFFFFFFFFFFFF;
-                carry = p >> 64;
-            }
-            product[i + 4] = carry;
-        }
-
-        // Barrett reduction (simplified)
-        // For now, use simple repeated subtraction for correctness
-        let mut result = [0u64; 4];
-        for i in 0..4 {
-            result[i] = product[i] as u64;
-        }
-
-        let mut r = Self { limbs: result };
+        (Fr::from(self) * Fr::from(rhs)).into()
+    }
+}
 
-        // Handle overflow from upper limbs
-        let mut overflow = Self::ZERO;
-        for i in 4..8 {
-            if product[i] != 0 {
-                // This is a simplified approach - in production use Montgomery reduction
-                let factor = Self { limbs: [product[i] as u64, 0, 0, 0] };
-                // 2^256 mod p = some constant, but for simplicity we reduce iteratively
-                overflow = overflow + factor;
-            }
-        }
+impl From<Fr> for Bn254Field {
+    fn from(value: Fr) -> Self {
+        Self { limbs: value.into_bigint().0 }
+    }
+}
 
-        // Reduce
-        r.reduce();
-        r
+/// The canonical value, e.g. as a calldata word
+impl From<Bn254Field> for U256 {
+    fn from(v: Bn254Field) -> Self {
+        U256::from_limbs(v.limbs)
     }
 }
 
-// ============ Poseidon Constants for BN254 ============
-// These match the poseidon-solidity library (T=3 for 2-to-1 hash)
-
-/// Poseidon T=3 (2 inputs + 1 capacity) round constants
-/// Generated using the Poseidon paper methodology for BN254
-pub const POSEIDON_T3_ROUND_CONSTANTS: [[u64; 4]; 65 * 3] = [
-    // Round 0
-    [0x6e08b9b9d8d47b8a, 0x3b9aca00f3e69a47, 0x2c23e0c2a4e94ef8, 0x0ee9a592ba9a9518],
-    [0x7c10b9b9d8d47b8a, 0x4b9aca00f3e69a47, 0x3c23e0c2a4e94ef8, 0x1ee9a592ba9a9518],
-    [0x8d10b9b9d8d47b8a, 0x5b9aca00f3e69a47, 0x4c23e0c2a4e94ef8, 0x2ee9a592ba9a9518],
-    // ... (simplified - in production, use full constants from circomlibjs)
-    // For now, using placeholder constants that will be replaced
-    [0, 0, 0, 0]; 192
-];
-
-/// MDS matrix for T=3 (circulant construction)
-pub const MDS_MATRIX_T3: [[Bn254Field; 3]; 3] = unsafe { std::mem::zeroed() };
-
-/// Poseidon hash state for T=3
-pub struct PoseidonT3 {
-    state: [Bn254Field; 3],
+impl From<Bn254Field> for Fr {
+    fn from(value: Bn254Field) -> Self {
+        // limbs are always reduced below the modulus
+        Fr::from_bigint(BigInt::new(value.limbs)).expect("Bn254Field is reduced")
+    }
 }
 
-impl PoseidonT3 {
-    const ROUNDS_F: usize = 8;  // Full rounds
-    const ROUNDS_P: usize = 57; // Partial rounds (for T=3)
+// ============ Poseidon Parameters ============
+// circomlib / poseidon-solidity parameters: x^5 S-box, 8 full rounds,
+// capacity element first. Round constants and the MDS matrix are derived
+// with the Grain LFSR from the Poseidon reference script
+// (generate_parameters_grain.sage), which is how circomlibjs produced its
+// constant tables.
+
+/// Round structure, round constants and MDS matrix for one state width
+#[derive(Clone, Debug)]
+pub struct PoseidonParams {
+    /// State width: inputs + 1 capacity element
+    pub width: usize,
+    pub full_rounds: usize,
+    pub partial_rounds: usize,
+    /// `width` constants per round, round-major
+    pub round_constants: Vec<Fr>,
+    /// `mds[i][j]` multiplies `state[j]` into `state[i]`
+    pub mds: Vec<Vec<Fr>>,
+}
 
-    pub fn new() -> Self {
-        Self {
-            state: [Bn254Field::ZERO; 3],
-        }
+impl PoseidonParams {
+    /// Parameters for T=3 (two inputs)
+    pub fn t3() -> &'static Self {
+        static PARAMS: OnceLock<PoseidonParams> = OnceLock::new();
+        PARAMS.get_or_init(|| Self::generate(3, 8, 57))
     }
 
-    /// Hash two field elements (for Merkle tree)
-    pub fn hash(inputs: [Bn254Field; 2]) -> Bn254Field {
-        let mut hasher = Self::new();
-        hasher.state[0] = inputs[0];
-        hasher.state[1] = inputs[1];
-        // state[2] is capacity, stays zero
-
-        hasher.permute();
-        hasher.state[0]
+    /// Parameters for T=4 (three inputs)
+    pub fn t4() -> &'static Self {
+        static PARAMS: OnceLock<PoseidonParams> = OnceLock::new();
+        PARAMS.get_or_init(|| Self::generate(4, 8, 56))
     }
 
-    fn permute(&mut self) {
-        // First half of full rounds
-        for r in 0..Self::ROUNDS_F / 2 {
-            self.add_round_constants(r);
-            self.full_sbox();
-            self.mds_mix();
-        }
+    fn generate(width: usize, full_rounds: usize, partial_rounds: usize) -> Self {
+        let mut grain = Grain::new(width, full_rounds, partial_rounds);
 
-        // Partial rounds
-        for r in 0..Self::ROUNDS_P {
-            self.add_round_constants(Self::ROUNDS_F / 2 + r);
-            self.partial_sbox();
-            self.mds_mix();
-        }
+        let round_constants = (0..(full_rounds + partial_rounds) * width)
+            .map(|_| grain.next_field_element())
+            .collect();
 
-        // Second half of full rounds
-        for r in 0..Self::ROUNDS_F / 2 {
-            self.add_round_constants(Self::ROUNDS_F / 2 + Self::ROUNDS_P + r);
-            self.full_sbox();
-            self.mds_mix();
-        }
-    }
+        // Cauchy matrix 1 / (x_i + y_j); the MDS sampling does not reject
+        // values above the modulus
+        let xy: Vec<Fr> = (0..2 * width)
+            .map(|_| Fr::from_le_bytes_mod_order(&grain.next_integer().to_bytes_le()))
+            .collect();
+        let mds = (0..width)
+            .map(|i| {
+                (0..width)
+                    .map(|j| (xy[i] + xy[width + j]).inverse().expect("distinct Cauchy points"))
+                    .collect()
+            })
+            .collect();
 
-    fn add_round_constants(&mut self, _round: usize) {
-        // Add round constants (simplified - using basic constants)
-        // In production, use the full circomlibjs constants
-        for i in 0..3 {
-            self.state[i] = self.state[i] + Bn254Field::new((i + 1) as u64);
-        }
+        Self { width, full_rounds, partial_rounds, round_constants, mds }
     }
 
-    fn full_sbox(&mut self) {
-        for i in 0..3 {
-            self.state[i] = self.state[i].sbox();
-        }
+    /// Whether round `r` applies the S-box to every element
+    pub fn is_full_round(&self, r: usize) -> bool {
+        r < self.full_rounds / 2 || r >= self.full_rounds / 2 + self.partial_rounds
     }
 
-    fn partial_sbox(&mut self) {
-        self.state[0] = self.state[0].sbox();
+    /// Apply the permutation in place
+    pub fn permute(&self, state: &mut [Fr]) {
+        assert_eq!(state.len(), self.width, "state width");
+        for r in 0..self.full_rounds + self.partial_rounds {
+            for (s, c) in state.iter_mut().zip(&self.round_constants[r * self.width..]) {
+                *s += c;
+            }
+            if self.is_full_round(r) {
+                state.iter_mut().for_each(|s| *s = s.pow([5]));
+            } else {
+                state[0] = state[0].pow([5]);
+            }
+            let old = state.to_vec();
+            for (s, row) in state.iter_mut().zip(&self.mds) {
+                *s = row.iter().zip(&old).map(|(m, x)| *m * x).sum();
+            }
+        }
     }
 
-    fn mds_mix(&mut self) {
-        // Simple MDS matrix for T=3 (circulant)
-        // [[2, 1, 1], [1, 2, 1], [1, 1, 2]]
-        let old = self.state;
-        let two = Bn254Field::new(2);
-        let one = Bn254Field::new(1);
-
-        self.state[0] = two * old[0] + one * old[1] + one * old[2];
-        self.state[1] = one * old[0] + two * old[1] + one * old[2];
-        self.state[2] = one * old[0] + one * old[1] + two * old[2];
+    /// Hash `width - 1` inputs: capacity zero in `state[0]`, output `state[0]`
+    pub fn hash(&self, inputs: &[Fr]) -> Fr {
+        assert_eq!(inputs.len() + 1, self.width, "input count");
+        let mut state = Vec::with_capacity(self.width);
+        state.push(Fr::ZERO);
+        state.extend_from_slice(inputs);
+        self.permute(&mut state);
+        state[0]
     }
 }
 
-/// Poseidon hash state for T=4 (3 inputs)
-pub struct PoseidonT4 {
-    state: [Bn254Field; 4],
+/// Grain LFSR in self-shrinking mode, seeded with the Poseidon instance
+struct Grain {
+    state: VecDeque<bool>,
 }
 
-impl PoseidonT4 {
-    const ROUNDS_F: usize = 8;
-    const ROUNDS_P: usize = 56; // For T=4
-
-    pub fn new() -> Self {
-        Self {
-            state: [Bn254Field::ZERO; 4],
+impl Grain {
+    const FIELD_BITS: usize = 254;
+
+    fn new(width: usize, full_rounds: usize, partial_rounds: usize) -> Self {
+        let mut state = VecDeque::with_capacity(80);
+        let mut push = |value: u64, bits: usize| {
+            state.extend((0..bits).rev().map(|i| (value >> i) & 1 == 1));
+        };
+        push(1, 2); // prime field
+        push(0, 4); // x^alpha S-box
+        push(Self::FIELD_BITS as u64, 12);
+        push(width as u64, 12);
+        push(full_rounds as u64, 10);
+        push(partial_rounds as u64, 10);
+        push((1 << 30) - 1, 30);
+
+        let mut grain = Self { state };
+        for _ in 0..160 {
+            grain.step();
         }
+        grain
     }
 
-    /// Hash three field elements (for commitment)
-    pub fn hash(inputs: [Bn254Field; 3]) -> Bn254Field {
-        let mut hasher = Self::new();
-        hasher.state[0] = inputs[0];
-        hasher.state[1] = inputs[1];
-        hasher.state[2] = inputs[2];
-        // state[3] is capacity
-
-        hasher.permute();
-        hasher.state[0]
+    fn step(&mut self) -> bool {
+        let s = &self.state;
+        let bit = s[62] ^ s[51] ^ s[38] ^ s[23] ^ s[13] ^ s[0];
+        self.state.pop_front();
+        self.state.push_back(bit);
+        bit
     }
 
-    fn permute(&mut self) {
-        for r in 0..Self::ROUNDS_F / 2 {
-            self.add_round_constants(r);
-            self.full_sbox();
-            self.mds_mix();
-        }
-
-        for r in 0..Self::ROUNDS_P {
-            self.add_round_constants(Self::ROUNDS_F / 2 + r);
-            self.partial_sbox();
-            self.mds_mix();
-        }
-
-        for r in 0..Self::ROUNDS_F / 2 {
-            self.add_round_constants(Self::ROUNDS_F / 2 + Self::ROUNDS_P + r);
-            self.full_sbox();
-            self.mds_mix();
+    /// Output bits come in pairs: keep the second when the first is set
+    fn next_bit(&mut self) -> bool {
+        loop {
+            let keep = self.step();
+            let bit = self.step();
+            if keep {
+                return bit;
+            }
         }
     }
 
-    fn add_round_constants(&mut self, _round: usize) {
-        for i in 0..4 {
-            self.state[i] = self.state[i] + Bn254Field::new((i + 1) as u64);
-        }
+    /// Next 254 output bits read as an integer, most significant bit first
+    fn next_integer(&mut self) -> BigInt<4> {
+        let bits: Vec<bool> = (0..Self::FIELD_BITS).map(|_| self.next_bit()).collect();
+        BigInt::from_bits_be(&bits)
     }
 
-    fn full_sbox(&mut self) {
-        for i in 0..4 {
-            self.state[i] = self.state[i].sbox();
+    /// Rejection-sample a field element
+    fn next_field_element(&mut self) -> Fr {
+        loop {
+            if let Some(f) = Fr::from_bigint(self.next_integer()) {
+                return f;
+            }
         }
     }
+}
+
+/// Poseidon hash with two inputs (T=3)
+pub struct PoseidonT3;
 
-    fn partial_sbox(&mut self) {
-        self.state[0] = self.state[0].sbox();
+impl PoseidonT3 {
+    /// Hash two field elements (for Merkle tree)
+    pub fn hash(inputs: [Bn254Field; 2]) -> Bn254Field {
+        PoseidonParams::t3().hash(&inputs.map(Fr::from)).into()
     }
+}
 
-    fn mds_mix(&mut self) {
-        let old = self.state;
-        let two = Bn254Field::new(2);
-        let one = Bn254Field::new(1);
+/// Poseidon hash with three inputs (T=4)
+pub struct PoseidonT4;
 
-        self.state[0] = two * old[0] + one * old[1] + one * old[2] + one * old[3];
-        self.state[1] = one * old[0] + two * old[1] + one * old[2] + one * old[3];
-        self.state[2] = one * old[0] + one * old[1] + two * old[2] + one * old[3];
-        self.state[3] = one * old[0] + one * old[1] + one * old[2] + two * old[3];
+impl PoseidonT4 {
+    /// Hash three field elements (for commitment)
+    pub fn hash(inputs: [Bn254Field; 3]) -> Bn254Field {
+        PoseidonParams::t4().hash(&inputs.map(Fr::from)).into()
     }
 }
 
@@ -446,6 +439,53 @@ pub fn compute_nullifier(spending_key: Bn254Field, note_index: Bn254Field) -> Bn
     hash_pair(spending_key, note_index)
 }
 
+// ============ Domain-separated derivation ============
+//
+// Version 1 above hashes only note secrets, so one note gives the same
+// commitment and nullifier in every deployment: on Base Sepolia and Base
+// mainnet, or in two vaults on one chain. Version 2 mixes in a deployment
+// domain, so a note's commitment and nullifier differ between deployments
+// and a proof for one cannot be replayed against another.
+
+/// Derivation version mixed into the domain
+pub const DERIVATION_VERSION: u64 = 2;
+
+/// An EVM address as a field element, i.e. `uint256(uint160(address))`
+pub fn address_to_field(address: &[u8; 20]) -> Bn254Field {
+    let mut bytes = [0u8; 32];
+    bytes[12..].copy_from_slice(address);
+    let mut limbs = [0u64; 4];
+    for (i, chunk) in bytes.rchunks_exact(8).enumerate() {
+        limbs[i] = u64::from_be_bytes(chunk.try_into().expect("8-byte chunk"));
+    }
+    Bn254Field::from_limbs(limbs)
+}
+
+/// Compute domain = Poseidon(DERIVATION_VERSION, chainId, vault)
+pub fn compute_domain(chain_id: u64, vault: &[u8; 20]) -> Bn254Field {
+    hash_3(Bn254Field::new(DERIVATION_VERSION), Bn254Field::new(chain_id), address_to_field(vault))
+}
+
+/// Compute commitment = Poseidon(Poseidon(domain, spendingKeyHash, asset), balance, randomness)
+///
+/// `asset` is the token address as `address_to_field`, zero for ETH, so a
+/// note of one token cannot be spent as another.
+pub fn compute_domain_commitment(
+    domain: Bn254Field,
+    spending_key_hash: Bn254Field,
+    asset: Bn254Field,
+    balance: Bn254Field,
+    randomness: Bn254Field,
+) -> Bn254Field {
+    compute_commitment(hash_3(domain, spending_key_hash, asset), balance, randomness)
+}
+
+/// Compute nullifier = Poseidon(domain, nk, noteIndex), with `nk` the
+/// nullifier deriving key of `keys`
+pub fn compute_domain_nullifier(domain: Bn254Field, nullifier_key: Bn254Field, note_index: Bn254Field) -> Bn254Field {
+    hash_3(domain, nullifier_key, note_index)
+}
+
 /// Compute Merkle root from leaf and path
 pub fn compute_merkle_root(leaf: Bn254Field, path: &[Bn254Field], indices: &[bool]) -> Bn254Field {
     let mut current = leaf;
@@ -463,6 +503,55 @@ pub fn compute_merkle_root(leaf: Bn254Field, path: &[Bn254Field], indices: &[boo
     current
 }
 
+/// Roots of empty subtrees: `zeros[0] = 0`, `zeros[i + 1] = hash(zeros[i], zeros[i])`
+///
+/// The `ZEROS` table of `BalanceVaultV4`.
+pub fn zero_hashes(depth: usize) -> Vec<Bn254Field> {
+    let mut zeros = vec![Bn254Field::ZERO];
+    for i in 0..depth {
+        zeros.push(hash_pair(zeros[i], zeros[i]));
+    }
+    zeros
+}
+
+/// Merkle proof for `index` in a tree of `depth` filled left to right with
+/// `leaves`, every unfilled slot an empty subtree, as `BalanceVaultV4`
+/// inserts notes
+///
+/// Returns `(root, path, indices)` in the `compute_merkle_root` convention:
+/// `indices[i]` is true when the current node is the left child.
+pub fn filled_tree_proof(
+    leaves: &[Bn254Field],
+    index: usize,
+    depth: usize,
+) -> Option<(Bn254Field, Vec<Bn254Field>, Vec<bool>)> {
+    if index >= leaves.len() || leaves.len() > 1 << depth {
+        return None;
+    }
+
+    let zeros = zero_hashes(depth);
+    let mut path = Vec::with_capacity(depth);
+    let mut indices = Vec::with_capacity(depth);
+    let mut layer = leaves.to_vec();
+    let mut current_index = index;
+
+    for zero in &zeros[..depth] {
+        let is_left = current_index.is_multiple_of(2);
+        let sibling_index = if is_left { current_index + 1 } else { current_index - 1 };
+
+        indices.push(is_left);
+        path.push(layer.get(sibling_index).copied().unwrap_or(*zero));
+
+        layer = layer
+            .chunks(2)
+            .map(|pair| hash_pair(pair[0], pair.get(1).copied().unwrap_or(*zero)))
+            .collect();
+        current_index /= 2;
+    }
+
+    Some((layer[0], path, indices))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -500,4 +589,116 @@ mod tests {
 
         assert_eq!(h1, h2);
     }
+
+    #[test]
+    fn test_field_mul_reduces_wide_product() {
+        // (p - 1)^2 = 1 mod p
+        let minus_one = Bn254Field::ZERO - Bn254Field::new(1);
+        assert_eq!(minus_one * minus_one, Bn254Field::new(1));
+    }
+
+    #[test]
+    fn test_circomlib_constants() {
+        let t3 = PoseidonParams::t3();
+        assert_eq!(t3.round_constants.len(), 65 * 3);
+        assert_eq!(
+            Bn254Field::from(t3.round_constants[0]).to_hex(),
+            "0x0ee9a592ba9a9518d05986d656f40c2114c4993c11bb29938d21d47304cd8e6e"
+        );
+        assert_eq!(
+            Bn254Field::from(t3.mds[0][0]).to_hex(),
+            "0x109b7f411ba0e4c9b2b70caf5c36a7b194be7c11ad24378bfedb68592ba8118b"
+        );
+    }
+
+    #[test]
+    fn test_circomlib_vectors() {
+        // circomlibjs poseidon([1, 2]) and poseidon([1, 2, 3])
+        let h2 = hash_pair(Bn254Field::new(1), Bn254Field::new(2));
+        assert_eq!(
+            h2.to_decimal_string(),
+            "7853200120776062878684798364095072458815029376092732009249414926327459813530"
+        );
+        let h3 = hash_3(Bn254Field::new(1), Bn254Field::new(2), Bn254Field::new(3));
+        assert_eq!(
+            h3.to_decimal_string(),
+            "6542985608222806190361240322586112750744169038454362455181422643027100751666"
+        );
+    }
+
+    #[test]
+    fn test_domain_separation() {
+        let vault = [0x44; 20];
+        let base_sepolia = compute_domain(84532, &vault);
+        let base = compute_domain(8453, &vault);
+        let other_vault = compute_domain(8453, &[0x45; 20]);
+        assert_ne!(base_sepolia, base);
+        assert_ne!(base, other_vault);
+
+        let (sk, idx) = (Bn254Field::new(12345), Bn254Field::new(45));
+        let skh = hash_pair(sk, Bn254Field::ZERO);
+        let (bal, rand) = (Bn254Field::new(1000), Bn254Field::new(99999));
+        let asset = address_to_field(&[0x77; 20]);
+        assert_ne!(compute_domain_nullifier(base_sepolia, sk, idx), compute_domain_nullifier(base, sk, idx));
+        assert_ne!(compute_domain_nullifier(base, sk, idx), compute_domain_nullifier(other_vault, sk, idx));
+        assert_ne!(
+            compute_domain_commitment(base_sepolia, skh, asset, bal, rand),
+            compute_domain_commitment(base, skh, asset, bal, rand)
+        );
+        // Nor does one note commit the same for two assets
+        assert_ne!(
+            compute_domain_commitment(base, skh, asset, bal, rand),
+            compute_domain_commitment(base, skh, Bn254Field::ZERO, bal, rand)
+        );
+        // Neither matches the version 1 derivation
+        assert_ne!(compute_domain_nullifier(base, sk, idx), compute_nullifier(sk, idx));
+        assert_ne!(compute_domain_commitment(base, skh, asset, bal, rand), compute_commitment(skh, bal, rand));
+    }
+
+    #[test]
+    fn test_address_to_field() {
+        let mut vault = [0u8; 20];
+        vault[0] = 0x44;
+        vault[19] = 0x86;
+        assert_eq!(
+            address_to_field(&vault),
+            Bn254Field::from_hex("0x4400000000000000000000000000000000000086")
+        );
+    }
+
+    /// Root after `BalanceVaultV4._insertNote` inserts each of `leaves`
+    fn vault_root(leaves: &[Bn254Field], depth: usize) -> Bn254Field {
+        let zeros = zero_hashes(depth);
+        let mut filled_subtrees = vec![Bn254Field::ZERO; depth];
+        let mut root = zeros[depth];
+        for (note_index, &leaf) in leaves.iter().enumerate() {
+            let mut current = leaf;
+            let mut index = note_index;
+            for i in 0..depth {
+                if index % 2 == 0 {
+                    filled_subtrees[i] = current;
+                    current = hash_pair(current, zeros[i]);
+                } else {
+                    current = hash_pair(filled_subtrees[i], current);
+                }
+                index /= 2;
+            }
+            root = current;
+        }
+        root
+    }
+
+    #[test]
+    fn test_filled_tree_matches_vault_insertion() {
+        let leaves: Vec<Bn254Field> = (1..=5).map(|i| Bn254Field::new(1000 + i)).collect();
+        let root = vault_root(&leaves, 20);
+
+        for (i, &leaf) in leaves.iter().enumerate() {
+            let (proof_root, path, indices) = filled_tree_proof(&leaves, i, 20).unwrap();
+            assert_eq!(proof_root, root);
+            assert_eq!(compute_merkle_root(leaf, &path, &indices), root);
+        }
+        assert!(filled_tree_proof(&leaves, 5, 20).is_none());
+        assert_eq!(zero_hashes(20)[20], vault_root(&[], 20));
+    }
 }
@@ -0,0 +1,1028 @@
+//! STARK prover and verifier for the Noctis circuits
+//!
+//! Wraps p3-uni-stark with the BabyBear configurations used by both circuits:
+//! - Degree-4 binomial extension for FRI challenges
+//! - Radix-2 DIT DFT
+//! - Either Poseidon2 (width 16) Merkle commitments and duplex challenger,
+//!   or Keccak-256 Merkle commitments and a serializing hash challenger
+//!
+//! The hash is picked per proof through `ProverConfig`. Poseidon2 is cheaper
+//! to prove; Keccak is cheaper to replay on the EVM, where `keccak256` is an
+//! opcode.
+//!
+//! Proofs are serialized with bincode into `proof_bytes`. The generated
+//! Solidity verifiers take the flat encoding from `evm_proof_bytes` instead,
+//! which exists for the Poseidon2 configuration only.
+
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use p3_baby_bear::{default_babybear_poseidon2_16, BabyBear, Poseidon2BabyBear};
+use p3_challenger::{DuplexChallenger, HashChallenger, SerializingChallenger32};
+use p3_commit::ExtensionMmcs;
+use p3_dft::Radix2DitParallel;
+use p3_field::extension::BinomialExtensionField;
+use p3_field::Field;
+use p3_fri::{FriParameters, TwoAdicFriPcs};
+use p3_keccak::Keccak256Hash;
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::Matrix;
+use p3_merkle_tree::MerkleTreeMmcs;
+use p3_symmetric::{
+    CompressionFunctionFromHasher, PaddingFreeSponge, SerializingHasher, TruncatedPermutation,
+};
+use p3_uni_stark::{prove, verify, Proof, StarkConfig, StarkGenericConfig};
+
+use crate::balance_withdrawal::{
+    BalanceWithdrawalCircuit, BalanceWithdrawalProof, BalanceWithdrawalWitness, PublicInputs,
+    WitnessError,
+};
+use crate::codegen::stark::encode_evm_proof;
+use crate::deposit::{DepositCircuit, DepositProof, DepositPublicInputs, DepositWitness};
+use crate::keccak_membership::{
+    KeccakMembershipCircuit, KeccakMembershipProof, KeccakMembershipWitness,
+};
+use crate::merge::{MergeCircuit, MergeProof, MergePublicInputs, MergeWitness};
+use crate::security::{security_level, FriPreset, SecurityLevel};
+use crate::transfer::{TransferCircuit, TransferProof, TransferPublicInputs, TransferWitness};
+use crate::withdrawal::{WithdrawalCircuit, WithdrawalProof, WithdrawalPublicInputs, WithdrawalWitness};
+
+pub type Val = BabyBear;
+pub type Challenge = BinomialExtensionField<Val, 4>;
+
+type Perm = Poseidon2BabyBear<16>;
+type MyHash = PaddingFreeSponge<Perm, 16, 8, 8>;
+type MyCompress = TruncatedPermutation<Perm, 2, 8, 16>;
+type ValMmcs =
+    MerkleTreeMmcs<<Val as Field>::Packing, <Val as Field>::Packing, MyHash, MyCompress, 8>;
+type ChallengeMmcs = ExtensionMmcs<Val, Challenge, ValMmcs>;
+type Challenger = DuplexChallenger<Val, Perm, 16, 8>;
+type Dft = Radix2DitParallel<Val>;
+type Pcs = TwoAdicFriPcs<Val, Dft, ValMmcs, ChallengeMmcs>;
+
+/// STARK configuration shared by all Noctis circuits
+pub type NoctisStarkConfig = StarkConfig<Pcs, Challenge, Challenger>;
+
+type ByteHash = Keccak256Hash;
+type KeccakFieldHash = SerializingHasher<ByteHash>;
+type KeccakCompress = CompressionFunctionFromHasher<ByteHash, 2, 32>;
+type KeccakValMmcs = MerkleTreeMmcs<Val, u8, KeccakFieldHash, KeccakCompress, 32>;
+type KeccakChallengeMmcs = ExtensionMmcs<Val, Challenge, KeccakValMmcs>;
+type KeccakChallenger = SerializingChallenger32<Val, HashChallenger<u8, ByteHash, 32>>;
+type KeccakPcs = TwoAdicFriPcs<Val, Dft, KeccakValMmcs, KeccakChallengeMmcs>;
+
+/// STARK configuration with Keccak-256 commitments and transcript
+pub type KeccakStarkConfig = StarkConfig<KeccakPcs, Challenge, KeccakChallenger>;
+
+/// Smallest trace height handed to the prover
+pub const MIN_TRACE_HEIGHT: usize = 4;
+
+/// FRI parameters: 116 conjectured bits, or less where the field caps a
+/// large trace (see `security`); short of 128, which this field cannot reach
+pub const LOG_BLOWUP: usize = 1;
+pub const NUM_QUERIES: usize = 100;
+pub const PROOF_OF_WORK_BITS: usize = 16;
+
+/// FRI parameters independent of the MMCS, shared with the verifier generator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FriParams {
+    pub log_blowup: usize,
+    pub log_final_poly_len: usize,
+    pub num_queries: usize,
+    pub proof_of_work_bits: usize,
+}
+
+impl Default for FriParams {
+    fn default() -> Self {
+        Self {
+            log_blowup: LOG_BLOWUP,
+            log_final_poly_len: 0,
+            num_queries: NUM_QUERIES,
+            proof_of_work_bits: PROOF_OF_WORK_BITS,
+        }
+    }
+}
+
+/// Hash behind the Merkle commitments and the Fiat-Shamir transcript
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StarkHash {
+    #[default]
+    Poseidon2,
+    Keccak,
+}
+
+/// Minimum conjectured security proving and verifying accept by default
+pub const MIN_SECURITY_BITS: f64 = 100.0;
+
+// One per `ProverConfig`, so the size difference costs nothing
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Backend {
+    Poseidon2(NoctisStarkConfig),
+    Keccak(KeccakStarkConfig),
+}
+
+/// STARK configuration handed to the prove and verify functions
+///
+/// A proof only verifies under the hash and FRI parameters it was produced
+/// with. Proving refuses to run, and verification rejects the proof, when
+/// the conjectured security for the trace falls below `min_security_bits`.
+pub struct ProverConfig {
+    pub(crate) backend: Backend,
+    params: FriParams,
+    min_security_bits: f64,
+}
+
+impl ProverConfig {
+    pub fn new(hash: StarkHash, params: &FriParams) -> Self {
+        let backend = match hash {
+            StarkHash::Poseidon2 => Backend::Poseidon2(config_with_params(params)),
+            StarkHash::Keccak => Backend::Keccak(keccak_config_with_params(params)),
+        };
+        Self {
+            backend,
+            params: *params,
+            min_security_bits: MIN_SECURITY_BITS,
+        }
+    }
+
+    /// Configuration for a named FRI preset
+    pub fn from_preset(hash: StarkHash, preset: FriPreset) -> Self {
+        Self::new(hash, &preset.params())
+    }
+
+    /// Change the security floor enforced by proving and verifying
+    pub fn with_min_security_bits(mut self, bits: f64) -> Self {
+        self.min_security_bits = bits;
+        self
+    }
+
+    pub fn hash(&self) -> StarkHash {
+        match self.backend {
+            Backend::Poseidon2(_) => StarkHash::Poseidon2,
+            Backend::Keccak(_) => StarkHash::Keccak,
+        }
+    }
+
+    pub fn params(&self) -> &FriParams {
+        &self.params
+    }
+
+    pub fn min_security_bits(&self) -> f64 {
+        self.min_security_bits
+    }
+
+    /// Security for a trace of `2^log_trace_height` rows
+    pub fn security(&self, log_trace_height: usize) -> SecurityLevel {
+        security_level(&self.params, self.hash(), log_trace_height)
+    }
+
+    pub(crate) fn check_security(&self, trace_height: usize) -> Result<(), ProverError> {
+        let bits = self.security(trace_height.ilog2() as usize).conjectured_bits;
+        if bits < self.min_security_bits {
+            return Err(ProverError::InsufficientSecurity {
+                bits,
+                required: self.min_security_bits,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Errors from proving or verifying
+#[derive(Debug)]
+pub enum ProverError {
+    /// The witness does not satisfy the statement
+    Witness(WitnessError),
+    /// The proof bytes could not be decoded
+    Deserialization(String),
+    /// The proof does not verify against the public inputs
+    Verification(String),
+    /// The configuration is below the required conjectured security
+    InsufficientSecurity { bits: f64, required: f64 },
+}
+
+impl fmt::Display for ProverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProverError::Witness(e) => write!(f, "Invalid witness: {}", e),
+            ProverError::Deserialization(e) => write!(f, "Invalid proof bytes: {}", e),
+            ProverError::Verification(e) => write!(f, "Proof verification failed: {}", e),
+            ProverError::InsufficientSecurity { bits, required } => write!(
+                f,
+                "Configuration gives {:.1} bits of conjectured security, {:.1} required",
+                bits, required
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProverError {}
+
+impl From<WitnessError> for ProverError {
+    fn from(e: WitnessError) -> Self {
+        ProverError::Witness(e)
+    }
+}
+
+/// Build the default STARK configuration (Poseidon2, default FRI parameters)
+pub fn default_config() -> ProverConfig {
+    ProverConfig::new(StarkHash::default(), &FriParams::default())
+}
+
+pub(crate) fn fri_config<M>(params: &FriParams, mmcs: M) -> FriParameters<M> {
+    FriParameters {
+        log_blowup: params.log_blowup,
+        log_final_poly_len: params.log_final_poly_len,
+        num_queries: params.num_queries,
+        proof_of_work_bits: params.proof_of_work_bits,
+        mmcs,
+    }
+}
+
+/// Build a Poseidon2 STARK configuration with the given FRI parameters
+///
+/// The Poseidon2 permutation uses the fixed BabyBear constants shipped with
+/// Plonky3, so prover and verifier agree without sharing a seed.
+pub fn config_with_params(params: &FriParams) -> NoctisStarkConfig {
+    let perm = default_babybear_poseidon2_16();
+    let hash = MyHash::new(perm.clone());
+    let compress = MyCompress::new(perm.clone());
+    let val_mmcs = ValMmcs::new(hash, compress);
+    let challenge_mmcs = ChallengeMmcs::new(val_mmcs.clone());
+
+    let pcs = Pcs::new(Dft::default(), val_mmcs, fri_config(params, challenge_mmcs));
+    let challenger = Challenger::new(perm);
+
+    NoctisStarkConfig::new(pcs, challenger)
+}
+
+/// Build a Keccak-256 STARK configuration with the given FRI parameters
+///
+/// Field elements are hashed as their little-endian u32 encodings; inner
+/// Merkle nodes are `keccak256(left || right)`.
+pub fn keccak_config_with_params(params: &FriParams) -> KeccakStarkConfig {
+    let byte_hash = ByteHash {};
+    let field_hash = KeccakFieldHash::new(byte_hash);
+    let compress = KeccakCompress::new(byte_hash);
+    let val_mmcs = KeccakValMmcs::new(field_hash, compress);
+    let challenge_mmcs = KeccakChallengeMmcs::new(val_mmcs.clone());
+
+    let pcs = KeccakPcs::new(Dft::default(), val_mmcs, fri_config(params, challenge_mmcs));
+    let challenger = KeccakChallenger::from_hasher(vec![], byte_hash);
+
+    KeccakStarkConfig::new(pcs, challenger)
+}
+
+/// Repeat the trace rows up to a power-of-two height of at least MIN_TRACE_HEIGHT
+pub(crate) fn pad_trace<F: Clone + Send + Sync>(trace: RowMajorMatrix<F>) -> RowMajorMatrix<F> {
+    let width = trace.width();
+    let height = trace.height();
+    let target = height.max(MIN_TRACE_HEIGHT).next_power_of_two();
+    if target == height {
+        return trace;
+    }
+
+    let mut values = Vec::with_capacity(target * width);
+    for i in 0..target {
+        let row = i % height;
+        values.extend_from_slice(&trace.values[row * width..(row + 1) * width]);
+    }
+    RowMajorMatrix::new(values, width)
+}
+
+/// Prove `air` under whichever configuration `config` holds
+macro_rules! prove_with {
+    ($config:expr, $air:expr, $trace:expr, $public_values:expr) => {
+        match &$config.backend {
+            Backend::Poseidon2(c) => bincode::serialize(&prove(c, $air, $trace, $public_values)),
+            Backend::Keccak(c) => bincode::serialize(&prove(c, $air, $trace, $public_values)),
+        }
+        .expect("proof serialization")
+    };
+}
+
+/// Decode `proof_bytes` for the configuration `config` holds and verify
+///
+/// The trace height comes from the proof, so the security floor is checked
+/// against what the prover actually committed to.
+macro_rules! verify_with {
+    ($config:expr, $air:expr, $proof_bytes:expr, $public_values:expr) => {
+        match &$config.backend {
+            Backend::Poseidon2(c) => {
+                let parts: ProofParts<NoctisStarkConfig> = decode_proof($proof_bytes)?;
+                $config.check_security(1 << parts.degree_bits)?;
+                let proof: Proof<NoctisStarkConfig> = decode_proof($proof_bytes)?;
+                guarded(|| verify(c, $air, &proof, $public_values).map_err(|e| format!("{:?}", e)))
+            }
+            Backend::Keccak(c) => {
+                let parts: ProofParts<KeccakStarkConfig> = decode_proof($proof_bytes)?;
+                $config.check_security(1 << parts.degree_bits)?;
+                let proof: Proof<KeccakStarkConfig> = decode_proof($proof_bytes)?;
+                guarded(|| verify(c, $air, &proof, $public_values).map_err(|e| format!("{:?}", e)))
+            }
+        }
+        .map_err(ProverError::Verification)
+    };
+}
+
+type Commitment<SC> = <<SC as StarkGenericConfig>::Pcs as p3_commit::Pcs<
+    <SC as StarkGenericConfig>::Challenge,
+    <SC as StarkGenericConfig>::Challenger,
+>>::Commitment;
+type PcsProof<SC> = <<SC as StarkGenericConfig>::Pcs as p3_commit::Pcs<
+    <SC as StarkGenericConfig>::Challenge,
+    <SC as StarkGenericConfig>::Challenger,
+>>::Proof;
+
+/// Contents of a p3-uni-stark `Proof`, which keeps its fields private
+///
+/// Same fields in the same order as `Proof`, so bincode decodes the same
+/// bytes into either. The shape checks and the EVM encoding read proofs
+/// through this.
+#[derive(serde::Deserialize)]
+#[serde(bound = "")]
+pub struct ProofParts<SC: StarkGenericConfig> {
+    pub commitments: CommitmentParts<Commitment<SC>>,
+    pub opened_values: OpenedValueParts<SC::Challenge>,
+    pub opening_proof: PcsProof<SC>,
+    pub degree_bits: usize,
+}
+
+/// Mirror of `p3_uni_stark::Commitments`
+#[derive(serde::Deserialize)]
+pub struct CommitmentParts<Com> {
+    pub trace: Com,
+    pub quotient_chunks: Com,
+    pub random: Option<Com>,
+}
+
+/// Mirror of `p3_uni_stark::OpenedValues`
+#[derive(serde::Deserialize)]
+pub struct OpenedValueParts<Challenge> {
+    pub trace_local: Vec<Challenge>,
+    pub trace_next: Vec<Challenge>,
+    pub quotient_chunks: Vec<Vec<Challenge>>,
+    pub random: Option<Vec<Challenge>>,
+}
+
+/// Run a verification, reporting a panic as a failure
+///
+/// Bytes from another configuration can decode into non-canonical field
+/// elements, on which p3 arithmetic overflows instead of returning an error.
+pub(crate) fn guarded(f: impl FnOnce() -> Result<(), String>) -> Result<(), String> {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| Err("malformed proof".to_string()))
+}
+
+/// Whether a proof of `trace` verifies, for traces `generate_trace` did not
+/// produce
+///
+/// Debug builds of the prover check the constraints themselves and panic on
+/// a violated one; release builds go on to a proof the verifier rejects.
+#[cfg(test)]
+pub(crate) fn forged_trace_verifies<A>(air: &A, trace: RowMajorMatrix<Val>, public_values: &Vec<Val>) -> bool
+where
+    A: p3_air::Air<p3_uni_stark::SymbolicAirBuilder<Val>>
+        + for<'a> p3_air::Air<p3_uni_stark::ProverConstraintFolder<'a, NoctisStarkConfig>>
+        + for<'a> p3_air::Air<p3_uni_stark::VerifierConstraintFolder<'a, NoctisStarkConfig>>
+        + for<'a> p3_air::Air<p3_uni_stark::DebugConstraintBuilder<'a, Val>>,
+{
+    let config = config_with_params(&FriParams::default());
+    catch_unwind(AssertUnwindSafe(|| {
+        let proof = prove(&config, air, pad_trace(trace), public_values);
+        verify(&config, air, &proof, public_values).is_ok()
+    }))
+    .unwrap_or(false)
+}
+
+pub(crate) fn decode_proof<P: serde::de::DeserializeOwned>(proof_bytes: &[u8]) -> Result<P, ProverError> {
+    bincode::deserialize(proof_bytes).map_err(|e| ProverError::Deserialization(e.to_string()))
+}
+
+/// Re-encode bincode proof bytes for the generated Solidity verifier
+///
+/// Only Poseidon2 proofs have an EVM encoding.
+pub fn evm_proof_bytes(proof_bytes: &[u8]) -> Result<Vec<u8>, ProverError> {
+    let proof: ProofParts<NoctisStarkConfig> = decode_proof(proof_bytes)?;
+    Ok(encode_evm_proof(&proof))
+}
+
+/// Prove a balance withdrawal
+pub fn prove_balance_withdrawal(
+    config: &ProverConfig,
+    circuit: &BalanceWithdrawalCircuit,
+    witness: &BalanceWithdrawalWitness,
+) -> Result<BalanceWithdrawalProof, ProverError> {
+    circuit.check_witness(witness)?;
+
+    let trace = pad_trace(circuit.generate_trace(witness));
+    config.check_security(trace.height())?;
+    let public_values = circuit.public_values();
+    let proof_bytes = prove_with!(config, circuit, trace, &public_values);
+
+    Ok(BalanceWithdrawalProof {
+        proof_bytes,
+        public_inputs: PublicInputs::from_circuit(circuit),
+    })
+}
+
+/// Verify a balance withdrawal proof against its public inputs
+pub fn verify_balance_withdrawal(
+    config: &ProverConfig,
+    proof: &BalanceWithdrawalProof,
+) -> Result<(), ProverError> {
+    let circuit: BalanceWithdrawalCircuit<Val> = proof.public_inputs.to_circuit();
+    let public_values = circuit.public_values();
+
+    verify_with!(config, &circuit, &proof.proof_bytes, &public_values)
+}
+
+/// Prove that a deposit commitment opens to the deposited amount
+pub fn prove_deposit(
+    config: &ProverConfig,
+    circuit: &DepositCircuit,
+    witness: &DepositWitness,
+) -> Result<DepositProof, ProverError> {
+    circuit.check_witness(witness)?;
+
+    let trace = pad_trace(circuit.generate_trace(witness));
+    config.check_security(trace.height())?;
+    let public_values = circuit.public_values();
+    let proof_bytes = prove_with!(config, circuit, trace, &public_values);
+
+    Ok(DepositProof {
+        proof_bytes,
+        public_inputs: DepositPublicInputs::from_circuit(circuit),
+    })
+}
+
+/// Verify a deposit proof against its public inputs
+pub fn verify_deposit(config: &ProverConfig, proof: &DepositProof) -> Result<(), ProverError> {
+    let circuit: DepositCircuit = proof.public_inputs.to_circuit();
+    let public_values = circuit.public_values();
+
+    verify_with!(config, &circuit, &proof.proof_bytes, &public_values)
+}
+
+/// Prove a 2-in-2-out transfer
+pub fn prove_transfer(
+    config: &ProverConfig,
+    circuit: &TransferCircuit,
+    witness: &TransferWitness,
+) -> Result<TransferProof, ProverError> {
+    circuit.check_witness(witness)?;
+
+    let trace = pad_trace(circuit.generate_trace(witness));
+    config.check_security(trace.height())?;
+    let public_values = circuit.public_values();
+    let proof_bytes = prove_with!(config, circuit, trace, &public_values);
+
+    Ok(TransferProof {
+        proof_bytes,
+        public_inputs: TransferPublicInputs::from_circuit(circuit),
+    })
+}
+
+/// Verify a transfer proof against its public inputs
+pub fn verify_transfer(config: &ProverConfig, proof: &TransferProof) -> Result<(), ProverError> {
+    let circuit: TransferCircuit = proof.public_inputs.to_circuit();
+    let public_values = circuit.public_values();
+
+    verify_with!(config, &circuit, &proof.proof_bytes, &public_values)
+}
+
+/// Prove a merge of up to MAX_MERGE_INPUTS notes into one
+pub fn prove_merge(
+    config: &ProverConfig,
+    circuit: &MergeCircuit,
+    witness: &MergeWitness,
+) -> Result<MergeProof, ProverError> {
+    circuit.check_witness(witness)?;
+
+    let trace = pad_trace(circuit.generate_trace(witness));
+    config.check_security(trace.height())?;
+    let public_values = circuit.public_values();
+    let proof_bytes = prove_with!(config, circuit, trace, &public_values);
+
+    Ok(MergeProof {
+        proof_bytes,
+        public_inputs: MergePublicInputs::from_circuit(circuit),
+    })
+}
+
+/// Verify a merge proof against its public inputs
+pub fn verify_merge(config: &ProverConfig, proof: &MergeProof) -> Result<(), ProverError> {
+    let circuit: MergeCircuit = proof.public_inputs.to_circuit();
+    let public_values = circuit.public_values();
+
+    verify_with!(config, &circuit, &proof.proof_bytes, &public_values)
+}
+
+/// Prove a fixed-denomination withdrawal
+pub fn prove_withdrawal(
+    config: &ProverConfig,
+    circuit: &WithdrawalCircuit,
+    witness: &WithdrawalWitness,
+) -> Result<WithdrawalProof, ProverError> {
+    let trace = pad_trace(circuit.generate_trace(witness));
+    config.check_security(trace.height())?;
+    let public_values = circuit.public_values();
+    let proof_bytes = prove_with!(config, circuit, trace, &public_values);
+
+    let public_inputs = WithdrawalPublicInputs::from_circuit(circuit);
+
+    Ok(WithdrawalProof { proof_bytes, public_inputs })
+}
+
+/// Verify a fixed-denomination withdrawal proof
+pub fn verify_withdrawal(
+    config: &ProverConfig,
+    proof: &WithdrawalProof,
+) -> Result<(), ProverError> {
+    let circuit: WithdrawalCircuit<Val> = proof.public_inputs.to_circuit();
+    let public_values = circuit.public_values();
+
+    verify_with!(config, &circuit, &proof.proof_bytes, &public_values)
+}
+
+/// Prove membership of a leaf in a Keccak-256 Merkle tree
+pub fn prove_keccak_membership(
+    config: &ProverConfig,
+    circuit: &KeccakMembershipCircuit,
+    witness: &KeccakMembershipWitness,
+) -> Result<KeccakMembershipProof, ProverError> {
+    circuit.check_witness(witness)?;
+
+    let trace = circuit.generate_trace(witness);
+    config.check_security(trace.height())?;
+    let public_values = circuit.public_values();
+    let proof_bytes = prove_with!(config, circuit, trace, &public_values);
+
+    Ok(KeccakMembershipProof {
+        proof_bytes,
+        leaf: circuit.leaf,
+        root: circuit.root,
+    })
+}
+
+/// Verify a Keccak-256 Merkle membership proof
+pub fn verify_keccak_membership(
+    config: &ProverConfig,
+    proof: &KeccakMembershipProof,
+) -> Result<(), ProverError> {
+    let circuit = KeccakMembershipCircuit::new(proof.leaf, proof.root);
+    let public_values = circuit.public_values();
+
+    verify_with!(config, &circuit, &proof.proof_bytes, &public_values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::keccak256;
+    use crate::association::AssociationSet;
+    use crate::balance_withdrawal::{compute_merkle_root_with_path, path_indices};
+    use crate::blocklist::FieldBlocklist;
+    use crate::keccak_merkle::{Digest, KeccakMerkleTree};
+    use crate::keys::{note_commitment, note_nullifier, nullifier_key};
+    use crate::merkle::TREE_DEPTH;
+    use crate::merge::plan_merge;
+    use crate::merkle::filled_tree_proof;
+    use crate::note::NoteSecrets;
+    use crate::poseidon::poseidon_hash;
+    use crate::transfer::{InputNote, OutputNote};
+    use crate::address::{asset_id, Address};
+    use p3_field::{PrimeField32, PrimeField64};
+
+    const RECIPIENT: Address = [0xab; 20];
+    const RELAYER: Address = [0xbe; 20];
+    const ASSET: Address = [0xa5; 20];
+    const DOMAIN: Val = Val::new(8453);
+
+    /// `address + p` as a 160-bit integer, the same field element
+    fn plus_modulus(address: Address) -> Address {
+        let mut out = address;
+        let mut carry = Val::ORDER_U32 as u64;
+        for byte in out.iter_mut().rev() {
+            carry += *byte as u64;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        out
+    }
+
+    fn sample_witness() -> BalanceWithdrawalWitness {
+        BalanceWithdrawalWitness {
+            domain: DOMAIN,
+            spending_key: Val::new(12345),
+            asset: [0; 20],
+            balance: Val::new(10000),
+            randomness: Val::new(99999),
+            note_index: 5,
+            merkle_path: [Val::new(0); TREE_DEPTH],
+            path_indices: path_indices(5),
+            new_randomness: Val::new(88888),
+            association_path: None,
+            blocklist_proof: None,
+        }
+    }
+
+    #[test]
+    fn test_prove_and_verify_balance_withdrawal() {
+        let witness = sample_witness();
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+
+        let config = default_config();
+        let proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        verify_balance_withdrawal(&config, &proof).unwrap();
+    }
+
+    #[test]
+    fn test_prove_and_verify_with_keccak() {
+        let witness = sample_witness();
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+
+        let config = ProverConfig::new(StarkHash::Keccak, &FriParams::default());
+        let proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        verify_balance_withdrawal(&config, &proof).unwrap();
+
+        // A Keccak proof does not verify under the Poseidon2 configuration
+        assert!(verify_balance_withdrawal(&default_config(), &proof).is_err());
+    }
+
+    #[test]
+    fn test_prove_refuses_insecure_config() {
+        let witness = sample_witness();
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+
+        let config = ProverConfig::from_preset(StarkHash::Poseidon2, FriPreset::FastTest);
+        assert!(matches!(
+            prove_balance_withdrawal(&config, &circuit, &witness),
+            Err(ProverError::InsufficientSecurity { .. })
+        ));
+
+        let config = config.with_min_security_bits(0.0);
+        let proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        verify_balance_withdrawal(&config, &proof).unwrap();
+
+        // Nor does a verifier with the default floor accept the proof
+        let strict = ProverConfig::from_preset(StarkHash::Poseidon2, FriPreset::FastTest);
+        assert!(matches!(
+            verify_balance_withdrawal(&strict, &proof),
+            Err(ProverError::InsufficientSecurity { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_change_commitment() {
+        let witness = sample_witness();
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+
+        let config = default_config();
+        let mut proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        proof.public_inputs.change_commitment = (proof.public_inputs.change_commitment + 1) % Val::ORDER_U32 as u64;
+
+        assert!(matches!(
+            verify_balance_withdrawal(&config, &proof),
+            Err(ProverError::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_rejects_change_added_to_full_withdrawal() {
+        let witness = sample_witness();
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(10000)).unwrap();
+        assert_eq!(circuit.change_commitment, Val::new(0));
+
+        let config = default_config();
+        let mut proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        proof.public_inputs.change_commitment = 42;
+
+        assert!(matches!(
+            verify_balance_withdrawal(&config, &proof),
+            Err(ProverError::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_relayer_or_fee() {
+        let witness = sample_witness();
+        let circuit = BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000))
+            .unwrap()
+            .with_relayer(RELAYER, Val::new(50));
+
+        let config = default_config();
+        let proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        verify_balance_withdrawal(&config, &proof).unwrap();
+
+        // A front-runner redirecting the fee to themselves
+        let mut stolen = BalanceWithdrawalProof {
+            proof_bytes: proof.proof_bytes.clone(),
+            public_inputs: proof.public_inputs,
+        };
+        stolen.public_inputs.relayer = [0xf0; 20];
+        assert!(matches!(
+            verify_balance_withdrawal(&config, &stolen),
+            Err(ProverError::Verification(_))
+        ));
+
+        // Addresses equal mod p are still different addresses
+        let mut stolen = BalanceWithdrawalProof {
+            proof_bytes: proof.proof_bytes.clone(),
+            public_inputs: proof.public_inputs,
+        };
+        stolen.public_inputs.relayer = plus_modulus(RELAYER);
+        assert!(matches!(
+            verify_balance_withdrawal(&config, &stolen),
+            Err(ProverError::Verification(_))
+        ));
+        let mut redirected = BalanceWithdrawalProof {
+            proof_bytes: proof.proof_bytes.clone(),
+            public_inputs: proof.public_inputs,
+        };
+        redirected.public_inputs.recipient = plus_modulus(RECIPIENT);
+        assert!(matches!(
+            verify_balance_withdrawal(&config, &redirected),
+            Err(ProverError::Verification(_))
+        ));
+
+        let mut raised = proof;
+        raised.public_inputs.fee = 5000;
+        assert!(matches!(
+            verify_balance_withdrawal(&config, &raised),
+            Err(ProverError::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_asset() {
+        let mut witness = sample_witness();
+        witness.asset = ASSET;
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+
+        let config = default_config();
+        let mut proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        assert_eq!(proof.public_inputs.asset, ASSET);
+        verify_balance_withdrawal(&config, &proof).unwrap();
+
+        // Withdrawing a cheap token's note as a valuable one, including one
+        // whose address equals the note's asset mod p
+        for asset in [[0; 20], plus_modulus(ASSET)] {
+            proof.public_inputs.asset = asset;
+            assert!(matches!(
+                verify_balance_withdrawal(&config, &proof),
+                Err(ProverError::Verification(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_rejects_other_domain() {
+        let witness = sample_witness();
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+
+        let config = default_config();
+        let mut proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        assert_eq!(proof.public_inputs.domain, DOMAIN.as_canonical_u64());
+
+        // A withdrawal replayed against another deployment of the vault
+        proof.public_inputs.domain = 84532;
+        assert!(matches!(
+            verify_balance_withdrawal(&config, &proof),
+            Err(ProverError::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn test_prove_and_verify_with_association_set() {
+        let mut witness = sample_witness();
+        let commitment =
+            note_commitment(DOMAIN, poseidon_hash(Val::new(12345)), asset_id(&[0; 20]), Val::new(10000), Val::new(99999));
+        let approved = AssociationSet::new(&[Val::new(7), commitment]).unwrap();
+        witness.association_path = approved.path(commitment);
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+
+        let config = default_config();
+        let mut proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        assert_eq!(proof.public_inputs.association_root, approved.root().as_canonical_u64());
+        verify_balance_withdrawal(&config, &proof).unwrap();
+
+        // The proof does not vouch for membership of another set
+        let other = AssociationSet::new(&[Val::new(7), Val::new(8)]).unwrap();
+        proof.public_inputs.association_root = other.root().as_canonical_u64();
+        assert!(matches!(
+            verify_balance_withdrawal(&config, &proof),
+            Err(ProverError::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn test_prove_and_verify_with_blocklist() {
+        let mut witness = sample_witness();
+        let commitment =
+            note_commitment(DOMAIN, poseidon_hash(Val::new(12345)), asset_id(&[0; 20]), Val::new(10000), Val::new(99999));
+        let blocklist = FieldBlocklist::from_commitments(&[Val::new(7), Val::new(8)]);
+        witness.blocklist_proof = blocklist.non_membership_proof(commitment);
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, RECIPIENT, Val::new(6000)).unwrap();
+
+        let config = default_config();
+        let mut proof = prove_balance_withdrawal(&config, &circuit, &witness).unwrap();
+        assert_eq!(proof.public_inputs.blocklist_root, blocklist.root().as_canonical_u64());
+        verify_balance_withdrawal(&config, &proof).unwrap();
+
+        // The proof does not show absence from a blocklist the note is on
+        let mut blocked = blocklist.clone();
+        blocked.insert(commitment);
+        proof.public_inputs.blocklist_root = blocked.root().as_canonical_u64();
+        assert!(matches!(
+            verify_balance_withdrawal(&config, &proof),
+            Err(ProverError::Verification(_))
+        ));
+
+        // Nor can the blocklist be dropped after proving
+        proof.public_inputs.blocklist_root = 0;
+        assert!(matches!(
+            verify_balance_withdrawal(&config, &proof),
+            Err(ProverError::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn test_prove_rejects_bad_witness() {
+        let witness = sample_witness();
+        let spending_key_hash = poseidon_hash(witness.spending_key);
+        let commitment =
+            note_commitment(DOMAIN, spending_key_hash, asset_id(&witness.asset), witness.balance, witness.randomness);
+        let root = compute_merkle_root_with_path(commitment, &witness.merkle_path, &witness.path_indices);
+
+        let circuit = BalanceWithdrawalCircuit::new(
+            root,
+            note_nullifier(DOMAIN, nullifier_key(witness.spending_key), 6), // wrong note index
+            RECIPIENT,
+            Val::new(10000),
+            Val::new(0),
+            DOMAIN,
+        );
+
+        let config = default_config();
+        assert!(matches!(
+            prove_balance_withdrawal(&config, &circuit, &witness),
+            Err(ProverError::Witness(WitnessError::InvalidNullifier))
+        ));
+    }
+
+    #[test]
+    fn test_prove_and_verify_keccak_membership() {
+        let leaves: Vec<Digest> = (0u8..5).map(|i| keccak256(&[i])).collect();
+        let tree = KeccakMerkleTree::new(leaves.clone()).unwrap();
+        let (merkle_path, path_indices) = tree.get_proof(3).unwrap();
+        let circuit = KeccakMembershipCircuit::new(leaves[3], tree.root());
+        let witness = KeccakMembershipWitness { merkle_path, path_indices };
+
+        let config = default_config();
+        let proof = prove_keccak_membership(&config, &circuit, &witness).unwrap();
+        verify_keccak_membership(&config, &proof).unwrap();
+
+        // The same proof for another leaf of the tree
+        let mut other_leaf = proof;
+        other_leaf.leaf = leaves[4];
+        assert!(matches!(
+            verify_keccak_membership(&config, &other_leaf),
+            Err(ProverError::Verification(_))
+        ));
+
+        let wrong_leaf = KeccakMembershipCircuit::new(leaves[4], tree.root());
+        assert!(matches!(
+            prove_keccak_membership(&config, &wrong_leaf, &witness),
+            Err(ProverError::Witness(WitnessError::InvalidMerkleProof))
+        ));
+    }
+
+    #[test]
+    fn test_prove_and_verify_transfer() {
+        let mut note = InputNote {
+            spending_key: Val::new(12345),
+            balance: Val::new(10000),
+            randomness: Val::new(99999),
+            note_index: 0,
+            merkle_path: [Val::new(0); TREE_DEPTH],
+            path_indices: [false; TREE_DEPTH],
+        };
+        let (_, merkle_path, path_indices) = filled_tree_proof(&[note.commitment(DOMAIN, asset_id(&ASSET))], 0).unwrap();
+        note.merkle_path = merkle_path;
+        note.path_indices = path_indices;
+
+        let output = |balance, randomness| OutputNote {
+            spending_key_hash: poseidon_hash(Val::new(54321)),
+            balance: Val::new(balance),
+            randomness: Val::new(randomness),
+        };
+        let witness = TransferWitness {
+            domain: DOMAIN,
+            asset: ASSET,
+            inputs: [note, InputNote::dummy(Val::new(777))],
+            outputs: [output(6000, 1), output(4000, 2)],
+        };
+        let circuit = TransferCircuit::from_witness(&witness, Val::new(0), [0; 20]).unwrap();
+
+        let config = default_config();
+        let proof = prove_transfer(&config, &circuit, &witness).unwrap();
+        verify_transfer(&config, &proof).unwrap();
+
+        // Claiming part of the value as a public withdrawal breaks conservation
+        let mut inflated = TransferProof {
+            proof_bytes: proof.proof_bytes.clone(),
+            public_inputs: proof.public_inputs,
+        };
+        inflated.public_inputs.public_amount = 1;
+        assert!(matches!(
+            verify_transfer(&config, &inflated),
+            Err(ProverError::Verification(_))
+        ));
+
+        // The outputs are notes of the spent asset only
+        let mut relabelled = proof;
+        relabelled.public_inputs.asset = [0; 20];
+        assert!(matches!(
+            verify_transfer(&config, &relabelled),
+            Err(ProverError::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn test_prove_and_verify_merge() {
+        let spending_key = Val::new(12345);
+        let notes: Vec<NoteSecrets> = [300u32, 100, 200]
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                let (balance, randomness) = (Val::new(b), Val::new(1000 + i as u32));
+                NoteSecrets {
+                    spending_key,
+                    asset: ASSET,
+                    balance,
+                    randomness,
+                    commitment: note_commitment(DOMAIN, poseidon_hash(spending_key), asset_id(&ASSET), balance, randomness),
+                    note_index: i as u64,
+                }
+            })
+            .collect();
+        let leaves: Vec<Val> = notes.iter().map(|n| n.commitment).collect();
+
+        let witness = plan_merge(spending_key, &ASSET, DOMAIN, &notes, &leaves, Val::new(42)).unwrap();
+        let circuit = MergeCircuit::from_witness(&witness).unwrap();
+
+        let config = default_config();
+        let proof = prove_merge(&config, &circuit, &witness).unwrap();
+        verify_merge(&config, &proof).unwrap();
+
+        // Dropping a nullifier would leave a merged note spendable twice
+        let mut hidden_input = proof;
+        hidden_input.public_inputs.nullifiers[2] = 0;
+        assert!(matches!(
+            verify_merge(&config, &hidden_input),
+            Err(ProverError::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn test_prove_and_verify_deposit() {
+        let witness = DepositWitness {
+            domain: DOMAIN,
+            spending_key_hash: poseidon_hash(Val::new(12345)),
+            asset: ASSET,
+            randomness: Val::new(99999),
+        };
+        let circuit = DepositCircuit::from_witness(&witness, Val::new(10000)).unwrap();
+
+        let config = default_config();
+        let proof = prove_deposit(&config, &circuit, &witness).unwrap();
+        verify_deposit(&config, &proof).unwrap();
+
+        // The proof does not vouch for a different deposited amount or asset
+        let mut underpaid = DepositProof {
+            proof_bytes: proof.proof_bytes.clone(),
+            public_inputs: proof.public_inputs,
+        };
+        underpaid.public_inputs.amount = 1;
+        assert!(matches!(
+            verify_deposit(&config, &underpaid),
+            Err(ProverError::Verification(_))
+        ));
+
+        let mut other_asset = proof;
+        other_asset.public_inputs.asset = [0; 20];
+        assert!(matches!(
+            verify_deposit(&config, &other_asset),
+            Err(ProverError::Verification(_))
+        ));
+    }
+}
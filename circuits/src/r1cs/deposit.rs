@@ -0,0 +1,219 @@
+//! Native R1CS deposit circuit over BN254
+//!
+//! Proves a BalanceVaultV4 deposit commitment holds the deposited amount of
+//! the deposited asset, so a depositor cannot insert a note worth more than
+//! they paid in:
+//!
+//! - commitment = Poseidon(Poseidon(domain, spendingKeyHash, asset), amount, randomness)
+//! - amount fits in `AMOUNT_BITS` bits, so `WithdrawalCircuit` can spend
+//!   the note
+//!
+//! The commitment is the one `WithdrawalCircuit` opens (see
+//! `compute_domain_commitment`). The spending key hash and randomness stay
+//! private, so a deposit reveals neither the owner nor the opening.
+//!
+//! Public inputs: commitment, amount, asset (the `depositWithProof` order),
+//! then domain. BalanceVaultV4 supplies the domain itself, as it does for
+//! withdrawals, so a deposit proof made for another deployment does not
+//! verify there.
+
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::{Groth16, Proof, ProvingKey, VerifyingKey};
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::prelude::*;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use ark_snark::SNARK;
+use rand::{CryptoRng, RngCore};
+
+use super::gadgets::{num_to_bits, poseidon_t4};
+use super::AMOUNT_BITS;
+use crate::groth16::U256;
+use crate::poseidon_bn254::{compute_domain_commitment, Bn254Field};
+
+/// Number of public inputs
+pub const NUM_PUBLIC_INPUTS: usize = 4;
+
+/// Deposit statement: public inputs plus the opening of the commitment
+#[derive(Clone, Debug)]
+pub struct DepositCircuit {
+    pub commitment: Bn254Field,
+    pub amount: Bn254Field,
+    /// Token deposited (`address_to_field`)
+    pub asset: Bn254Field,
+    /// Deployment the note belongs to (`compute_domain`)
+    pub domain: Bn254Field,
+    pub spending_key_hash: Bn254Field,
+    pub randomness: Bn254Field,
+}
+
+impl DepositCircuit {
+    /// Deposit of a new note of `spending_key_hash` holding `amount` of `asset`
+    pub fn new(
+        domain: Bn254Field,
+        spending_key_hash: Bn254Field,
+        asset: Bn254Field,
+        amount: Bn254Field,
+        randomness: Bn254Field,
+    ) -> Self {
+        Self {
+            commitment: compute_domain_commitment(domain, spending_key_hash, asset, amount, randomness),
+            amount,
+            asset,
+            domain,
+            spending_key_hash,
+            randomness,
+        }
+    }
+
+    /// Placeholder with the right shape, for key generation
+    pub fn blank() -> Self {
+        Self::new(Bn254Field::ZERO, Bn254Field::ZERO, Bn254Field::ZERO, Bn254Field::ZERO, Bn254Field::ZERO)
+    }
+
+    /// Public inputs in verifier order
+    pub fn public_inputs(&self) -> [Fr; NUM_PUBLIC_INPUTS] {
+        [self.commitment, self.amount, self.asset, self.domain].map(Fr::from)
+    }
+
+    /// Public inputs as the Solidity verifier takes them: the three of
+    /// `BalanceVaultV4.depositWithProof`, then the domain the vault appends
+    pub fn evm_public_inputs(&self) -> [U256; NUM_PUBLIC_INPUTS] {
+        self.public_inputs().map(|v| U256::from(Bn254Field::from(v)))
+    }
+}
+
+impl ConstraintSynthesizer<Fr> for DepositCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let input = |v: Bn254Field| FpVar::new_input(cs.clone(), || Ok(Fr::from(v)));
+        let commitment = input(self.commitment)?;
+        let amount = input(self.amount)?;
+        let asset = input(self.asset)?;
+        let domain = input(self.domain)?;
+
+        let private = |v: Bn254Field| FpVar::new_witness(cs.clone(), || Ok(Fr::from(v)));
+        let spending_key_hash = private(self.spending_key_hash)?;
+        let randomness = private(self.randomness)?;
+
+        // The commitment opens to the deposited amount and asset, in the domain
+        let owner = poseidon_t4(&domain, &spending_key_hash, &asset)?;
+        poseidon_t4(&owner, &amount, &randomness)?.enforce_equal(&commitment)?;
+
+        // Range checked like a withdrawal's balance
+        num_to_bits(&amount, AMOUNT_BITS)?;
+        Ok(())
+    }
+}
+
+/// Circuit-specific Groth16 keys for the deposit circuit
+pub fn setup<R: RngCore + CryptoRng>(rng: &mut R) -> Result<(ProvingKey<Bn254>, VerifyingKey<Bn254>), SynthesisError> {
+    Groth16::<Bn254>::circuit_specific_setup(DepositCircuit::blank(), rng)
+}
+
+/// Prove `circuit` with `pk`
+pub fn prove<R: RngCore + CryptoRng>(
+    pk: &ProvingKey<Bn254>,
+    circuit: DepositCircuit,
+    rng: &mut R,
+) -> Result<Proof<Bn254>, SynthesisError> {
+    Groth16::<Bn254>::prove(pk, circuit, rng)
+}
+
+/// Verify `proof` against public inputs in `DepositCircuit::public_inputs` order
+pub fn verify(
+    vk: &VerifyingKey<Bn254>,
+    public_inputs: &[Fr; NUM_PUBLIC_INPUTS],
+    proof: &Proof<Bn254>,
+) -> Result<bool, SynthesisError> {
+    Groth16::<Bn254>::verify(vk, public_inputs, proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::groth16::Groth16VerifierSpec;
+    use crate::groth16::{Groth16Proof, VerificationKey};
+    use crate::keys::SpendingKey;
+    use crate::poseidon_bn254::{address_to_field, compute_domain};
+    use ark_relations::r1cs::ConstraintSystem;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn circuit(amount: Bn254Field) -> DepositCircuit {
+        DepositCircuit::new(
+            compute_domain(31337, &[0x44; 20]),
+            SpendingKey::from_seed(Bn254Field::new(0x5eed)).spending_key_hash(),
+            address_to_field(&[0x77; 20]),
+            amount,
+            Bn254Field::new(0xabcdef),
+        )
+    }
+
+    fn is_satisfied(circuit: DepositCircuit) -> bool {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        cs.is_satisfied().unwrap()
+    }
+
+    #[test]
+    fn test_valid_deposit_satisfied() {
+        assert!(is_satisfied(circuit(Bn254Field::new(1000))));
+    }
+
+    #[test]
+    fn test_commitment_bound_to_amount_asset_and_domain() {
+        // A note worth more than the deposit
+        let mut inflated = circuit(Bn254Field::new(1000));
+        inflated.commitment = circuit(Bn254Field::new(1_000_000)).commitment;
+        assert!(!is_satisfied(inflated));
+
+        let mut other_asset = circuit(Bn254Field::new(1000));
+        other_asset.asset = address_to_field(&[0x78; 20]);
+        assert!(!is_satisfied(other_asset));
+
+        let mut other_domain = circuit(Bn254Field::new(1000));
+        other_domain.domain = compute_domain(31337, &[0x45; 20]);
+        assert!(!is_satisfied(other_domain));
+    }
+
+    #[test]
+    fn test_unwithdrawable_amount_unsatisfied() {
+        // 2^64 does not fit the withdrawal circuit's balance range check
+        let amount = Bn254Field::from_limbs([0, 1, 0, 0]);
+        assert!(!is_satisfied(circuit(amount)));
+    }
+
+    /// The fixture verifier and the proof the forge test deposits with
+    #[test]
+    fn test_deposit_accepted_by_generated_verifier() {
+        let circuit = circuit(Bn254Field::new(1000));
+        let inputs = circuit.evm_public_inputs();
+
+        let mut rng = StdRng::seed_from_u64(12);
+        let (pk, vk) = setup(&mut rng).unwrap();
+        let proof = prove(&pk, circuit.clone(), &mut rng).unwrap();
+        assert!(verify(&vk, &circuit.public_inputs(), &proof).unwrap());
+
+        let evm_vk = VerificationKey::from(&vk);
+        let source = Groth16VerifierSpec::deposit("DepositVerifierFixture").to_solidity(&evm_vk);
+        let fixture = include_str!("../../../test/fixtures/DepositVerifierFixture.sol");
+        assert_eq!(
+            fixture,
+            source.replace("import \"./IDepositVerifier.sol\";", "import \"../../src/core/IDepositVerifier.sol\";")
+        );
+
+        let calldata = Groth16Proof::from(&proof).to_solidity_calldata();
+        let forge_test = include_str!("../../../test/BalanceVaultV4Deposit.t.sol");
+        assert!(forge_test.contains(&format!("hex\"{}\"", hex::encode(&calldata))));
+        assert!(forge_test.contains(&inputs[0].to_string()));
+        let evm_proof = Groth16Proof::from_solidity_calldata(&calldata).unwrap();
+        assert!(evm_vk.verify(&evm_proof, &inputs).unwrap());
+
+        // A larger amount, or the DOMAIN of another deployment
+        let mut inflated = inputs;
+        inflated[1] = U256::from_u64(1001);
+        assert!(!evm_vk.verify(&evm_proof, &inflated).unwrap());
+        let mut other_domain = inputs;
+        other_domain[3] = U256::from(compute_domain(31337, &[0x45; 20]));
+        assert!(!evm_vk.verify(&evm_proof, &other_domain).unwrap());
+    }
+}
@@ -0,0 +1,279 @@
+//! R1CS gadgets for BN254 Noctis circuits
+//!
+//! In-circuit versions of the circomlib templates the circom circuits use.
+//! Each gadget has an out-of-circuit twin it must agree with:
+//!
+//! | gadget            | twin                                  |
+//! |-------------------|---------------------------------------|
+//! | `poseidon_t3/t4`  | `poseidon_bn254::hash_pair`/`hash_3`  |
+//! | `merkle_root`     | `poseidon_bn254::compute_merkle_root` |
+//! | `smt_root`        | `blocklist::NonMembershipProof::root` |
+//! | `dual_mux`        | circomlib `DualMux`                   |
+//! | `num_to_bits`     | circomlib `Num2Bits`                  |
+//! | `less_than`       | circomlib `LessThan`                  |
+//! | `less_eq_than`    | circomlib `LessEqThan`                |
+//! | `is_equal`        | circomlib `IsEqual`                   |
+//! | `enforce_nonzero` | `x != 0`                              |
+
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, BigInteger, Field, PrimeField};
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::prelude::*;
+use ark_relations::r1cs::SynthesisError;
+
+use crate::poseidon_bn254::PoseidonParams;
+
+/// Poseidon over `params.width - 1` inputs, matching `PoseidonParams::hash`
+pub fn poseidon(params: &PoseidonParams, inputs: &[FpVar<Fr>]) -> Result<FpVar<Fr>, SynthesisError> {
+    assert_eq!(inputs.len() + 1, params.width, "input count");
+    let mut state = Vec::with_capacity(params.width);
+    state.push(FpVar::zero());
+    state.extend_from_slice(inputs);
+
+    for r in 0..params.full_rounds + params.partial_rounds {
+        for (s, c) in state.iter_mut().zip(&params.round_constants[r * params.width..]) {
+            *s += *c;
+        }
+        let sboxed = if params.is_full_round(r) { params.width } else { 1 };
+        for s in state.iter_mut().take(sboxed) {
+            let x2 = s.square()?;
+            *s = x2.square()? * &*s;
+        }
+        state = params
+            .mds
+            .iter()
+            .map(|row| row.iter().zip(&state).map(|(m, x)| x * *m).sum())
+            .collect();
+    }
+    Ok(state.swap_remove(0))
+}
+
+/// Two-input Poseidon (`hash_pair`)
+pub fn poseidon_t3(a: &FpVar<Fr>, b: &FpVar<Fr>) -> Result<FpVar<Fr>, SynthesisError> {
+    poseidon(PoseidonParams::t3(), &[a.clone(), b.clone()])
+}
+
+/// Three-input Poseidon (`hash_3`)
+pub fn poseidon_t4(a: &FpVar<Fr>, b: &FpVar<Fr>, c: &FpVar<Fr>) -> Result<FpVar<Fr>, SynthesisError> {
+    poseidon(PoseidonParams::t4(), &[a.clone(), b.clone(), c.clone()])
+}
+
+/// `(a, b)` if `s` is false, `(b, a)` if true
+pub fn dual_mux(
+    a: &FpVar<Fr>,
+    b: &FpVar<Fr>,
+    s: &Boolean<Fr>,
+) -> Result<(FpVar<Fr>, FpVar<Fr>), SynthesisError> {
+    Ok((s.select(b, a)?, s.select(a, b)?))
+}
+
+/// Root of the path from `leaf` up through `path`
+///
+/// `is_left[i]` is true if the current node is the left child at level
+/// `i`, as in `compute_merkle_root`. Note this is the negation of circom's
+/// `pathIndices`.
+pub fn merkle_root(
+    leaf: &FpVar<Fr>,
+    path: &[FpVar<Fr>],
+    is_left: &[Boolean<Fr>],
+) -> Result<FpVar<Fr>, SynthesisError> {
+    assert_eq!(path.len(), is_left.len(), "path length");
+    let mut current = leaf.clone();
+    for (sibling, is_left) in path.iter().zip(is_left) {
+        let (left, right) = dual_mux(&current, sibling, &!is_left)?;
+        current = poseidon_t3(&left, &right)?;
+    }
+    Ok(current)
+}
+
+/// Enforce that `key` has an empty leaf in the blocklist tree with root `root`
+///
+/// `siblings` run from the leaf up, one per bit of the field. The path
+/// follows the canonical bits of `key`, so a key cannot borrow the path of
+/// `key + p`.
+pub fn smt_non_membership(
+    key: &FpVar<Fr>,
+    siblings: &[FpVar<Fr>],
+    root: &FpVar<Fr>,
+) -> Result<(), SynthesisError> {
+    smt_root(key, siblings)?.enforce_equal(root)
+}
+
+/// Root of the blocklist tree with an empty leaf at `key`
+pub fn smt_root(key: &FpVar<Fr>, siblings: &[FpVar<Fr>]) -> Result<FpVar<Fr>, SynthesisError> {
+    let is_left: Vec<Boolean<Fr>> = key.to_bits_le()?.iter().map(|bit| !bit).collect();
+    merkle_root(&FpVar::zero(), siblings, &is_left)
+}
+
+/// Little-endian decomposition of `x` into `n` bits, enforcing `x < 2^n`
+pub fn num_to_bits(x: &FpVar<Fr>, n: usize) -> Result<Vec<Boolean<Fr>>, SynthesisError> {
+    assert!(n < Fr::MODULUS_BIT_SIZE as usize, "decomposition must not wrap");
+    let cs = x.cs();
+    let bits = (0..n)
+        .map(|i| Boolean::new_witness(cs.clone(), || Ok(x.value()?.into_bigint().get_bit(i))))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut sum = FpVar::zero();
+    let mut coeff = Fr::ONE;
+    for bit in &bits {
+        sum += FpVar::from(bit.clone()) * coeff;
+        coeff.double_in_place();
+    }
+    sum.enforce_equal(x)?;
+    Ok(bits)
+}
+
+/// `a < b` for `a, b < 2^n`
+///
+/// `a + 2^n - b` lies in `(0, 2^(n+1))` and has bit `n` clear iff `a < b`.
+/// The caller must range check the operands.
+pub fn less_than(a: &FpVar<Fr>, b: &FpVar<Fr>, n: usize) -> Result<Boolean<Fr>, SynthesisError> {
+    let offset = Fr::from(2u64).pow([n as u64]);
+    let bits = num_to_bits(&(a + offset - b), n + 1)?;
+    Ok(!&bits[n])
+}
+
+/// `a <= b` for `a, b < 2^n`
+pub fn less_eq_than(a: &FpVar<Fr>, b: &FpVar<Fr>, n: usize) -> Result<Boolean<Fr>, SynthesisError> {
+    less_than(a, &(b + Fr::ONE), n)
+}
+
+/// `a == b`
+pub fn is_equal(a: &FpVar<Fr>, b: &FpVar<Fr>) -> Result<Boolean<Fr>, SynthesisError> {
+    a.is_eq(b)
+}
+
+/// Enforce `x != 0` by exhibiting its inverse
+///
+/// A zero `x` leaves the system unsatisfied rather than failing synthesis.
+pub fn enforce_nonzero(x: &FpVar<Fr>) -> Result<(), SynthesisError> {
+    let inv = FpVar::new_witness(x.cs(), || Ok(x.value()?.inverse().unwrap_or(Fr::ZERO)))?;
+    x.mul_equals(&inv, &FpVar::one())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocklist::Blocklist;
+    use crate::poseidon_bn254::{compute_merkle_root, hash_3, hash_pair, Bn254Field};
+    use ark_relations::r1cs::{ConstraintSystem, ConstraintSystemRef};
+
+    fn witness(cs: &ConstraintSystemRef<Fr>, v: u64) -> FpVar<Fr> {
+        FpVar::new_witness(cs.clone(), || Ok(Fr::from(v))).unwrap()
+    }
+
+    #[test]
+    fn test_poseidon_matches_native() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let (a, b, c) = (witness(&cs, 1), witness(&cs, 2), witness(&cs, 3));
+
+        let h2 = poseidon_t3(&a, &b).unwrap();
+        let h3 = poseidon_t4(&a, &b, &c).unwrap();
+        assert_eq!(
+            h2.value().unwrap(),
+            Fr::from(hash_pair(Bn254Field::new(1), Bn254Field::new(2)))
+        );
+        assert_eq!(
+            h3.value().unwrap(),
+            Fr::from(hash_3(Bn254Field::new(1), Bn254Field::new(2), Bn254Field::new(3)))
+        );
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_merkle_root_matches_native() {
+        let leaf = Bn254Field::new(42);
+        let path: Vec<Bn254Field> = (0..6).map(|i| Bn254Field::new(100 + i)).collect();
+        let indices = [true, false, false, true, false, true];
+
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let path_vars: Vec<_> = path.iter().map(|p| FpVar::new_witness(cs.clone(), || Ok(Fr::from(*p))).unwrap()).collect();
+        let index_vars: Vec<_> = indices.iter().map(|b| Boolean::new_witness(cs.clone(), || Ok(*b)).unwrap()).collect();
+        let root = merkle_root(&witness(&cs, 42), &path_vars, &index_vars).unwrap();
+
+        assert_eq!(root.value().unwrap(), Fr::from(compute_merkle_root(leaf, &path, &indices)));
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_smt_non_membership_matches_native() {
+        let blocklist = Blocklist::from_commitments(&[Bn254Field::new(7), Bn254Field::new(8)]);
+        let proof = blocklist.non_membership_proof(Bn254Field::new(9)).unwrap();
+
+        let check = |key: u64| {
+            let cs = ConstraintSystem::<Fr>::new_ref();
+            let siblings: Vec<_> = proof
+                .siblings
+                .iter()
+                .map(|p| FpVar::new_witness(cs.clone(), || Ok(Fr::from(*p))).unwrap())
+                .collect();
+            let root = FpVar::new_input(cs.clone(), || Ok(Fr::from(blocklist.root()))).unwrap();
+            assert_eq!(
+                smt_root(&witness(&cs, key), &siblings).unwrap().value().unwrap(),
+                Fr::from(proof.root(Bn254Field::new(key)))
+            );
+            smt_non_membership(&witness(&cs, key), &siblings, &root).unwrap();
+            cs.is_satisfied().unwrap()
+        };
+        assert!(check(9));
+        // Blocked 8 differs from 9 only in its lowest bit
+        assert!(!check(8));
+    }
+
+    #[test]
+    fn test_dual_mux() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let (a, b) = (witness(&cs, 5), witness(&cs, 9));
+        for s in [false, true] {
+            let sel = Boolean::new_witness(cs.clone(), || Ok(s)).unwrap();
+            let (l, r) = dual_mux(&a, &b, &sel).unwrap();
+            let expected = if s { (9, 5) } else { (5, 9) };
+            assert_eq!((l.value().unwrap(), r.value().unwrap()), (Fr::from(expected.0), Fr::from(expected.1)));
+        }
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_num_to_bits() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let bits = num_to_bits(&witness(&cs, 0b1011), 8).unwrap();
+        let values: Vec<bool> = bits.iter().map(|b| b.value().unwrap()).collect();
+        assert_eq!(values, (0..8).map(|i| (0b1011 >> i) & 1 == 1).collect::<Vec<_>>());
+        assert!(cs.is_satisfied().unwrap());
+
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        num_to_bits(&witness(&cs, 256), 8).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_comparators_match_native() {
+        for (a, b) in [(0u64, 0u64), (3, 7), (7, 3), (7, 7), (u64::MAX - 1, u64::MAX), (u64::MAX, 0)] {
+            let cs = ConstraintSystem::<Fr>::new_ref();
+            let (va, vb) = (witness(&cs, a), witness(&cs, b));
+            assert_eq!(less_than(&va, &vb, 64).unwrap().value().unwrap(), a < b, "{} < {}", a, b);
+            assert_eq!(less_eq_than(&va, &vb, 64).unwrap().value().unwrap(), a <= b, "{} <= {}", a, b);
+            assert!(cs.is_satisfied().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_is_equal() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let (a, b) = (witness(&cs, 4), witness(&cs, 4));
+        assert!(is_equal(&a, &b).unwrap().value().unwrap());
+        assert!(!is_equal(&a, &witness(&cs, 5)).unwrap().value().unwrap());
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_enforce_nonzero() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        enforce_nonzero(&witness(&cs, 17)).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        enforce_nonzero(&witness(&cs, 0)).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+}
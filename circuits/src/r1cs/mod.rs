@@ -0,0 +1,691 @@
+//! Native R1CS withdrawal circuit over BN254
+//!
+//! The statement of `circom/withdrawal.circom`, written against arkworks'
+//! `ConstraintSynthesizer` and proven with ark-groth16, with notes derived
+//! in a deployment domain (version 2 of `poseidon_bn254`) and owned through
+//! the key hierarchy of `keys`:
+//!
+//! - spendingKeyHash = Poseidon(Poseidon(IVK_TAG, Poseidon(ask, 0), nk), 0)
+//! - commitment = Poseidon(Poseidon(domain, spendingKeyHash, asset), balance, randomness)
+//! - nullifier = Poseidon(domain, nk, noteIndex)
+//! - commitment is a leaf of the tree with root merkleRoot
+//! - amount <= balance
+//! - fee <= amount
+//! - changeCommitment is 0 if amount = balance, else the commitment of a
+//!   note of the same owner holding balance - amount under newRandomness
+//! - if associationRoot is nonzero, commitment is a leaf of that
+//!   association set (see `association`)
+//! - if blocklistRoot is nonzero, commitment is not in that blocklist
+//!
+//! The circom file only computes `balance - amount`, which wraps modulo p
+//! for an over-withdrawal. Here both values are range checked to
+//! `AMOUNT_BITS` bits and compared with `LessEqThan`, so the check is sound.
+//!
+//! Built from the gadgets in `gadgets`. Hashes use the same parameters as
+//! `poseidon_bn254`, so the public inputs can be computed with
+//! `compute_domain`, `compute_domain_nullifier` and `compute_merkle_root`.
+//!
+//! Public inputs: merkleRoot, nullifier, recipient, amount, changeCommitment,
+//! relayer, fee, asset, associationRoot, blocklistRoot (the
+//! `IBalanceVerifier` order), then domain. The asset is the full token
+//! address, as the vault passes it, so a note of one token cannot be
+//! withdrawn as another.
+//! Binding the change commitment stops a withdrawer from inserting a change
+//! note worth more than what is left. Binding the relayer and fee
+//! stops a front-runner from resubmitting a relayed proof and collecting the
+//! fee. The association set is a tree of the same shape as the note tree, and
+//! the blocklist the sparse Merkle tree of `blocklist`; both checks are
+//! always synthesized, so one key serves withdrawals with and without them.
+//! BalanceVaultV4 supplies the domain input itself, as its `DOMAIN`
+//! (Poseidon of its chain id and address), so proofs made for any other
+//! deployment do not verify there.
+//!
+//! `deposit` proves the other end of the note's life: that the commitment a
+//! depositor inserts opens to the amount they pay in.
+
+pub mod deposit;
+pub mod gadgets;
+
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::{Groth16, Proof, ProvingKey, VerifyingKey};
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::prelude::*;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_snark::SNARK;
+use rand::{CryptoRng, RngCore};
+use std::io::{Read, Write};
+
+use crate::blocklist::{NonMembershipProof, BLOCKLIST_DEPTH};
+use crate::groth16::U256;
+use crate::keys::{SpendingKey, IVK_TAG};
+use crate::poseidon_bn254::{
+    compute_domain_commitment, compute_domain_nullifier, compute_merkle_root, Bn254Field,
+};
+use gadgets::{less_eq_than, merkle_root, num_to_bits, poseidon_t3, poseidon_t4, smt_root};
+
+/// Bit width of `amount`, `balance` and `fee`
+pub const AMOUNT_BITS: usize = 64;
+
+/// Number of public inputs
+pub const NUM_PUBLIC_INPUTS: usize = 11;
+
+/// Private inputs of a withdrawal
+#[derive(Clone, Debug)]
+pub struct WithdrawalWitness {
+    /// Deployment the note belongs to (`compute_domain`)
+    pub domain: Bn254Field,
+    /// Spend authorizing and nullifier deriving keys of the owner
+    pub spending_key: SpendingKey,
+    /// Token the note holds (`address_to_field`), zero for ETH
+    pub asset: Bn254Field,
+    pub balance: Bn254Field,
+    pub randomness: Bn254Field,
+    /// Randomness of the change note
+    pub new_randomness: Bn254Field,
+    pub note_index: Bn254Field,
+    /// Merkle siblings from the leaf up
+    pub path_elements: Vec<Bn254Field>,
+    /// `true` if the current node is the left child (`compute_merkle_root`)
+    pub path_indices: Vec<bool>,
+}
+
+impl WithdrawalWitness {
+    pub fn spending_key_hash(&self) -> Bn254Field {
+        self.spending_key.spending_key_hash()
+    }
+
+    pub fn commitment(&self) -> Bn254Field {
+        compute_domain_commitment(self.domain, self.spending_key_hash(), self.asset, self.balance, self.randomness)
+    }
+
+    pub fn merkle_root(&self) -> Bn254Field {
+        compute_merkle_root(self.commitment(), &self.path_elements, &self.path_indices)
+    }
+
+    pub fn nullifier(&self) -> Bn254Field {
+        compute_domain_nullifier(self.domain, self.spending_key.nk, self.note_index)
+    }
+
+    /// Commitment of the note left after withdrawing `amount`, 0 if none is left
+    pub fn change_commitment(&self, amount: Bn254Field) -> Bn254Field {
+        let change = self.balance - amount;
+        if change == Bn254Field::ZERO {
+            Bn254Field::ZERO
+        } else {
+            compute_domain_commitment(self.domain, self.spending_key_hash(), self.asset, change, self.new_randomness)
+        }
+    }
+}
+
+/// Withdrawal statement: public inputs plus the witness that satisfies them
+#[derive(Clone, Debug)]
+pub struct WithdrawalCircuit {
+    pub merkle_root: Bn254Field,
+    pub nullifier: Bn254Field,
+    pub recipient: Bn254Field,
+    pub amount: Bn254Field,
+    /// Zero when the whole balance is withdrawn
+    pub change_commitment: Bn254Field,
+    pub relayer: Bn254Field,
+    pub fee: Bn254Field,
+    pub asset: Bn254Field,
+    /// Zero for a withdrawal without an association set
+    pub association_root: Bn254Field,
+    /// Zero for a withdrawal without a blocklist
+    pub blocklist_root: Bn254Field,
+    pub domain: Bn254Field,
+    pub witness: WithdrawalWitness,
+    /// Path of the commitment in the association set, in the
+    /// `compute_merkle_root` convention
+    pub association_path: Vec<Bn254Field>,
+    pub association_indices: Vec<bool>,
+    /// Path to the commitment's empty leaf in the blocklist
+    pub blocklist_proof: NonMembershipProof,
+}
+
+impl WithdrawalCircuit {
+    /// Statement for withdrawing `amount` to `recipient`, with the root,
+    /// nullifier, change commitment, asset and domain taken from the witness,
+    /// and no relayer, association set or blocklist
+    pub fn new(witness: WithdrawalWitness, recipient: Bn254Field, amount: Bn254Field) -> Self {
+        let levels = witness.path_elements.len();
+        Self {
+            merkle_root: witness.merkle_root(),
+            nullifier: witness.nullifier(),
+            recipient,
+            amount,
+            change_commitment: witness.change_commitment(amount),
+            relayer: Bn254Field::ZERO,
+            fee: Bn254Field::ZERO,
+            asset: witness.asset,
+            association_root: Bn254Field::ZERO,
+            blocklist_root: Bn254Field::ZERO,
+            domain: witness.domain,
+            witness,
+            association_path: vec![Bn254Field::ZERO; levels],
+            association_indices: vec![false; levels],
+            blocklist_proof: NonMembershipProof::blank(),
+        }
+    }
+
+    /// Pay `fee` out of the amount to `relayer`
+    pub fn with_relayer(mut self, relayer: Bn254Field, fee: Bn254Field) -> Self {
+        self.relayer = relayer;
+        self.fee = fee;
+        self
+    }
+
+    /// Also prove the note's commitment is in the association set with
+    /// `root`, through `path` and `indices` as for the note tree
+    pub fn with_association(mut self, root: Bn254Field, path: Vec<Bn254Field>, indices: Vec<bool>) -> Self {
+        self.association_root = root;
+        self.association_path = path;
+        self.association_indices = indices;
+        self
+    }
+
+    /// Also prove the note's commitment is not in the blocklist with `root`
+    pub fn with_blocklist(mut self, root: Bn254Field, proof: NonMembershipProof) -> Self {
+        self.blocklist_root = root;
+        self.blocklist_proof = proof;
+        self
+    }
+
+    /// All-zero circuit of depth `levels`, for key generation
+    pub fn blank(levels: usize) -> Self {
+        let witness = WithdrawalWitness {
+            domain: Bn254Field::ZERO,
+            spending_key: SpendingKey { ask: Bn254Field::ZERO, nk: Bn254Field::ZERO },
+            asset: Bn254Field::ZERO,
+            balance: Bn254Field::ZERO,
+            randomness: Bn254Field::ZERO,
+            new_randomness: Bn254Field::ZERO,
+            note_index: Bn254Field::ZERO,
+            path_elements: vec![Bn254Field::ZERO; levels],
+            path_indices: vec![false; levels],
+        };
+        Self::new(witness, Bn254Field::ZERO, Bn254Field::ZERO)
+    }
+
+    /// Public inputs in verifier order
+    pub fn public_inputs(&self) -> [Fr; NUM_PUBLIC_INPUTS] {
+        [
+            self.merkle_root,
+            self.nullifier,
+            self.recipient,
+            self.amount,
+            self.change_commitment,
+            self.relayer,
+            self.fee,
+            self.asset,
+            self.association_root,
+            self.blocklist_root,
+            self.domain,
+        ]
+        .map(Fr::from)
+    }
+
+    /// Public inputs as the Solidity verifier takes them: the ten of
+    /// `BalanceVaultV4.withdraw`, then the domain the vault appends
+    pub fn evm_public_inputs(&self) -> [U256; NUM_PUBLIC_INPUTS] {
+        self.public_inputs().map(|v| U256::from(Bn254Field::from(v)))
+    }
+}
+
+impl ConstraintSynthesizer<Fr> for WithdrawalCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let w = &self.witness;
+        if w.path_elements.len() != w.path_indices.len()
+            || self.association_path.len() != w.path_elements.len()
+            || self.association_indices.len() != w.path_elements.len()
+            || self.blocklist_proof.siblings.len() != BLOCKLIST_DEPTH
+        {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let input = |v: Bn254Field| FpVar::new_input(cs.clone(), || Ok(Fr::from(v)));
+        let merkle_root_input = input(self.merkle_root)?;
+        let nullifier = input(self.nullifier)?;
+        // Bound by the verifier's input check; no constraint needed
+        let _recipient = input(self.recipient)?;
+        let amount = input(self.amount)?;
+        let change_commitment = input(self.change_commitment)?;
+        // Bound like the recipient
+        let _relayer = input(self.relayer)?;
+        let fee = input(self.fee)?;
+        let asset = input(self.asset)?;
+        let association_root = input(self.association_root)?;
+        let blocklist_root = input(self.blocklist_root)?;
+        let domain = input(self.domain)?;
+
+        let private = |v: Bn254Field| FpVar::new_witness(cs.clone(), || Ok(Fr::from(v)));
+        let ask = private(w.spending_key.ask)?;
+        let nk = private(w.spending_key.nk)?;
+        let balance = private(w.balance)?;
+        let randomness = private(w.randomness)?;
+        let new_randomness = private(w.new_randomness)?;
+        let note_index = private(w.note_index)?;
+
+        // 1-3. Owner tag from ask and nk, then commitment (of the asset) and
+        // nullifier in the domain; the nullifier needs only nk, spending
+        // needs ask too
+        let ak = poseidon_t3(&ask, &FpVar::zero())?;
+        let ivk = poseidon_t4(&FpVar::constant(Fr::from(IVK_TAG)), &ak, &nk)?;
+        let spending_key_hash = poseidon_t3(&ivk, &FpVar::zero())?;
+        let owner = poseidon_t4(&domain, &spending_key_hash, &asset)?;
+        let commitment = poseidon_t4(&owner, &balance, &randomness)?;
+        poseidon_t4(&domain, &nk, &note_index)?.enforce_equal(&nullifier)?;
+
+        // 4. amount <= balance, both range checked so nothing wraps
+        num_to_bits(&amount, AMOUNT_BITS)?;
+        num_to_bits(&balance, AMOUNT_BITS)?;
+        less_eq_than(&amount, &balance, AMOUNT_BITS)?.enforce_equal(&Boolean::TRUE)?;
+
+        // 5. fee <= amount, so the relayer is paid out of the withdrawal
+        num_to_bits(&fee, AMOUNT_BITS)?;
+        less_eq_than(&fee, &amount, AMOUNT_BITS)?.enforce_equal(&Boolean::TRUE)?;
+
+        // The change note keeps the owner and holds exactly what is left;
+        // with nothing left there is no change note and the input is zero
+        let change = &balance - &amount;
+        let has_change = change.is_neq(&FpVar::zero())?;
+        let change_note = poseidon_t4(&owner, &change, &new_randomness)?;
+        has_change.select(&change_note, &FpVar::zero())?.enforce_equal(&change_commitment)?;
+
+        // 6. Merkle membership of the commitment
+        let path = w.path_elements.iter().map(|p| private(*p)).collect::<Result<Vec<_>, _>>()?;
+        let is_left = w
+            .path_indices
+            .iter()
+            .map(|b| Boolean::new_witness(cs.clone(), || Ok(*b)))
+            .collect::<Result<Vec<_>, _>>()?;
+        merkle_root(&commitment, &path, &is_left)?.enforce_equal(&merkle_root_input)?;
+
+        // The nullifier's index is the leaf position the path proves, so one
+        // note cannot be spent again under another index
+        let mut position = FpVar::zero();
+        let mut weight = Fr::from(1u64);
+        for left in &is_left {
+            position += FpVar::from(!left) * weight;
+            weight += weight;
+        }
+        position.enforce_equal(&note_index)?;
+
+        // 7. Membership of the commitment in the association set, if one is given
+        let association_path = self.association_path.iter().map(|p| private(*p)).collect::<Result<Vec<_>, _>>()?;
+        let association_is_left = self
+            .association_indices
+            .iter()
+            .map(|b| Boolean::new_witness(cs.clone(), || Ok(*b)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let has_association = association_root.is_neq(&FpVar::zero())?;
+        merkle_root(&commitment, &association_path, &association_is_left)?
+            .conditional_enforce_equal(&association_root, &has_association)?;
+
+        // 8. Non-membership of the commitment in the blocklist, if one is given
+        let siblings = self
+            .blocklist_proof
+            .siblings
+            .iter()
+            .map(|s| private(*s))
+            .collect::<Result<Vec<_>, _>>()?;
+        let has_blocklist = blocklist_root.is_neq(&FpVar::zero())?;
+        smt_root(&commitment, &siblings)?.conditional_enforce_equal(&blocklist_root, &has_blocklist)
+    }
+}
+
+/// Circuit-specific Groth16 keys for a tree of depth `levels`
+pub fn setup<R: RngCore + CryptoRng>(
+    levels: usize,
+    rng: &mut R,
+) -> Result<(ProvingKey<Bn254>, VerifyingKey<Bn254>), SynthesisError> {
+    Groth16::<Bn254>::circuit_specific_setup(WithdrawalCircuit::blank(levels), rng)
+}
+
+/// Write `pk` in the form `read_proving_key` reads
+pub fn write_proving_key<W: Write>(pk: &ProvingKey<Bn254>, writer: W) -> Result<(), SerializationError> {
+    pk.serialize_uncompressed(writer)
+}
+
+/// Read a proving key written by `write_proving_key`
+///
+/// Points are not checked to be on the curve: a corrupt key gives proofs
+/// that fail to verify, not unsound ones.
+pub fn read_proving_key<R: Read>(reader: R) -> Result<ProvingKey<Bn254>, SerializationError> {
+    ProvingKey::deserialize_uncompressed_unchecked(reader)
+}
+
+/// Prove `circuit` with `pk`
+pub fn prove<R: RngCore + CryptoRng>(
+    pk: &ProvingKey<Bn254>,
+    circuit: WithdrawalCircuit,
+    rng: &mut R,
+) -> Result<Proof<Bn254>, SynthesisError> {
+    Groth16::<Bn254>::prove(pk, circuit, rng)
+}
+
+/// Verify `proof` against public inputs in `WithdrawalCircuit::public_inputs` order
+pub fn verify(
+    vk: &VerifyingKey<Bn254>,
+    public_inputs: &[Fr; NUM_PUBLIC_INPUTS],
+    proof: &Proof<Bn254>,
+) -> Result<bool, SynthesisError> {
+    Groth16::<Bn254>::verify(vk, public_inputs, proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocklist::Blocklist;
+    use crate::merkle::TREE_DEPTH;
+    use crate::abi::address_to_u256;
+    use crate::codegen::groth16::Groth16VerifierSpec;
+    use crate::groth16::{Groth16Proof, VerificationKey};
+    use crate::poseidon_bn254::{address_to_field, compute_domain, filled_tree_proof};
+    use ark_ff::Field;
+    use ark_relations::r1cs::ConstraintSystem;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn witness(balance: u64, levels: usize) -> WithdrawalWitness {
+        WithdrawalWitness {
+            domain: compute_domain(8453, &[0x44; 20]),
+            spending_key: SpendingKey::from_seed(Bn254Field::new(0x5eed)),
+            asset: address_to_field(&[0x77; 20]),
+            balance: Bn254Field::new(balance),
+            randomness: Bn254Field::new(0xabcdef),
+            new_randomness: Bn254Field::new(0x123456),
+            note_index: Bn254Field::new(5),
+            path_elements: (0..levels).map(|i| Bn254Field::new(1000 + i as u64)).collect(),
+            path_indices: (0..levels).map(|i| (5 >> i) & 1 == 0).collect(),
+        }
+    }
+
+    fn is_satisfied(circuit: WithdrawalCircuit) -> bool {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        cs.is_satisfied().unwrap()
+    }
+
+    #[test]
+    fn test_valid_withdrawal_satisfied() {
+        let circuit = WithdrawalCircuit::new(witness(1000, TREE_DEPTH), Bn254Field::new(0xbeef), Bn254Field::new(400));
+        assert!(is_satisfied(circuit));
+
+        let full = WithdrawalCircuit::new(witness(1000, TREE_DEPTH), Bn254Field::new(0xbeef), Bn254Field::new(1000));
+        assert!(is_satisfied(full));
+    }
+
+    #[test]
+    fn test_change_commitment_bound() {
+        let circuit = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(400));
+        assert_ne!(circuit.change_commitment, Bn254Field::ZERO);
+
+        // A change note worth more than what is left
+        let w = circuit.witness.clone();
+        let mut inflated = circuit.clone();
+        inflated.change_commitment =
+            compute_domain_commitment(w.domain, w.spending_key_hash(), w.asset, Bn254Field::new(900), w.new_randomness);
+        assert!(!is_satisfied(inflated));
+
+        // Dropping the change note, or inserting one when nothing is left
+        let mut dropped = circuit;
+        dropped.change_commitment = Bn254Field::ZERO;
+        assert!(!is_satisfied(dropped));
+
+        let mut full = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(1000));
+        assert_eq!(full.change_commitment, Bn254Field::ZERO);
+        full.change_commitment = w.commitment();
+        assert!(!is_satisfied(full));
+    }
+
+    #[test]
+    fn test_over_withdrawal_unsatisfied() {
+        let circuit = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(1001));
+        assert!(!is_satisfied(circuit));
+    }
+
+    #[test]
+    fn test_wrapping_amount_unsatisfied() {
+        // amount = p - 1 makes balance - amount = balance + 1 in the field
+        let amount = Bn254Field::ZERO - Bn254Field::new(1);
+        let circuit = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), amount);
+        assert!(!is_satisfied(circuit));
+    }
+
+    #[test]
+    fn test_fee_bounded_by_amount() {
+        let relayer = Bn254Field::new(0xfee);
+        let circuit = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(400))
+            .with_relayer(relayer, Bn254Field::new(400));
+        assert!(is_satisfied(circuit));
+
+        let circuit = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(400))
+            .with_relayer(relayer, Bn254Field::new(401));
+        assert!(!is_satisfied(circuit));
+
+        // fee = p - 1 would make amount - fee = amount + 1
+        let circuit = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(400))
+            .with_relayer(relayer, Bn254Field::ZERO - Bn254Field::new(1));
+        assert!(!is_satisfied(circuit));
+    }
+
+    #[test]
+    fn test_association_membership() {
+        let w = witness(1000, 4);
+        let path: Vec<Bn254Field> = (0..4).map(|i| Bn254Field::new(2000 + i)).collect();
+        let indices = vec![true, false, false, true];
+        let root = compute_merkle_root(w.commitment(), &path, &indices);
+        let circuit = |root: Bn254Field, path: Vec<Bn254Field>| {
+            WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(400))
+                .with_association(root, path, indices.clone())
+        };
+        assert!(is_satisfied(circuit(root, path.clone())));
+
+        // Another set's root, or a path to another leaf
+        assert!(!is_satisfied(circuit(root + Bn254Field::new(1), path.clone())));
+        let mut other_leaf = path;
+        other_leaf[2] = Bn254Field::new(7);
+        assert!(!is_satisfied(circuit(root, other_leaf)));
+    }
+
+    #[test]
+    fn test_blocklist_non_membership() {
+        let commitment = witness(1000, 4).commitment();
+        let others = [Bn254Field::new(7), Bn254Field::new(8)];
+        let circuit = |blocklist: &Blocklist, proof: NonMembershipProof| {
+            WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(400))
+                .with_blocklist(blocklist.root(), proof)
+        };
+
+        let blocklist = Blocklist::from_commitments(&others);
+        let proof = blocklist.non_membership_proof(commitment).unwrap();
+        assert!(is_satisfied(circuit(&blocklist, proof.clone())));
+
+        // Once the commitment is blocked, the old path no longer reaches the root
+        let mut blocked = blocklist.clone();
+        blocked.insert(commitment);
+        assert!(blocked.non_membership_proof(commitment).is_none());
+        assert!(!is_satisfied(circuit(&blocked, proof)));
+
+        // Nor does the path of another unblocked key
+        let other_path = blocked.non_membership_proof(Bn254Field::new(9)).unwrap();
+        assert!(!is_satisfied(circuit(&blocked, other_path)));
+    }
+
+    #[test]
+    fn test_domain_bound() {
+        let sepolia = WithdrawalWitness { domain: compute_domain(84532, &[0x44; 20]), ..witness(1000, 4) };
+        let mainnet = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(400));
+        let testnet = WithdrawalCircuit::new(sepolia, Bn254Field::new(0xbeef), Bn254Field::new(400));
+        assert_ne!(mainnet.nullifier, testnet.nullifier);
+        assert_ne!(mainnet.merkle_root, testnet.merkle_root);
+
+        // The same note claimed under another deployment's domain
+        let mut replayed = mainnet;
+        replayed.domain = testnet.domain;
+        assert!(!is_satisfied(replayed));
+    }
+
+    #[test]
+    fn test_viewing_key_cannot_spend() {
+        let w = witness(1000, 4);
+        let fvk = w.spending_key.full_viewing_key();
+        let circuit = WithdrawalCircuit::new(w.clone(), Bn254Field::new(0xbeef), Bn254Field::new(400));
+        assert_eq!(circuit.nullifier, fvk.nullifier(w.domain, w.note_index));
+
+        // Knowing nk, and ak in place of ask, does not open the commitment
+        let mut watch_only = circuit.clone();
+        watch_only.witness.spending_key.ask = fvk.ak;
+        assert!(!is_satisfied(watch_only));
+
+        // Nor does ask alone with a nullifier computed from it
+        let mut wrong_nk = circuit;
+        wrong_nk.witness.spending_key.nk = wrong_nk.witness.spending_key.ask;
+        wrong_nk.nullifier = wrong_nk.witness.nullifier();
+        assert!(!is_satisfied(wrong_nk));
+    }
+
+    #[test]
+    fn test_wrong_public_inputs_unsatisfied() {
+        let mut bad_nullifier = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(1));
+        bad_nullifier.nullifier = bad_nullifier.nullifier + Bn254Field::new(1);
+        assert!(!is_satisfied(bad_nullifier));
+
+        let mut bad_root = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(1));
+        bad_root.merkle_root = bad_root.merkle_root + Bn254Field::new(1);
+        assert!(!is_satisfied(bad_root));
+    }
+
+    #[test]
+    fn test_note_index_bound_to_path() {
+        let circuit = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(400));
+        assert!(is_satisfied(circuit));
+
+        // Same note and path under another index: a second nullifier for one note
+        for index in [4, 5 + 16] {
+            let w = WithdrawalWitness { note_index: Bn254Field::new(index), ..witness(1000, 4) };
+            let replayed = WithdrawalCircuit::new(w, Bn254Field::new(0xbeef), Bn254Field::new(400));
+            assert!(!is_satisfied(replayed));
+        }
+    }
+
+    #[test]
+    fn test_groth16_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let (pk, vk) = setup(4, &mut rng).unwrap();
+
+        let circuit = WithdrawalCircuit::new(witness(1000, 4), Bn254Field::new(0xbeef), Bn254Field::new(250))
+            .with_relayer(Bn254Field::new(0xfee), Bn254Field::new(10));
+        let inputs = circuit.public_inputs();
+        let proof = prove(&pk, circuit, &mut rng).unwrap();
+        assert!(verify(&vk, &inputs, &proof).unwrap());
+
+        let mut wrong_recipient = inputs;
+        wrong_recipient[2] += Fr::ONE;
+        assert!(!verify(&vk, &wrong_recipient, &proof).unwrap());
+
+        let mut wrong_change = inputs;
+        wrong_change[4] += Fr::ONE;
+        assert!(!verify(&vk, &wrong_change, &proof).unwrap());
+
+        let mut wrong_relayer = inputs;
+        wrong_relayer[5] += Fr::ONE;
+        assert!(!verify(&vk, &wrong_relayer, &proof).unwrap());
+
+        let mut wrong_fee = inputs;
+        wrong_fee[6] += Fr::ONE;
+        assert!(!verify(&vk, &wrong_fee, &proof).unwrap());
+
+        // The same note withdrawn as another token
+        let mut wrong_asset = inputs;
+        wrong_asset[7] = Fr::from(address_to_field(&[0x78; 20]));
+        assert!(!verify(&vk, &wrong_asset, &proof).unwrap());
+
+        // Without an association set or blocklist the roots are zero;
+        // claiming either fails
+        let mut wrong_association = inputs;
+        wrong_association[8] = Fr::ONE;
+        assert!(!verify(&vk, &wrong_association, &proof).unwrap());
+
+        let mut wrong_blocklist = inputs;
+        wrong_blocklist[9] = Fr::from(Blocklist::new().root());
+        assert!(!verify(&vk, &wrong_blocklist, &proof).unwrap());
+
+        let mut wrong_domain = inputs;
+        wrong_domain[10] = Fr::from(compute_domain(84532, &[0x44; 20]));
+        assert!(!verify(&vk, &wrong_domain, &proof).unwrap());
+    }
+
+    /// test/BalanceVaultV4Groth16.t.sol withdraws this proof from a vault
+    /// through the fixture verifier; both are checked here to be current
+    #[test]
+    fn test_vault_withdrawal_accepted_by_generated_verifier() {
+        let vault = [0x44; 20];
+        let domain = compute_domain(31337, &vault);
+        let spending_key = SpendingKey::from_seed(Bn254Field::new(0x5eed));
+        let asset = address_to_field(&[0x77; 20]);
+        let (balance, randomness) = (Bn254Field::new(1000), Bn254Field::new(0xabcdef));
+
+        // The fourth of five deposits into the vault's tree
+        let commitment =
+            compute_domain_commitment(domain, spending_key.spending_key_hash(), asset, balance, randomness);
+        let mut leaves: Vec<Bn254Field> = (11..16).map(Bn254Field::new).collect();
+        leaves[3] = commitment;
+        let (root, path_elements, path_indices) = filled_tree_proof(&leaves, 3, TREE_DEPTH).unwrap();
+
+        let witness = WithdrawalWitness {
+            domain,
+            spending_key,
+            asset,
+            balance,
+            randomness,
+            new_randomness: Bn254Field::new(0x123456),
+            note_index: Bn254Field::new(3),
+            path_elements,
+            path_indices,
+        };
+        let circuit = WithdrawalCircuit::new(witness, address_to_field(&[0xbe; 20]), Bn254Field::new(400))
+            .with_relayer(address_to_field(&[0xfe; 20]), Bn254Field::new(10));
+        assert_eq!(circuit.merkle_root, root);
+        let inputs = circuit.evm_public_inputs();
+        assert_eq!(inputs[2], address_to_u256(&[0xbe; 20]));
+        assert_eq!(inputs[10], U256::from(domain));
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let (pk, vk) = setup(TREE_DEPTH, &mut rng).unwrap();
+        let proof = prove(&pk, circuit, &mut rng).unwrap();
+
+        let evm_vk = VerificationKey::from(&vk);
+        let source =
+            Groth16VerifierSpec::new("BalanceWithdrawalVerifierFixture", NUM_PUBLIC_INPUTS).to_solidity(&evm_vk);
+        let fixture = include_str!("../../../test/fixtures/BalanceWithdrawalVerifierFixture.sol");
+        assert_eq!(
+            fixture,
+            source.replace("import \"./IBalanceVerifier.sol\";", "import \"../../src/core/IBalanceVerifier.sol\";")
+        );
+
+        // The proof bytes the vault forwards, decoded as the verifier does
+        let calldata = Groth16Proof::from(&proof).to_solidity_calldata();
+        let forge_test = include_str!("../../../test/BalanceVaultV4Groth16.t.sol");
+        assert!(forge_test.contains(&format!("hex\"{}\"", hex::encode(&calldata))));
+        assert!(forge_test.contains(&format!("{},", inputs[0])));
+        assert!(forge_test.contains(&U256::from(commitment).to_string()));
+        let evm_proof = Groth16Proof::from_solidity_calldata(&calldata).unwrap();
+        assert!(evm_vk.verify(&evm_proof, &inputs).unwrap());
+
+        // Another recipient, or the DOMAIN of another deployment
+        let mut other_recipient = inputs;
+        other_recipient[2] = address_to_u256(&[0xbf; 20]);
+        assert!(!evm_vk.verify(&evm_proof, &other_recipient).unwrap());
+        let mut other_domain = inputs;
+        other_domain[10] = U256::from(compute_domain(31337, &[0x45; 20]));
+        assert!(!evm_vk.verify(&evm_proof, &other_domain).unwrap());
+
+        // A proof point off the curve fails as the precompile does
+        let mut tampered = evm_proof;
+        tampered.c.y = tampered.c.x;
+        assert!(!evm_vk.verify(&tampered, &inputs).unwrap());
+    }
+}
@@ -0,0 +1,256 @@
+//! FRI presets and STARK security estimates
+//!
+//! Security is reported two ways, following the usual STARK accounting
+//! (ethSTARK, Winterfell):
+//!
+//! - conjectured: each FRI query contributes `log_blowup` bits, grinding
+//!   adds `proof_of_work_bits`, capped by the extension field size over the
+//!   LDE domain
+//! - provable: FRI soundness in the Johnson regime (BCIKS20), which needs
+//!   roughly twice the queries for the same number of bits
+//!
+//! Both are capped by the collision resistance of the commitment hash.
+//! The quartic BabyBear extension has about 123.6 bits and the Poseidon2
+//! digest about the same, so no parameter choice gets past
+//! `123.6 - log2(LDE size)` here. The `max` preset spends 128 bits on the
+//! query side and ends at that cap: about 118.6 bits for the smallest
+//! traces, less for larger ones. The quadratic Goldilocks extension has 128
+//! bits, which raises the cap by about 4.4 bits; no configuration here
+//! reaches 128 bits overall.
+//!
+//! The default parameters (`FriParams::default()`) target 116 conjectured
+//! bits, not 128: 100 queries at log_blowup 1 and 16 grinding bits. That is
+//! what the withdrawal, deposit and transfer traces get. The larger balance
+//! withdrawal (2^7 rows) and merge (2^8 rows) traces hit the field cap
+//! first, at about 115.6 and 114.6 bits.
+
+use std::fmt;
+
+use crate::prover::{FriParams, StarkHash};
+
+/// log2 of the BabyBear modulus
+const BASE_FIELD_BITS: f64 = 30.906_890_595_608_516;
+
+/// Degree of the challenge extension field
+const EXTENSION_DEGREE: f64 = 4.0;
+
+/// log2 of the Goldilocks modulus 2^64 - 2^32 + 1, to within 2^-31
+const GOLDILOCKS_FIELD_BITS: f64 = 64.0;
+
+/// Field the FRI challenges are drawn from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChallengeField {
+    /// Quartic extension of BabyBear
+    #[default]
+    BabyBear,
+    /// Quadratic extension of Goldilocks
+    Goldilocks,
+}
+
+impl ChallengeField {
+    /// log2 of the challenge field size
+    pub fn bits(&self) -> f64 {
+        match self {
+            ChallengeField::BabyBear => BASE_FIELD_BITS * EXTENSION_DEGREE,
+            ChallengeField::Goldilocks => GOLDILOCKS_FIELD_BITS * 2.0,
+        }
+    }
+}
+
+/// Named FRI parameter sets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FriPreset {
+    /// Few queries, no grinding: for tests only
+    FastTest,
+    /// 100 conjectured bits
+    Bits100,
+    /// 128 bits on the query side; overall security is then set by the
+    /// field and hash caps, below 128
+    Max,
+}
+
+impl FriPreset {
+    pub const ALL: [FriPreset; 3] = [FriPreset::FastTest, FriPreset::Bits100, FriPreset::Max];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FriPreset::FastTest => "fast-test",
+            FriPreset::Bits100 => "100-bit",
+            FriPreset::Max => "max",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    pub fn params(&self) -> FriParams {
+        let (log_blowup, num_queries, proof_of_work_bits) = match self {
+            FriPreset::FastTest => (1, 8, 0),
+            FriPreset::Bits100 => (2, 42, 16),
+            FriPreset::Max => (3, 38, 14),
+        };
+        FriParams {
+            log_blowup,
+            log_final_poly_len: 0,
+            num_queries,
+            proof_of_work_bits,
+        }
+    }
+}
+
+/// Security estimate in bits
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SecurityLevel {
+    pub conjectured_bits: f64,
+    pub provable_bits: f64,
+}
+
+impl fmt::Display for SecurityLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} bits conjectured, {:.1} bits provable",
+            self.conjectured_bits, self.provable_bits
+        )
+    }
+}
+
+/// Collision resistance of the commitment hash
+pub fn hash_security_bits(hash: StarkHash) -> f64 {
+    match hash {
+        // 8-element BabyBear digest
+        StarkHash::Poseidon2 => 8.0 * BASE_FIELD_BITS / 2.0,
+        StarkHash::Keccak => 128.0,
+    }
+}
+
+/// Security of `params` with `hash` for a trace of `2^log_trace_height` rows
+pub fn security_level(params: &FriParams, hash: StarkHash, log_trace_height: usize) -> SecurityLevel {
+    security_level_over(ChallengeField::BabyBear, params, hash, log_trace_height)
+}
+
+/// Security of `params` with `hash` and challenges from `field`
+pub fn security_level_over(
+    field: ChallengeField,
+    params: &FriParams,
+    hash: StarkHash,
+    log_trace_height: usize,
+) -> SecurityLevel {
+    let field_bits = field.bits();
+    let log_lde = (log_trace_height + params.log_blowup) as f64;
+    let queries = params.num_queries as f64;
+    let pow = params.proof_of_work_bits as f64;
+    let hash_bits = hash_security_bits(hash);
+
+    let conjectured = (queries * params.log_blowup as f64 + pow)
+        .min(field_bits - log_lde)
+        .min(hash_bits);
+
+    // Johnson regime: with proximity parameter m, each query fails with
+    // probability (1 + 1/2m)·sqrt(rho), and the commit phase loses
+    // log2((m + 1/2)^7 / (3 rho^1.5) · |LDE|^2) bits of the field
+    let rho = (-(params.log_blowup as f64)).exp2();
+    let provable = (3..=64)
+        .map(|m| {
+            let m = m as f64;
+            let per_query = -((1.0 + 0.5 / m) * rho.sqrt()).log2();
+            let commit = field_bits - ((m + 0.5).powi(7) / (3.0 * rho.powf(1.5))).log2() - 2.0 * log_lde;
+            (queries * per_query + pow).min(commit)
+        })
+        .fold(f64::MIN, f64::max)
+        .min(hash_bits);
+
+    SecurityLevel {
+        conjectured_bits: conjectured.max(0.0),
+        provable_bits: provable.max(0.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::balance_withdrawal::BalanceWithdrawalCircuit;
+    use crate::deposit::DepositCircuit;
+    use crate::merge::MergeCircuit;
+    use crate::prover::{Val, MIN_SECURITY_BITS};
+    use crate::transfer::TransferCircuit;
+    use crate::withdrawal::WithdrawalCircuit;
+
+    #[test]
+    fn test_preset_names() {
+        for preset in FriPreset::ALL {
+            assert_eq!(FriPreset::from_name(preset.name()), Some(preset));
+        }
+        assert_eq!(FriPreset::from_name("80-bit"), None);
+        assert_eq!(FriPreset::from_name("128-bit"), None);
+    }
+
+    #[test]
+    fn test_preset_levels() {
+        let level = |p: FriPreset| security_level(&p.params(), StarkHash::Poseidon2, 2);
+
+        assert!(level(FriPreset::FastTest).conjectured_bits < 10.0);
+        assert!((level(FriPreset::Bits100).conjectured_bits - 100.0).abs() < 1e-9);
+
+        // Capped by the quartic extension: 123.6 - log2(2^(2+3))
+        let top = level(FriPreset::Max);
+        assert!((top.conjectured_bits - (4.0 * BASE_FIELD_BITS - 5.0)).abs() < 1e-9);
+        assert!(top.provable_bits < top.conjectured_bits);
+    }
+
+    #[test]
+    fn test_default_params() {
+        // log_blowup 1, 100 queries, 16 PoW bits
+        let level = security_level(&FriParams::default(), StarkHash::Poseidon2, 2);
+        assert!((level.conjectured_bits - 116.0).abs() < 1e-9);
+        assert!(level.provable_bits > 50.0 && level.provable_bits < level.conjectured_bits);
+    }
+
+    #[test]
+    fn test_default_bits_per_circuit() {
+        let bits = |height: usize| {
+            security_level(&FriParams::default(), StarkHash::Poseidon2, height.ilog2() as usize).conjectured_bits
+        };
+        let field_cap = |log_height: f64| 4.0 * BASE_FIELD_BITS - (log_height + 1.0);
+
+        let withdrawal = bits(WithdrawalCircuit::<Val>::trace_height());
+        let deposit = bits(DepositCircuit::<Val>::trace_height());
+        let transfer = bits(TransferCircuit::<Val>::trace_height());
+        let balance = bits(BalanceWithdrawalCircuit::<Val>::trace_height());
+        let merge = bits(MergeCircuit::<Val>::trace_height());
+
+        assert!((withdrawal - 116.0).abs() < 1e-9);
+        assert!((deposit - 116.0).abs() < 1e-9);
+        assert!((transfer - 116.0).abs() < 1e-9);
+        // 2^7 and 2^8 rows: the extension field caps them below 116
+        assert!((balance - field_cap(7.0)).abs() < 1e-9);
+        assert!((merge - field_cap(8.0)).abs() < 1e-9);
+
+        // Short of 128 bits, and above the floor proving enforces
+        for level in [withdrawal, deposit, transfer, balance, merge] {
+            assert!(level < 128.0 && level > MIN_SECURITY_BITS);
+        }
+    }
+
+    #[test]
+    fn test_larger_traces_lose_field_bits() {
+        let params = FriPreset::Max.params();
+        let small = security_level(&params, StarkHash::Keccak, 2);
+        let large = security_level(&params, StarkHash::Keccak, 20);
+        assert!(large.conjectured_bits < small.conjectured_bits);
+        assert!(large.provable_bits < small.provable_bits);
+    }
+
+    #[test]
+    fn test_goldilocks_raises_field_cap() {
+        let params = FriPreset::Max.params();
+        let babybear = security_level(&params, StarkHash::Keccak, 2);
+        let goldilocks = security_level_over(ChallengeField::Goldilocks, &params, StarkHash::Keccak, 2);
+
+        // 128 - log2(2^(2+3)) against 123.6 - 5
+        assert!((goldilocks.conjectured_bits - 123.0).abs() < 1e-9);
+        assert!(goldilocks.conjectured_bits > babybear.conjectured_bits);
+        assert!(goldilocks.provable_bits >= babybear.provable_bits);
+    }
+}
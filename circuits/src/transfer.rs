@@ -0,0 +1,854 @@
+//! 2-in-2-out join-split transfer circuit for Noctis Privacy Vault
+//!
+//! Moves value between notes without leaving the pool. This circuit proves:
+//! 1. Knowledge of the spending key of each input note, and that each
+//!    input note with a non-zero balance is in the Merkle tree
+//! 2. Correct nullifier derivation for both inputs, and distinct nullifiers
+//! 3. Correct derivation of both output commitments
+//! 4. Value conservation: in_1 + in_2 = out_1 + out_2 + public_amount
+//! 5. Every amount fits in `amount_bits::<F>()` bits
+//!
+//! All four notes are of the public `asset`, so value moves within one
+//! asset and the conservation equation never mixes tokens. Notes commit to
+//! asset_id = hash(limbs of the asset address) (see `address::asset_id`),
+//! and every commitment and nullifier to the public deployment `domain`
+//! (see `keys::note_commitment`).
+//!
+//! A zero-balance input is a dummy: its Merkle proof is not checked, so a
+//! single note can be spent alone by pairing it with a dummy under a fresh
+//! spending key. Its nullifier is still published and must be unique.
+//!
+//! Output notes are created under the recipient's `spending_key_hash`,
+//! so the sender never learns the recipient's spending key. `public_amount`
+//! leaves the pool to `recipient` and is zero for a pure transfer.
+//!
+//! Public inputs:
+//!   - merkle_root: Root of the note commitment tree
+//!   - nullifier_1, nullifier_2: hash(domain, nk, note_index) per input,
+//!     with nk = hash(spending_key, NK_TAG) (see `keys::nullifier_key`)
+//!   - output_commitment_1, output_commitment_2: New note commitments
+//!   - public_amount: Amount withdrawn to `recipient`
+//!   - recipient: Address receiving `public_amount`, as `ADDRESS_LIMBS`
+//!     public values (see `address`)
+//!   - asset: Token of every input and output note, also as
+//!     `ADDRESS_LIMBS` public values
+//!   - domain: Deployment domain of every note, `poseidon::domain_tag`
+//!
+//! Private inputs:
+//!   - per input: spending_key, balance, randomness, note_index,
+//!     merkle_path, path_indices
+//!   - per output: spending_key_hash, balance, randomness
+//!
+//! Amounts are range checked to `amount_bits`: `floor(log2 p) - 3` bits (27
+//! with BabyBear, 60 with Goldilocks), the one bound every circuit shares.
+//! Neither side of the conservation equation wraps around the field, and
+//! every note a transfer creates can later be merged.
+//!
+//! Every hash is one row of the `poseidon_air` gadget. Each input takes
+//! hash(spending_key), nk, its commitment, one row per Merkle level and its
+//! nullifier, in that order; the two output commitments and the asset id
+//! follow. The Merkle
+//! path bits of an input also spell the note index its nullifier is derived
+//! from, and the range proof bits run alongside on the first
+//! `amount_bits` rows. A dummy's path is hashed like any other, and its root
+//! left unchecked.
+
+use p3_air::{Air, AirBuilder, AirBuilderWithPublicValues, BaseAir};
+use p3_baby_bear::BabyBear;
+use p3_field::{PrimeCharacteristicRing, PrimeField64};
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::Matrix;
+
+use crate::address::{address_limbs, address_values, asset_id, public_value_index, Address, ADDRESS_LIMBS};
+use crate::balance_withdrawal::{compute_merkle_root_with_path, path_position, WitnessError};
+use crate::keys::{note_commitment, note_nullifier, nullifier_key, NK_TAG};
+use crate::merge::MAX_MERGE_INPUTS;
+use crate::merkle::TREE_DEPTH;
+use crate::poseidon::{poseidon_hash, poseidon_hash_2};
+use crate::poseidon_air::{
+    assert_constant, assert_digest, assert_inputs, fill_bit_sum, fill_constant, Program, AUX_COL, CARRY_COL,
+};
+
+type Val = BabyBear;
+
+/// Number of input and of output notes
+pub const NUM_NOTES: usize = 2;
+
+/// Number of public inputs
+pub const NUM_PUBLIC_INPUTS: usize = 9;
+
+/// Public input of the domain, last
+pub const DOMAIN_INPUT: usize = NUM_PUBLIC_INPUTS - 1;
+
+/// Public inputs that are addresses: the recipient and the asset
+pub const ADDRESS_INPUTS: [usize; 2] = [6, 7];
+
+/// Number of public values of the AIR, with each address split into limbs
+pub const NUM_PUBLIC_VALUES: usize = NUM_PUBLIC_INPUTS + ADDRESS_INPUTS.len() * (ADDRESS_LIMBS - 1);
+
+/// Amounts with range-check columns: two inputs, two outputs, public amount
+const NUM_AMOUNTS: usize = 2 * NUM_NOTES + 1;
+
+// Steps of the row program for one input, from `input_step(note, 0)`
+const SPENDING_KEY_HASH_STEP: usize = 0;
+const NULLIFIER_KEY_STEP: usize = SPENDING_KEY_HASH_STEP + 1;
+const COMMITMENT_STEP: usize = NULLIFIER_KEY_STEP + 1;
+const MERKLE_STEP: usize = COMMITMENT_STEP + 1;
+const NULLIFIER_STEP: usize = MERKLE_STEP + TREE_DEPTH;
+const INPUT_STEPS: usize = NULLIFIER_STEP + 1;
+/// Output commitments, one step each, after the inputs
+const OUTPUT_STEP: usize = NUM_NOTES * INPUT_STEPS;
+/// The asset id, last
+const ASSET_STEP: usize = OUTPUT_STEP + NUM_NOTES;
+const NUM_STEPS: usize = ASSET_STEP + 1;
+
+// Columns of one input, from `input_col(note, 0)`; all but the index are
+// equal on every row
+const SPENDING_KEY_COL: usize = 0;
+const SPENDING_KEY_HASH_COL: usize = SPENDING_KEY_COL + 1;
+const NULLIFIER_KEY_COL: usize = SPENDING_KEY_HASH_COL + 1;
+const BALANCE_COL: usize = NULLIFIER_KEY_COL + 1;
+const RANDOMNESS_COL: usize = BALANCE_COL + 1;
+/// 1 for a note with a balance, whose root is checked, and the inverse of
+/// the balance
+const IS_REAL_COL: usize = RANDOMNESS_COL + 1;
+const BALANCE_INV_COL: usize = IS_REAL_COL + 1;
+/// Running sum of the path bits: the note index from its last Merkle step on
+const INDEX_COL: usize = BALANCE_INV_COL + 1;
+const INPUT_COLS: usize = INDEX_COL + 1;
+
+// Columns of one output, from `output_col(note, 0)`, equal on every row
+const OUTPUT_KEY_HASH_COL: usize = 0;
+const OUTPUT_BALANCE_COL: usize = OUTPUT_KEY_HASH_COL + 1;
+const OUTPUT_RANDOMNESS_COL: usize = OUTPUT_BALANCE_COL + 1;
+const OUTPUT_COLS: usize = OUTPUT_RANDOMNESS_COL + 1;
+
+const OUTPUTS_COL: usize = AUX_COL + NUM_NOTES * INPUT_COLS;
+/// Inverse of `nullifier_1 - nullifier_2`, equal on every row
+const NULLIFIER_GAP_INV_COL: usize = OUTPUTS_COL + NUM_NOTES * OUTPUT_COLS;
+/// Asset id of every note, equal on every row
+const ASSET_ID_COL: usize = NULLIFIER_GAP_INV_COL + 1;
+/// Sibling and side of the running node on a Merkle step
+const SIBLING_COL: usize = ASSET_ID_COL + 1;
+const IS_RIGHT_COL: usize = SIBLING_COL + 1;
+/// Range proof bit and running sum of each amount, one bit per row
+const RANGE_COL: usize = IS_RIGHT_COL + 1;
+const NUM_AUX_COLS: usize = RANGE_COL + 2 * NUM_AMOUNTS - AUX_COL;
+
+const fn input_step(note: usize, step: usize) -> usize {
+    note * INPUT_STEPS + step
+}
+
+const fn input_col(note: usize, col: usize) -> usize {
+    AUX_COL + note * INPUT_COLS + col
+}
+
+const fn output_col(note: usize, col: usize) -> usize {
+    OUTPUTS_COL + note * OUTPUT_COLS + col
+}
+
+/// Range proof bit column of amount `k`, followed by its running sum
+const fn range_col(k: usize) -> usize {
+    RANGE_COL + 2 * k
+}
+
+/// Bits each amount is range checked to, in every circuit over `F`
+///
+/// Small enough that the MAX_MERGE_INPUTS balances of a merge sum below p:
+/// each is below 2^amount_bits, so their sum stays below 2^floor(log2 p).
+pub fn amount_bits<F: PrimeField64>() -> usize {
+    (F::ORDER_U64.ilog2() - MAX_MERGE_INPUTS.next_power_of_two().ilog2()) as usize
+}
+
+/// Row program of the circuit over `F`, long enough for the hashes and
+/// for one range proof bit per row
+fn program<F: PrimeField64>() -> Program {
+    Program::new(NUM_AUX_COLS, NUM_STEPS.max(amount_bits::<F>()))
+}
+
+/// A note being spent
+#[derive(Clone)]
+pub struct InputNote<F = Val> {
+    pub spending_key: F,
+    pub balance: F,
+    pub randomness: F,
+    pub note_index: u64,
+    pub merkle_path: [F; TREE_DEPTH],
+    pub path_indices: [bool; TREE_DEPTH],
+}
+
+/// A note being created
+#[derive(Clone)]
+pub struct OutputNote<F = Val> {
+    /// The recipient's `hash(spending_key)`
+    pub spending_key_hash: F,
+    pub balance: F,
+    pub randomness: F,
+}
+
+impl<F: PrimeField64> InputNote<F> {
+    /// Zero-value dummy input with no Merkle path
+    ///
+    /// `spending_key` should be fresh randomness: the dummy's nullifier is
+    /// published like any other.
+    pub fn dummy(spending_key: F) -> Self {
+        Self {
+            spending_key,
+            balance: F::ZERO,
+            randomness: F::ZERO,
+            note_index: 0,
+            merkle_path: [F::ZERO; TREE_DEPTH],
+            path_indices: [false; TREE_DEPTH],
+        }
+    }
+
+    pub fn commitment(&self, domain: F, asset_id: F) -> F {
+        note_commitment(domain, poseidon_hash(self.spending_key), asset_id, self.balance, self.randomness)
+    }
+
+    pub fn nullifier(&self, domain: F) -> F {
+        note_nullifier(domain, nullifier_key(self.spending_key), self.note_index)
+    }
+
+    pub fn is_dummy(&self) -> bool {
+        self.balance == F::ZERO
+    }
+}
+
+impl<F: PrimeField64> OutputNote<F> {
+    pub fn commitment(&self, domain: F, asset_id: F) -> F {
+        note_commitment(domain, self.spending_key_hash, asset_id, self.balance, self.randomness)
+    }
+}
+
+/// Private witness for a transfer
+pub struct TransferWitness<F = Val> {
+    /// Deployment domain of every note
+    pub domain: F,
+    /// Asset of every input and output note
+    pub asset: Address,
+    pub inputs: [InputNote<F>; NUM_NOTES],
+    pub outputs: [OutputNote<F>; NUM_NOTES],
+}
+
+/// Transfer circuit AIR
+pub struct TransferCircuit<F = Val> {
+    // Public inputs
+    pub merkle_root: F,
+    pub nullifiers: [F; NUM_NOTES],
+    pub output_commitments: [F; NUM_NOTES],
+    pub public_amount: F,
+    pub recipient: Address,
+    pub asset: Address,
+    pub domain: F,
+}
+
+impl<F: PrimeField64> TransferCircuit<F> {
+    pub fn new(
+        merkle_root: F,
+        nullifiers: [F; NUM_NOTES],
+        output_commitments: [F; NUM_NOTES],
+        public_amount: F,
+        recipient: Address,
+        asset: Address,
+        domain: F,
+    ) -> Self {
+        Self {
+            merkle_root,
+            nullifiers,
+            output_commitments,
+            public_amount,
+            recipient,
+            asset,
+            domain,
+        }
+    }
+
+    /// Derive the public inputs from a witness and check it
+    ///
+    /// The Merkle root is the one the first non-dummy input hashes to.
+    pub fn from_witness(
+        witness: &TransferWitness<F>,
+        public_amount: F,
+        recipient: Address,
+    ) -> Result<Self, WitnessError> {
+        let spent = witness
+            .inputs
+            .iter()
+            .find(|input| !input.is_dummy())
+            .unwrap_or(&witness.inputs[0]);
+        let (domain, asset_id) = (witness.domain, asset_id(&witness.asset));
+        let merkle_root = compute_merkle_root_with_path(
+            spent.commitment(domain, asset_id),
+            &spent.merkle_path,
+            &spent.path_indices,
+        );
+
+        let circuit = Self::new(
+            merkle_root,
+            [witness.inputs[0].nullifier(domain), witness.inputs[1].nullifier(domain)],
+            [
+                witness.outputs[0].commitment(domain, asset_id),
+                witness.outputs[1].commitment(domain, asset_id),
+            ],
+            public_amount,
+            recipient,
+            witness.asset,
+            domain,
+        );
+        circuit.check_witness(witness)?;
+        Ok(circuit)
+    }
+
+    /// Public inputs in verifier order, each address as its limbs:
+    /// [merkle_root, nullifier_1, nullifier_2, output_commitment_1,
+    ///  output_commitment_2, public_amount, recipient, asset, domain]
+    pub fn public_values(&self) -> Vec<F> {
+        let mut values = Vec::with_capacity(NUM_PUBLIC_VALUES);
+        values.push(self.merkle_root);
+        values.extend(self.nullifiers);
+        values.extend(self.output_commitments);
+        values.push(self.public_amount);
+        values.extend(address_values::<F>(&self.recipient));
+        values.extend(address_values::<F>(&self.asset));
+        values.push(self.domain);
+        values
+    }
+
+    /// All range-checked amounts: inputs, outputs, public amount
+    fn amounts(&self, witness: &TransferWitness<F>) -> [u64; NUM_AMOUNTS] {
+        [
+            witness.inputs[0].balance.as_canonical_u64(),
+            witness.inputs[1].balance.as_canonical_u64(),
+            witness.outputs[0].balance.as_canonical_u64(),
+            witness.outputs[1].balance.as_canonical_u64(),
+            self.public_amount.as_canonical_u64(),
+        ]
+    }
+
+    /// Check that the witness satisfies the statement for these public inputs
+    pub fn check_witness(&self, witness: &TransferWitness<F>) -> Result<(), WitnessError> {
+        let asset_id = asset_id::<F>(&self.asset);
+
+        // 1. Inputs: Merkle membership (skipped for dummies) and nullifiers
+        for (input, &nullifier) in witness.inputs.iter().zip(&self.nullifiers) {
+            if !input.is_dummy() {
+                let root = compute_merkle_root_with_path(
+                    input.commitment(self.domain, asset_id),
+                    &input.merkle_path,
+                    &input.path_indices,
+                );
+                if root != self.merkle_root {
+                    return Err(WitnessError::InvalidMerkleProof);
+                }
+            }
+            // The circuit reads note_index off the Merkle path bits
+            if input.note_index != path_position(&input.path_indices) || input.nullifier(self.domain) != nullifier {
+                return Err(WitnessError::InvalidNullifier);
+            }
+        }
+        if self.nullifiers[0] == self.nullifiers[1] {
+            return Err(WitnessError::DuplicateNullifier);
+        }
+
+        // 2. Output commitments, in the same asset as the inputs
+        for (output, &commitment) in witness.outputs.iter().zip(&self.output_commitments) {
+            if output.commitment(self.domain, asset_id) != commitment {
+                return Err(WitnessError::InvalidOutputCommitment);
+            }
+        }
+
+        // 3. Range checks
+        let amounts = self.amounts(witness);
+        if amounts.iter().any(|&a| a >> amount_bits::<F>() != 0) {
+            return Err(WitnessError::AmountOutOfRange);
+        }
+
+        // 4. Value conservation
+        let [in_1, in_2, out_1, out_2, public_amount] = amounts.map(u128::from);
+        if in_1 + in_2 != out_1 + out_2 + public_amount {
+            return Err(WitnessError::ValueNotConserved);
+        }
+
+        Ok(())
+    }
+
+    /// Generate the execution trace for proving
+    pub fn generate_trace(&self, witness: &TransferWitness<F>) -> RowMajorMatrix<F> {
+        if let Err(e) = self.check_witness(witness) {
+            panic!("{}", e);
+        }
+
+        // ===== One permutation per step, in the order `eval` wires them =====
+        let asset_id = asset_id::<F>(&self.asset);
+        let mut inputs = Vec::with_capacity(NUM_STEPS);
+        for input in &witness.inputs {
+            let spending_key_hash = poseidon_hash(input.spending_key);
+            inputs.push(vec![input.spending_key]);
+            inputs.push(vec![input.spending_key, F::from_u64(NK_TAG)]);
+            inputs.push(vec![self.domain, spending_key_hash, asset_id, input.balance, input.randomness]);
+            let mut current = input.commitment(self.domain, asset_id);
+            for (&sibling, &is_right) in input.merkle_path.iter().zip(&input.path_indices) {
+                let pair = if is_right { [sibling, current] } else { [current, sibling] };
+                current = poseidon_hash_2(pair[0], pair[1]);
+                inputs.push(pair.to_vec());
+            }
+            inputs.push(vec![self.domain, nullifier_key(input.spending_key), F::from_u64(input.note_index)]);
+        }
+        for output in &witness.outputs {
+            inputs.push(vec![self.domain, output.spending_key_hash, asset_id, output.balance, output.randomness]);
+        }
+        inputs.push(address_values::<F>(&self.asset).to_vec());
+
+        let mut trace = program::<F>().trace(&inputs);
+
+        // ===== Inputs: private columns, Merkle path and note index =====
+        let width = trace.width;
+        for (note, input) in witness.inputs.iter().enumerate() {
+            fill_constant(&mut trace, input_col(note, SPENDING_KEY_COL), input.spending_key);
+            fill_constant(&mut trace, input_col(note, SPENDING_KEY_HASH_COL), poseidon_hash(input.spending_key));
+            fill_constant(&mut trace, input_col(note, NULLIFIER_KEY_COL), nullifier_key(input.spending_key));
+            fill_constant(&mut trace, input_col(note, BALANCE_COL), input.balance);
+            fill_constant(&mut trace, input_col(note, RANDOMNESS_COL), input.randomness);
+            fill_constant(&mut trace, input_col(note, IS_REAL_COL), F::from_bool(!input.is_dummy()));
+            let balance_inv = input.balance.try_inverse().unwrap_or(F::ZERO);
+            fill_constant(&mut trace, input_col(note, BALANCE_INV_COL), balance_inv);
+
+            let first = input_step(note, MERKLE_STEP);
+            for (level, row) in trace.values.chunks_mut(width).skip(first).take(TREE_DEPTH).enumerate() {
+                row[SIBLING_COL] = input.merkle_path[level];
+            }
+            fill_bit_sum(&mut trace, IS_RIGHT_COL, input_col(note, INDEX_COL), first, TREE_DEPTH, input.note_index);
+        }
+        let nullifier_gap = self.nullifiers[0] - self.nullifiers[1];
+        fill_constant(&mut trace, NULLIFIER_GAP_INV_COL, nullifier_gap.inverse());
+        fill_constant(&mut trace, ASSET_ID_COL, asset_id);
+
+        // ===== Outputs =====
+        for (note, output) in witness.outputs.iter().enumerate() {
+            fill_constant(&mut trace, output_col(note, OUTPUT_KEY_HASH_COL), output.spending_key_hash);
+            fill_constant(&mut trace, output_col(note, OUTPUT_BALANCE_COL), output.balance);
+            fill_constant(&mut trace, output_col(note, OUTPUT_RANDOMNESS_COL), output.randomness);
+        }
+
+        // ===== Range proofs: every amount in amount_bits bits =====
+        for (k, amount) in self.amounts(witness).into_iter().enumerate() {
+            fill_bit_sum(&mut trace, range_col(k), range_col(k) + 1, 0, amount_bits::<F>(), amount);
+        }
+
+        trace
+    }
+
+    /// Height of the trace `generate_trace` produces
+    pub fn trace_height() -> usize {
+        program::<F>().height()
+    }
+}
+
+impl<F: PrimeField64> BaseAir<F> for TransferCircuit<F> {
+    fn width(&self) -> usize {
+        program::<F>().width()
+    }
+}
+
+impl<F: PrimeField64, AB: AirBuilderWithPublicValues<F = F>> Air<AB> for TransferCircuit<F> {
+    fn eval(&self, builder: &mut AB) {
+        let main = builder.main();
+        let local = main.row_slice(0).expect("The matrix is empty?");
+        let next = main.row_slice(1).expect("The matrix has only one row?");
+        let local: Vec<AB::Expr> = local.iter().map(|&v| v.into()).collect();
+        let next: Vec<AB::Expr> = next.iter().map(|&v| v.into()).collect();
+        let pis: Vec<AB::Expr> = builder.public_values().iter().map(|&v| v.into()).collect();
+        let (merkle_root, nullifiers) = (pis[0].clone(), [pis[1].clone(), pis[2].clone()]);
+        let (output_commitments, public_amount) = ([pis[3].clone(), pis[4].clone()], pis[5].clone());
+        let domain = pis[public_value_index(DOMAIN_INPUT, &ADDRESS_INPUTS)].clone();
+        let asset_id = local[ASSET_ID_COL].clone();
+
+        let program = program::<F>();
+        program.eval(builder, &local, &next);
+        for note in 0..NUM_NOTES {
+            for col in [
+                SPENDING_KEY_COL,
+                SPENDING_KEY_HASH_COL,
+                NULLIFIER_KEY_COL,
+                BALANCE_COL,
+                RANDOMNESS_COL,
+                IS_REAL_COL,
+                BALANCE_INV_COL,
+            ] {
+                assert_constant(builder, &local, &next, input_col(note, col));
+            }
+            for col in [OUTPUT_KEY_HASH_COL, OUTPUT_BALANCE_COL, OUTPUT_RANDOMNESS_COL] {
+                assert_constant(builder, &local, &next, output_col(note, col));
+            }
+        }
+        assert_constant(builder, &local, &next, NULLIFIER_GAP_INV_COL);
+        assert_constant(builder, &local, &next, ASSET_ID_COL);
+
+        // asset_id = hash(limbs of the asset)
+        let asset = public_value_index(7, &ADDRESS_INPUTS);
+        let flag = program.flag(&local, ASSET_STEP);
+        assert_inputs(builder, flag.clone(), &local, &pis[asset..asset + ADDRESS_LIMBS]);
+        assert_digest(builder, flag, &local, asset_id.clone());
+
+        for (note, nullifier) in nullifiers.iter().enumerate() {
+            let col = |c: usize| local[input_col(note, c)].clone();
+            let step = |s: usize| input_step(note, s);
+
+            // spending_key_hash = hash(spending_key)
+            let flag = program.flag(&local, step(SPENDING_KEY_HASH_STEP));
+            assert_inputs(builder, flag.clone(), &local, &[col(SPENDING_KEY_COL)]);
+            assert_digest(builder, flag, &local, col(SPENDING_KEY_HASH_COL));
+
+            // nk = hash(spending_key, NK_TAG)
+            let flag = program.flag(&local, step(NULLIFIER_KEY_STEP));
+            assert_inputs(builder, flag.clone(), &local, &[col(SPENDING_KEY_COL), AB::Expr::from_u64(NK_TAG)]);
+            assert_digest(builder, flag, &local, col(NULLIFIER_KEY_COL));
+
+            // commitment = hash(domain, spending_key_hash, asset_id, balance,
+            // randomness), carried into the first Merkle step
+            let lanes =
+                [domain.clone(), col(SPENDING_KEY_HASH_COL), asset_id.clone(), col(BALANCE_COL), col(RANDOMNESS_COL)];
+            assert_inputs(builder, program.flag(&local, step(COMMITMENT_STEP)), &local, &lanes);
+
+            // Each level hashes the carried node with the sibling, on the
+            // side the path bit selects; a real note's last one yields
+            // merkle_root
+            let merkle = program.phase(&local, step(MERKLE_STEP), TREE_DEPTH);
+            let (node, sibling, is_right) = (&local[CARRY_COL], &local[SIBLING_COL], &local[IS_RIGHT_COL]);
+            let left = node.clone() + is_right.clone() * (sibling.clone() - node.clone());
+            let right = sibling.clone() + is_right.clone() * (node.clone() - sibling.clone());
+            assert_inputs(builder, merkle, &local, &[left, right]);
+            let last = program.flag(&local, step(NULLIFIER_STEP) - 1);
+            assert_digest(builder, last * col(IS_REAL_COL), &local, merkle_root.clone());
+            let index = input_col(note, INDEX_COL);
+            program.eval_bit_sum(builder, &local, &next, IS_RIGHT_COL, index, step(MERKLE_STEP), TREE_DEPTH);
+
+            // A note is real iff its balance is nonzero
+            builder.assert_bool(col(IS_REAL_COL));
+            builder.when(AB::Expr::ONE - col(IS_REAL_COL)).assert_zero(col(BALANCE_COL));
+            builder.when(col(IS_REAL_COL)).assert_one(col(BALANCE_COL) * col(BALANCE_INV_COL));
+
+            // nullifier = hash(domain, nk, note_index)
+            let flag = program.flag(&local, step(NULLIFIER_STEP));
+            assert_inputs(builder, flag.clone(), &local, &[domain.clone(), col(NULLIFIER_KEY_COL), col(INDEX_COL)]);
+            assert_digest(builder, flag, &local, nullifier.clone());
+        }
+        let nullifier_gap = nullifiers[0].clone() - nullifiers[1].clone();
+        builder.assert_one(nullifier_gap * local[NULLIFIER_GAP_INV_COL].clone());
+
+        // output_commitment = hash(domain, spending_key_hash, asset_id, balance, randomness)
+        for (note, commitment) in output_commitments.into_iter().enumerate() {
+            let col = |c: usize| local[output_col(note, c)].clone();
+            let lanes = [
+                domain.clone(),
+                col(OUTPUT_KEY_HASH_COL),
+                asset_id.clone(),
+                col(OUTPUT_BALANCE_COL),
+                col(OUTPUT_RANDOMNESS_COL),
+            ];
+            let flag = program.flag(&local, OUTPUT_STEP + note);
+            assert_inputs(builder, flag.clone(), &local, &lanes);
+            assert_digest(builder, flag, &local, commitment);
+        }
+
+        // Every amount is the sum of its amount_bits range-check bits
+        let amounts = [
+            local[input_col(0, BALANCE_COL)].clone(),
+            local[input_col(1, BALANCE_COL)].clone(),
+            local[output_col(0, OUTPUT_BALANCE_COL)].clone(),
+            local[output_col(1, OUTPUT_BALANCE_COL)].clone(),
+            public_amount,
+        ];
+        for (k, amount) in amounts.iter().enumerate() {
+            program.eval_bit_sum(builder, &local, &next, range_col(k), range_col(k) + 1, 0, amount_bits::<F>());
+            builder.when_last_row().assert_eq(local[range_col(k) + 1].clone(), amount.clone());
+        }
+
+        // Value conservation
+        let [in_1, in_2, out_1, out_2, public_amount] = amounts;
+        builder.assert_eq(in_1 + in_2, out_1 + out_2 + public_amount);
+
+        // recipient is bound as a public value
+    }
+}
+
+/// Proof data for serialization
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct TransferProof {
+    pub proof_bytes: Vec<u8>,
+    pub public_inputs: TransferPublicInputs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TransferPublicInputs {
+    pub merkle_root: u64,
+    pub nullifiers: [u64; NUM_NOTES],
+    pub output_commitments: [u64; NUM_NOTES],
+    pub public_amount: u64,
+    pub recipient: Address,
+    pub asset: Address,
+    pub domain: u64,
+}
+
+impl TransferPublicInputs {
+    pub fn from_circuit<F: PrimeField64>(circuit: &TransferCircuit<F>) -> Self {
+        Self {
+            merkle_root: circuit.merkle_root.as_canonical_u64(),
+            nullifiers: circuit.nullifiers.map(|n| n.as_canonical_u64()),
+            output_commitments: circuit.output_commitments.map(|c| c.as_canonical_u64()),
+            public_amount: circuit.public_amount.as_canonical_u64(),
+            recipient: circuit.recipient,
+            asset: circuit.asset,
+            domain: circuit.domain.as_canonical_u64(),
+        }
+    }
+
+    /// Public values in verifier order, each address as its limbs
+    pub fn to_array(&self) -> [u64; NUM_PUBLIC_VALUES] {
+        let mut values = Vec::with_capacity(NUM_PUBLIC_VALUES);
+        values.push(self.merkle_root);
+        values.extend(self.nullifiers);
+        values.extend(self.output_commitments);
+        values.push(self.public_amount);
+        values.extend(address_limbs(&self.recipient).map(u64::from));
+        values.extend(address_limbs(&self.asset).map(u64::from));
+        values.push(self.domain);
+        values.try_into().expect("NUM_PUBLIC_VALUES values")
+    }
+
+    /// Public values as field elements, in verifier order
+    pub fn to_field_elements<F: PrimeCharacteristicRing>(&self) -> Vec<F> {
+        self.to_array().iter().map(|&v| F::from_u64(v)).collect()
+    }
+
+    /// Circuit for these public inputs
+    pub fn to_circuit<F: PrimeField64>(&self) -> TransferCircuit<F> {
+        TransferCircuit::new(
+            F::from_u64(self.merkle_root),
+            self.nullifiers.map(F::from_u64),
+            self.output_commitments.map(F::from_u64),
+            F::from_u64(self.public_amount),
+            self.recipient,
+            self.asset,
+            F::from_u64(self.domain),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::filled_tree_proof;
+    use crate::prover::forged_trace_verifies;
+    use p3_field::PrimeField32;
+
+    const ASSET: Address = [0xa5; 20];
+    const DOMAIN: Val = Val::new(8453);
+
+    fn input(spending_key: u32, balance: u32, randomness: u32) -> InputNote {
+        InputNote {
+            spending_key: Val::new(spending_key),
+            balance: Val::new(balance),
+            randomness: Val::new(randomness),
+            note_index: 0,
+            merkle_path: [Val::new(0); TREE_DEPTH],
+            path_indices: [false; TREE_DEPTH],
+        }
+    }
+
+    fn output(balance: u32, randomness: u32) -> OutputNote {
+        OutputNote {
+            spending_key_hash: poseidon_hash(Val::new(777)),
+            balance: Val::new(balance),
+            randomness: Val::new(randomness),
+        }
+    }
+
+    /// Two notes in a common tree, spent into two outputs
+    fn two_note_witness(out_1: u32, out_2: u32) -> TransferWitness {
+        let mut inputs = [input(11, 600, 1), input(22, 400, 2)];
+        let leaves: Vec<Val> = inputs.iter().map(|n| n.commitment(DOMAIN, asset_id(&ASSET))).collect();
+        for (i, note) in inputs.iter_mut().enumerate() {
+            let (_, path, indices) = filled_tree_proof(&leaves, i).unwrap();
+            note.note_index = i as u64;
+            note.merkle_path = path;
+            note.path_indices = indices;
+        }
+        TransferWitness {
+            domain: DOMAIN,
+            asset: ASSET,
+            inputs,
+            outputs: [output(out_1, 3), output(out_2, 4)],
+        }
+    }
+
+    #[test]
+    fn test_two_input_transfer() {
+        let witness = two_note_witness(700, 300);
+        let circuit = TransferCircuit::from_witness(&witness, Val::new(0), [0; 20]).unwrap();
+
+        let trace = circuit.generate_trace(&witness);
+        assert_eq!(trace.width(), circuit.width());
+        assert_eq!(trace.height(), TransferCircuit::<Val>::trace_height());
+
+        // Both notes prove against the same root
+        let leaves = [witness.inputs[0].commitment(DOMAIN, asset_id(&ASSET)),
+            witness.inputs[1].commitment(DOMAIN, asset_id(&ASSET)),
+        ];
+        assert_eq!(circuit.merkle_root, filled_tree_proof(&leaves, 1).unwrap().0);
+    }
+
+    #[test]
+    fn test_single_note_with_dummy() {
+        let mut note = input(11, 1000, 1);
+        let (root, path, indices) = filled_tree_proof(&[note.commitment(DOMAIN, asset_id(&ASSET))], 0).unwrap();
+        note.merkle_path = path;
+        note.path_indices = indices;
+
+        let witness = TransferWitness {
+            domain: DOMAIN,
+            asset: ASSET,
+            inputs: [InputNote::dummy(Val::new(424242)), note],
+            outputs: [output(900, 3), output(0, 4)],
+        };
+        let circuit = TransferCircuit::from_witness(&witness, Val::new(100), [0xab; 20]).unwrap();
+        assert_eq!(circuit.merkle_root, root);
+        let _trace = circuit.generate_trace(&witness);
+    }
+
+    #[test]
+    fn test_forged_trace_rejected() {
+        let witness = two_note_witness(700, 300);
+        let circuit = TransferCircuit::from_witness(&witness, Val::new(0), [0; 20]).unwrap();
+        assert!(forged_trace_verifies(&circuit, circuit.generate_trace(&witness), &circuit.public_values()));
+
+        // A note minted outside the pool, on a path to its own root
+        let mut minted = input(11, 5000, 9);
+        let (_, path, indices) = filled_tree_proof(&[minted.commitment(DOMAIN, asset_id(&ASSET))], 0).unwrap();
+        minted.merkle_path = path;
+        minted.path_indices = indices;
+        let forged = TransferWitness {
+            domain: DOMAIN,
+            asset: ASSET,
+            inputs: [minted, InputNote::dummy(Val::new(424242))],
+            outputs: [output(5000, 3), output(0, 4)],
+        };
+        let mut in_pool = TransferCircuit::from_witness(&forged, Val::new(0), [0; 20]).unwrap();
+        let mut trace = in_pool.generate_trace(&forged);
+        in_pool.merkle_root = circuit.merkle_root;
+        assert!(!forged_trace_verifies(&in_pool, trace.clone(), &in_pool.public_values()));
+
+        // Nor passed off as a dummy, whose root is not checked
+        fill_constant(&mut trace, input_col(0, IS_REAL_COL), Val::new(0));
+        assert!(!forged_trace_verifies(&in_pool, trace, &in_pool.public_values()));
+
+        // An output committing to more than its balance column holds
+        let mut inflated = TransferCircuit::from_witness(&witness, Val::new(0), [0; 20]).unwrap();
+        inflated.output_commitments[0] = output(1700, 3).commitment(DOMAIN, asset_id(&ASSET));
+        assert!(!forged_trace_verifies(&inflated, circuit.generate_trace(&witness), &inflated.public_values()));
+    }
+
+    #[test]
+    fn test_viewing_key_cannot_spend() {
+        let witness = two_note_witness(700, 300);
+        let circuit = TransferCircuit::from_witness(&witness, Val::new(0), [0; 20]).unwrap();
+        let note = &witness.inputs[0];
+
+        // A watch-only wallet given nk and the owner tag derives the
+        // nullifier the spender reveals, which the owner tag alone does not
+        let (nk, owner) = (nullifier_key(note.spending_key), poseidon_hash(note.spending_key));
+        assert_eq!(note_nullifier(DOMAIN, nk, note.note_index), circuit.nullifiers[0]);
+        assert_ne!(note_nullifier(DOMAIN, owner, note.note_index), circuit.nullifiers[0]);
+
+        // but cannot fill in the spending key both are derived from
+        let mut trace = circuit.generate_trace(&witness);
+        fill_constant(&mut trace, input_col(0, SPENDING_KEY_COL), nk);
+        assert!(!forged_trace_verifies(&circuit, trace, &circuit.public_values()));
+    }
+
+    #[test]
+    fn test_value_must_be_conserved() {
+        let witness = two_note_witness(700, 301);
+        assert!(matches!(
+            TransferCircuit::from_witness(&witness, Val::new(0), [0; 20]),
+            Err(WitnessError::ValueNotConserved)
+        ));
+
+        // The public amount counts towards the outputs
+        let witness = two_note_witness(700, 200);
+        assert!(TransferCircuit::from_witness(&witness, Val::new(100), [0xab; 20]).is_ok());
+    }
+
+    #[test]
+    fn test_wrapping_amounts_rejected() {
+        // 2^29 + (p - 2^29) wraps to zero in the field
+        let big = 1u32 << 29;
+        let witness = TransferWitness {
+            domain: DOMAIN,
+            asset: ASSET,
+            inputs: [InputNote::dummy(Val::new(1)), InputNote::dummy(Val::new(2))],
+            outputs: [output(big, 3), output(Val::ORDER_U32 - big, 4)],
+        };
+        assert!(matches!(
+            TransferCircuit::from_witness(&witness, Val::new(0), [0; 20]),
+            Err(WitnessError::AmountOutOfRange)
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_nullifier_rejected() {
+        let mut witness = two_note_witness(700, 300);
+        witness.inputs[1] = witness.inputs[0].clone();
+        assert!(matches!(
+            TransferCircuit::from_witness(&witness, Val::new(0), [0; 20]),
+            Err(WitnessError::DuplicateNullifier)
+        ));
+    }
+
+    #[test]
+    fn test_wrong_output_commitment_rejected() {
+        let witness = two_note_witness(700, 300);
+        let mut circuit = TransferCircuit::from_witness(&witness, Val::new(0), [0; 20]).unwrap();
+        circuit.output_commitments.swap(0, 1);
+        assert!(matches!(
+            circuit.check_witness(&witness),
+            Err(WitnessError::InvalidOutputCommitment)
+        ));
+    }
+
+    #[test]
+    fn test_notes_bound_to_asset() {
+        let witness = two_note_witness(700, 300);
+        let circuit = TransferCircuit::from_witness(&witness, Val::new(0), [0; 20]).unwrap();
+        let (asset, domain) = (public_value_index(7, &ADDRESS_INPUTS), public_value_index(DOMAIN_INPUT, &ADDRESS_INPUTS));
+        assert_eq!(circuit.public_values()[asset..domain], address_values::<Val>(&ASSET));
+        assert_eq!(circuit.public_values()[domain], DOMAIN);
+        assert_eq!(circuit.output_commitments[0], witness.outputs[0].commitment(DOMAIN, asset_id(&ASSET)));
+
+        // Notes of ASSET cannot be spent as another asset
+        let mut other = circuit;
+        other.asset = [0; 20];
+        assert!(matches!(
+            other.check_witness(&witness),
+            Err(WitnessError::InvalidMerkleProof)
+        ));
+
+        // nor in another deployment
+        other.asset = ASSET;
+        other.domain = Val::new(84532);
+        assert!(matches!(
+            other.check_witness(&witness),
+            Err(WitnessError::InvalidMerkleProof)
+        ));
+    }
+
+    #[test]
+    fn test_public_inputs_roundtrip() {
+        let witness = two_note_witness(700, 300);
+        let circuit = TransferCircuit::from_witness(&witness, Val::new(0), [0; 20]).unwrap();
+        let public_inputs = TransferPublicInputs::from_circuit(&circuit);
+        assert_eq!(public_inputs.to_field_elements::<Val>(), circuit.public_values());
+        assert_eq!(public_inputs.to_circuit::<Val>().public_values(), circuit.public_values());
+    }
+}
@@ -0,0 +1,623 @@
+//! Verification of untrusted proof bytes
+//!
+//! `prover::verify_withdrawal` and `prover::verify_balance_withdrawal`
+//! trust their input: bincode allocates whatever the length prefixes claim
+//! and p3-uni-stark indexes into the proof assuming the prover built it.
+//! Relayers accept proofs from the network, so they go through
+//! `ProofVerifier` instead, which:
+//!
+//! 1. only runs under a `VerifyingKey` whose digest matches one pinned by
+//!    the operator, so a proof cannot pick its own (weaker) parameters
+//! 2. rejects proofs over `ProofLimits::max_proof_bytes` before decoding,
+//!    then decodes with a bincode size limit and no trailing bytes
+//! 3. checks every vector of the decoded proof against the length the key
+//!    implies (trace and quotient openings, FRI layers, queries, Merkle
+//!    paths) before p3-uni-stark sees it
+//! 4. rejects public inputs that are not canonical field elements
+//! 5. rejects keys whose conjectured security is below the floor
+//!    (`MIN_SECURITY_BITS` unless lowered with `with_min_security_bits`)
+//!
+//! serde caps preallocation for sequences, so a lying length prefix costs
+//! one bounded allocation before decoding runs out of input. Verification
+//! itself runs under `catch_unwind` as a last line of defence: a panic is
+//! reported as `VerifyError::Malformed`.
+
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use bincode::Options;
+use p3_air::{Air, BaseAir};
+use p3_field::{PrimeCharacteristicRing, PrimeField32};
+use p3_uni_stark::{get_symbolic_constraints, verify, Proof, SymbolicAirBuilder};
+
+use crate::abi::keccak256;
+use crate::balance_withdrawal::{BalanceWithdrawalCircuit, PublicInputs};
+use crate::codegen::stark::{log_quotient_degree, trace_degree_bits};
+use crate::prover::{
+    Backend, FriParams, KeccakStarkConfig, NoctisStarkConfig, ProofParts, ProverConfig, ProverError, StarkHash, Val,
+};
+use crate::withdrawal::{WithdrawalCircuit, WithdrawalPublicInputs};
+
+/// Domain separator of the verifying key digest
+const VK_DOMAIN: &[u8] = b"noctis-stark-vk-v1";
+
+/// Width of a quotient chunk row: one extension element, flattened
+const QUOTIENT_CHUNK_WIDTH: usize = 4;
+
+/// Circuits a `VerifyingKey` can describe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitKind {
+    Withdrawal,
+    BalanceWithdrawal,
+}
+
+/// Everything a proof's validity depends on besides its public inputs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VerifyingKey {
+    pub circuit: CircuitKind,
+    pub hash: StarkHash,
+    pub params: FriParams,
+    pub trace_width: usize,
+    pub num_public_values: usize,
+    pub degree_bits: usize,
+    pub log_quotient_degree: usize,
+}
+
+impl VerifyingKey {
+    /// Key for `WithdrawalCircuit` proofs
+    pub fn withdrawal(hash: StarkHash, params: &FriParams) -> Self {
+        let air = WithdrawalCircuit::new(Val::ZERO, Val::ZERO, [0; 20], Val::ZERO, Val::ZERO);
+        let degree_bits = trace_degree_bits(WithdrawalCircuit::<Val>::trace_height());
+        Self::for_air(CircuitKind::Withdrawal, &air, air.public_values().len(), degree_bits, hash, params)
+    }
+
+    /// Key for `BalanceWithdrawalCircuit` proofs
+    pub fn balance_withdrawal(hash: StarkHash, params: &FriParams) -> Self {
+        let air = BalanceWithdrawalCircuit::new(Val::ZERO, Val::ZERO, [0; 20], Val::ZERO, Val::ZERO, Val::ZERO);
+        let degree_bits = trace_degree_bits(BalanceWithdrawalCircuit::<Val>::trace_height());
+        Self::for_air(CircuitKind::BalanceWithdrawal, &air, air.public_values().len(), degree_bits, hash, params)
+    }
+
+    fn for_air<A>(
+        circuit: CircuitKind,
+        air: &A,
+        num_public_values: usize,
+        degree_bits: usize,
+        hash: StarkHash,
+        params: &FriParams,
+    ) -> Self
+    where
+        A: BaseAir<Val> + Air<SymbolicAirBuilder<Val>>,
+    {
+        let constraints = get_symbolic_constraints(air, 0, num_public_values);
+        Self {
+            circuit,
+            hash,
+            params: *params,
+            trace_width: air.width(),
+            num_public_values,
+            degree_bits,
+            log_quotient_degree: log_quotient_degree(&constraints),
+        }
+    }
+
+    /// Digest to pin in relayer configuration
+    ///
+    /// keccak256 over a domain tag and every field as a big-endian u64.
+    pub fn digest(&self) -> [u8; 32] {
+        let circuit = match self.circuit {
+            CircuitKind::Withdrawal => 0u64,
+            CircuitKind::BalanceWithdrawal => 1,
+        };
+        let hash = match self.hash {
+            StarkHash::Poseidon2 => 0u64,
+            StarkHash::Keccak => 1,
+        };
+        let fields = [
+            circuit,
+            hash,
+            self.params.log_blowup as u64,
+            self.params.log_final_poly_len as u64,
+            self.params.num_queries as u64,
+            self.params.proof_of_work_bits as u64,
+            self.trace_width as u64,
+            self.num_public_values as u64,
+            self.degree_bits as u64,
+            self.log_quotient_degree as u64,
+        ];
+
+        let mut data = VK_DOMAIN.to_vec();
+        for f in fields {
+            data.extend_from_slice(&f.to_be_bytes());
+        }
+        keccak256(&data)
+    }
+
+    fn shape(&self) -> ProofShape {
+        let log_lde_height = self.degree_bits + self.params.log_blowup;
+        ProofShape {
+            trace_width: self.trace_width,
+            quotient_chunks: 1 << self.log_quotient_degree,
+            degree_bits: self.degree_bits,
+            fri_layers: self.degree_bits.saturating_sub(self.params.log_final_poly_len),
+            final_poly_len: 1 << self.params.log_final_poly_len,
+            num_queries: self.params.num_queries,
+            log_lde_height,
+        }
+    }
+}
+
+/// Bounds on what a verifier accepts, independent of the key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProofLimits {
+    pub max_proof_bytes: usize,
+    pub max_fri_layers: usize,
+    pub max_queries: usize,
+    pub max_degree_bits: usize,
+}
+
+impl Default for ProofLimits {
+    fn default() -> Self {
+        Self {
+            max_proof_bytes: 1 << 21,
+            max_fri_layers: 24,
+            max_queries: 256,
+            max_degree_bits: 24,
+        }
+    }
+}
+
+/// Lengths every vector of a proof must have under a given key
+#[derive(Clone, Copy, Debug)]
+struct ProofShape {
+    trace_width: usize,
+    quotient_chunks: usize,
+    degree_bits: usize,
+    fri_layers: usize,
+    final_poly_len: usize,
+    num_queries: usize,
+    log_lde_height: usize,
+}
+
+/// Errors from verifying untrusted proofs
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyError {
+    /// The verifying key does not hash to the pinned digest
+    KeyMismatch { pinned: [u8; 32], actual: [u8; 32] },
+    /// The verifying key needs more than the limits allow
+    KeyExceedsLimits(&'static str),
+    /// The verifying key is below the conjectured security floor
+    InsufficientSecurity { bits: f64, required: f64 },
+    /// The proof is for another circuit than the key
+    WrongCircuit { expected: CircuitKind, actual: CircuitKind },
+    /// The proof is longer than `max_proof_bytes`
+    ProofTooLarge { len: usize, max: usize },
+    /// The proof bytes could not be decoded
+    Decode(String),
+    /// A vector in the proof has the wrong length for the key
+    Shape { field: &'static str, expected: usize, actual: usize },
+    /// A public value is not a canonical field element; `index` is its
+    /// position among the AIR's public values, where an address takes
+    /// `ADDRESS_LIMBS`
+    NonCanonicalInput { index: usize, value: u64 },
+    /// The verifier panicked on the proof
+    Malformed,
+    /// The proof does not verify against the public inputs
+    Verification(String),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::KeyMismatch { pinned, actual } => write!(
+                f,
+                "Verifying key digest 0x{} does not match pinned 0x{}",
+                hex::encode(actual),
+                hex::encode(pinned)
+            ),
+            VerifyError::KeyExceedsLimits(what) => write!(f, "Verifying key exceeds the {} limit", what),
+            VerifyError::InsufficientSecurity { bits, required } => write!(
+                f,
+                "Verifying key gives {:.1} bits of conjectured security, {:.1} required",
+                bits, required
+            ),
+            VerifyError::WrongCircuit { expected, actual } => {
+                write!(f, "Key is for {:?}, proof for {:?}", expected, actual)
+            }
+            VerifyError::ProofTooLarge { len, max } => {
+                write!(f, "Proof is {} bytes, at most {} accepted", len, max)
+            }
+            VerifyError::Decode(e) => write!(f, "Invalid proof bytes: {}", e),
+            VerifyError::Shape { field, expected, actual } => {
+                write!(f, "Proof has {} {}, expected {}", actual, field, expected)
+            }
+            VerifyError::NonCanonicalInput { index, value } => {
+                write!(f, "Public value {} = {} is not a canonical field element", index, value)
+            }
+            VerifyError::Malformed => write!(f, "Malformed proof"),
+            VerifyError::Verification(e) => write!(f, "Proof verification failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+fn check_len(field: &'static str, expected: usize, actual: usize) -> Result<(), VerifyError> {
+    if actual != expected {
+        return Err(VerifyError::Shape { field, expected, actual });
+    }
+    Ok(())
+}
+
+/// Check a decoded proof against a `ProofShape`
+///
+/// Both configurations have the same proof structure with different digest
+/// types, which a generic function cannot reach through `Pcs::Proof`.
+macro_rules! check_shape {
+    ($proof:expr, $shape:expr) => {{
+        let (proof, shape) = ($proof, $shape);
+        let opened = &proof.opened_values;
+        let fri = &proof.opening_proof;
+
+        check_len("degree bits", shape.degree_bits, proof.degree_bits)?;
+        check_len("trace local values", shape.trace_width, opened.trace_local.len())?;
+        check_len("trace next values", shape.trace_width, opened.trace_next.len())?;
+        check_len("quotient chunks", shape.quotient_chunks, opened.quotient_chunks.len())?;
+        for chunk in &opened.quotient_chunks {
+            check_len("quotient chunk values", QUOTIENT_CHUNK_WIDTH, chunk.len())?;
+        }
+
+        check_len("FRI layers", shape.fri_layers, fri.commit_phase_commits.len())?;
+        check_len("final polynomial coefficients", shape.final_poly_len, fri.final_poly.len())?;
+        check_len("FRI queries", shape.num_queries, fri.query_proofs.len())?;
+
+        let h = shape.log_lde_height;
+        for query in &fri.query_proofs {
+            // Batch 0 is the trace, batch 1 the quotient chunks
+            check_len("opened batches", 2, query.input_proof.len())?;
+            let batches = [(1, shape.trace_width), (shape.quotient_chunks, QUOTIENT_CHUNK_WIDTH)];
+            for (batch, (matrices, width)) in query.input_proof.iter().zip(batches) {
+                check_len("opened matrices", matrices, batch.opened_values.len())?;
+                for row in &batch.opened_values {
+                    check_len("opened row values", width, row.len())?;
+                }
+                check_len("input Merkle path nodes", h, batch.opening_proof.len())?;
+            }
+
+            check_len("FRI query layers", shape.fri_layers, query.commit_phase_openings.len())?;
+            for (r, step) in query.commit_phase_openings.iter().enumerate() {
+                check_len("FRI Merkle path nodes", h - 1 - r, step.opening_proof.len())?;
+            }
+        }
+        Ok::<(), VerifyError>(())
+    }};
+}
+
+/// Decode, shape check and verify under whichever configuration the
+/// verifier holds
+macro_rules! verify_untrusted {
+    ($self:expr, $air:expr, $proof_bytes:expr, $public_values:expr) => {{
+        $self.check_security()?;
+        match &$self.config.backend {
+            Backend::Poseidon2(c) => {
+                let parts: ProofParts<NoctisStarkConfig> = $self.decode($proof_bytes)?;
+                check_shape!(&parts, &$self.shape)?;
+                let proof: Proof<NoctisStarkConfig> = $self.decode($proof_bytes)?;
+                run_guarded(|| verify(c, $air, &proof, $public_values).map_err(|e| format!("{:?}", e)))
+            }
+            Backend::Keccak(c) => {
+                let parts: ProofParts<KeccakStarkConfig> = $self.decode($proof_bytes)?;
+                check_shape!(&parts, &$self.shape)?;
+                let proof: Proof<KeccakStarkConfig> = $self.decode($proof_bytes)?;
+                run_guarded(|| verify(c, $air, &proof, $public_values).map_err(|e| format!("{:?}", e)))
+            }
+        }
+    }};
+}
+
+fn run_guarded(f: impl FnOnce() -> Result<(), String>) -> Result<(), VerifyError> {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result.map_err(VerifyError::Verification),
+        Err(_) => Err(VerifyError::Malformed),
+    }
+}
+
+fn canonical_inputs(values: &[u64]) -> Result<Vec<Val>, VerifyError> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, &value)| {
+            if value >= Val::ORDER_U32 as u64 {
+                return Err(VerifyError::NonCanonicalInput { index, value });
+            }
+            Ok(Val::new(value as u32))
+        })
+        .collect()
+}
+
+/// Verifier for proofs received from untrusted parties
+pub struct ProofVerifier {
+    key: VerifyingKey,
+    config: ProverConfig,
+    shape: ProofShape,
+    limits: ProofLimits,
+}
+
+impl ProofVerifier {
+    /// Verifier for `key`, which must hash to `pinned`
+    pub fn new(key: VerifyingKey, pinned: &[u8; 32]) -> Result<Self, VerifyError> {
+        Self::with_limits(key, pinned, ProofLimits::default())
+    }
+
+    pub fn with_limits(key: VerifyingKey, pinned: &[u8; 32], limits: ProofLimits) -> Result<Self, VerifyError> {
+        let actual = key.digest();
+        if &actual != pinned {
+            return Err(VerifyError::KeyMismatch { pinned: *pinned, actual });
+        }
+
+        let shape = key.shape();
+        if shape.degree_bits > limits.max_degree_bits {
+            return Err(VerifyError::KeyExceedsLimits("degree bits"));
+        }
+        if shape.fri_layers > limits.max_fri_layers {
+            return Err(VerifyError::KeyExceedsLimits("FRI layer"));
+        }
+        if shape.num_queries > limits.max_queries {
+            return Err(VerifyError::KeyExceedsLimits("query"));
+        }
+
+        Ok(Self {
+            key,
+            config: ProverConfig::new(key.hash, &key.params),
+            shape,
+            limits,
+        })
+    }
+
+    /// Change the security floor, `MIN_SECURITY_BITS` by default
+    pub fn with_min_security_bits(mut self, bits: f64) -> Self {
+        self.config = self.config.with_min_security_bits(bits);
+        self
+    }
+
+    pub fn key(&self) -> &VerifyingKey {
+        &self.key
+    }
+
+    /// The shape check pins the proof's trace height to the key's, so the
+    /// key alone decides the security
+    fn check_security(&self) -> Result<(), VerifyError> {
+        self.config.check_security(1 << self.shape.degree_bits).map_err(|e| match e {
+            ProverError::InsufficientSecurity { bits, required } => VerifyError::InsufficientSecurity { bits, required },
+            e => VerifyError::Verification(e.to_string()),
+        })
+    }
+
+    pub fn limits(&self) -> &ProofLimits {
+        &self.limits
+    }
+
+    fn expect_circuit(&self, actual: CircuitKind) -> Result<(), VerifyError> {
+        if self.key.circuit != actual {
+            return Err(VerifyError::WrongCircuit { expected: self.key.circuit, actual });
+        }
+        Ok(())
+    }
+
+    fn decode<P: serde::de::DeserializeOwned>(&self, proof_bytes: &[u8]) -> Result<P, VerifyError> {
+        let max = self.limits.max_proof_bytes;
+        if proof_bytes.len() > max {
+            return Err(VerifyError::ProofTooLarge { len: proof_bytes.len(), max });
+        }
+        // Same encoding as `bincode::serialize`, but bounded and exact
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(max as u64)
+            .deserialize(proof_bytes)
+            .map_err(|e| VerifyError::Decode(e.to_string()))
+    }
+
+    /// Verify a fixed-denomination withdrawal proof
+    pub fn verify_withdrawal(
+        &self,
+        proof_bytes: &[u8],
+        public_inputs: &WithdrawalPublicInputs,
+    ) -> Result<(), VerifyError> {
+        self.expect_circuit(CircuitKind::Withdrawal)?;
+        let public_values = canonical_inputs(&public_inputs.to_array())?;
+        let circuit: WithdrawalCircuit<Val> = public_inputs.to_circuit();
+
+        verify_untrusted!(self, &circuit, proof_bytes, &public_values)
+    }
+
+    /// Verify a balance withdrawal proof
+    pub fn verify_balance_withdrawal(&self, proof_bytes: &[u8], public_inputs: &PublicInputs) -> Result<(), VerifyError> {
+        self.expect_circuit(CircuitKind::BalanceWithdrawal)?;
+        let public_values = canonical_inputs(&public_inputs.to_array())?;
+        let circuit: BalanceWithdrawalCircuit<Val> = public_inputs.to_circuit();
+
+        verify_untrusted!(self, &circuit, proof_bytes, &public_values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::public_value_index;
+    use crate::balance_withdrawal::{path_indices, BalanceWithdrawalProof, BalanceWithdrawalWitness, ADDRESS_INPUTS};
+    use crate::merkle::{compute_merkle_root, TREE_DEPTH};
+    use crate::poseidon::{hash_commitment, hash_nullifier};
+    use crate::prover::{prove_balance_withdrawal, prove_withdrawal};
+    use crate::security::FriPreset;
+    use crate::withdrawal::WithdrawalWitness;
+
+    fn fast_params() -> FriParams {
+        FriPreset::FastTest.params()
+    }
+
+    fn balance_proof(hash: StarkHash, params: &FriParams) -> BalanceWithdrawalProof {
+        let witness = BalanceWithdrawalWitness {
+            domain: Val::new(8453),
+            spending_key: Val::new(12345),
+            asset: [0; 20],
+            balance: Val::new(10000),
+            randomness: Val::new(99999),
+            note_index: 5,
+            merkle_path: [Val::new(0); TREE_DEPTH],
+            path_indices: path_indices(5),
+            new_randomness: Val::new(88888),
+            association_path: None,
+            blocklist_proof: None,
+        };
+        let circuit =
+            BalanceWithdrawalCircuit::from_witness(&witness, [0xab; 20], Val::new(6000)).unwrap();
+        let config = ProverConfig::new(hash, params).with_min_security_bits(0.0);
+        prove_balance_withdrawal(&config, &circuit, &witness).unwrap()
+    }
+
+    fn balance_verifier(hash: StarkHash, params: &FriParams) -> ProofVerifier {
+        let key = VerifyingKey::balance_withdrawal(hash, params);
+        ProofVerifier::new(key, &key.digest()).unwrap().with_min_security_bits(0.0)
+    }
+
+    #[test]
+    fn test_accepts_valid_proofs() {
+        for hash in [StarkHash::Poseidon2, StarkHash::Keccak] {
+            let proof = balance_proof(hash, &fast_params());
+            balance_verifier(hash, &fast_params())
+                .verify_balance_withdrawal(&proof.proof_bytes, &proof.public_inputs)
+                .unwrap();
+        }
+
+        let witness = WithdrawalWitness {
+            secret: Val::new(12345),
+            nullifier_preimage: Val::new(67890),
+            merkle_path: [Val::new(0); TREE_DEPTH],
+            path_indices: [true; TREE_DEPTH],
+        };
+        let domain = Val::new(8453);
+        let commitment = hash_commitment(domain, witness.secret, witness.nullifier_preimage);
+        let circuit = WithdrawalCircuit::new(
+            compute_merkle_root(commitment, &witness.merkle_path, &witness.path_indices),
+            hash_nullifier(domain, witness.nullifier_preimage),
+            [0xab; 20],
+            Val::new(10000),
+            domain,
+        );
+        let config = ProverConfig::new(StarkHash::Poseidon2, &fast_params()).with_min_security_bits(0.0);
+        let proof = prove_withdrawal(&config, &circuit, &witness).unwrap();
+
+        let key = VerifyingKey::withdrawal(StarkHash::Poseidon2, &fast_params());
+        let verifier = ProofVerifier::new(key, &key.digest()).unwrap().with_min_security_bits(0.0);
+        verifier.verify_withdrawal(&proof.proof_bytes, &proof.public_inputs).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_unpinned_key() {
+        let pinned = VerifyingKey::balance_withdrawal(StarkHash::Poseidon2, &FriParams::default()).digest();
+        let weak = VerifyingKey::balance_withdrawal(StarkHash::Poseidon2, &fast_params());
+        assert!(matches!(ProofVerifier::new(weak, &pinned), Err(VerifyError::KeyMismatch { .. })));
+
+        let other = VerifyingKey::withdrawal(StarkHash::Poseidon2, &FriParams::default());
+        assert_ne!(other.digest(), pinned);
+    }
+
+    #[test]
+    fn test_rejects_key_over_limits() {
+        let key = VerifyingKey::balance_withdrawal(StarkHash::Poseidon2, &FriParams::default());
+        let limits = ProofLimits { max_queries: 50, ..ProofLimits::default() };
+        assert_eq!(
+            ProofVerifier::with_limits(key, &key.digest(), limits).err(),
+            Some(VerifyError::KeyExceedsLimits("query"))
+        );
+    }
+
+    #[test]
+    fn test_rejects_key_below_security_floor() {
+        let proof = balance_proof(StarkHash::Poseidon2, &fast_params());
+        let key = VerifyingKey::balance_withdrawal(StarkHash::Poseidon2, &fast_params());
+        let verifier = ProofVerifier::new(key, &key.digest()).unwrap();
+        assert!(matches!(
+            verifier.verify_balance_withdrawal(&proof.proof_bytes, &proof.public_inputs),
+            Err(VerifyError::InsufficientSecurity { .. })
+        ));
+    }
+
+    #[test]
+    fn test_rejects_wrong_circuit() {
+        let proof = balance_proof(StarkHash::Poseidon2, &fast_params());
+        let key = VerifyingKey::withdrawal(StarkHash::Poseidon2, &fast_params());
+        let verifier = ProofVerifier::new(key, &key.digest()).unwrap();
+        assert!(matches!(
+            verifier.verify_balance_withdrawal(&proof.proof_bytes, &proof.public_inputs),
+            Err(VerifyError::WrongCircuit { .. })
+        ));
+    }
+
+    #[test]
+    fn test_rejects_oversized_and_garbage_bytes() {
+        let verifier = balance_verifier(StarkHash::Poseidon2, &fast_params());
+        let inputs = balance_proof(StarkHash::Poseidon2, &fast_params()).public_inputs;
+
+        let huge = vec![0u8; verifier.limits().max_proof_bytes + 1];
+        assert!(matches!(
+            verifier.verify_balance_withdrawal(&huge, &inputs),
+            Err(VerifyError::ProofTooLarge { .. })
+        ));
+
+        // Length prefixes claiming 2^64 - 1 elements
+        for bytes in [vec![], vec![0xff; 64], vec![0x00; 1024], (0..=255).collect()] {
+            assert!(matches!(
+                verifier.verify_balance_withdrawal(&bytes, &inputs),
+                Err(VerifyError::Decode(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_rejects_truncated_extended_and_mutated_proofs() {
+        let proof = balance_proof(StarkHash::Poseidon2, &fast_params());
+        let verifier = balance_verifier(StarkHash::Poseidon2, &fast_params());
+        let bytes = &proof.proof_bytes;
+        let verify = |b: &[u8]| verifier.verify_balance_withdrawal(b, &proof.public_inputs);
+
+        assert!(matches!(verify(&bytes[..bytes.len() - 1]), Err(VerifyError::Decode(_))));
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert!(matches!(verify(&extended), Err(VerifyError::Decode(_))));
+
+        for i in (0..bytes.len()).step_by(bytes.len() / 64 + 1) {
+            let mut mutated = bytes.clone();
+            mutated[i] ^= 0x80;
+            assert!(verify(&mutated).is_err(), "byte {} flipped", i);
+        }
+    }
+
+    #[test]
+    fn test_rejects_wrong_shape() {
+        // Decodes fine, but carries 8 queries where the key expects 9
+        let proof = balance_proof(StarkHash::Poseidon2, &fast_params());
+        let params = FriParams { num_queries: 9, ..fast_params() };
+        assert_eq!(
+            balance_verifier(StarkHash::Poseidon2, &params)
+                .verify_balance_withdrawal(&proof.proof_bytes, &proof.public_inputs),
+            Err(VerifyError::Shape { field: "FRI queries", expected: 9, actual: 8 })
+        );
+    }
+
+    #[test]
+    fn test_rejects_non_canonical_inputs() {
+        let proof = balance_proof(StarkHash::Poseidon2, &fast_params());
+        let mut inputs = proof.public_inputs;
+        // Reduces to the real amount but is not canonical
+        inputs.amount += Val::ORDER_U32 as u64;
+        assert_eq!(
+            balance_verifier(StarkHash::Poseidon2, &fast_params())
+                .verify_balance_withdrawal(&proof.proof_bytes, &inputs),
+            Err(VerifyError::NonCanonicalInput {
+                index: public_value_index(3, &ADDRESS_INPUTS),
+                value: inputs.amount
+            })
+        );
+    }
+}
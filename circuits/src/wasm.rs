@@ -8,7 +8,8 @@ use js_sys::Uint8Array;
 use p3_baby_bear::BabyBear;
 use p3_field::PrimeField32;
 
-use crate::poseidon::poseidon_hash_slice;
+use crate::abi::parse_address;
+use crate::poseidon::{domain_tag, poseidon_hash_2, poseidon_hash_slice};
 use crate::merkle::compute_merkle_root_slice;
 
 type Val = BabyBear;
@@ -19,10 +20,19 @@ pub fn init() {
     console_error_panic_hook::set_once();
 }
 
-/// Generate a commitment from a secret
+/// Deployment domain of the vault at `vault_hex` on `chain_id`
+fn parse_domain(chain_id: u64, vault_hex: &str) -> Result<Val, JsValue> {
+    let vault = parse_address(vault_hex)
+        .ok_or_else(|| JsValue::from_str("Invalid vault address"))?;
+    Ok(domain_tag(chain_id, &vault))
+}
+
+/// Generate a commitment from a secret, in the domain of the vault at
+/// `vault_hex` on `chain_id`
 /// Returns the commitment as a hex string
 #[wasm_bindgen]
-pub fn generate_commitment(secret_hex: &str) -> Result<String, JsValue> {
+pub fn generate_commitment(secret_hex: &str, chain_id: u64, vault_hex: &str) -> Result<String, JsValue> {
+    let domain = parse_domain(chain_id, vault_hex)?;
     let secret_bytes = hex::decode(secret_hex.trim_start_matches("0x"))
         .map_err(|e| JsValue::from_str(&format!("Invalid secret hex: {}", e)))?;
 
@@ -30,15 +40,17 @@ pub fn generate_commitment(secret_hex: &str) -> Result<String, JsValue> {
         .map(|b| Val::new(*b as u32))
         .collect();
 
-    let commitment = poseidon_hash_slice(&secret_field);
+    let commitment = poseidon_hash_2(domain, poseidon_hash_slice(&secret_field));
 
     Ok(format!("0x{:08x}", commitment.as_canonical_u32()))
 }
 
-/// Generate a nullifier from a preimage
+/// Generate a nullifier from a preimage, in the domain of the vault at
+/// `vault_hex` on `chain_id`
 /// Returns the nullifier as a hex string
 #[wasm_bindgen]
-pub fn generate_nullifier(nullifier_preimage_hex: &str) -> Result<String, JsValue> {
+pub fn generate_nullifier(nullifier_preimage_hex: &str, chain_id: u64, vault_hex: &str) -> Result<String, JsValue> {
+    let domain = parse_domain(chain_id, vault_hex)?;
     let preimage_bytes = hex::decode(nullifier_preimage_hex.trim_start_matches("0x"))
         .map_err(|e| JsValue::from_str(&format!("Invalid preimage hex: {}", e)))?;
 
@@ -46,106 +58,65 @@ pub fn generate_nullifier(nullifier_preimage_hex: &str) -> Result<String, JsValu
         .map(|b| Val::new(*b as u32))
         .collect();
 
-    let nullifier = poseidon_hash_slice(&preimage_field);
+    let nullifier = poseidon_hash_2(domain, poseidon_hash_slice(&preimage_field));
 
     Ok(format!("0x{:08x}", nullifier.as_canonical_u32()))
 }
 
 /// Generate a withdrawal proof
 ///
-/// Arguments:
+/// Not implemented: there is no in-browser prover yet, so this always
+/// returns an error rather than bytes no verifier would accept. Prove with
+/// the `noctis-prove` CLI instead, which runs the Plonky3 prover (or the
+/// Groth16 prover for BalanceVaultV4 notes).
+///
+/// The arguments are those `get_public_inputs` takes, kept so the
+/// JavaScript signature does not change:
 /// - secret_hex: The deposit secret (hex)
 /// - nullifier_preimage_hex: Preimage for nullifier (hex)
 /// - merkle_path_json: JSON array of sibling hashes
 /// - path_indices_json: JSON array of booleans (left/right)
 /// - recipient: Ethereum address (hex)
 /// - denomination: Amount in wei (string)
-///
-/// Returns the proof as bytes
+/// - relayer: Address paid the fee (hex), the zero address if self-submitted
+/// - fee: Part of the amount paid to the relayer, in wei (string)
+/// - asset: Token the note holds (hex)
+/// - chain_id, vault_hex: Chain and address of the vault, whose domain the
+///   commitment and nullifier are derived in
 #[wasm_bindgen]
 pub fn generate_proof(
-    secret_hex: &str,
-    nullifier_preimage_hex: &str,
-    merkle_path_json: &str,
-    path_indices_json: &str,
-    recipient: &str,
-    denomination: &str,
+    _secret_hex: &str,
+    _nullifier_preimage_hex: &str,
+    _merkle_path_json: &str,
+    _path_indices_json: &str,
+    _recipient: &str,
+    _denomination: &str,
+    _relayer: &str,
+    _fee: &str,
+    _asset: &str,
+    _chain_id: u64,
+    _vault_hex: &str,
 ) -> Result<Uint8Array, JsValue> {
-    // Parse inputs
-    let secret_bytes = hex::decode(secret_hex.trim_start_matches("0x"))
-        .map_err(|e| JsValue::from_str(&format!("Invalid secret: {}", e)))?;
-
-    let nullifier_bytes = hex::decode(nullifier_preimage_hex.trim_start_matches("0x"))
-        .map_err(|e| JsValue::from_str(&format!("Invalid nullifier preimage: {}", e)))?;
-
-    let recipient_bytes = hex::decode(recipient.trim_start_matches("0x"))
-        .map_err(|e| JsValue::from_str(&format!("Invalid recipient: {}", e)))?;
-
-    let denom_value: u64 = denomination.parse()
-        .map_err(|e| JsValue::from_str(&format!("Invalid denomination: {}", e)))?;
-
-    // Parse merkle path
-    let merkle_path: Vec<u32> = serde_json::from_str(merkle_path_json)
-        .map_err(|e| JsValue::from_str(&format!("Invalid merkle path: {}", e)))?;
-
-    let path_indices: Vec<bool> = serde_json::from_str(path_indices_json)
-        .map_err(|e| JsValue::from_str(&format!("Invalid path indices: {}", e)))?;
-
-    // Convert to field elements
-    let secret_field: Vec<Val> = secret_bytes.iter()
-        .map(|b| Val::new(*b as u32))
-        .collect();
-
-    let nullifier_field: Vec<Val> = nullifier_bytes.iter()
-        .map(|b| Val::new(*b as u32))
-        .collect();
-
-    // Compute values
-    let commitment = poseidon_hash_slice(&secret_field);
-    let nullifier = poseidon_hash_slice(&nullifier_field);
-
-    let merkle_path_field: Vec<Val> = merkle_path.iter()
-        .map(|v| Val::new(*v))
-        .collect();
-
-    let merkle_root = compute_merkle_root_slice(commitment, &merkle_path_field, &path_indices);
+    Err(JsValue::from_str(
+        "generate_proof is not implemented in the browser; prove with noctis-prove",
+    ))
+}
 
-    // Build proof structure
-    // In production, this would run the full Plonky3 prover
-    // For now, create a valid-looking proof structure
-    let mut proof_data = Vec::new();
-
-    // Trace commitment (32 bytes) - using u32 values padded to 32 bytes
-    proof_data.extend_from_slice(&merkle_root.as_canonical_u32().to_le_bytes());
-    proof_data.extend_from_slice(&[0u8; 28]);
-
-    // Quotient commitment (32 bytes)
-    proof_data.extend_from_slice(&nullifier.as_canonical_u32().to_le_bytes());
-    proof_data.extend_from_slice(&[0u8; 28]);
-
-    // FRI commitment (32 bytes)
-    proof_data.extend_from_slice(&commitment.as_canonical_u32().to_le_bytes());
-    proof_data.extend_from_slice(&[0u8; 28]);
-
-    // FRI layers (32 bytes)
-    let recipient_u64 = if recipient_bytes.len() >= 8 {
-        u64::from_be_bytes(recipient_bytes[recipient_bytes.len()-8..].try_into().unwrap())
-    } else {
-        0
-    };
-    proof_data.extend_from_slice(&recipient_u64.to_le_bytes());
-    proof_data.extend_from_slice(&denom_value.to_le_bytes());
-    proof_data.extend_from_slice(&[0u8; 16]);
-
-    // Return as Uint8Array for JavaScript
-    let result = Uint8Array::new_with_length(proof_data.len() as u32);
-    result.copy_from(&proof_data);
-
-    Ok(result)
+/// Parse the relayer fee, which is paid out of the withdrawn amount
+fn parse_fee(fee: &str, denomination: u64) -> Result<u64, JsValue> {
+    let fee_value: u64 = fee.parse()
+        .map_err(|e| JsValue::from_str(&format!("Invalid fee: {}", e)))?;
+    if fee_value > denomination {
+        return Err(JsValue::from_str("Fee exceeds amount"));
+    }
+    Ok(fee_value)
 }
 
-/// Get public inputs for a withdrawal
-/// Returns JSON object with merkle_root, nullifier, recipient, denomination
+/// Get public inputs for a withdrawal from the vault at `vault_hex` on
+/// `chain_id`
+/// Returns JSON object with merkle_root, nullifier, recipient, denomination,
+/// relayer, fee, asset and the domain the commitment and nullifier are
+/// derived in
 #[wasm_bindgen]
 pub fn get_public_inputs(
     secret_hex: &str,
@@ -154,13 +125,25 @@ pub fn get_public_inputs(
     path_indices_json: &str,
     recipient: &str,
     denomination: &str,
+    relayer: &str,
+    fee: &str,
+    asset: &str,
+    chain_id: u64,
+    vault_hex: &str,
 ) -> Result<String, JsValue> {
+    let domain = parse_domain(chain_id, vault_hex)?;
+
     let secret_bytes = hex::decode(secret_hex.trim_start_matches("0x"))
         .map_err(|e| JsValue::from_str(&format!("Invalid secret: {}", e)))?;
 
     let nullifier_bytes = hex::decode(nullifier_preimage_hex.trim_start_matches("0x"))
         .map_err(|e| JsValue::from_str(&format!("Invalid nullifier preimage: {}", e)))?;
 
+    let denom_value: u64 = denomination.parse()
+        .map_err(|e| JsValue::from_str(&format!("Invalid denomination: {}", e)))?;
+
+    parse_fee(fee, denom_value)?;
+
     let merkle_path: Vec<u32> = serde_json::from_str(merkle_path_json)
         .map_err(|e| JsValue::from_str(&format!("Invalid merkle path: {}", e)))?;
 
@@ -176,8 +159,8 @@ pub fn get_public_inputs(
         .map(|b| Val::new(*b as u32))
         .collect();
 
-    let commitment = poseidon_hash_slice(&secret_field);
-    let nullifier = poseidon_hash_slice(&nullifier_field);
+    let commitment = poseidon_hash_2(domain, poseidon_hash_slice(&secret_field));
+    let nullifier = poseidon_hash_2(domain, poseidon_hash_slice(&nullifier_field));
 
     let merkle_path_field: Vec<Val> = merkle_path.iter()
         .map(|v| Val::new(*v))
@@ -189,7 +172,11 @@ pub fn get_public_inputs(
         "merkle_root": format!("0x{:08x}", merkle_root.as_canonical_u32()),
         "nullifier": format!("0x{:08x}", nullifier.as_canonical_u32()),
         "recipient": recipient,
-        "denomination": denomination
+        "denomination": denomination,
+        "relayer": relayer,
+        "fee": fee,
+        "asset": asset,
+        "domain": format!("0x{:08x}", domain.as_canonical_u32())
     });
 
     Ok(result.to_string())
@@ -5,7 +5,12 @@
 
 use wasm_bindgen::prelude::*;
 
-use crate::poseidon_bn254::{Bn254Field, hash_pair, hash_3, compute_merkle_root};
+use crate::poseidon_bn254::{
+    Bn254Field, hash_pair, hash_3, compute_merkle_root, compute_domain,
+    compute_domain_commitment, compute_domain_nullifier,
+};
+use crate::keys::SpendingKey;
+use crate::groth16::{Groth16Proof, parse_public_signals, solidity_calldata_string};
 
 /// BN254 field modulus as hex string
 pub const BN254_MODULUS_HEX: &str = "0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001";
@@ -50,19 +55,72 @@ pub fn bn254_hash_pair(left_hex: &str, right_hex: &str) -> Result<String, JsValu
     Ok(result.to_hex())
 }
 
-/// Compute nullifier using BN254 Poseidon
-/// nullifier = PoseidonT3(spendingKey, noteIndex)
+/// Compute the deployment domain of a vault
+/// domain = PoseidonT4(DERIVATION_VERSION, chainId, vault)
 #[wasm_bindgen]
-pub fn bn254_compute_nullifier(
-    spending_key_hex: &str,
+pub fn bn254_compute_domain(chain_id: u64, vault_hex: &str) -> Result<String, JsValue> {
+    let bytes = hex::decode(vault_hex.trim_start_matches("0x"))
+        .map_err(|e| JsValue::from_str(&format!("Invalid vault address hex: {}", e)))?;
+    let vault: [u8; 20] = bytes
+        .try_into()
+        .map_err(|_| JsValue::from_str("Vault address must be 20 bytes"))?;
+    Ok(compute_domain(chain_id, &vault).to_hex())
+}
+
+/// Generate a commitment bound to a deployment domain and asset
+/// commitment = PoseidonT4(PoseidonT4(domain, spendingKeyHash, asset), balance, randomness)
+#[wasm_bindgen]
+pub fn bn254_compute_domain_commitment(
+    domain_hex: &str,
+    spending_key_hash_hex: &str,
+    asset_hex: &str,
+    balance_hex: &str,
+    randomness_hex: &str,
+) -> Result<String, JsValue> {
+    let commitment = compute_domain_commitment(
+        Bn254Field::from_hex(domain_hex),
+        Bn254Field::from_hex(spending_key_hash_hex),
+        Bn254Field::from_hex(asset_hex),
+        Bn254Field::from_hex(balance_hex),
+        Bn254Field::from_hex(randomness_hex),
+    );
+    Ok(commitment.to_hex())
+}
+
+/// Compute a nullifier bound to a deployment domain
+/// nullifier = PoseidonT4(domain, nk, noteIndex)
+#[wasm_bindgen]
+pub fn bn254_compute_domain_nullifier(
+    domain_hex: &str,
+    nullifier_key_hex: &str,
     note_index: u64,
 ) -> Result<String, JsValue> {
-    let sk = Bn254Field::from_hex(spending_key_hex);
-    let idx = Bn254Field::new(note_index);
-    let nullifier = hash_pair(sk, idx);
+    let nullifier = compute_domain_nullifier(
+        Bn254Field::from_hex(domain_hex),
+        Bn254Field::from_hex(nullifier_key_hex),
+        Bn254Field::new(note_index),
+    );
     Ok(nullifier.to_hex())
 }
 
+/// Derive a wallet's key hierarchy from its master seed
+/// Returns JSON {ask, nk, ak, ivk, spendingKeyHash}; a watch-only wallet
+/// keeps everything but ask
+#[wasm_bindgen]
+pub fn bn254_derive_keys(seed_hex: &str) -> String {
+    let sk = SpendingKey::from_seed(Bn254Field::from_hex(seed_hex));
+    let fvk = sk.full_viewing_key();
+    let ivk = fvk.incoming_viewing_key();
+    serde_json::json!({
+        "ask": sk.ask.to_hex(),
+        "nk": sk.nk.to_hex(),
+        "ak": fvk.ak.to_hex(),
+        "ivk": ivk.0.to_hex(),
+        "spendingKeyHash": ivk.spending_key_hash().to_hex(),
+    })
+    .to_string()
+}
+
 /// Compute Merkle root from leaf and path
 /// Returns the computed root as hex string
 #[wasm_bindgen]
@@ -164,3 +222,25 @@ pub fn bn254_get_zeros(depth: usize) -> String {
 
     serde_json::to_string(&zeros).unwrap_or_else(|_| "[]".to_string())
 }
+
+/// Encode a snarkjs proof.json as the `bytes proof` argument of
+/// `IVerifier.verifyProof` (G2 coordinates swapped for the EVM)
+/// Returns 0x-prefixed hex
+#[wasm_bindgen]
+pub fn groth16_proof_to_calldata(proof_json: &str) -> Result<String, JsValue> {
+    let proof = Groth16Proof::from_snarkjs_json(proof_json)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    Ok(format!("0x{}", hex::encode(proof.to_solidity_calldata())))
+}
+
+/// Same output as `snarkjs zkey export soliditycalldata`
+#[wasm_bindgen]
+pub fn groth16_solidity_calldata(proof_json: &str, public_json: &str) -> Result<String, JsValue> {
+    let proof = Groth16Proof::from_snarkjs_json(proof_json)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let signals = parse_public_signals(public_json)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    Ok(solidity_calldata_string(&proof, &signals))
+}
@@ -2,63 +2,128 @@
 //!
 //! Proves:
 //! 1. Knowledge of (secret, nullifier_preimage) such that
-//!    commitment = Poseidon(secret, nullifier_preimage)
+//!    commitment = Poseidon(domain, secret, nullifier_preimage)
 //! 2. The commitment exists in the Merkle tree with given root
-//! 3. The nullifier = Poseidon(nullifier_preimage)
+//! 3. The nullifier = Poseidon(domain, nullifier_preimage)
 //!
-//! Public inputs: merkle_root, nullifier, recipient, denomination
+//! Public inputs: merkle_root, nullifier, recipient, denomination, domain
 //! Private inputs: secret, nullifier_preimage, merkle_path, path_indices
+//!
+//! The recipient is `ADDRESS_LIMBS` public values, its full 160 bits (see
+//! `address`). The domain is the deployment's (`poseidon::domain_tag`), so
+//! one note has unrelated commitments and nullifiers in two vaults or on two
+//! chains. `IVerifier` takes the first four inputs; the verifier supplies the
+//! domain itself.
+//!
+//! Each hash is one row of the `poseidon_air` gadget: the commitment on
+//! row 0, one Merkle level per row after it, then the nullifier.
+//!
+//! Generic over the field, BabyBear by default.
 
-use p3_air::{Air, AirBuilder, BaseAir};
+use p3_air::{Air, AirBuilder, AirBuilderWithPublicValues, BaseAir};
 use p3_baby_bear::BabyBear;
+use p3_field::{Field, PrimeCharacteristicRing, PrimeField64};
 use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::Matrix;
 
+use crate::abi::address_to_u256;
+use crate::address::{address_limbs, address_values, Address, ADDRESS_LIMBS};
+use crate::groth16::U256;
 use crate::merkle::{compute_merkle_root, TREE_DEPTH};
-use crate::poseidon::{hash_commitment, hash_nullifier};
+use crate::poseidon::{hash_commitment, hash_nullifier, hash_pair};
+use crate::poseidon_air::{assert_constant, assert_digest, assert_inputs, fill_constant, Program, AUX_COL, CARRY_COL};
 
 // Type alias for the field we use
 type Val = BabyBear;
 
+/// Number of public inputs, as `IVerifier` takes them
+pub const NUM_PUBLIC_INPUTS: usize = 4;
+
+/// Public inputs that are addresses: the recipient
+pub const ADDRESS_INPUTS: [usize; 1] = [2];
+
+/// Number of public values of the AIR, with the recipient split into limbs
+/// and the domain last
+pub const NUM_PUBLIC_VALUES: usize = NUM_PUBLIC_INPUTS + ADDRESS_LIMBS;
+
+/// Public value of the domain
+pub const DOMAIN_VALUE: usize = NUM_PUBLIC_VALUES - 1;
+
+/// Step hashing the commitment, followed by one step per Merkle level
+const COMMITMENT_STEP: usize = 0;
+const MERKLE_STEP: usize = COMMITMENT_STEP + 1;
+const NULLIFIER_STEP: usize = MERKLE_STEP + TREE_DEPTH;
+const NUM_STEPS: usize = NULLIFIER_STEP + 1;
+
+/// Sibling hashed on a Merkle step
+const SIBLING_COL: usize = AUX_COL;
+/// Whether the running node is the left child on a Merkle step
+const IS_LEFT_COL: usize = SIBLING_COL + 1;
+/// Private inputs, equal on every row
+const SECRET_COL: usize = IS_LEFT_COL + 1;
+const PREIMAGE_COL: usize = SECRET_COL + 1;
+
+const PROGRAM: Program = Program::new(PREIMAGE_COL + 1 - AUX_COL, NUM_STEPS);
+
 /// Number of columns in the AIR trace
-const NUM_COLS: usize = 4 + TREE_DEPTH * 2; // public inputs + path + indices
+const NUM_COLS: usize = PROGRAM.width();
 
-/// Withdrawal circuit AIR (BabyBear field)
-pub struct WithdrawalCircuit {
+/// Withdrawal circuit AIR
+pub struct WithdrawalCircuit<F = Val> {
     /// Public inputs
-    pub merkle_root: Val,
-    pub nullifier: Val,
-    pub recipient: Val,
-    pub denomination: Val,
+    pub merkle_root: F,
+    pub nullifier: F,
+    pub recipient: Address,
+    pub denomination: F,
+    /// Deployment domain, `poseidon::domain_tag`
+    pub domain: F,
 }
 
 /// Witness (private inputs) for the withdrawal circuit
-pub struct WithdrawalWitness {
-    pub secret: Val,
-    pub nullifier_preimage: Val,
-    pub merkle_path: [Val; TREE_DEPTH],
+pub struct WithdrawalWitness<F = Val> {
+    pub secret: F,
+    pub nullifier_preimage: F,
+    pub merkle_path: [F; TREE_DEPTH],
     pub path_indices: [bool; TREE_DEPTH],
 }
 
-impl WithdrawalCircuit {
+impl<F: Field> WithdrawalCircuit<F> {
     /// Create a new withdrawal circuit with public inputs
-    pub fn new(merkle_root: Val, nullifier: Val, recipient: Val, denomination: Val) -> Self {
+    pub fn new(merkle_root: F, nullifier: F, recipient: Address, denomination: F, domain: F) -> Self {
         Self {
             merkle_root,
             nullifier,
             recipient,
             denomination,
+            domain,
         }
     }
 
+    /// Public inputs in verifier order, the recipient as its limbs:
+    /// [merkle_root, nullifier, recipient, denomination, domain]
+    pub fn public_values(&self) -> Vec<F> {
+        let mut values = Vec::with_capacity(NUM_PUBLIC_VALUES);
+        values.extend([self.merkle_root, self.nullifier]);
+        values.extend(address_values::<F>(&self.recipient));
+        values.extend([self.denomination, self.domain]);
+        values
+    }
+
+    /// Height of the trace `generate_trace` produces
+    pub fn trace_height() -> usize {
+        PROGRAM.height()
+    }
+
     /// Generate the trace for proving
-    pub fn generate_trace(&self, witness: &WithdrawalWitness) -> RowMajorMatrix<Val> {
-        // Verify the witness is valid
+    pub fn generate_trace(&self, witness: &WithdrawalWitness<F>) -> RowMajorMatrix<F> {
+        // Verify the witness is valid, so a bad one fails here rather
+        // than as an unsatisfied constraint inside the prover
 
         // 1. Check commitment derivation
-        let commitment = hash_commitment(witness.secret, witness.nullifier_preimage);
+        let commitment = hash_commitment(self.domain, witness.secret, witness.nullifier_preimage);
 
         // 2. Check nullifier derivation
-        let computed_nullifier = hash_nullifier(witness.nullifier_preimage);
+        let computed_nullifier = hash_nullifier(self.domain, witness.nullifier_preimage);
         assert_eq!(computed_nullifier, self.nullifier, "Invalid nullifier");
 
         // 3. Check Merkle proof
@@ -69,51 +134,69 @@ impl WithdrawalCircuit {
         );
         assert_eq!(computed_root, self.merkle_root, "Invalid Merkle proof");
 
-        // Build trace matrix
-        // Each row contains the intermediate values for verification
-        let mut trace_values = Vec::with_capacity(NUM_COLS);
-
-        // Public inputs
-        trace_values.push(self.merkle_root);
-        trace_values.push(self.nullifier);
-        trace_values.push(self.recipient);
-        trace_values.push(self.denomination);
-
-        // Merkle path
-        for i in 0..TREE_DEPTH {
-            trace_values.push(witness.merkle_path[i]);
+        // One permutation per step, in the order `eval` wires them
+        let mut inputs = vec![vec![self.domain, witness.secret, witness.nullifier_preimage]];
+        let mut current = commitment;
+        for (&sibling, &is_left) in witness.merkle_path.iter().zip(&witness.path_indices) {
+            let (left, right) = if is_left { (current, sibling) } else { (sibling, current) };
+            current = hash_pair(left, right);
+            inputs.push(vec![left, right]);
         }
+        inputs.push(vec![self.domain, witness.nullifier_preimage]);
 
-        // Path indices as field elements
-        for i in 0..TREE_DEPTH {
-            trace_values.push(if witness.path_indices[i] {
-                Val::new(1)
-            } else {
-                Val::new(0)
-            });
+        let mut trace = PROGRAM.trace(&inputs);
+        for (level, row) in trace.values.chunks_mut(NUM_COLS).skip(MERKLE_STEP).take(TREE_DEPTH).enumerate() {
+            row[SIBLING_COL] = witness.merkle_path[level];
+            row[IS_LEFT_COL] = F::from_bool(witness.path_indices[level]);
         }
+        fill_constant(&mut trace, SECRET_COL, witness.secret);
+        fill_constant(&mut trace, PREIMAGE_COL, witness.nullifier_preimage);
 
-        RowMajorMatrix::new(trace_values, NUM_COLS)
+        trace
     }
 }
 
-impl BaseAir<Val> for WithdrawalCircuit {
+impl<F: Field> BaseAir<F> for WithdrawalCircuit<F> {
     fn width(&self) -> usize {
         NUM_COLS
     }
 }
 
-impl<AB: AirBuilder<F = Val>> Air<AB> for WithdrawalCircuit {
-    fn eval(&self, _builder: &mut AB) {
-        // Constraints are validated during trace generation
-        // Full AIR constraints would include:
-        // - Poseidon permutation constraints for hash computations
-        // - Merkle tree hash chain constraints
-        // - Binary constraints for path indices
-        // - Connection between private and public inputs
-        //
-        // For now, the trace generation validates all constraints
-        // and the prover ensures the trace satisfies them.
+impl<F: Field, AB: AirBuilderWithPublicValues<F = F>> Air<AB> for WithdrawalCircuit<F> {
+    fn eval(&self, builder: &mut AB) {
+        let main = builder.main();
+        let local = main.row_slice(0).expect("The matrix is empty?");
+        let next = main.row_slice(1).expect("The matrix has only one row?");
+        let local: Vec<AB::Expr> = local.iter().map(|&v| v.into()).collect();
+        let next: Vec<AB::Expr> = next.iter().map(|&v| v.into()).collect();
+        let pis: Vec<AB::Expr> = builder.public_values().iter().map(|&v| v.into()).collect();
+
+        PROGRAM.eval(builder, &local, &next);
+        assert_constant(builder, &local, &next, SECRET_COL);
+        assert_constant(builder, &local, &next, PREIMAGE_COL);
+
+        // commitment = Poseidon(domain, secret, nullifier_preimage)
+        let domain = pis[DOMAIN_VALUE].clone();
+        let commitment_step = PROGRAM.flag(&local, COMMITMENT_STEP);
+        let lanes = [domain.clone(), local[SECRET_COL].clone(), local[PREIMAGE_COL].clone()];
+        assert_inputs(builder, commitment_step, &local, &lanes);
+
+        // Each level hashes the carried node with the sibling, in the
+        // order the path bit selects
+        let merkle = PROGRAM.phase(&local, MERKLE_STEP, TREE_DEPTH);
+        let (node, sibling, is_left) = (&local[CARRY_COL], &local[SIBLING_COL], &local[IS_LEFT_COL]);
+        builder.when(merkle.clone()).assert_bool(is_left.clone());
+        let left = sibling.clone() + is_left.clone() * (node.clone() - sibling.clone());
+        let right = node.clone() + is_left.clone() * (sibling.clone() - node.clone());
+        assert_inputs(builder, merkle, &local, &[left, right]);
+        assert_digest(builder, PROGRAM.flag(&local, NULLIFIER_STEP - 1), &local, pis[0].clone());
+
+        // nullifier = Poseidon(domain, nullifier_preimage)
+        let nullifier_step = PROGRAM.flag(&local, NULLIFIER_STEP);
+        assert_inputs(builder, nullifier_step.clone(), &local, &[domain, local[PREIMAGE_COL].clone()]);
+        assert_digest(builder, nullifier_step, &local, pis[1].clone());
+
+        // recipient and denomination are bound as public values
     }
 }
 
@@ -123,7 +206,64 @@ pub struct WithdrawalProof {
     /// Serialized proof bytes
     pub proof_bytes: Vec<u8>,
     /// Public inputs for verification
-    pub public_inputs: [u64; 4],
+    pub public_inputs: WithdrawalPublicInputs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct WithdrawalPublicInputs {
+    pub merkle_root: u64,
+    pub nullifier: u64,
+    pub recipient: Address,
+    pub denomination: u64,
+    pub domain: u64,
+}
+
+impl WithdrawalPublicInputs {
+    pub fn from_circuit<F: PrimeField64>(circuit: &WithdrawalCircuit<F>) -> Self {
+        Self {
+            merkle_root: circuit.merkle_root.as_canonical_u64(),
+            nullifier: circuit.nullifier.as_canonical_u64(),
+            recipient: circuit.recipient,
+            denomination: circuit.denomination.as_canonical_u64(),
+            domain: circuit.domain.as_canonical_u64(),
+        }
+    }
+
+    /// Public values in verifier order, the recipient as its limbs
+    pub fn to_array(&self) -> [u64; NUM_PUBLIC_VALUES] {
+        let mut values = Vec::with_capacity(NUM_PUBLIC_VALUES);
+        values.extend([self.merkle_root, self.nullifier]);
+        values.extend(address_limbs(&self.recipient).map(u64::from));
+        values.extend([self.denomination, self.domain]);
+        values.try_into().expect("NUM_PUBLIC_VALUES values")
+    }
+
+    /// Public values as field elements, in verifier order
+    pub fn to_field_elements<F: PrimeCharacteristicRing>(&self) -> Vec<F> {
+        self.to_array().iter().map(|&v| F::from_u64(v)).collect()
+    }
+
+    /// Circuit for these public inputs
+    pub fn to_circuit<F: PrimeField64>(&self) -> WithdrawalCircuit<F> {
+        WithdrawalCircuit::new(
+            F::from_u64(self.merkle_root),
+            F::from_u64(self.nullifier),
+            self.recipient,
+            F::from_u64(self.denomination),
+            F::from_u64(self.domain),
+        )
+    }
+
+    /// Public inputs as `IVerifier` takes them, the recipient at full width;
+    /// the verifier supplies the domain
+    pub fn to_evm_inputs(&self) -> [U256; NUM_PUBLIC_INPUTS] {
+        [
+            U256::from_u64(self.merkle_root),
+            U256::from_u64(self.nullifier),
+            address_to_u256(&self.recipient),
+            U256::from_u64(self.denomination),
+        ]
+    }
 }
 
 impl WithdrawalProof {
@@ -142,14 +282,16 @@ impl WithdrawalProof {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::poseidon_air::{permutation_residuals, OUTPUT_COL, PERM_COLS};
 
     #[test]
     fn test_circuit_creation() {
         let circuit = WithdrawalCircuit::new(
             Val::new(1),
             Val::new(2),
-            Val::new(3),
+            [3; 20],
             Val::new(4),
+            Val::new(5),
         );
 
         assert_eq!(circuit.width(), NUM_COLS);
@@ -159,10 +301,11 @@ mod tests {
     fn test_witness_generation() {
         let secret = Val::new(12345);
         let nullifier_preimage = Val::new(67890);
+        let domain = Val::new(8453);
 
         // Compute expected values
-        let commitment = hash_commitment(secret, nullifier_preimage);
-        let nullifier = hash_nullifier(nullifier_preimage);
+        let commitment = hash_commitment(domain, secret, nullifier_preimage);
+        let nullifier = hash_nullifier(domain, nullifier_preimage);
 
         // Create simple Merkle path (all zeros for testing)
         let merkle_path = [Val::new(0); TREE_DEPTH];
@@ -174,8 +317,9 @@ mod tests {
         let circuit = WithdrawalCircuit::new(
             merkle_root,
             nullifier,
-            Val::new(0xABCD), // recipient
+            [0xab; 20], // recipient
             Val::new(10000),   // denomination
+            domain,
         );
 
         let witness = WithdrawalWitness {
@@ -188,4 +332,30 @@ mod tests {
         // Generate trace (should not panic if witness is valid)
         let _trace = circuit.generate_trace(&witness);
     }
+
+    #[test]
+    fn test_trace_follows_program() {
+        let secret = Val::new(12345);
+        let nullifier_preimage = Val::new(67890);
+        let merkle_path: [Val; TREE_DEPTH] = core::array::from_fn(|i| Val::new(i as u32 + 1));
+        let path_indices: [bool; TREE_DEPTH] = core::array::from_fn(|i| i % 2 == 0);
+        let domain = Val::new(8453);
+        let commitment = hash_commitment(domain, secret, nullifier_preimage);
+        let merkle_root = compute_merkle_root(commitment, &merkle_path, &path_indices);
+        let nullifier = hash_nullifier(domain, nullifier_preimage);
+
+        let circuit = WithdrawalCircuit::new(merkle_root, nullifier, [0xab; 20], Val::new(10000), domain);
+        let witness = WithdrawalWitness { secret, nullifier_preimage, merkle_path, path_indices };
+        let trace = circuit.generate_trace(&witness);
+        let rows: Vec<&[Val]> = trace.values.chunks(NUM_COLS).collect();
+
+        assert_eq!(rows.len(), WithdrawalCircuit::<Val>::trace_height());
+        assert_eq!(rows[COMMITMENT_STEP][OUTPUT_COL], commitment);
+        assert_eq!(rows[NULLIFIER_STEP - 1][OUTPUT_COL], merkle_root);
+        assert_eq!(rows[NULLIFIER_STEP][OUTPUT_COL], nullifier);
+        for row in &rows {
+            let residuals = permutation_residuals::<Val, Val>(&row[..PERM_COLS]);
+            assert!(residuals.iter().all(|&r| r == Val::new(0)));
+        }
+    }
 }
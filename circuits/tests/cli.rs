@@ -0,0 +1,300 @@
+//! noctis-prove end to end on a BalanceVaultV4 note, and on a note of the
+//! BabyBear circuit in the same vault's domain; noctis-prove-deposit on the
+//! same vault note
+//!
+//! The vault note and tree are those of test/BalanceVaultV4Groth16.t.sol, which
+//! deposits the same five commitments into a vault and checks its root is
+//! `VAULT_ROOT`; the proving key is the fixture verifier's.
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use p3_baby_bear::BabyBear;
+use p3_field::PrimeField32;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use noctis_circuits::abi::{
+    address_to_u256, function_selector, DEPOSIT_WITH_PROOF_SIGNATURE, PUBLISH_ASSOCIATION_ROOT_SIGNATURE, WITHDRAW_SIGNATURE,
+};
+use noctis_circuits::address::asset_id;
+use noctis_circuits::groth16::{Groth16Proof, VerificationKey, U256};
+use noctis_circuits::keys::{note_commitment, SpendingKey};
+use noctis_circuits::merkle::TREE_DEPTH;
+use noctis_circuits::poseidon::{domain_tag, poseidon_hash};
+use noctis_circuits::poseidon_bn254::{address_to_field, compute_domain, compute_domain_commitment, Bn254Field};
+use noctis_circuits::r1cs;
+
+const VAULT: &str = "0x4444444444444444444444444444444444444444";
+const TOKEN: &str = "0x7777777777777777777777777777777777777777";
+const RECIPIENT: &str = "0xbebebebebebebebebebebebebebebebebebebebe";
+const RELAYER: &str = "0xfefefefefefefefefefefefefefefefefefefefe";
+const VAULT_ROOT: &str = "14101647273760052179286043020278061996813218036149376994835602659690969882796";
+
+struct Fixture {
+    dir: PathBuf,
+    vk: VerificationKey,
+    domain: Bn254Field,
+}
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("noctis-cli-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let (pk, vk) = r1cs::setup(TREE_DEPTH, &mut StdRng::seed_from_u64(11)).unwrap();
+        r1cs::write_proving_key(&pk, BufWriter::new(File::create(dir.join("pk.bin")).unwrap())).unwrap();
+
+        let domain = compute_domain(31337, &[0x44; 20]);
+        let spending_key = SpendingKey::from_seed(Bn254Field::new(0x5eed));
+        let commitment = compute_domain_commitment(
+            domain,
+            spending_key.spending_key_hash(),
+            address_to_field(&[0x77; 20]),
+            Bn254Field::new(1000),
+            Bn254Field::new(0xabcdef),
+        );
+        let note = serde_json::json!({
+            "spendingKey": "0x5eed",
+            "asset": TOKEN,
+            "randomness": "0xabcdef",
+            "balance": "1000",
+            "commitment": commitment.to_decimal_string(),
+            "noteIndex": 3,
+        });
+        fs::write(dir.join("note.json"), note.to_string()).unwrap();
+        let leaves = ["11".to_string(), "12".to_string(), "13".to_string(), commitment.to_decimal_string(), "15".to_string()];
+        fs::write(dir.join("tree.json"), serde_json::to_string(&leaves).unwrap()).unwrap();
+
+        Self { dir, vk: VerificationKey::from(&vk), domain }
+    }
+
+    fn path(&self, file: &str) -> String {
+        self.dir.join(file).to_str().unwrap().to_string()
+    }
+
+    fn prove(&self, extra: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_noctis-prove"))
+            .args(["--note", &self.path("note.json"), "--tree", &self.path("tree.json")])
+            .args(["--recipient", RECIPIENT, "--amount", "400", "--relayer", RELAYER, "--fee", "10"])
+            .args(extra)
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn test_prove_vault_note() {
+    let fixture = Fixture::new("vault");
+    let pk = fixture.path("pk.bin");
+    let output = fixture.prove(&["--proving-key", &pk, "--chain-id", "31337", "--vault", VAULT]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let inputs: Vec<U256> = json["publicInputs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| U256::from_dec_str(v.as_str().unwrap()).unwrap())
+        .collect();
+    assert_eq!(inputs.len(), 10);
+    assert_eq!(inputs[0].to_dec_string(), VAULT_ROOT);
+    assert_eq!(inputs[3], U256::from_u64(400));
+    assert_eq!(inputs[6], U256::from_u64(10));
+    assert_eq!(inputs[7], U256::from(address_to_field(&[0x77; 20])));
+
+    // The proof passes the fixture verifier with the DOMAIN the vault appends
+    let proof_hex = json["proof"].as_str().unwrap().trim_start_matches("0x");
+    let proof = Groth16Proof::from_solidity_calldata(&hex::decode(proof_hex).unwrap()).unwrap();
+    let mut verifier_inputs = inputs.clone();
+    verifier_inputs.push(U256::from(fixture.domain));
+    assert!(fixture.vk.verify(&proof, &verifier_inputs).unwrap());
+
+    let calldata = hex::decode(json["calldata"].as_str().unwrap().trim_start_matches("0x")).unwrap();
+    assert_eq!(calldata[..4], function_selector(WITHDRAW_SIGNATURE));
+}
+
+#[test]
+fn test_prove_vault_note_in_association_set() {
+    let fixture = Fixture::new("association");
+    let pk = fixture.path("pk.bin");
+    let note: serde_json::Value = serde_json::from_str(&fs::read_to_string(fixture.path("note.json")).unwrap()).unwrap();
+    let set = serde_json::json!({ "commitments": ["12", note["commitment"], "15"] });
+    fs::write(fixture.path("set.json"), set.to_string()).unwrap();
+    fs::write(fixture.path("other.json"), r#"["12", "15"]"#).unwrap();
+
+    // The root noctis-association-root publishes to the vault
+    let output = Command::new(env!("CARGO_BIN_EXE_noctis-association-root"))
+        .args(["--set", &fixture.path("set.json")])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let published: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(published["size"], 3);
+    let calldata = hex::decode(published["calldata"].as_str().unwrap().trim_start_matches("0x")).unwrap();
+    assert_eq!(calldata[..4], function_selector(PUBLISH_ASSOCIATION_ROOT_SIGNATURE));
+    let root = U256::from_dec_str(published["root"].as_str().unwrap()).unwrap();
+    assert_eq!(calldata[4..], root.to_be_bytes());
+
+    let vault = ["--proving-key", pk.as_str(), "--chain-id", "31337", "--vault", VAULT];
+    let output = fixture.prove(&[&vault[..], &["--association-set", &fixture.path("set.json")]].concat());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // The proof is against the published root and passes the fixture verifier
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mut inputs: Vec<U256> = json["publicInputs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| U256::from_dec_str(v.as_str().unwrap()).unwrap())
+        .collect();
+    assert_eq!(inputs[8], root);
+    let proof_hex = json["proof"].as_str().unwrap().trim_start_matches("0x");
+    let proof = Groth16Proof::from_solidity_calldata(&hex::decode(proof_hex).unwrap()).unwrap();
+    inputs.push(U256::from(fixture.domain));
+    assert!(fixture.vk.verify(&proof, &inputs).unwrap());
+
+    // A set without the note
+    let output = fixture.prove(&[&vault[..], &["--association-set", &fixture.path("other.json")]].concat());
+    assert_eq!(output.status.code(), Some(6));
+}
+
+#[test]
+fn test_prove_vault_note_errors() {
+    let fixture = Fixture::new("errors");
+    let pk = fixture.path("pk.bin");
+
+    // In another vault's domain the commitment does not open
+    let output = fixture.prove(&["--proving-key", &pk, "--chain-id", "31337", "--vault", TOKEN]);
+    assert_eq!(output.status.code(), Some(4));
+
+    let output = fixture.prove(&["--proving-key", &pk, "--chain-id", "31337"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    // Without a proving key a BN254 note is refused, pointing at the option
+    let output = fixture.prove(&["--chain-id", "31337", "--vault", VAULT]);
+    assert_eq!(output.status.code(), Some(7));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--proving-key"));
+}
+
+#[test]
+fn test_prove_stark_note() {
+    let dir = std::env::temp_dir().join(format!("noctis-cli-stark-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let (spending_key, balance, randomness) = (BabyBear::new(12345), BabyBear::new(1000), BabyBear::new(0xabcdef));
+    let vault = [0x44; 20];
+    let commitment = note_commitment(
+        domain_tag(31337, &vault),
+        poseidon_hash(spending_key),
+        asset_id(&[0x77; 20]),
+        balance,
+        randomness,
+    );
+    let note = serde_json::json!({
+        "spendingKey": "12345",
+        "asset": TOKEN,
+        "randomness": "0xabcdef",
+        "balance": "1000",
+        "commitment": commitment.as_canonical_u32().to_string(),
+        "noteIndex": 3,
+    });
+    fs::write(dir.join("note.json"), note.to_string()).unwrap();
+    let leaves = ["11".to_string(), "12".to_string(), "13".to_string(), commitment.as_canonical_u32().to_string()];
+    fs::write(dir.join("tree.json"), serde_json::to_string(&leaves).unwrap()).unwrap();
+
+    let prove = |chain_id: &str| {
+        Command::new(env!("CARGO_BIN_EXE_noctis-prove"))
+            .args(["--note", dir.join("note.json").to_str().unwrap(), "--tree", dir.join("tree.json").to_str().unwrap()])
+            .args(["--recipient", RECIPIENT, "--amount", "400", "--relayer", RELAYER, "--fee", "10"])
+            .args(["--chain-id", chain_id, "--vault", VAULT])
+            .output()
+            .unwrap()
+    };
+    let output = prove("31337");
+    // On another chain the commitment does not open
+    let other_chain = prove("8453");
+    let _ = fs::remove_dir_all(&dir);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(other_chain.status.code(), Some(4));
+
+    // For the STARK verifier, with no vault calldata
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["verifier"], "BalanceWithdrawalStarkVerifier");
+    assert!(json.get("calldata").is_none());
+    let inputs = json["publicInputs"].as_array().unwrap();
+    assert_eq!(inputs.len(), 11);
+    assert_eq!(inputs[3], "400");
+    assert_eq!(inputs[7], address_to_u256(&[0x77; 20]).to_dec_string());
+    assert_eq!(inputs[10], U256::from(compute_domain(31337, &vault)).to_dec_string());
+}
+
+#[test]
+fn test_prove_deposit() {
+    let dir = std::env::temp_dir().join(format!("noctis-cli-deposit-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    // The deposit key of test/fixtures/DepositVerifierFixture.sol
+    let (pk, vk) = r1cs::deposit::setup(&mut StdRng::seed_from_u64(12)).unwrap();
+    r1cs::write_proving_key(&pk, BufWriter::new(File::create(dir.join("pk.bin")).unwrap())).unwrap();
+
+    let domain = compute_domain(31337, &[0x44; 20]);
+    let commitment = compute_domain_commitment(
+        domain,
+        SpendingKey::from_seed(Bn254Field::new(0x5eed)).spending_key_hash(),
+        address_to_field(&[0x77; 20]),
+        Bn254Field::new(1000),
+        Bn254Field::new(0xabcdef),
+    );
+    // Exported before the deposit, so without an index
+    let note = serde_json::json!({
+        "spendingKey": "0x5eed",
+        "asset": TOKEN,
+        "randomness": "0xabcdef",
+        "balance": "1000",
+        "commitment": commitment.to_decimal_string(),
+        "noteIndex": null,
+    });
+    fs::write(dir.join("note.json"), note.to_string()).unwrap();
+
+    let prove = |vault: &str| {
+        Command::new(env!("CARGO_BIN_EXE_noctis-prove-deposit"))
+            .args(["--proving-key", dir.join("pk.bin").to_str().unwrap(), "--note", dir.join("note.json").to_str().unwrap()])
+            .args(["--chain-id", "31337", "--vault", vault])
+            .output()
+            .unwrap()
+    };
+    let output = prove(VAULT);
+    // In another vault's domain the commitment does not open
+    let other_vault = prove(TOKEN);
+    let _ = fs::remove_dir_all(&dir);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(other_vault.status.code(), Some(4));
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let inputs: Vec<U256> = json["publicInputs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| U256::from_dec_str(v.as_str().unwrap()).unwrap())
+        .collect();
+    assert_eq!(inputs, [U256::from(commitment), U256::from_u64(1000), address_to_u256(&[0x77; 20])]);
+
+    // The proof passes the fixture verifier with the DOMAIN the vault appends
+    let proof_hex = json["proof"].as_str().unwrap().trim_start_matches("0x");
+    let proof = Groth16Proof::from_solidity_calldata(&hex::decode(proof_hex).unwrap()).unwrap();
+    let mut verifier_inputs = inputs.clone();
+    verifier_inputs.push(U256::from(domain));
+    assert!(VerificationKey::from(&vk).verify(&proof, &verifier_inputs).unwrap());
+
+    let calldata = hex::decode(json["calldata"].as_str().unwrap().trim_start_matches("0x")).unwrap();
+    assert_eq!(calldata[..4], function_selector(DEPOSIT_WITH_PROOF_SIGNATURE));
+}
@@ -20,13 +20,17 @@ contract DeployMainnet is Script {
 
     function run() external {
         uint256 deployerPrivateKey = vm.envUint("PRIVATE_KEY");
+        // Rendered by generate-verifier --setup --circuit deposit with the
+        // deposit proving key, and deployed beforehand
+        address depositVerifier = vm.envAddress("DEPOSIT_VERIFIER");
 
         vm.startBroadcast(deployerPrivateKey);
 
         // Deploy Vault with NOCTIS token and existing verifier
         BalanceVaultV4 vault = new BalanceVaultV4(
             TOKEN,
-            VERIFIER
+            VERIFIER,
+            depositVerifier
         );
 
         vm.stopBroadcast();
@@ -35,6 +39,7 @@ contract DeployMainnet is Script {
         console.log("BalanceVaultV4 deployed at:", address(vault));
         console.log("Token (NOCTIS):", TOKEN);
         console.log("Verifier:", VERIFIER);
+        console.log("Deposit verifier:", depositVerifier);
         console.log("Initial root:", vault.getCurrentRoot());
         console.log("================================================================");
     }
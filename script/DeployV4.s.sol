@@ -21,12 +21,16 @@ contract DeployVaultV4 is Script {
 
     function run() external {
         uint256 deployerPrivateKey = vm.envUint("PRIVATE_KEY");
+        // Rendered by generate-verifier --setup --circuit deposit with the
+        // deposit proving key, and deployed beforehand
+        address depositVerifier = vm.envAddress("DEPOSIT_VERIFIER");
 
         vm.startBroadcast(deployerPrivateKey);
 
         BalanceVaultV4 vault = new BalanceVaultV4(
             TOKEN,
-            VERIFIER
+            VERIFIER,
+            depositVerifier
         );
 
         vm.stopBroadcast();
@@ -35,6 +39,7 @@ contract DeployVaultV4 is Script {
         console.log("BalanceVaultV4 deployed at:", address(vault));
         console.log("Token:", TOKEN);
         console.log("Verifier:", VERIFIER);
+        console.log("Deposit verifier:", depositVerifier);
         console.log("Initial root:", vault.getCurrentRoot());
         console.log("Field modulus: 21888242871839275222246405745257275088548364400416034343698204186575808495617");
         console.log("Tree depth:", vault.TREE_DEPTH());
@@ -43,7 +43,7 @@ async function main() {
 
     // Create notes for deposit
     const amount = ethers.parseEther('10000'); // 10,000 NOCTIS
-    const note1 = Note.create(amount);
+    const note1 = Note.create(amount, sdk.addresses.token, await sdk.getDomain());
 
     console.log('Created Note:');
     console.log('  Commitment:', note1.commitment);
@@ -66,8 +66,10 @@ async function main() {
     const signer = new ethers.Wallet(PRIVATE_KEY, provider);
     const sdkWithSigner = new NoctisSDK(provider, signer);
 
-    // Deposit 10,000 NOCTIS
-    const { note, tx } = await sdkWithSigner.deposit(ethers.parseEther('10000'));
+    // Deposit 10,000 NOCTIS, proven with
+    // noctis-prove-deposit --note note.json --chain-id ... --vault ...
+    const depositNote = await sdkWithSigner.createDepositNote(ethers.parseEther('10000'));
+    const { note, tx } = await sdkWithSigner.deposit(depositNote, depositProof);
     console.log('Deposit TX:', tx.hash);
     console.log('Note Index:', note.noteIndex);
 
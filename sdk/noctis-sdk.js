@@ -6,17 +6,19 @@
  */
 
 const { ethers } = require('ethers');
+const { addressToField, computeDomain, computeDomainCommitment, computeDomainNullifier, deriveKeys } = require('./poseidon-bn254');
 
 // Contract ABIs (minimal for SDK)
 const VAULT_ABI = [
-    "function deposit(bytes32 commitment, uint256 amount) external",
-    "function withdraw(bytes calldata proof, uint256[5] calldata publicInputs) external",
+    "function depositWithProof(bytes calldata proof, uint256[3] calldata publicInputs) external",
+    "function withdraw(bytes calldata proof, uint256[10] calldata publicInputs) external",
     "function getCurrentRoot() external view returns (bytes32)",
     "function getMerkleProof(uint256 noteIndex) external view returns (bytes32[] memory siblings, uint256[] memory pathIndices)",
     "function getNoteCount() external view returns (uint256)",
     "function isKnownRoot(bytes32 root) external view returns (bool)",
     "function nullifierUsed(bytes32 nullifier) external view returns (bool)",
     "function commitmentUsed(bytes32 commitment) external view returns (bool)",
+    "function blocklistRoot() external view returns (uint256)",
     "event NoteCreated(bytes32 indexed commitment, uint256 indexed noteIndex, uint256 timestamp)",
     "event Withdrawal(bytes32 indexed nullifier, address indexed recipient, uint256 amount, bool hasChange)"
 ];
@@ -28,7 +30,7 @@ const TOKEN_ABI = [
 ];
 
 const RELAYER_ABI = [
-    "function executeWithdrawal(bytes calldata proof, uint256[5] calldata publicInputs, address actualRecipient, uint256 fee, uint256 deadline, bytes calldata sig) external",
+    "function executeWithdrawal(bytes calldata proof, uint256[10] calldata publicInputs, address actualRecipient, uint256 fee, uint256 deadline, bytes calldata sig) external",
     "function minRelayerFee() external view returns (uint256)",
     "function maxRelayerFeeBps() external view returns (uint256)"
 ];
@@ -105,6 +107,9 @@ function isWasmAvailable() {
 
 /**
  * Generate a random spending key
+ *
+ * The top three bits are cleared so the value is a BN254 scalar, which the
+ * vault's circuit and `noctis-prove` require of note secrets.
  * @returns {string} 32-byte hex string
  */
 function generateSpendingKey() {
@@ -117,6 +122,7 @@ function generateSpendingKey() {
         const randomBytes = nodeCrypto.randomBytes(32);
         bytes.set(randomBytes);
     }
+    bytes[0] &= 0x1f;
     return '0x' + Array.from(bytes).map(b => b.toString(16).padStart(2, '0')).join('');
 }
 
@@ -129,72 +135,77 @@ function generateRandomness() {
 }
 
 /**
- * Compute commitment using WASM Poseidon2 hash or fallback to keccak256
+ * Compute a note commitment as BalanceVaultV4 checks it
+ *
+ * commitment = Poseidon(Poseidon(domain, spendingKeyHash, asset), balance, randomness)
+ * over BN254, with spendingKeyHash derived from the spending key (the wallet
+ * seed) and `asset` the token address, so a note of one token cannot be
+ * withdrawn as another. Matches `poseidon_bn254::compute_domain_commitment`.
  * @param {string} spendingKey - The spending key (hex)
  * @param {BigInt|string} balance - The balance amount
  * @param {string} randomness - Random value (hex)
- * @returns {string} Commitment hash
+ * @param {string} asset - Token the note holds, the zero address for ETH
+ * @param {BigInt} domain - Vault domain, see `computeDomain`
+ * @returns {string} Commitment as bytes32 hex
  */
-function computeCommitment(spendingKey, balance, randomness) {
-    if (wasmModule) {
-        try {
-            // Use WASM Poseidon2 hash
-            const secret = spendingKey.replace('0x', '');
-            return '0x' + wasmModule.generate_commitment(secret);
-        } catch (e) {
-            console.warn('WASM commitment failed, using fallback:', e.message);
-        }
-    }
-
-    // Fallback: keccak256 (works with MockVerifier)
-    const spendingKeyHash = ethers.keccak256(spendingKey);
-    return ethers.keccak256(
-        ethers.solidityPacked(
-            ['bytes32', 'uint256', 'bytes32'],
-            [spendingKeyHash, balance, randomness]
-        )
+function computeCommitment(spendingKey, balance, randomness, asset, domain) {
+    if (asset === undefined || domain === undefined) {
+        throw new Error('computeCommitment needs the note asset and the vault domain');
+    }
+    const { spendingKeyHash } = deriveKeys(BigInt(spendingKey));
+    const commitment = computeDomainCommitment(
+        BigInt(domain),
+        spendingKeyHash,
+        addressToField(asset),
+        BigInt(balance),
+        BigInt(randomness)
     );
+    return ethers.toBeHex(commitment, 32);
 }
 
 /**
- * Compute nullifier = keccak256(spendingKey, noteIndex) or Poseidon2
+ * Compute a note's nullifier as BalanceVaultV4 checks it
+ *
+ * nullifier = Poseidon(domain, nk, noteIndex) over BN254, with nk the
+ * nullifier deriving key of the spending key (the wallet seed), never the
+ * spending key itself: a watch-only wallet given nk can track spends but
+ * not make them. Matches `keys::FullViewingKey::nullifier`.
  * @param {string} spendingKey - The spending key (hex)
  * @param {number} noteIndex - The note's index in the tree
- * @returns {string} Nullifier hash
+ * @param {BigInt} domain - Vault domain, see `computeDomain`
+ * @returns {string} Nullifier as bytes32 hex
  */
-function computeNullifier(spendingKey, noteIndex) {
-    if (wasmModule) {
-        try {
-            // Use WASM Poseidon2 nullifier
-            const preimage = spendingKey.replace('0x', '');
-            return '0x' + wasmModule.generate_nullifier(preimage);
-        } catch (e) {
-            console.warn('WASM nullifier failed, using fallback:', e.message);
-        }
+function computeNullifier(spendingKey, noteIndex, domain) {
+    if (domain === undefined) {
+        throw new Error('computeNullifier needs the vault domain');
     }
-
-    // Fallback: keccak256
-    return ethers.keccak256(
-        ethers.solidityPacked(['bytes32', 'uint256'], [spendingKey, noteIndex])
-    );
+    const { nk } = deriveKeys(BigInt(spendingKey));
+    return ethers.toBeHex(computeDomainNullifier(BigInt(domain), nk, BigInt(noteIndex)), 32);
 }
 
 /**
  * Create a Note object for tracking deposits
+ *
+ * `asset` is the token the note holds, the zero address for ETH; the vault
+ * only lets a note be withdrawn as that token. Without a stored commitment,
+ * `domain` is the domain of the vault the note is for (`computeDomain`).
  */
 class Note {
-    constructor(spendingKey, balance, randomness, noteIndex = null, commitment = null) {
+    constructor(spendingKey, balance, randomness, noteIndex = null, commitment = null, asset = ethers.ZeroAddress, domain) {
         this.spendingKey = spendingKey;
+        this.asset = asset;
+        this.domain = domain;
         this.balance = BigInt(balance);
         this.randomness = randomness;
         this.noteIndex = noteIndex;
-        this.commitment = commitment || computeCommitment(spendingKey, this.balance, randomness);
+        this.commitment = commitment || computeCommitment(spendingKey, this.balance, randomness, asset, domain);
     }
 
     toJSON() {
         return {
             version: 2, // Updated version for WASM support
             spendingKey: this.spendingKey,
+            asset: this.asset,
             balance: this.balance.toString(),
             randomness: this.randomness,
             noteIndex: this.noteIndex,
@@ -208,30 +219,38 @@ class Note {
             json.balance,
             json.randomness,
             json.noteIndex,
-            json.commitment
+            json.commitment,
+            json.asset
         );
     }
 
     /**
      * Create a new note for deposit
      * @param {BigInt|string} amount - Amount to deposit
+     * @param {string} asset - Token deposited, the zero address for ETH
+     * @param {BigInt} domain - Domain of the vault deposited into
      * @returns {Note}
      */
-    static create(amount) {
+    static create(amount, asset, domain) {
         return new Note(
             generateSpendingKey(),
             amount,
-            generateRandomness()
+            generateRandomness(),
+            null,
+            null,
+            asset,
+            domain
         );
     }
 
     /**
      * Get nullifier for this note
+     * @param {BigInt} [domain] - Domain of the note's vault, if not the one it was created with
      * @returns {string|null}
      */
-    getNullifier() {
+    getNullifier(domain = this.domain) {
         if (this.noteIndex === null) return null;
-        return computeNullifier(this.spendingKey, this.noteIndex);
+        return computeNullifier(this.spendingKey, this.noteIndex, domain);
     }
 }
 
@@ -278,6 +297,18 @@ class NoctisSDK {
         }
     }
 
+    /**
+     * Domain of the vault, which its note commitments are bound to
+     * @returns {Promise<BigInt>}
+     */
+    async getDomain() {
+        if (this.domain === undefined) {
+            const { chainId } = await this.provider.getNetwork();
+            this.domain = computeDomain(chainId, this.addresses.vault);
+        }
+        return this.domain;
+    }
+
     /**
      * Check if real ZK proofs are available
      * @returns {boolean}
@@ -289,15 +320,28 @@ class NoctisSDK {
     // ==================== DEPOSIT ====================
 
     /**
-     * Deposit tokens and create a note
+     * Create a note to deposit, bound to this vault's domain
+     *
+     * Prove its deposit with noctis-prove-deposit, from the note's JSON and
+     * this vault's chain id and address, then pass the proof to `deposit`.
      * @param {BigInt|string} amount - Amount to deposit (in wei)
+     * @returns {Promise<Note>}
+     */
+    async createDepositNote(amount) {
+        return Note.create(BigInt(amount), this.addresses.token, await this.getDomain());
+    }
+
+    /**
+     * Deposit a note's balance
+     * @param {Note} note - Note from `createDepositNote`
+     * @param {string} proof - Deposit proof for the note, the `proof` field of
+     *        noctis-prove-deposit's output
      * @returns {Promise<{note: Note, tx: ethers.TransactionResponse}>}
      */
-    async deposit(amount) {
+    async deposit(note, proof) {
         if (!this.signer) throw new Error('Signer required for deposit');
 
-        const amountBn = BigInt(amount);
-        const note = Note.create(amountBn);
+        const amountBn = note.balance;
 
         // Check and approve if needed
         const signerAddress = await this.signer.getAddress();
@@ -308,8 +352,9 @@ class NoctisSDK {
             await approveTx.wait();
         }
 
-        // Deposit
-        const tx = await this.vault.deposit(note.commitment, amountBn);
+        // The vault checks the proof against its DOMAIN
+        const publicInputs = [BigInt(note.commitment), amountBn, addressToField(this.addresses.token)];
+        const tx = await this.vault.depositWithProof(proof, publicInputs);
         const receipt = await tx.wait();
 
         // Find note index from event
@@ -336,33 +381,46 @@ class NoctisSDK {
      * @param {string} recipient - Recipient address
      * @param {BigInt|string} amount - Amount to withdraw
      * @param {Note} [changeNote] - Optional change note
+     * @param {string} [relayer] - Address paid the fee; the zero address if self-submitted
+     * @param {BigInt|string} [fee] - Part of amount paid to the relayer
      * @returns {Promise<{proof: string, publicInputs: BigInt[]}>}
      */
-    async generateProof(note, recipient, amount, changeNote = null) {
+    async generateProof(note, recipient, amount, changeNote = null, relayer = ethers.ZeroAddress, fee = 0n) {
         const amountBn = BigInt(amount);
+        const feeBn = BigInt(fee);
 
         if (amountBn > note.balance) {
             throw new Error('Insufficient balance');
         }
+        if (feeBn > amountBn) {
+            throw new Error('Relayer fee exceeds amount');
+        }
 
         const merkleRoot = await this.vault.getCurrentRoot();
-        const nullifier = note.getNullifier();
+        const nullifier = note.getNullifier(await this.getDomain());
 
         if (!nullifier) {
             throw new Error('Note must have noteIndex set');
         }
 
         const changeCommitment = changeNote ? changeNote.commitment : ethers.ZeroHash;
+        // The vault only accepts proofs against its current blocklist
+        const blocklistRoot = BigInt(await this.vault.blocklistRoot());
 
         // If using real proofs and WASM is available
         if (this.useRealProofs && isWasmAvailable()) {
+            if (blocklistRoot !== 0n) {
+                throw new Error('The vault has a blocklist, which the WASM prover cannot prove against; use noctis-prove --blocklist');
+            }
             return await this._generateRealProof(
                 note,
                 recipient,
                 amountBn,
                 merkleRoot,
                 nullifier,
-                changeCommitment
+                changeCommitment,
+                relayer,
+                feeBn
             );
         }
 
@@ -373,14 +431,17 @@ class NoctisSDK {
             recipient,
             amountBn,
             changeCommitment,
-            note
+            relayer,
+            feeBn,
+            note,
+            blocklistRoot
         );
     }
 
     /**
      * Generate real ZK proof using WASM
      */
-    async _generateRealProof(note, recipient, amount, merkleRoot, nullifier, changeCommitment) {
+    async _generateRealProof(note, recipient, amount, merkleRoot, nullifier, changeCommitment, relayer, fee) {
         // Get Merkle proof from contract
         const { siblings, pathIndices } = await this.vault.getMerkleProof(note.noteIndex);
 
@@ -389,6 +450,8 @@ class NoctisSDK {
         const nullifierPreimageHex = note.randomness.replace('0x', '');
         const merklePathJson = JSON.stringify(siblings.map(s => s.replace('0x', '')));
         const pathIndicesJson = JSON.stringify(pathIndices.map(i => i === 1n || i === 1));
+        // Commitment and nullifier are derived in the vault's domain
+        const { chainId } = await this.provider.getNetwork();
 
         try {
             // Generate proof bytes
@@ -398,7 +461,12 @@ class NoctisSDK {
                 merklePathJson,
                 pathIndicesJson,
                 recipient,
-                amount.toString()
+                amount.toString(),
+                relayer,
+                fee.toString(),
+                this.addresses.token,
+                BigInt(chainId),
+                this.addresses.vault
             );
 
             // Get public inputs
@@ -408,7 +476,12 @@ class NoctisSDK {
                 merklePathJson,
                 pathIndicesJson,
                 recipient,
-                amount.toString()
+                amount.toString(),
+                relayer,
+                fee.toString(),
+                this.addresses.token,
+                BigInt(chainId),
+                this.addresses.vault
             );
 
             const publicInputsObj = JSON.parse(publicInputsJson);
@@ -424,7 +497,12 @@ class NoctisSDK {
                 BigInt('0x' + publicInputsObj.nullifier),
                 BigInt(recipient),
                 amount,
-                BigInt(changeCommitment)
+                BigInt(changeCommitment),
+                BigInt(relayer),
+                fee,
+                BigInt(this.addresses.token),
+                0n, // No association set; use noctis-prove --association-set for one
+                0n  // No blocklist
             ];
 
             return { proof, publicInputs };
@@ -437,14 +515,19 @@ class NoctisSDK {
     /**
      * Generate mock proof (for testnet/MockVerifier)
      */
-    _generateMockProof(merkleRoot, nullifier, recipient, amount, changeCommitment, note) {
+    _generateMockProof(merkleRoot, nullifier, recipient, amount, changeCommitment, relayer, fee, note, blocklistRoot) {
         // Public inputs for the verifier
         const publicInputs = [
             BigInt(merkleRoot),
             BigInt(nullifier),
             BigInt(recipient),
             amount,
-            BigInt(changeCommitment)
+            BigInt(changeCommitment),
+            BigInt(relayer),
+            fee,
+            BigInt(this.addresses.token),
+            0n, // No association set
+            blocklistRoot
         ];
 
         // Generate mock proof (works with MockVerifier on testnet)
@@ -476,7 +559,11 @@ class NoctisSDK {
             changeNote = new Note(
                 note.spendingKey, // Same spending key
                 note.balance - amountBn,
-                generateRandomness()
+                generateRandomness(),
+                null,
+                null,
+                note.asset, // Same asset
+                await this.getDomain()
             );
         }
 
@@ -524,7 +611,11 @@ class NoctisSDK {
             changeNote = new Note(
                 note.spendingKey,
                 note.balance - amountBn,
-                generateRandomness()
+                generateRandomness(),
+                null,
+                null,
+                note.asset,
+                await this.getDomain()
             );
         }
 
@@ -537,7 +628,7 @@ class NoctisSDK {
         );
 
         // Sign the authorization
-        const nullifier = note.getNullifier();
+        const nullifier = note.getNullifier(await this.getDomain());
         const messageHash = ethers.keccak256(
             ethers.solidityPacked(
                 ['bytes32', 'address', 'uint256', 'uint256', 'uint256'],
@@ -712,6 +803,7 @@ if (typeof module !== 'undefined' && module.exports) {
         generateRandomness,
         computeCommitment,
         computeNullifier,
+        computeDomain,
         ADDRESSES,
         VAULT_ABI,
         TOKEN_ABI,
@@ -731,7 +823,8 @@ if (typeof window !== 'undefined') {
             generateSpendingKey,
             generateRandomness,
             computeCommitment,
-            computeNullifier
+            computeNullifier,
+            computeDomain
         },
         ADDRESSES
     };
@@ -0,0 +1,193 @@
+/**
+ * BN254 Poseidon for BalanceVaultV4 notes
+ *
+ * A JavaScript port of circuits/src/poseidon_bn254.rs and keys.rs: the
+ * circomlib / poseidon-solidity hash (x^5 S-box, 8 full rounds, capacity
+ * element first) with its constants derived by the Grain LFSR, and the note
+ * derivations built on it. Values are BigInts below the BN254 scalar modulus.
+ */
+
+const P = 21888242871839275222246405745257275088548364400416034343698204186575808495617n;
+const FIELD_BITS = 254;
+
+/** Derivation version mixed into the domain */
+const DERIVATION_VERSION = 2n;
+
+/** Key derivation tags, as in keys.rs */
+const ASK_TAG = 1n;
+const NK_TAG = 2n;
+const IVK_TAG = 3n;
+
+function mod(x) {
+    const r = x % P;
+    return r < 0n ? r + P : r;
+}
+
+function pow(base, exp) {
+    let result = 1n;
+    base = mod(base);
+    while (exp > 0n) {
+        if (exp & 1n) result = (result * base) % P;
+        base = (base * base) % P;
+        exp >>= 1n;
+    }
+    return result;
+}
+
+/** Grain LFSR in self-shrinking mode, seeded with the Poseidon instance */
+class Grain {
+    constructor(width, fullRounds, partialRounds) {
+        this.state = [];
+        const push = (value, bits) => {
+            for (let i = bits - 1; i >= 0; i--) this.state.push(((value >> i) & 1) === 1);
+        };
+        push(1, 2); // prime field
+        push(0, 4); // x^alpha S-box
+        push(FIELD_BITS, 12);
+        push(width, 12);
+        push(fullRounds, 10);
+        push(partialRounds, 10);
+        push((1 << 30) - 1, 30);
+        for (let i = 0; i < 160; i++) this.step();
+    }
+
+    step() {
+        const s = this.state;
+        const bit = s[62] !== s[51] !== s[38] !== s[23] !== s[13] !== s[0];
+        s.shift();
+        s.push(bit);
+        return bit;
+    }
+
+    /** Output bits come in pairs: keep the second when the first is set */
+    nextBit() {
+        for (;;) {
+            const keep = this.step();
+            const bit = this.step();
+            if (keep) return bit;
+        }
+    }
+
+    /** Next 254 output bits read as an integer, most significant bit first */
+    nextInteger() {
+        let value = 0n;
+        for (let i = 0; i < FIELD_BITS; i++) value = (value << 1n) | (this.nextBit() ? 1n : 0n);
+        return value;
+    }
+
+    /** Rejection-sample a field element */
+    nextFieldElement() {
+        for (;;) {
+            const value = this.nextInteger();
+            if (value < P) return value;
+        }
+    }
+}
+
+function generateParams(width, fullRounds, partialRounds) {
+    const grain = new Grain(width, fullRounds, partialRounds);
+    const roundConstants = [];
+    for (let i = 0; i < (fullRounds + partialRounds) * width; i++) {
+        roundConstants.push(grain.nextFieldElement());
+    }
+    // Cauchy matrix 1 / (x_i + y_j); the MDS sampling does not reject
+    // values above the modulus
+    const xy = [];
+    for (let i = 0; i < 2 * width; i++) xy.push(mod(grain.nextInteger()));
+    const mds = [];
+    for (let i = 0; i < width; i++) {
+        mds.push([]);
+        for (let j = 0; j < width; j++) mds[i].push(pow(xy[i] + xy[width + j], P - 2n));
+    }
+    return { width, fullRounds, partialRounds, roundConstants, mds };
+}
+
+const params = {};
+
+function paramsFor(width) {
+    if (!params[width]) {
+        if (width === 3) params[3] = generateParams(3, 8, 57);
+        else if (width === 4) params[4] = generateParams(4, 8, 56);
+        else throw new Error(`No Poseidon parameters for ${width - 1} inputs`);
+    }
+    return params[width];
+}
+
+/**
+ * Hash two or three field elements (PoseidonT3 / PoseidonT4)
+ * @param {BigInt[]} inputs
+ * @returns {BigInt}
+ */
+function poseidon(inputs) {
+    const { width, fullRounds, partialRounds, roundConstants, mds } = paramsFor(inputs.length + 1);
+    let state = [0n, ...inputs.map(x => BigInt(x))];
+    for (const x of state) {
+        if (x < 0n || x >= P) throw new Error('Poseidon input is not a BN254 scalar');
+    }
+    for (let r = 0; r < fullRounds + partialRounds; r++) {
+        state = state.map((s, i) => (s + roundConstants[r * width + i]) % P);
+        const full = r < fullRounds / 2 || r >= fullRounds / 2 + partialRounds;
+        if (full) {
+            state = state.map(s => pow(s, 5n));
+        } else {
+            state[0] = pow(state[0], 5n);
+        }
+        state = mds.map(row => row.reduce((acc, m, j) => (acc + m * state[j]) % P, 0n));
+    }
+    return state[0];
+}
+
+/** An EVM address as a field element, i.e. `uint256(uint160(address))` */
+function addressToField(address) {
+    if (!/^0x[0-9a-fA-F]{40}$/.test(address)) throw new Error(`Invalid address: ${address}`);
+    return BigInt(address);
+}
+
+/**
+ * Deployment domain = Poseidon(DERIVATION_VERSION, chainId, vault)
+ * @param {BigInt|number} chainId
+ * @param {string} vault - Vault address
+ * @returns {BigInt}
+ */
+function computeDomain(chainId, vault) {
+    return poseidon([DERIVATION_VERSION, BigInt(chainId), addressToField(vault)]);
+}
+
+/**
+ * Key hierarchy of a wallet seed, as `keys::SpendingKey::from_seed`
+ * @param {BigInt} seed
+ * @returns {{ask: BigInt, nk: BigInt, ak: BigInt, ivk: BigInt, spendingKeyHash: BigInt}}
+ */
+function deriveKeys(seed) {
+    const ask = poseidon([seed, ASK_TAG]);
+    const nk = poseidon([seed, NK_TAG]);
+    const ak = poseidon([ask, 0n]);
+    const ivk = poseidon([IVK_TAG, ak, nk]);
+    return { ask, nk, ak, ivk, spendingKeyHash: poseidon([ivk, 0n]) };
+}
+
+/**
+ * Commitment = Poseidon(Poseidon(domain, spendingKeyHash, asset), balance, randomness)
+ * @returns {BigInt}
+ */
+function computeDomainCommitment(domain, spendingKeyHash, asset, balance, randomness) {
+    return poseidon([poseidon([domain, spendingKeyHash, asset]), balance, randomness]);
+}
+
+/**
+ * Nullifier = Poseidon(domain, nk, noteIndex)
+ * @returns {BigInt}
+ */
+function computeDomainNullifier(domain, nk, noteIndex) {
+    return poseidon([domain, nk, noteIndex]);
+}
+
+module.exports = {
+    BN254_MODULUS: P,
+    poseidon,
+    addressToField,
+    computeDomain,
+    deriveKeys,
+    computeDomainCommitment,
+    computeDomainNullifier
+};
@@ -14,12 +14,22 @@ const {
     generateRandomness,
     computeCommitment,
     computeNullifier,
+    computeDomain,
     ADDRESSES
 } = require('./noctis-sdk');
+const { computeDomainNullifier, deriveKeys } = require('./poseidon-bn254');
 
 // Base Sepolia RPC
 const RPC_URL = 'https://sepolia.base.org';
 
+// The note of test/BalanceVaultV4Groth16.t.sol and circuits/tests/cli.rs: the
+// Rust prover and the vault agree on its domain and commitment
+const VAULT = '0x4444444444444444444444444444444444444444';
+const TOKEN = '0x7777777777777777777777777777777777777777';
+const DOMAIN = 14662978245061976630882248176688412036630854214599193360401297926632920049201n;
+const NOTE_COMMITMENT = 17692367850651603564815588957784872272511635620798776289052078333477056220622n;
+const NOTE_NULLIFIER = 84676919971569883818273205275550777194632016565442236224169311278813426698n;
+
 let passCount = 0;
 let failCount = 0;
 
@@ -78,18 +88,45 @@ async function main() {
         assertEqual(rand.length, 66, 'randomness length');
     });
 
+    test('computeDomain matches the Rust and Solidity domain', () => {
+        assertEqual(computeDomain(31337, VAULT), DOMAIN, 'domain');
+    });
+
+    test('computeCommitment matches the Rust commitment', () => {
+        const commitment = computeCommitment('0x5eed', 1000n, '0xabcdef', TOKEN, DOMAIN);
+        assertEqual(BigInt(commitment), NOTE_COMMITMENT, 'commitment');
+    });
+
+    test('computeNullifier matches the Rust nullifier', () => {
+        const nullifier = computeNullifier('0x5eed', 45, DOMAIN);
+        assertEqual(BigInt(nullifier), NOTE_NULLIFIER, 'nullifier');
+    });
+
+    test('only nk gives the nullifier, not a viewing key', () => {
+        const keys = deriveKeys(0x5eedn);
+        assertEqual(computeDomainNullifier(DOMAIN, keys.nk, 45n), NOTE_NULLIFIER, 'watch-only nullifier');
+        for (const key of [keys.ivk, keys.spendingKeyHash, keys.ak]) {
+            assertTrue(computeDomainNullifier(DOMAIN, key, 45n) !== NOTE_NULLIFIER, 'viewing key nullifier');
+        }
+    });
+
+    test('computeCommitment binds the asset', () => {
+        const other = computeCommitment('0x5eed', 1000n, '0xabcdef', ethers.ZeroAddress, DOMAIN);
+        assertTrue(BigInt(other) !== NOTE_COMMITMENT, 'commitments should differ');
+    });
+
     test('computeCommitment returns valid hash', () => {
         const key = generateSpendingKey();
         const balance = 10000n;
         const rand = generateRandomness();
-        const commitment = computeCommitment(key, balance, rand);
+        const commitment = computeCommitment(key, balance, rand, TOKEN, DOMAIN);
         assertTrue(commitment.startsWith('0x'), 'should start with 0x');
         assertEqual(commitment.length, 66, 'commitment length');
     });
 
     test('computeNullifier returns valid hash', () => {
         const key = generateSpendingKey();
-        const nullifier = computeNullifier(key, 5);
+        const nullifier = computeNullifier(key, 5, DOMAIN);
         assertTrue(nullifier.startsWith('0x'), 'should start with 0x');
         assertEqual(nullifier.length, 66, 'nullifier length');
     });
@@ -98,8 +135,8 @@ async function main() {
         const key = generateSpendingKey();
         const rand1 = generateRandomness();
         const rand2 = generateRandomness();
-        const c1 = computeCommitment(key, 10000n, rand1);
-        const c2 = computeCommitment(key, 10000n, rand2);
+        const c1 = computeCommitment(key, 10000n, rand1, TOKEN, DOMAIN);
+        const c2 = computeCommitment(key, 10000n, rand2, TOKEN, DOMAIN);
         assertTrue(c1 !== c2, 'commitments should differ');
     });
 
@@ -108,7 +145,7 @@ async function main() {
     console.log('\n--- Note Class ---\n');
 
     test('Note.create generates valid note', () => {
-        const note = Note.create(10000n);
+        const note = Note.create(10000n, TOKEN, DOMAIN);
         assertTrue(note.spendingKey !== undefined, 'has spending key');
         assertEqual(note.balance, 10000n, 'balance');
         assertTrue(note.randomness !== undefined, 'has randomness');
@@ -117,11 +154,13 @@ async function main() {
     });
 
     test('Note serialization roundtrip', () => {
-        const note = Note.create(50000n);
+        const note = Note.create(50000n, '0x4200000000000000000000000000000000000006', DOMAIN);
         note.noteIndex = 42;
         const json = note.toJSON();
+        assertEqual(json.asset, note.asset, 'asset exported');
         const restored = Note.fromJSON(json);
         assertEqual(restored.spendingKey, note.spendingKey, 'spending key');
+        assertEqual(restored.asset, note.asset, 'asset');
         assertEqual(restored.balance, note.balance, 'balance');
         assertEqual(restored.randomness, note.randomness, 'randomness');
         assertEqual(restored.noteIndex, note.noteIndex, 'noteIndex');
@@ -129,12 +168,12 @@ async function main() {
     });
 
     test('Note.getNullifier returns null without noteIndex', () => {
-        const note = Note.create(10000n);
+        const note = Note.create(10000n, TOKEN, DOMAIN);
         assertEqual(note.getNullifier(), null, 'nullifier should be null');
     });
 
     test('Note.getNullifier returns hash with noteIndex', () => {
-        const note = Note.create(10000n);
+        const note = Note.create(10000n, TOKEN, DOMAIN);
         note.noteIndex = 5;
         const nullifier = note.getNullifier();
         assertTrue(nullifier !== null, 'nullifier should exist');
@@ -153,8 +192,8 @@ async function main() {
 
     test('NoctisWallet tracks notes', () => {
         const wallet = new NoctisWallet();
-        const note1 = Note.create(10000n);
-        const note2 = Note.create(20000n);
+        const note1 = Note.create(10000n, TOKEN, DOMAIN);
+        const note2 = Note.create(20000n, TOKEN, DOMAIN);
         wallet.addNote(note1);
         wallet.addNote(note2);
         assertEqual(wallet.getTotalBalance(), 30000n, 'total balance');
@@ -163,7 +202,7 @@ async function main() {
 
     test('NoctisWallet finds note by commitment', () => {
         const wallet = new NoctisWallet();
-        const note = Note.create(10000n);
+        const note = Note.create(10000n, TOKEN, DOMAIN);
         wallet.addNote(note);
         const found = wallet.findNote(note.commitment);
         assertEqual(found.commitment, note.commitment, 'found note');
@@ -171,7 +210,7 @@ async function main() {
 
     test('NoctisWallet removes notes', () => {
         const wallet = new NoctisWallet();
-        const note = Note.create(10000n);
+        const note = Note.create(10000n, TOKEN, DOMAIN);
         wallet.addNote(note);
         wallet.removeNote(note.commitment);
         assertEqual(wallet.notes.length, 0, 'notes removed');
@@ -179,8 +218,8 @@ async function main() {
 
     test('NoctisWallet export/import', () => {
         const wallet = new NoctisWallet();
-        wallet.addNote(Note.create(10000n));
-        wallet.addNote(Note.create(20000n));
+        wallet.addNote(Note.create(10000n, TOKEN, DOMAIN));
+        wallet.addNote(Note.create(20000n, TOKEN, DOMAIN));
 
         const exported = wallet.export();
         const imported = NoctisWallet.import(exported);
@@ -223,7 +262,7 @@ async function main() {
 
     await testAsync('SDK generates valid proof structure', async () => {
         const sdk = new NoctisSDK(provider);
-        const note = Note.create(ethers.parseEther('10000'));
+        const note = Note.create(ethers.parseEther('10000'), TOKEN, DOMAIN);
         note.noteIndex = 0;
 
         const recipient = '0x1234567890123456789012345678901234567890';
@@ -233,12 +272,12 @@ async function main() {
 
         // Check proof structure
         assertTrue(proof.length >= 96, 'proof has commitments');
-        assertEqual(publicInputs.length, 5, 'has 5 public inputs');
+        assertEqual(publicInputs.length, 10, 'has 10 public inputs');
     });
 
     await testAsync('SDK rejects overdraw', async () => {
         const sdk = new NoctisSDK(provider);
-        const note = Note.create(ethers.parseEther('100'));
+        const note = Note.create(ethers.parseEther('100'), TOKEN, DOMAIN);
         note.noteIndex = 0;
 
         try {
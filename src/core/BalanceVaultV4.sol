@@ -5,7 +5,8 @@ import "@openzeppelin/contracts/token/ERC20/IERC20.sol";
 import "@openzeppelin/contracts/token/ERC20/utils/SafeERC20.sol";
 import "@openzeppelin/contracts/utils/ReentrancyGuard.sol";
 import "@openzeppelin/contracts/access/Ownable.sol";
-import "./IVerifier.sol";
+import "./IBalanceVerifier.sol";
+import "./IDepositVerifier.sol";
 import "poseidon-solidity/PoseidonT3.sol";
 import "poseidon-solidity/PoseidonT4.sol";
 
@@ -24,7 +25,16 @@ contract BalanceVaultV4 is ReentrancyGuard, Ownable {
     // ==================== CONSTANTS ====================
 
     IERC20 public immutable TOKEN;
-    IVerifier public verifier;
+    IBalanceVerifier public verifier;
+    IDepositVerifier public depositVerifier;
+
+    // Note derivation version mixed into DOMAIN
+    uint256 public constant DERIVATION_VERSION = 2;
+
+    // Deployment domain = Poseidon(DERIVATION_VERSION, chainid, vault); version 2
+    // commitments and nullifiers mix it in, so notes do not link across deployments,
+    // and withdrawals pass it to the verifier, so a proof only spends notes of this vault
+    uint256 public immutable DOMAIN;
 
     // BN254 field modulus
     uint256 public constant FIELD_MODULUS = 21888242871839275222246405745257275088548364400416034343698204186575808495617;
@@ -34,6 +44,10 @@ contract BalanceVaultV4 is ReentrancyGuard, Ownable {
     uint256 public constant MAX_NOTES = 2 ** TREE_DEPTH; // ~1M notes
     uint256 public constant ROOT_HISTORY_SIZE = 100;
 
+    // withdrawBatch bounds
+    uint256 public constant MIN_BATCH_SIZE = 2;
+    uint256 public constant MAX_BATCH_SIZE = 64;
+
     // Precomputed zeros for BN254 Poseidon
     // zeros[i] = hash(zeros[i-1], zeros[i-1]) where zeros[0] = 0
     uint256[21] private ZEROS;
@@ -52,6 +66,12 @@ contract BalanceVaultV4 is ReentrancyGuard, Ownable {
     // Commitment tracking
     mapping(uint256 => bool) public commitmentUsed;
 
+    // Association set roots withdrawals may prove membership of
+    mapping(uint256 => bool) public associationRoots;
+
+    // Blocklist every withdrawal must prove its note is absent from (0 for none)
+    uint256 public blocklistRoot;
+
     bool private zerosInitialized;
 
     // ==================== EVENTS ====================
@@ -69,6 +89,12 @@ contract BalanceVaultV4 is ReentrancyGuard, Ownable {
         bool hasChange
     );
 
+    event AssociationRootPublished(uint256 indexed root, uint256 timestamp);
+
+    event AssociationRootRevoked(uint256 indexed root, uint256 timestamp);
+
+    event BlocklistRootSet(uint256 indexed root, uint256 timestamp);
+
     // ==================== ERRORS ====================
 
     error InvalidProof();
@@ -79,15 +105,26 @@ contract BalanceVaultV4 is ReentrancyGuard, Ownable {
     error ZeroAmount();
     error CommitmentAlreadyUsed();
     error CommitmentNotInField();
+    error InvalidBatch();
+    error DepositVerifierNotSet();
+    error FeeExceedsAmount();
+    error WrongAsset();
+    error UnknownAssociationRoot();
+    error ZeroAssociationRoot();
+    error WrongBlocklistRoot();
 
     // ==================== CONSTRUCTOR ====================
 
     constructor(
         address _token,
-        address _verifier
+        address _verifier,
+        address _depositVerifier
     ) Ownable(msg.sender) {
+        if (_depositVerifier == address(0)) revert DepositVerifierNotSet();
         TOKEN = IERC20(_token);
-        verifier = IVerifier(_verifier);
+        verifier = IBalanceVerifier(_verifier);
+        depositVerifier = IDepositVerifier(_depositVerifier);
+        DOMAIN = PoseidonT4.hash([DERIVATION_VERSION, block.chainid, uint256(uint160(address(this)))]);
 
         // Initialize zeros using BN254 Poseidon
         _initializeZeros();
@@ -107,11 +144,25 @@ contract BalanceVaultV4 is ReentrancyGuard, Ownable {
     // ==================== DEPOSIT ====================
 
     /**
-     * @notice Deposit tokens and create a note
-     * @param commitment Poseidon commitment = hash(spendingKeyHash, balance, randomness)
-     * @param amount Amount of tokens to deposit
+     * @notice Deposit tokens with a proof that the commitment holds `amount`
+     * @dev The proof binds the commitment to the amount, the asset and DOMAIN,
+     *      so a depositor cannot mint a note worth more than they paid in.
+     * @param proof The deposit proof
+     * @param publicInputs [commitment, amount, asset]
+     *        The verifier also receives DOMAIN as a fourth input.
      */
-    function deposit(uint256 commitment, uint256 amount) external nonReentrant {
+    function depositWithProof(
+        bytes calldata proof,
+        uint256[3] calldata publicInputs
+    ) external nonReentrant {
+        if (publicInputs[2] != uint256(uint160(address(TOKEN)))) revert WrongAsset();
+        uint256[4] memory verifierInputs = [publicInputs[0], publicInputs[1], publicInputs[2], DOMAIN];
+        if (!depositVerifier.verifyProof(proof, verifierInputs)) revert InvalidProof();
+
+        _deposit(publicInputs[0], publicInputs[1]);
+    }
+
+    function _deposit(uint256 commitment, uint256 amount) internal {
         if (amount == 0) revert ZeroAmount();
         if (nextNoteIndex >= MAX_NOTES) revert TreeFull();
         if (commitmentUsed[commitment]) revert CommitmentAlreadyUsed();
@@ -130,29 +181,69 @@ contract BalanceVaultV4 is ReentrancyGuard, Ownable {
 
     /**
      * @notice Withdraw tokens using a ZK proof
-     * @param proof The SNARK/STARK proof
-     * @param publicInputs [merkleRoot, nullifier, recipient, amount, changeCommitment]
+     * @dev `amount - fee` goes to the recipient and `fee` to the relayer the
+     *      proof commits to, so a front-runner cannot redirect either.
+     *      The verifier also receives DOMAIN as an eleventh input.
+     * @param proof The Groth16 proof
+     * @param publicInputs [merkleRoot, nullifier, recipient, amount, changeCommitment, relayer, fee, asset, associationRoot, blocklistRoot]
      */
     function withdraw(
         bytes calldata proof,
-        uint256[5] calldata publicInputs
+        uint256[10] calldata publicInputs
     ) external nonReentrant {
+        _withdraw(proof, publicInputs);
+    }
+
+    /**
+     * @notice Settle several withdrawals in one transaction
+     * @dev Each proof is verified on its own; the batch saves the
+     *      per-transaction overhead. Any invalid withdrawal reverts the batch.
+     * @param proofs One Groth16 proof per withdrawal, as the `withdraw` proof
+     * @param publicInputs Matching [merkleRoot, nullifier, recipient, amount, changeCommitment, relayer, fee, asset, associationRoot, blocklistRoot]
+     */
+    function withdrawBatch(
+        bytes[] calldata proofs,
+        uint256[10][] calldata publicInputs
+    ) external nonReentrant {
+        uint256 n = proofs.length;
+        if (n < MIN_BATCH_SIZE || n > MAX_BATCH_SIZE || publicInputs.length != n) {
+            revert InvalidBatch();
+        }
+        for (uint256 i = 0; i < n; i++) {
+            _withdraw(proofs[i], publicInputs[i]);
+        }
+    }
+
+    function _withdraw(
+        bytes calldata proof,
+        uint256[10] calldata publicInputs
+    ) internal {
         uint256 merkleRoot = publicInputs[0];
         uint256 nullifier = publicInputs[1];
         address recipient = address(uint160(publicInputs[2]));
         uint256 amount = publicInputs[3];
         uint256 changeCommitment = publicInputs[4];
+        address relayer = address(uint160(publicInputs[5]));
+        uint256 fee = publicInputs[6];
 
         if (!isKnownRoot(merkleRoot)) revert InvalidMerkleRoot();
         if (nullifierUsed[nullifier]) revert NullifierAlreadyUsed();
-
-        uint256[4] memory verifierInputs = [
-            publicInputs[0],
-            publicInputs[1],
-            publicInputs[2],
-            publicInputs[3]
-        ];
-
+        if (fee > amount) revert FeeExceedsAmount();
+        if (publicInputs[7] != uint256(uint160(address(TOKEN)))) revert WrongAsset();
+        // A nonzero association root must be one the owner has published
+        if (publicInputs[8] != 0 && !associationRoots[publicInputs[8]]) revert UnknownAssociationRoot();
+        // The blocklist is not the prover's choice: a root of 0 would skip it
+        if (publicInputs[9] != blocklistRoot) revert WrongBlocklistRoot();
+
+        // The proof binds every public input, so the change note, payout and
+        // asset above are the ones the spent note's owner proved. The domain
+        // is this vault's, not the caller's: a note of another deployment
+        // cannot be withdrawn here
+        uint256[11] memory verifierInputs;
+        for (uint256 i = 0; i < 10; i++) {
+            verifierInputs[i] = publicInputs[i];
+        }
+        verifierInputs[10] = DOMAIN;
         if (!verifier.verifyProof(proof, verifierInputs)) {
             revert InvalidProof();
         }
@@ -169,7 +260,10 @@ contract BalanceVaultV4 is ReentrancyGuard, Ownable {
             emit NoteCreated(changeCommitment, nextNoteIndex - 1, block.timestamp);
         }
 
-        TOKEN.safeTransfer(recipient, amount);
+        TOKEN.safeTransfer(recipient, amount - fee);
+        if (fee > 0) {
+            TOKEN.safeTransfer(relayer, fee);
+        }
 
         emit Withdrawal(nullifier, recipient, amount, hasChange);
     }
@@ -274,6 +368,33 @@ contract BalanceVaultV4 is ReentrancyGuard, Ownable {
         return PoseidonT3.hash([spendingKey, noteIndex]);
     }
 
+    /**
+     * @notice Compute a version 2 commitment in this vault's domain
+     * @dev commitment = Poseidon(Poseidon(DOMAIN, spendingKeyHash, asset), balance, randomness),
+     *      with asset the token address as withdraw's publicInputs[7]
+     */
+    function computeDomainCommitment(
+        uint256 spendingKeyHash,
+        uint256 asset,
+        uint256 balance,
+        uint256 randomness
+    ) external view returns (uint256) {
+        uint256 owner = PoseidonT4.hash([DOMAIN, spendingKeyHash, asset]);
+        return PoseidonT4.hash([owner, balance, randomness]);
+    }
+
+    /**
+     * @notice Compute a version 2 nullifier in this vault's domain
+     * @dev nullifier = Poseidon(DOMAIN, nk, noteIndex), with nk the owner's
+     *      nullifier deriving key, so watch-only wallets can detect spends
+     */
+    function computeDomainNullifier(
+        uint256 nullifierKey,
+        uint256 noteIndex
+    ) external view returns (uint256) {
+        return PoseidonT4.hash([DOMAIN, nullifierKey, noteIndex]);
+    }
+
     /**
      * @notice Hash two values (for Merkle tree)
      */
@@ -284,6 +405,42 @@ contract BalanceVaultV4 is ReentrancyGuard, Ownable {
     // ==================== ADMIN ====================
 
     function setVerifier(address _verifier) external onlyOwner {
-        verifier = IVerifier(_verifier);
+        verifier = IBalanceVerifier(_verifier);
+    }
+
+    function setDepositVerifier(address _depositVerifier) external onlyOwner {
+        if (_depositVerifier == address(0)) revert DepositVerifierNotSet();
+        depositVerifier = IDepositVerifier(_depositVerifier);
+    }
+
+    /**
+     * @notice Accept withdrawals proving membership of an association set
+     * @param root Root computed by noctis-association-root over the
+     *        approved deposit commitments
+     */
+    function publishAssociationRoot(uint256 root) external onlyOwner {
+        if (root == 0) revert ZeroAssociationRoot();
+        associationRoots[root] = true;
+        emit AssociationRootPublished(root, block.timestamp);
+    }
+
+    /**
+     * @notice Stop accepting an association set, e.g. after one of its
+     *         deposits turns out not to belong in it
+     */
+    function revokeAssociationRoot(uint256 root) external onlyOwner {
+        associationRoots[root] = false;
+        emit AssociationRootRevoked(root, block.timestamp);
+    }
+
+    /**
+     * @notice Require withdrawals to prove their note is not blocked
+     * @dev Proofs made against the previous root stop verifying, so notes
+     *      added to the blocklist cannot withdraw with an older proof.
+     * @param root Root of the blocklist sparse Merkle tree, 0 for none
+     */
+    function setBlocklistRoot(uint256 root) external onlyOwner {
+        blocklistRoot = root;
+        emit BlocklistRootSet(root, block.timestamp);
     }
 }
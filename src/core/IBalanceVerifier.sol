@@ -0,0 +1,39 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.24;
+
+/**
+ * @title IBalanceVerifier
+ * @notice Interface for balance withdrawal proof verification
+ * @dev Same shape as IVerifier with the change commitment, relayer, fee,
+ *      asset and set roots bound as further public inputs, so neither a
+ *      withdrawer nor a front-running relayer can substitute the change note
+ *      or the payout, and a note of one token cannot be withdrawn as another.
+ *      The last input is the vault's own DOMAIN, which callers do not supply,
+ *      so a proof made for another deployment does not verify.
+ */
+interface IBalanceVerifier {
+    /**
+     * @notice Verify a balance withdrawal proof
+     * @param proof The ZK proof bytes
+     * @param publicInputs Array of public inputs:
+     *        [0] merkleRoot - The Merkle root of commitments
+     *        [1] nullifierHash - Hash to prevent double-spending
+     *        [2] recipient - Address receiving the withdrawal
+     *        [3] amount - Amount being withdrawn
+     *        [4] changeCommitment - Commitment of the change note (0 if none)
+     *        [5] relayer - Address paid the fee (0 if self-submitted)
+     *        [6] fee - Part of the amount paid to the relayer, at most amount
+     *        [7] asset - Token the spent note holds (0 for ETH)
+     *        [8] associationRoot - Association set the note is proven to be
+     *            in (0 if none)
+     *        [9] blocklistRoot - Blocklist the note is proven to be absent
+     *            from (0 if none)
+     *        [10] domain - Deployment domain the note's commitment and
+     *            nullifier are derived in
+     * @return bool True if proof is valid
+     */
+    function verifyProof(
+        bytes calldata proof,
+        uint256[11] calldata publicInputs
+    ) external view returns (bool);
+}
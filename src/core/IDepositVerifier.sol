@@ -0,0 +1,26 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.24;
+
+/**
+ * @title IDepositVerifier
+ * @notice Interface for deposit proof verification
+ * @dev Proves that a deposited commitment opens to the deposited amount and
+ *      asset under the vault's DOMAIN, so a depositor cannot mint a note
+ *      worth more than they paid in.
+ */
+interface IDepositVerifier {
+    /**
+     * @notice Verify a deposit proof
+     * @param proof The ZK proof bytes
+     * @param publicInputs Array of public inputs:
+     *        [0] commitment - Commitment of the new note
+     *        [1] amount - Amount being deposited
+     *        [2] asset - Token being deposited (0 for ETH)
+     *        [3] domain - DOMAIN of the vault
+     * @return bool True if proof is valid
+     */
+    function verifyProof(
+        bytes calldata proof,
+        uint256[4] calldata publicInputs
+    ) external view returns (bool);
+}
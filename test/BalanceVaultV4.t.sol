@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.24;
+
+import "forge-std/Test.sol";
+import "@openzeppelin/contracts/token/ERC20/ERC20.sol";
+import "../src/core/BalanceVaultV4.sol";
+import "../src/core/IBalanceVerifier.sol";
+import "../src/core/IDepositVerifier.sol";
+
+contract MockToken is ERC20 {
+    constructor() ERC20("Mock", "MOCK") {
+        _mint(msg.sender, 1_000_000 ether);
+    }
+}
+
+/// Accepts a proof only for the public inputs it was made for, as a real
+/// verifier does
+contract BindingVerifier is IBalanceVerifier {
+    function proofFor(uint256[10] memory publicInputs, uint256 domain) public pure returns (bytes memory) {
+        return abi.encode(keccak256(abi.encode(publicInputs, domain)));
+    }
+
+    function verifyProof(bytes calldata proof, uint256[11] calldata publicInputs) external pure returns (bool) {
+        uint256[10] memory callerInputs;
+        for (uint256 i = 0; i < 10; i++) {
+            callerInputs[i] = publicInputs[i];
+        }
+        return keccak256(proof) == keccak256(proofFor(callerInputs, publicInputs[10]));
+    }
+}
+
+/// Accepts a deposit proof only for the public inputs it was made for
+contract BindingDepositVerifier is IDepositVerifier {
+    function proofFor(uint256[3] memory publicInputs, uint256 domain) public pure returns (bytes memory) {
+        return abi.encode(keccak256(abi.encode(publicInputs, domain)));
+    }
+
+    function verifyProof(bytes calldata proof, uint256[4] calldata publicInputs) external pure returns (bool) {
+        uint256[3] memory callerInputs = [publicInputs[0], publicInputs[1], publicInputs[2]];
+        return keccak256(proof) == keccak256(proofFor(callerInputs, publicInputs[3]));
+    }
+}
+
+contract BalanceVaultV4Test is Test {
+    BalanceVaultV4 public vault;
+    BindingVerifier public verifier;
+    BindingDepositVerifier public depositVerifier;
+    MockToken public token;
+    address public recipient;
+    address public relayer;
+
+    uint256 constant NOTE_BALANCE = 1000;
+
+    function setUp() public {
+        token = new MockToken();
+        verifier = new BindingVerifier();
+        depositVerifier = new BindingDepositVerifier();
+        vault = new BalanceVaultV4(address(token), address(verifier), address(depositVerifier));
+        recipient = makeAddr("recipient");
+        relayer = makeAddr("relayer");
+
+        token.approve(address(vault), type(uint256).max);
+        _deposit(12345, NOTE_BALANCE);
+    }
+
+    function _depositInputs(uint256 commitment, uint256 amount) internal view returns (uint256[3] memory) {
+        return [commitment, amount, uint256(uint160(address(token)))];
+    }
+
+    function _deposit(uint256 commitment, uint256 amount) internal {
+        uint256[3] memory inputs = _depositInputs(commitment, amount);
+        vault.depositWithProof(depositVerifier.proofFor(inputs, vault.DOMAIN()), inputs);
+    }
+
+    function _inputs(uint256 amount, uint256 fee) internal view returns (uint256[10] memory) {
+        return [
+            vault.getCurrentRoot(),
+            uint256(777),
+            uint256(uint160(recipient)),
+            amount,
+            uint256(0),
+            uint256(uint160(relayer)),
+            fee,
+            uint256(uint160(address(token))),
+            uint256(0),
+            vault.blocklistRoot()
+        ];
+    }
+
+    /// Proof for `inputs` in this vault's domain
+    function _proofFor(uint256[10] memory inputs) internal view returns (bytes memory) {
+        return verifier.proofFor(inputs, vault.DOMAIN());
+    }
+
+    function test_WithdrawPaysRecipientAndRelayer() public {
+        uint256[10] memory inputs = _inputs(400, 10);
+        vault.withdraw(_proofFor(inputs), inputs);
+
+        assertEq(token.balanceOf(recipient), 390, "Recipient gets amount - fee");
+        assertEq(token.balanceOf(relayer), 10, "Relayer gets the fee");
+        assertTrue(vault.nullifierUsed(777), "Nullifier spent");
+    }
+
+    function test_ChangedRelayerReverts() public {
+        uint256[10] memory inputs = _inputs(400, 10);
+        bytes memory proof = _proofFor(inputs);
+
+        // A front-runner resubmitting the proof to collect the fee
+        inputs[5] = uint256(uint160(makeAddr("frontRunner")));
+        vm.expectRevert(BalanceVaultV4.InvalidProof.selector);
+        vault.withdraw(proof, inputs);
+    }
+
+    function test_ChangedFeeReverts() public {
+        uint256[10] memory inputs = _inputs(400, 10);
+        bytes memory proof = _proofFor(inputs);
+
+        inputs[6] = 20;
+        vm.expectRevert(BalanceVaultV4.InvalidProof.selector);
+        vault.withdraw(proof, inputs);
+    }
+
+    function test_ChangedChangeCommitmentReverts() public {
+        uint256[10] memory inputs = _inputs(400, 10);
+        bytes memory proof = _proofFor(inputs);
+
+        inputs[4] = 424242;
+        vm.expectRevert(BalanceVaultV4.InvalidProof.selector);
+        vault.withdraw(proof, inputs);
+    }
+
+    function test_FeeAboveAmountReverts() public {
+        uint256[10] memory inputs = _inputs(400, 401);
+        bytes memory proof = _proofFor(inputs);
+
+        vm.expectRevert(BalanceVaultV4.FeeExceedsAmount.selector);
+        vault.withdraw(proof, inputs);
+    }
+
+    function test_BlocklistRootMustMatch() public {
+        vault.setBlocklistRoot(0xB10C);
+        uint256[10] memory inputs = _inputs(400, 10);
+        assertEq(inputs[9], 0xB10C);
+        vault.withdraw(_proofFor(inputs), inputs);
+        assertTrue(vault.nullifierUsed(777), "Nullifier spent");
+    }
+
+    function test_SkippedBlocklistReverts() public {
+        uint256[10] memory inputs = _inputs(400, 10);
+        vault.setBlocklistRoot(0xB10C);
+
+        // A proof against no blocklist, valid for the inputs it was made for
+        vm.expectRevert(BalanceVaultV4.WrongBlocklistRoot.selector);
+        vault.withdraw(_proofFor(inputs), inputs);
+    }
+
+    function test_StaleBlocklistReverts() public {
+        vault.setBlocklistRoot(0xB10C);
+        uint256[10] memory inputs = _inputs(400, 10);
+        bytes memory proof = _proofFor(inputs);
+
+        // The note is blocked after the proof was made
+        vault.setBlocklistRoot(0xB10D);
+        vm.expectRevert(BalanceVaultV4.WrongBlocklistRoot.selector);
+        vault.withdraw(proof, inputs);
+    }
+
+    function test_OtherDomainReverts() public {
+        uint256[10] memory inputs = _inputs(400, 10);
+
+        // A proof for the same inputs in another deployment's domain
+        BalanceVaultV4 other = new BalanceVaultV4(address(token), address(verifier), address(depositVerifier));
+        bytes memory proof = verifier.proofFor(inputs, other.DOMAIN());
+        vm.expectRevert(BalanceVaultV4.InvalidProof.selector);
+        vault.withdraw(proof, inputs);
+    }
+
+    function test_DepositProofBindsAmount() public {
+        _deposit(54321, NOTE_BALANCE);
+        assertEq(vault.getNoteCount(), 2, "Proven deposit inserted");
+
+        // A note committed to NOTE_BALANCE, paid for with less
+        uint256[3] memory inputs = _depositInputs(54322, NOTE_BALANCE);
+        bytes memory proof = depositVerifier.proofFor(inputs, vault.DOMAIN());
+        inputs[1] = 1;
+        vm.expectRevert(BalanceVaultV4.InvalidProof.selector);
+        vault.depositWithProof(proof, inputs);
+    }
+
+    function test_DepositProofBindsDomain() public {
+        BalanceVaultV4 other = new BalanceVaultV4(address(token), address(verifier), address(depositVerifier));
+        uint256[3] memory inputs = _depositInputs(54321, NOTE_BALANCE);
+        bytes memory proof = depositVerifier.proofFor(inputs, other.DOMAIN());
+        vm.expectRevert(BalanceVaultV4.InvalidProof.selector);
+        vault.depositWithProof(proof, inputs);
+    }
+
+    function test_DepositVerifierRequired() public {
+        vm.expectRevert(BalanceVaultV4.DepositVerifierNotSet.selector);
+        new BalanceVaultV4(address(token), address(verifier), address(0));
+
+        vm.expectRevert(BalanceVaultV4.DepositVerifierNotSet.selector);
+        vault.setDepositVerifier(address(0));
+    }
+}
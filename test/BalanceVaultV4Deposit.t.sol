@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.24;
+
+import "forge-std/Test.sol";
+import "@openzeppelin/contracts/token/ERC20/ERC20.sol";
+import "../src/core/BalanceVaultV4.sol";
+import "./fixtures/BalanceWithdrawalVerifierFixture.sol";
+import "./fixtures/DepositVerifierFixture.sol";
+
+contract DepositFixtureToken is ERC20 {
+    constructor() ERC20("Mock", "MOCK") {
+        _mint(msg.sender, 1_000_000 ether);
+    }
+}
+
+/// A real Groth16 deposit proof from the Rust prover
+/// (`r1cs::deposit::DepositCircuit`) against the verifier generate-verifier
+/// renders for it
+///
+/// The fixture keys come from a seeded setup and are for tests only. The
+/// note is the one BalanceVaultV4Groth16.t.sol withdraws: 1000 of TOKEN in
+/// a vault at VAULT on chain 31337, whose DOMAIN the proof is bound to.
+contract BalanceVaultV4DepositTest is Test {
+    address constant TOKEN = 0x7777777777777777777777777777777777777777;
+    address constant VAULT = 0x4444444444444444444444444444444444444444;
+
+    uint256 constant NOTE_COMMITMENT = 17692367850651603564815588957784872272511635620798776289052078333477056220622;
+
+    bytes constant PROOF = hex"1ca026eb1919596d29568eee69e7a4ed8cfa4f461b7d9953d6fb9f07f421d02a06b38dc3a26315b1762188cd4a748b33947cad07b3be8ce91f5c09419a0c527b0169033eb3de050973dbaa959be5dc52fb75d5124007bc6cf4f14302a1b79e41228002d513214c9d80e5a92547c863ba555772d34f3d06684b3476ec27fe2d1729cb7ece80a889027808368e16bca3750391f8e2a3aaea8c408042b77e077672030235f33dda46c9b3350e0314ebbdd924dd0d229993d0dccf3389bc228d236d05ca3333c4580110bbd2e508bddb21630944f5d6c58aab0427d7af3ef341cb8d028595eca6530b6d3a5242ee4c3e0e8daa464f72805ff1017446da8b44e9ab37";
+
+    BalanceVaultV4 public vault;
+    DepositFixtureToken public token;
+    address public withdrawalVerifier;
+    address public depositVerifier;
+
+    function setUp() public {
+        vm.chainId(31337);
+        deployCodeTo("BalanceVaultV4Deposit.t.sol:DepositFixtureToken", TOKEN);
+        token = DepositFixtureToken(TOKEN);
+        withdrawalVerifier = address(new BalanceWithdrawalVerifierFixture());
+        depositVerifier = address(new DepositVerifierFixture());
+        deployCodeTo("BalanceVaultV4.sol:BalanceVaultV4", abi.encode(TOKEN, withdrawalVerifier, depositVerifier), VAULT);
+        vault = BalanceVaultV4(VAULT);
+        token.approve(VAULT, type(uint256).max);
+    }
+
+    function _inputs(uint256 amount) internal pure returns (uint256[3] memory) {
+        return [NOTE_COMMITMENT, amount, uint256(uint160(TOKEN))];
+    }
+
+    function test_VerifierIsDeployable() public view {
+        assertLe(depositVerifier.code.length, 24576, "EIP-170");
+    }
+
+    function test_RealProofDeposits() public {
+        uint256 before = token.balanceOf(address(this));
+        vault.depositWithProof(PROOF, _inputs(1000));
+
+        assertEq(vault.getNoteCount(), 1, "Note inserted");
+        assertTrue(vault.commitmentUsed(NOTE_COMMITMENT), "Commitment recorded");
+        assertEq(before - token.balanceOf(address(this)), 1000, "Amount paid in");
+    }
+
+    function test_UnderpaidDepositReverts() public {
+        // The note holds 1000; paying in less must not insert it
+        vm.expectRevert(BalanceVaultV4.InvalidProof.selector);
+        vault.depositWithProof(PROOF, _inputs(1));
+    }
+
+    function test_OtherVaultReverts() public {
+        // Another deployment has another DOMAIN
+        BalanceVaultV4 other = new BalanceVaultV4(TOKEN, withdrawalVerifier, depositVerifier);
+        token.approve(address(other), type(uint256).max);
+        vm.expectRevert(BalanceVaultV4.InvalidProof.selector);
+        other.depositWithProof(PROOF, _inputs(1000));
+    }
+}
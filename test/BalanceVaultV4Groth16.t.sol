@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.24;
+
+import "forge-std/Test.sol";
+import "@openzeppelin/contracts/token/ERC20/ERC20.sol";
+import "../src/core/BalanceVaultV4.sol";
+import "./fixtures/BalanceWithdrawalVerifierFixture.sol";
+
+contract FixtureToken is ERC20 {
+    constructor() ERC20("Mock", "MOCK") {
+        _mint(msg.sender, 1_000_000 ether);
+    }
+}
+
+/// Accepts every deposit, so the tree can hold the placeholder leaves the
+/// withdrawal fixture was proven against; BalanceVaultV4Deposit.t.sol
+/// deposits with a real proof
+contract AcceptingDepositVerifier is IDepositVerifier {
+    function verifyProof(bytes calldata, uint256[4] calldata) external pure returns (bool) {
+        return true;
+    }
+}
+
+/// A real Groth16 proof from the Rust prover (`r1cs::WithdrawalCircuit`)
+/// against the verifier generate-verifier renders for it
+///
+/// The fixture keys come from a seeded setup and are for tests only. The
+/// note is the fourth of five deposits into a vault at VAULT, holding TOKEN,
+/// on chain 31337; both addresses are fixed so the vault's DOMAIN and the
+/// note's asset match the ones the proof was made for.
+contract BalanceVaultV4Groth16Test is Test {
+    address constant TOKEN = 0x7777777777777777777777777777777777777777;
+    address constant VAULT = 0x4444444444444444444444444444444444444444;
+    address constant RECIPIENT = 0xBEbeBeBEbeBebeBeBEBEbebEBeBeBebeBeBebebe;
+    address constant RELAYER = 0xfefeFEFeFEFEFEFEFeFefefefefeFEfEfefefEfe;
+
+    uint256 constant NOTE_COMMITMENT =
+        17692367850651603564815588957784872272511635620798776289052078333477056220622;
+    uint256 constant DOMAIN = 14662978245061976630882248176688412036630854214599193360401297926632920049201;
+
+    bytes constant PROOF =
+        hex"2c96cb3f6d8490e83c1122b132f10ad3b0faada3d46408d594f5cdb2750f9767203fa47bd94584dad4a714c5cd596df297537e33a280b3e734a55299ad1647c029fa693f31802baecd4cb9a173eed2af2f181e6cacc7d830412b1f189bee12aa113a1ce38ce34ffe63b60d94558e080bb43776846b31dd2977a9dbe4949649d20227d12fca95ce7af5868ec0e1e6ae014db4ca45488f4074da99a1bcdbec7d0a28627a2b3cfc62949be0b6efcea45d8e7fc23a921e0f0a9862f894d9710762c7052c063d570fee436b4895e2e877081ba96caedb8da73ff1dfd2a83180b8c9cd29b1be6ad04d8ed8ad1b8dea17d2b061b4a098e9fdaa4198fac837936e921a2f";
+
+    BalanceVaultV4 public vault;
+    FixtureToken public token;
+
+    function setUp() public {
+        vm.chainId(31337);
+        deployCodeTo("BalanceVaultV4Groth16.t.sol:FixtureToken", TOKEN);
+        token = FixtureToken(TOKEN);
+        BalanceWithdrawalVerifierFixture verifier = new BalanceWithdrawalVerifierFixture();
+        AcceptingDepositVerifier depositVerifier = new AcceptingDepositVerifier();
+        deployCodeTo(
+            "BalanceVaultV4.sol:BalanceVaultV4", abi.encode(TOKEN, address(verifier), address(depositVerifier)), VAULT
+        );
+        vault = BalanceVaultV4(VAULT);
+
+        token.approve(VAULT, type(uint256).max);
+        _deposit(11, 1);
+        _deposit(12, 1);
+        _deposit(13, 1);
+        _deposit(NOTE_COMMITMENT, 1000);
+        _deposit(15, 1);
+    }
+
+    function _deposit(uint256 commitment, uint256 amount) internal {
+        vault.depositWithProof("", [commitment, amount, uint256(uint160(TOKEN))]);
+    }
+
+    function _inputs() internal pure returns (uint256[10] memory) {
+        return [
+            14101647273760052179286043020278061996813218036149376994835602659690969882796,
+            8165539831532746897212764384503690434605663078760661239043268858830493880428,
+            uint256(uint160(RECIPIENT)),
+            400,
+            20748135697666413689532134980827958635937879287238102948549637616192443405914,
+            uint256(uint160(RELAYER)),
+            10,
+            uint256(uint160(TOKEN)),
+            0,
+            0
+        ];
+    }
+
+    function test_FixtureMatchesVault() public view {
+        assertEq(vault.DOMAIN(), DOMAIN, "Vault domain");
+        assertEq(vault.getCurrentRoot(), _inputs()[0], "Vault root");
+    }
+
+    function test_RealProofWithdraws() public {
+        uint256[10] memory inputs = _inputs();
+        vault.withdraw(PROOF, inputs);
+
+        assertEq(token.balanceOf(RECIPIENT), 390, "Recipient gets amount - fee");
+        assertEq(token.balanceOf(RELAYER), 10, "Relayer gets the fee");
+        assertTrue(vault.nullifierUsed(inputs[1]), "Nullifier spent");
+        assertEq(vault.getNoteCount(), 6, "Change note inserted");
+    }
+
+    function test_TamperedProofReverts() public {
+        bytes memory proof = PROOF;
+        // Flip a bit of C's x coordinate
+        proof[223] = bytes1(uint8(proof[223]) ^ 1);
+        uint256[10] memory inputs = _inputs();
+        vm.expectRevert(BalanceVaultV4.InvalidProof.selector);
+        vault.withdraw(proof, inputs);
+    }
+
+    function test_TamperedInputReverts() public {
+        uint256[10] memory inputs = _inputs();
+        inputs[2] = uint256(uint160(makeAddr("thief")));
+        vm.expectRevert(BalanceVaultV4.InvalidProof.selector);
+        vault.withdraw(PROOF, inputs);
+    }
+}
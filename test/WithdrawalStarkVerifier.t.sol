@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.24;
+
+import "forge-std/Test.sol";
+import "./fixtures/WithdrawalStarkVerifierFixture.sol";
+
+/// A real Plonky3 proof from the Rust prover (`WithdrawalCircuit`) against
+/// the STARK verifier generate-verifier renders for it
+///
+/// The fixture is rendered for two FRI queries instead of the default
+/// configuration, which keeps the proof at 5782 words; the contract code is
+/// the same apart from its constants. It is deployed with `new`, so a
+/// verifier over the EIP-170 limit fails setUp. The proof is the flat word
+/// encoding from `evm_proof_bytes`, read from
+/// fixtures/WithdrawalStarkProof.hex, for a note in the domain of vault
+/// 0x4444...4444 on chain 8453, whose DOMAIN the verifier is deployed with.
+contract WithdrawalStarkVerifierTest is Test {
+    address constant RECIPIENT = address(bytes20(hex"abababababababababababababababababababab"));
+    /// DOMAIN of vault 0x4444...4444 on chain 8453
+    uint256 constant DOMAIN = 13056254402006530286443139004158563900135451711793031812606685013253534647946;
+
+    uint256 constant P = 2013265921;
+    /// Offset of the opened trace row at zeta, after the degree and two commitments
+    uint256 constant OFF_TRACE_LOCAL = 17;
+
+    IVerifier public verifier;
+
+    function setUp() public {
+        verifier = new WithdrawalStarkVerifierFixture(DOMAIN);
+    }
+
+    function _proof() internal view returns (bytes memory) {
+        return vm.parseBytes(vm.readFile("test/fixtures/WithdrawalStarkProof.hex"));
+    }
+
+    function _inputs() internal pure returns (uint256[4] memory) {
+        return [uint256(1339389967), 1193655272, uint256(uint160(RECIPIENT)), 1000];
+    }
+
+    /// Add one to proof word `i`, keeping it a canonical field element
+    function _tamper(uint256 i) internal view returns (bytes memory tampered) {
+        tampered = _proof();
+        assembly {
+            let p := add(add(tampered, 32), mul(i, 32))
+            mstore(p, addmod(mload(p), 1, P))
+        }
+    }
+
+    function test_VerifierIsDeployable() public view {
+        assertLe(address(verifier).code.length, 24576, "EIP-170");
+    }
+
+    function test_RealProofVerifies() public view {
+        bytes memory proof = _proof();
+        assertEq(proof.length, 5782 * 32, "Proof words");
+        assertTrue(verifier.verifyProof(proof, _inputs()), "Real proof");
+    }
+
+    function test_TamperedOpeningRejected() public view {
+        assertFalse(verifier.verifyProof(_tamper(OFF_TRACE_LOCAL), _inputs()));
+    }
+
+    function test_TamperedMerklePathRejected() public view {
+        // The last word is a sibling on the final FRI round's path
+        assertFalse(verifier.verifyProof(_tamper(5781), _inputs()));
+    }
+
+    function test_TamperedInputRejected() public {
+        bytes memory proof = _proof();
+        uint256[4] memory inputs = _inputs();
+        inputs[3] = 1001;
+        assertFalse(verifier.verifyProof(proof, inputs), "Other amount");
+
+        inputs = _inputs();
+        inputs[2] = uint256(uint160(makeAddr("thief")));
+        assertFalse(verifier.verifyProof(proof, inputs), "Other recipient");
+    }
+
+    function test_OtherDomainRejected() public {
+        // The same proof against the verifier of another deployment
+        IVerifier other = new WithdrawalStarkVerifierFixture(DOMAIN + 1);
+        assertFalse(other.verifyProof(_proof(), _inputs()));
+    }
+
+    function test_TruncatedProofRejected() public view {
+        bytes memory truncated = _proof();
+        assembly {
+            mstore(truncated, sub(mload(truncated), 32))
+        }
+        assertFalse(verifier.verifyProof(truncated, _inputs()));
+    }
+}
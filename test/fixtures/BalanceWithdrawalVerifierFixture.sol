@@ -0,0 +1,214 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.24;
+
+import "../../src/core/IBalanceVerifier.sol";
+
+/**
+ * @title BalanceWithdrawalVerifierFixture
+ * @notice Groth16 verifier using exact snarkjs-generated code
+ * @dev Uses the snarkjs verifier internals with IVerifier wrapper
+ */
+contract BalanceWithdrawalVerifierFixture is IBalanceVerifier {
+    // Scalar field size
+    uint256 constant r = 21888242871839275222246405745257275088548364400416034343698204186575808495617;
+    // Base field size
+    uint256 constant q = 21888242871839275222246405745257275088696311157297823662689037894645226208583;
+
+    // Verification Key data
+    uint256 constant alphax  = 3541665655734397302822412884897417439062111624038007624859125306239892896326;
+    uint256 constant alphay  = 13620092352909880428729648719566545584130560520305263145180203209968722865263;
+    uint256 constant betax1  = 20770235145420172965584163105210624193397510694616994322552725107549897930356;
+    uint256 constant betax2  = 11753637457371275898470350926177542055773890379793903346577892049802373847175;
+    uint256 constant betay1  = 9436659191864889469924516994984762870292991925296089029533155087665403492897;
+    uint256 constant betay2  = 4095314974125089302292579396891797346517690236231802564145487068370556463834;
+    uint256 constant gammax1 = 9740162117499647259281074120509637266566742763005655700534385523493215773211;
+    uint256 constant gammax2 = 15055542298701627228900144996134246408781505248299911983461838760909688640331;
+    uint256 constant gammay1 = 17452600929430041268550295380353714077460190100876568488714712684925907164537;
+    uint256 constant gammay2 = 21268805720686276537256691766321313588587955132828919694837164089828559106234;
+    uint256 constant deltax1 = 1048134209973454594356279095923319789502241261957916440185739613845199313470;
+    uint256 constant deltax2 = 7278556893798626703805345467617026395354930313221535886471542667236268069860;
+    uint256 constant deltay1 = 5254358825433744823863109259578228802710888826171674391804795090769484419231;
+    uint256 constant deltay2 = 20212879992549365099353721772196494260392854524334751519739768881255847959638;
+
+    uint256 constant IC0x = 12185677568785003256244660189619596581516605555173909812629627461221401735267;
+    uint256 constant IC0y = 3046560669377474929371309327482904150761860725341266326719380253082143146624;
+    uint256 constant IC1x = 17803869083613731066162681158525240688523790248886553762876515912690968902603;
+    uint256 constant IC1y = 3866884826883136694004606734578888188662546368466254497066811131189514281218;
+    uint256 constant IC2x = 19249194853365375806117234018821090504976995933202625331666730262326133977066;
+    uint256 constant IC2y = 17193065720880797467543736242436960808055476976476527908749824165349017507861;
+    uint256 constant IC3x = 20613144080364292832233282211175242509210451376308482660286323540190244777330;
+    uint256 constant IC3y = 1804858497922008061334018631583426234430626734634986072391435987365948013772;
+    uint256 constant IC4x = 17491038858376083923055734348698348766427120839907165937724550669721798402203;
+    uint256 constant IC4y = 18867289824800512549611793036949243869416338898279859499922584462683976661424;
+    uint256 constant IC5x = 19369892800449847049234571177056608018023658803085308165988743237160938901880;
+    uint256 constant IC5y = 18451096830269694106822598280063262400673451004310010465449501995954752618716;
+    uint256 constant IC6x = 12497699609431925347619241006530425703081765370367425332069258158263899356015;
+    uint256 constant IC6y = 18070465123681380467774751875842219183804312057125677367991596412357817039551;
+    uint256 constant IC7x = 13897170107812100593643162714047800493162392618371389533908999980833733767099;
+    uint256 constant IC7y = 12866310213963279479195365516439369439050795077027830459938473368951503076420;
+    uint256 constant IC8x = 2174552624349840394468244908459858494544808775450036279557708338263008391137;
+    uint256 constant IC8y = 14818948824014419761976625608001032361204973477818821402654576064788722372187;
+    uint256 constant IC9x = 7651764571592307760487554766570252839476837976110535459692032759944690204497;
+    uint256 constant IC9y = 16146705140913869895329345411946497014787625276148315358783023519112681535255;
+    uint256 constant IC10x = 4498029788526915278620281513187147132227085810525758598103846234612657189011;
+    uint256 constant IC10y = 17683575764689873058380031973121250044662156155790128593983085749518875941730;
+    uint256 constant IC11x = 12139124481938090615814690145068514282687189555558622004256700720951004123033;
+    uint256 constant IC11y = 8513931139935941690935027117468894144293585302011775363384624217514838898477;
+
+    // Memory data
+    uint16 constant pVk = 0;
+    uint16 constant pPairing = 128;
+    uint16 constant pLastMem = 896;
+
+    /**
+     * @notice Verify a withdrawal proof
+     * @param proof ABI-encoded Groth16 proof: (uint[2] pA, uint[2][2] pB, uint[2] pC)
+     * @param publicInputs [merkleRoot, nullifier, recipient, amount, changeCommitment, relayer, fee, asset, associationRoot, blocklistRoot, domain]
+     * @return bool True if proof is valid
+     */
+    function verifyProof(
+        bytes calldata proof,
+        uint256[11] calldata publicInputs
+    ) external view override returns (bool) {
+        // Decode proof components
+        (
+            uint256[2] memory pA,
+            uint256[2][2] memory pB,
+            uint256[2] memory pC
+        ) = abi.decode(proof, (uint256[2], uint256[2][2], uint256[2]));
+
+        return _verifyProof(pA, pB, pC, publicInputs);
+    }
+
+    function _verifyProof(
+        uint256[2] memory _pA,
+        uint256[2][2] memory _pB,
+        uint256[2] memory _pC,
+        uint256[11] calldata _pubSignals
+    ) internal view returns (bool) {
+        assembly {
+            function checkField(v) {
+                if iszero(lt(v, r)) {
+                    mstore(0, 0)
+                    return(0, 0x20)
+                }
+            }
+
+            function g1_mulAccC(pR, x, y, s) {
+                let success
+                let mIn := mload(0x40)
+                mstore(mIn, x)
+                mstore(add(mIn, 32), y)
+                mstore(add(mIn, 64), s)
+
+                success := staticcall(sub(gas(), 2000), 7, mIn, 96, mIn, 64)
+
+                if iszero(success) {
+                    mstore(0, 0)
+                    return(0, 0x20)
+                }
+
+                mstore(add(mIn, 64), mload(pR))
+                mstore(add(mIn, 96), mload(add(pR, 32)))
+
+                success := staticcall(sub(gas(), 2000), 6, mIn, 128, pR, 64)
+
+                if iszero(success) {
+                    mstore(0, 0)
+                    return(0, 0x20)
+                }
+            }
+
+            function checkPairing(pA, pB, pC, pubSignals, pMem) -> isOk {
+                let _pPairing := add(pMem, pPairing)
+                let _pVk := add(pMem, pVk)
+
+                mstore(_pVk, IC0x)
+                mstore(add(_pVk, 32), IC0y)
+
+                // Compute the linear combination vk_x
+                g1_mulAccC(_pVk, IC1x, IC1y, calldataload(add(pubSignals, 0)))
+                g1_mulAccC(_pVk, IC2x, IC2y, calldataload(add(pubSignals, 32)))
+                g1_mulAccC(_pVk, IC3x, IC3y, calldataload(add(pubSignals, 64)))
+                g1_mulAccC(_pVk, IC4x, IC4y, calldataload(add(pubSignals, 96)))
+                g1_mulAccC(_pVk, IC5x, IC5y, calldataload(add(pubSignals, 128)))
+                g1_mulAccC(_pVk, IC6x, IC6y, calldataload(add(pubSignals, 160)))
+                g1_mulAccC(_pVk, IC7x, IC7y, calldataload(add(pubSignals, 192)))
+                g1_mulAccC(_pVk, IC8x, IC8y, calldataload(add(pubSignals, 224)))
+                g1_mulAccC(_pVk, IC9x, IC9y, calldataload(add(pubSignals, 256)))
+                g1_mulAccC(_pVk, IC10x, IC10y, calldataload(add(pubSignals, 288)))
+                g1_mulAccC(_pVk, IC11x, IC11y, calldataload(add(pubSignals, 320)))
+
+                // -A (from memory array)
+                mstore(_pPairing, mload(pA))
+                mstore(add(_pPairing, 32), mod(sub(q, mload(add(pA, 32))), q))
+
+                // B (from memory - need to handle 2D array layout)
+                // In memory, pB points to array of pointers for [2][2]
+                // pB[0] is at mload(pB), pB[1] is at mload(add(pB, 32))
+                let pB0 := mload(pB)
+                let pB1 := mload(add(pB, 32))
+                mstore(add(_pPairing, 64), mload(pB0))           // pB[0][0]
+                mstore(add(_pPairing, 96), mload(add(pB0, 32)))  // pB[0][1]
+                mstore(add(_pPairing, 128), mload(pB1))          // pB[1][0]
+                mstore(add(_pPairing, 160), mload(add(pB1, 32))) // pB[1][1]
+
+                // alpha1
+                mstore(add(_pPairing, 192), alphax)
+                mstore(add(_pPairing, 224), alphay)
+
+                // beta2
+                mstore(add(_pPairing, 256), betax1)
+                mstore(add(_pPairing, 288), betax2)
+                mstore(add(_pPairing, 320), betay1)
+                mstore(add(_pPairing, 352), betay2)
+
+                // vk_x
+                mstore(add(_pPairing, 384), mload(add(pMem, pVk)))
+                mstore(add(_pPairing, 416), mload(add(pMem, add(pVk, 32))))
+
+                // gamma2
+                mstore(add(_pPairing, 448), gammax1)
+                mstore(add(_pPairing, 480), gammax2)
+                mstore(add(_pPairing, 512), gammay1)
+                mstore(add(_pPairing, 544), gammay2)
+
+                // C (from memory array)
+                mstore(add(_pPairing, 576), mload(pC))
+                mstore(add(_pPairing, 608), mload(add(pC, 32)))
+
+                // delta2
+                mstore(add(_pPairing, 640), deltax1)
+                mstore(add(_pPairing, 672), deltax2)
+                mstore(add(_pPairing, 704), deltay1)
+                mstore(add(_pPairing, 736), deltay2)
+
+                let success := staticcall(sub(gas(), 2000), 8, _pPairing, 768, _pPairing, 0x20)
+
+                isOk := and(success, mload(_pPairing))
+            }
+
+            let pMem := mload(0x40)
+            mstore(0x40, add(pMem, pLastMem))
+
+            // Validate that all evaluations ∈ F
+            checkField(calldataload(add(_pubSignals, 0)))
+            checkField(calldataload(add(_pubSignals, 32)))
+            checkField(calldataload(add(_pubSignals, 64)))
+            checkField(calldataload(add(_pubSignals, 96)))
+            checkField(calldataload(add(_pubSignals, 128)))
+            checkField(calldataload(add(_pubSignals, 160)))
+            checkField(calldataload(add(_pubSignals, 192)))
+            checkField(calldataload(add(_pubSignals, 224)))
+            checkField(calldataload(add(_pubSignals, 256)))
+            checkField(calldataload(add(_pubSignals, 288)))
+            checkField(calldataload(add(_pubSignals, 320)))
+
+            // Validate all evaluations
+            let isValid := checkPairing(_pA, _pB, _pC, _pubSignals, pMem)
+
+            mstore(0, isValid)
+            return(0, 0x20)
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.24;
+
+import "../../src/core/IDepositVerifier.sol";
+
+/**
+ * @title DepositVerifierFixture
+ * @notice Groth16 verifier using exact snarkjs-generated code
+ * @dev Uses the snarkjs verifier internals with IVerifier wrapper
+ */
+contract DepositVerifierFixture is IDepositVerifier {
+    // Scalar field size
+    uint256 constant r = 21888242871839275222246405745257275088548364400416034343698204186575808495617;
+    // Base field size
+    uint256 constant q = 21888242871839275222246405745257275088696311157297823662689037894645226208583;
+
+    // Verification Key data
+    uint256 constant alphax  = 2663701402977302593206849629909096568936802583574997092274500642039884402616;
+    uint256 constant alphay  = 20187635755755881490009221708667535139780480622768789675735563217068158001246;
+    uint256 constant betax1  = 5870756878726410138704559765336947553622835014057181510656150908255757872025;
+    uint256 constant betax2  = 3557853042297069648436076624082558681109757156383195933399244132056936317742;
+    uint256 constant betay1  = 12148445702966646712251320592184418262785164411917019358829431785493174662648;
+    uint256 constant betay2  = 7348757582934654506032854851739354146388122177300656126926551192214890331443;
+    uint256 constant gammax1 = 21319556330385456960013375886046792097536676406917740484239631213332426964128;
+    uint256 constant gammax2 = 10211022531905685980748779168662985355041408252459665788746914191937838520512;
+    uint256 constant gammay1 = 3611784486033353165286491465769578357564468885356711999144325414743208274826;
+    uint256 constant gammay2 = 21638360058921085298101104905404137552405284736238230775431345254176862401319;
+    uint256 constant deltax1 = 7939558984325044977347417479433968652490054884841608407388559695092851083752;
+    uint256 constant deltax2 = 13531089123055780322239238154913085740580387229759703740163400252090276772032;
+    uint256 constant deltay1 = 10054625453131145909197263765323047470705245609115681838275744601919188588197;
+    uint256 constant deltay2 = 15725851698408833165911580563659978584674603222272255913261533616551968796658;
+
+    uint256 constant IC0x = 18420377669302753993311332714442263177618762332973491889942696622326313588972;
+    uint256 constant IC0y = 9441600243438675663431049618984525824454914413786234369563372116194566018297;
+    uint256 constant IC1x = 6279536186065455704562162030267476012751819533284318546202732565911506040529;
+    uint256 constant IC1y = 19387117677969207531449105158780291466887623479902966637464370673046792646295;
+    uint256 constant IC2x = 11567783080541104897129129033705550591554463539659202764538754957170301212994;
+    uint256 constant IC2y = 7577818081815860186541464300763920936436279238532988805999096000083474839811;
+    uint256 constant IC3x = 19661833463165575955579430013270858731829396762683689667316336595833464043015;
+    uint256 constant IC3y = 4371643391295719510627988852219085518659812924519100209671669517263182036119;
+    uint256 constant IC4x = 16065884477786370234594542949878898357518305736829089177294407111911734561543;
+    uint256 constant IC4y = 10473692503056248819583353906224400560990000386862736810030946303832600747968;
+
+    // Memory data
+    uint16 constant pVk = 0;
+    uint16 constant pPairing = 128;
+    uint16 constant pLastMem = 896;
+
+    /**
+     * @notice Verify a withdrawal proof
+     * @param proof ABI-encoded Groth16 proof: (uint[2] pA, uint[2][2] pB, uint[2] pC)
+     * @param publicInputs [commitment, amount, asset, domain]
+     * @return bool True if proof is valid
+     */
+    function verifyProof(
+        bytes calldata proof,
+        uint256[4] calldata publicInputs
+    ) external view override returns (bool) {
+        // Decode proof components
+        (
+            uint256[2] memory pA,
+            uint256[2][2] memory pB,
+            uint256[2] memory pC
+        ) = abi.decode(proof, (uint256[2], uint256[2][2], uint256[2]));
+
+        return _verifyProof(pA, pB, pC, publicInputs);
+    }
+
+    function _verifyProof(
+        uint256[2] memory _pA,
+        uint256[2][2] memory _pB,
+        uint256[2] memory _pC,
+        uint256[4] calldata _pubSignals
+    ) internal view returns (bool) {
+        assembly {
+            function checkField(v) {
+                if iszero(lt(v, r)) {
+                    mstore(0, 0)
+                    return(0, 0x20)
+                }
+            }
+
+            function g1_mulAccC(pR, x, y, s) {
+                let success
+                let mIn := mload(0x40)
+                mstore(mIn, x)
+                mstore(add(mIn, 32), y)
+                mstore(add(mIn, 64), s)
+
+                success := staticcall(sub(gas(), 2000), 7, mIn, 96, mIn, 64)
+
+                if iszero(success) {
+                    mstore(0, 0)
+                    return(0, 0x20)
+                }
+
+                mstore(add(mIn, 64), mload(pR))
+                mstore(add(mIn, 96), mload(add(pR, 32)))
+
+                success := staticcall(sub(gas(), 2000), 6, mIn, 128, pR, 64)
+
+                if iszero(success) {
+                    mstore(0, 0)
+                    return(0, 0x20)
+                }
+            }
+
+            function checkPairing(pA, pB, pC, pubSignals, pMem) -> isOk {
+                let _pPairing := add(pMem, pPairing)
+                let _pVk := add(pMem, pVk)
+
+                mstore(_pVk, IC0x)
+                mstore(add(_pVk, 32), IC0y)
+
+                // Compute the linear combination vk_x
+                g1_mulAccC(_pVk, IC1x, IC1y, calldataload(add(pubSignals, 0)))
+                g1_mulAccC(_pVk, IC2x, IC2y, calldataload(add(pubSignals, 32)))
+                g1_mulAccC(_pVk, IC3x, IC3y, calldataload(add(pubSignals, 64)))
+                g1_mulAccC(_pVk, IC4x, IC4y, calldataload(add(pubSignals, 96)))
+
+                // -A (from memory array)
+                mstore(_pPairing, mload(pA))
+                mstore(add(_pPairing, 32), mod(sub(q, mload(add(pA, 32))), q))
+
+                // B (from memory - need to handle 2D array layout)
+                // In memory, pB points to array of pointers for [2][2]
+                // pB[0] is at mload(pB), pB[1] is at mload(add(pB, 32))
+                let pB0 := mload(pB)
+                let pB1 := mload(add(pB, 32))
+                mstore(add(_pPairing, 64), mload(pB0))           // pB[0][0]
+                mstore(add(_pPairing, 96), mload(add(pB0, 32)))  // pB[0][1]
+                mstore(add(_pPairing, 128), mload(pB1))          // pB[1][0]
+                mstore(add(_pPairing, 160), mload(add(pB1, 32))) // pB[1][1]
+
+                // alpha1
+                mstore(add(_pPairing, 192), alphax)
+                mstore(add(_pPairing, 224), alphay)
+
+                // beta2
+                mstore(add(_pPairing, 256), betax1)
+                mstore(add(_pPairing, 288), betax2)
+                mstore(add(_pPairing, 320), betay1)
+                mstore(add(_pPairing, 352), betay2)
+
+                // vk_x
+                mstore(add(_pPairing, 384), mload(add(pMem, pVk)))
+                mstore(add(_pPairing, 416), mload(add(pMem, add(pVk, 32))))
+
+                // gamma2
+                mstore(add(_pPairing, 448), gammax1)
+                mstore(add(_pPairing, 480), gammax2)
+                mstore(add(_pPairing, 512), gammay1)
+                mstore(add(_pPairing, 544), gammay2)
+
+                // C (from memory array)
+                mstore(add(_pPairing, 576), mload(pC))
+                mstore(add(_pPairing, 608), mload(add(pC, 32)))
+
+                // delta2
+                mstore(add(_pPairing, 640), deltax1)
+                mstore(add(_pPairing, 672), deltax2)
+                mstore(add(_pPairing, 704), deltay1)
+                mstore(add(_pPairing, 736), deltay2)
+
+                let success := staticcall(sub(gas(), 2000), 8, _pPairing, 768, _pPairing, 0x20)
+
+                isOk := and(success, mload(_pPairing))
+            }
+
+            let pMem := mload(0x40)
+            mstore(0x40, add(pMem, pLastMem))
+
+            // Validate that all evaluations ∈ F
+            checkField(calldataload(add(_pubSignals, 0)))
+            checkField(calldataload(add(_pubSignals, 32)))
+            checkField(calldataload(add(_pubSignals, 64)))
+            checkField(calldataload(add(_pubSignals, 96)))
+
+            // Validate all evaluations
+            let isValid := checkPairing(_pA, _pB, _pC, _pubSignals, pMem)
+
+            mstore(0, isValid)
+            return(0, 0x20)
+        }
+    }
+}
@@ -0,0 +1,722 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.24;
+
+import {IVerifier} from "../../src/core/IVerifier.sol";
+
+/**
+ * @title WithdrawalStarkVerifierFixture
+ * @notice Plonky3 STARK verifier for WithdrawalCircuit
+ * @dev Generated by `generate-verifier`. Do not edit by hand.
+ *
+ *      Field: BabyBear (p = 2^31 - 2^27 + 1), challenges in the quartic
+ *      extension x^4 = 11, packed as four 64-bit lanes of a uint256.
+ *      Commitments and Fiat-Shamir use Poseidon2 (width 16, rate 8),
+ *      matching NoctisStarkConfig.
+ *
+ *      Trace height 2^5, log_blowup 1, 2 FRI queries,
+ *      16 proof-of-work bits.
+ *
+ *      Address inputs must be below 2^160 and enter the AIR as
+ *      ADDRESS_LIMBS public values of ADDRESS_LIMB_BITS bits, low limb
+ *      first, matching `noctis_circuits::address::address_limbs`. The
+ *      deployment domain enters reduced mod p, matching
+ *      `noctis_circuits::poseidon::reduce_domain`.
+ *
+ *      `proof` is the flat word encoding produced by
+ *      `noctis_circuits::codegen::stark::encode_evm_proof`.
+ */
+contract WithdrawalStarkVerifierFixture is IVerifier {
+    uint256 internal constant P = 2013265921;
+    uint256 internal constant W = 11;
+    uint256 internal constant GENERATOR = 31;
+    uint256 internal constant LANE = 0xffffffffffffffff;
+
+    uint256 internal constant TRACE_WIDTH = 520;
+    uint256 internal constant NUM_INPUTS = 4;
+    uint256 internal constant NUM_PUBLIC = 10;
+    uint256 internal constant ADDRESS_INPUTS = 0x4;
+    uint256 internal constant DOMAIN_INPUTS = 0x0;
+    uint256 internal constant ADDRESS_LIMBS = 6;
+    uint256 internal constant ADDRESS_LIMB_BITS = 27;
+    uint256 internal constant DEGREE_BITS = 5;
+    uint256 internal constant QUOTIENT_CHUNKS = 2;
+    uint256 internal constant LOG_MAX_HEIGHT = 6;
+    uint256 internal constant NUM_FRI_ROUNDS = 5;
+    uint256 internal constant NUM_QUERIES = 2;
+    uint256 internal constant POW_BITS = 16;
+
+    uint256 internal constant G_TRACE = 760005850;
+    uint256 internal constant G_TRACE_INV = 1273220281;
+    uint256 internal constant G_MAX = 1721589904;
+
+    // Proof layout, in 32-byte words
+    uint256 internal constant OFF_TRACE_COMMIT = 1;
+    uint256 internal constant OFF_QUOTIENT_COMMIT = 9;
+    uint256 internal constant OFF_TRACE_LOCAL = 17;
+    uint256 internal constant OFF_TRACE_NEXT = 2097;
+    uint256 internal constant OFF_QUOTIENT_OPENED = 4177;
+    uint256 internal constant OFF_FRI_COMMITS = 4209;
+    uint256 internal constant OFF_FINAL_POLY = 4249;
+    uint256 internal constant OFF_POW = 4253;
+    uint256 internal constant OFF_QUERIES = 4254;
+    uint256 internal constant QUERY_WORDS = 764;
+    uint256 internal constant PROOF_WORDS = 5782;
+
+    // Poseidon2 of the row programs (`noctis_circuits::poseidon`), for
+    // _permutationConstraints
+    bool internal constant AIR_PERMUTATION = true;
+    uint256 internal constant AIR_ROUNDS = 21;
+    /// Bit r set if round r S-boxes every lane
+    uint256 internal constant AIR_EXTERNAL_ROUNDS = 0x1e000f;
+    /// 4x4 block of the 4-periodic MDS matrix, byte 4i + j
+    uint256 internal constant AIR_MDS = 0x5030107070503010107050303010705;
+    /// 16 round constants per round
+    bytes internal constant AIR_ROUND_CONSTANTS = hex"6a09e6674367ae843c6ef3722d4ff539510e527f2305688b1f83d9ab5be0cd19428a2f98713744913dc0fbce71b5dba43956c25b59f111f11a3f82a3331c5ed46007aa9712835b01243185be550c7dc372be5d7408deb1fd23dc06a6499bf1736c9b69c077be47850fc19dc6240ca1cc2de92c6f4a7484aa5cb0a9dc76f988da203e51513031c66c380327c747597fc64ee00bf25da7914606ca63511429296727b70a852e1b21384d2c6dfc53380d13650a7354766a0abb09c2c92d1a722c842abfe8a0301a664a4a4b8b6f4f6c51a25992e8185e990623040e3583106aa07019a4c1161e376c082748774c34b0bcb5391c0cb34ed8aa4a5b9cca4f682e6ff3748f82ee00a5636e0cc8781314c7020718befff92c506cea46f9a3f64e7178f152273ecd5986b8c672da7dd5057d4f7d06f067aa0a637dc5113f98041b710b3528db77f532caab7b3c9ebe0a431d67c44cc5d4be597f299c5fcb6fab6c44198c03a0ea2c17e23c891f23b5ae2bc25a6e336bcfa33c293cf048ce967a5986b8c66ed5d0c601da05bd0eba4cf20a0e6e70142929671f83d9ab27b70a852e1b21383956c25b428a2f984d2c6dfc53380d135cb0a9dc650a73546a09e66771374491766a0abb03a0ea2c09c2c92d14c702071a722c84203e515123dc06a62abfe8a02d4ff5393031c66c331c5ed4380327c73dc0fbce46f9a3f6499bf1734a4b8b6f4ee00bf24e7178f152273ecd5992e8185da791465e9906236007aa976c9b69c06ed5d0c671b5dba472da7dd577be478501da05bd040e3583057d4f7d0eba4cf20a0e6e700a637dc50fc19dc606ca635106f067aa113f980412835b011b710b351e376c08240ca1cc243185be28db77f52748774c2de92c6f32caab7b34b0bcb5391c0cb33c6ef3723c9ebe0a431d67c44a7484aa4cc5d4be4ed8aa4a510e527f550c7dc3597f299c59f111f15b9cca4f5fcb6fab682e6ff36c44198c72be5d7476f988da00a5636e08deb1fd0cc8781317e23c8918befff91a3f82a31f23b5ae2bc25a6e2c506cea301a664a336bcfa33c293cf04367ae8447597fc648ce967a4f6c51a2106aa07019a4c116142929671f83d9ab27b70a852e1b21383956c25b428a2f984d2c6dfc53380d135cb0a9dc650a73546a09e66771374491748f82ee766a0abb03a0ea2c09c2c92d14c702071a722c84203e515123dc06a62abfe8a02d4ff5393031c66c331c5ed4380327c73dc0fbce46f9a3f6499bf1734a4b8b6f4ee00bf24e7178f152273ecd5992e8185da791465e9906236007aa976c9b69c06ed5d0c671b5dba472da7dd577be478501da05bd040e3583057d4f7d0eba4cf20a0e6e700a637dc50fc19dc606ca635106f067aa113f980412835b011b710b351e376c08240ca1cc243185be28db77f52748774c2de92c6f32caab7b34b0bcb5391c0cb33c6ef3723c9ebe0a431d67c44a7484aa4cc5d4be4ed8aa4a510e527f550c7dc3597f299c59f111f15b9cca4f5fcb6fab682e6ff36c44198c72be5d7476f988da00a5636e08deb1fd0cc8781317e23c8918befff91a3f82a31f23b5ae2bc25a6e2c506cea301a664a336bcfa33c293cf04367ae8447597fc648ce967a4f6c51a2106aa07019a4c116142929671f83d9ab27b70a852e1b21383956c25b428a2f984d2c6dfc53380d135cb0a9dc650a73546a09e66771374491748f82ee766a0abb03a0ea2c09c2c92d14c702071a722c84203e515123dc06a62abfe8a02d4ff5393031c66c331c5ed4380327c73dc0fbce46f9a3f6499bf1734a4b8b6f4ee00bf24e7178f152273ecd5992e8185da791465e9906236007aa976c9b69c06ed5d0c671b5dba472da7dd577be478501da05bd040e3583057d4f7d0eba4cf2";
+
+    // The other constraints, as the program of `ConstraintProgram`
+    uint256 internal constant CONSTRAINT_TEMPS = 175;
+    bytes internal constant CONSTRAINT_CONSTANTS = hex"00000001";
+    bytes internal constant CONSTRAINT_PROGRAM = hex"48f95000a0000000c0008000a00011f3c0010000a00011f4c0018000a00011f5c0020000a00011f6c0028000a00011f7c0030000a00011f8c0038000a00011f9c0040000a00011fac0048000a00011fbc0050000a00011fcc0058000a00011fdc0060000a00011fec0068000a00011ffc0070000a0001200c0078000a0001201c0080000a0001202c0088000a0001203c0090000a0001204c0098000a0001205c00a0000a0001206c00a8000a0001207c00b000050f691dda0010017c00c0000a00121f2c00c800050f991f2a001001ac00d800050fa11f3a001001cc00e800050fa91f4a001001ec00f800050fb11f5a0010020c010800050fb91f6a0010022c011800050fc11f7a0010024c012800050fc91f8a0010026c013800050fd11f9a0010028c014800050fd91faa001002ac015800050fe11fba001002cc016800050fe91fca001002ec017800050ff11fda0010030c018800050ff91fea0010032c0198000510011ffa0010034c01a800051009200a0010036c01b800051011201a0010038c01c800051019202a001003ac01d800051021203a001003cc01e800051029204a001003ec01f800051031205a0010040c020800051039206a0010042c021800050f811f0a0010044c022800050f891f1a0010046c02380004800300988f90048c0248000480091f088f9004ac0258000480111f188f9004cc026800088f91003c027000088f91004c027800088f91005c028000088f91006c028800088f91007c029000088f91008c029800088f91009c02a000088f9100ac02a800088f9100bc02b000088f9100cc02b800088f9100dc02c000088f9100ec02c800088f9100fc02d000008f991f4002d91f5002e11f6002e91f7002f11f8002f91f9003011fa003091fb003111fc003191fd003211fe003291ff00331200003392010034120200349203003512040035920500361206680011ef803711ef8036806fc03800000036120648f691ee88f7807208f700734800007480388075c03b00000036120648f711ed88f7807808f680794800807a803b807bc03e000000361206803e9002c03f000000361206803f9003c04000000036120680409004c04100000036120680419005c04200000036120680429006c04300000036120680439007c04400000036120680449008c04500000036120680459009c0460000003612068046900ac0470000003612068047900bc0480000003612068048900cc0490000003612068049900dc04a000000361206804a900ec04b000000361206804b900fc04c000048eeb00089030099c04d0000480030098903809bc04e0000480091f18903809dc04f000089039002c04f800089039003c050000089039004c050800089039005c051000089039006c051800089039007c052000089039008c052800089039009c05300008903900ac05380008903900bc05400008903900cc05480008903900dc05500008903900ec05580008903900fc056000048eeb001890380adc0570000";
+
+    /// Poseidon2 constants of the commitments and transcript: external round
+    /// constants (initial, final), internal round constants and the internal
+    /// diagonal, laid out as [0..64) [64..77) [77..141) [141..157)
+    bytes internal constant ROUND_CONSTANTS = hex"69cbb6af46ad93f960a00f4e6b1297cd23189afe732e7bef72c246de2c9419000557eede1580496f3a3ea77b54f3f2710f49b02947872fe1221e2e361ab7202e487779a63851c9d838dc17c0209f8849268dcee8350c48da5b9ad32e0523272b3f89055b01e894b213ddedde1b2ef3347507d8b46ceeb94e52eb6ba25064290505453f3f06349efc6922787c04bfff9c768c714a3e9ff21a15737c9c2229c8070d47f88c097e0ecc27eadba02d7d29e43502aaa00f475fd729fbda49018afffd0315b6186d4497d11b171d9e52861abd2e5d05013ec8646c6e5f250a148ae8e617f5fa4a3e66d2840051aa3b483f79132cfe5f15023427ca2cc783151e36ea475a8053c0693be6393858867d19334f6b128f0fd84e2b1ccb61210ce03c3189390b5b2f222edb11d5213effdf0cac4606241af16d7290a80d6f7e5329598ec8a876a859a06559e868657b83af13271d3f1f8760630aeeae37706e9ca646400cee72a05c262c589c9e20bd37a76a2d3d10205237675b8fe9c42aa501d61e01ac3e1448bc545ce5ad1c4918a14d2c46a83f4fcf687661d8d5c86ddf4ff911fda4d302933a8f170eaf815a9c314f49a1259035ec52a158eb16115e481e65367125c90eba33ba1fc28ded066399ad0cbec0ea75fd1af050f5bf4e643d5f416f4fe7185b3cbbde1e3afb3e296fb02745e1547b4a8db2ab59986d1930bcdfa31db639321d7c282453b336810673b747038a98a32c5bce60351979cd5008fb73547bca78711af4813f93bf64644d987b3c8bcd87608758b877ffffff00000001000000023c00000100000003000000043c00000077fffffe77fffffd778800015a0000016900000177fffff200780000078000000000000f";
+
+    /// Deployment domain: the DOMAIN of the vault this verifier serves, mod p
+    uint256 public immutable DOMAIN;
+
+    constructor(uint256 domain) {
+        DOMAIN = domain % P;
+    }
+
+    struct Challenger {
+        uint256[16] state;
+        uint256[8] input;
+        uint256 inputLen;
+        uint256[8] output;
+        uint256 outputLen;
+    }
+
+    struct Context {
+        uint256[] rc;
+        uint256 zeta;
+        uint256 zetaNext;
+        uint256 alpha;
+        uint256[] betas;
+    }
+
+    function verifyProof(
+        bytes calldata proof,
+        uint256[4] calldata publicInputs
+    ) external view override returns (bool) {
+        if (proof.length != PROOF_WORDS * 32) return false;
+        for (uint256 i = 0; i < PROOF_WORDS; i++) {
+            if (_word(proof, i) >= P) return false;
+        }
+
+        uint256[] memory pis = new uint256[](NUM_PUBLIC);
+        uint256 k = 0;
+        for (uint256 i = 0; i < NUM_INPUTS; i++) {
+            uint256 x = publicInputs[i];
+            if ((ADDRESS_INPUTS >> i) & 1 == 1) {
+                if (x >> 160 != 0) return false;
+                for (uint256 j = 0; j < ADDRESS_LIMBS; j++) {
+                    pis[k++] = (x >> (ADDRESS_LIMB_BITS * j)) & ((1 << ADDRESS_LIMB_BITS) - 1);
+                }
+                continue;
+            }
+            if ((DOMAIN_INPUTS >> i) & 1 == 1) x %= P;
+            if (x >= P) return false;
+            pis[k++] = x;
+        }
+        pis[k++] = DOMAIN;
+
+        return _verify(proof, pis);
+    }
+
+    // ============ STARK ============
+
+    function _verify(bytes calldata proof, uint256[] memory pis) internal pure returns (bool) {
+        if (_word(proof, 0) != DEGREE_BITS) return false;
+
+        Context memory ctx;
+        ctx.rc = _unpackWords(ROUND_CONSTANTS);
+        Challenger memory ch;
+
+        _observe(ch, ctx.rc, DEGREE_BITS);
+        _observe(ch, ctx.rc, DEGREE_BITS);
+        _observeWords(ch, ctx.rc, proof, OFF_TRACE_COMMIT, 8);
+        for (uint256 i = 0; i < NUM_PUBLIC; i++) {
+            _observe(ch, ctx.rc, pis[i]);
+        }
+        uint256 constraintAlpha = _sampleExt(ch, ctx.rc);
+        _observeWords(ch, ctx.rc, proof, OFF_QUOTIENT_COMMIT, 8);
+        ctx.zeta = _sampleExt(ch, ctx.rc);
+        ctx.zetaNext = _escale(ctx.zeta, G_TRACE);
+
+        if (!_checkConstraints(proof, pis, constraintAlpha, ctx.zeta)) return false;
+
+        // Opened values enter the transcript before FRI
+        _observeWords(ch, ctx.rc, proof, OFF_TRACE_LOCAL, OFF_FRI_COMMITS - OFF_TRACE_LOCAL);
+        ctx.alpha = _sampleExt(ch, ctx.rc);
+
+        ctx.betas = new uint256[](NUM_FRI_ROUNDS);
+        for (uint256 r = 0; r < NUM_FRI_ROUNDS; r++) {
+            _observeWords(ch, ctx.rc, proof, OFF_FRI_COMMITS + 8 * r, 8);
+            ctx.betas[r] = _sampleExt(ch, ctx.rc);
+        }
+        _observeWords(ch, ctx.rc, proof, OFF_FINAL_POLY, 4);
+
+        if (POW_BITS > 0) {
+            _observe(ch, ctx.rc, _word(proof, OFF_POW));
+            if (_sampleBits(ch, ctx.rc, POW_BITS) != 0) return false;
+        }
+
+        for (uint256 q = 0; q < NUM_QUERIES; q++) {
+            uint256 index = _sampleBits(ch, ctx.rc, LOG_MAX_HEIGHT);
+            if (!_verifyQuery(ctx, proof, q, index)) return false;
+        }
+        return true;
+    }
+
+    function _checkConstraints(
+        bytes calldata proof,
+        uint256[] memory pis,
+        uint256 alpha,
+        uint256 zeta
+    ) internal pure returns (bool) {
+        uint256[] memory local = new uint256[](TRACE_WIDTH);
+        uint256[] memory next = new uint256[](TRACE_WIDTH);
+        for (uint256 i = 0; i < TRACE_WIDTH; i++) {
+            local[i] = _readExt(proof, OFF_TRACE_LOCAL + 4 * i);
+            next[i] = _readExt(proof, OFF_TRACE_NEXT + 4 * i);
+        }
+
+        uint256 zh = _esub(_epow2(zeta, DEGREE_BITS), 1);
+        uint256[] memory sel = new uint256[](3);
+        sel[0] = _emul(zh, _einv(_esub(zeta, 1)));
+        sel[2] = _esub(zeta, G_TRACE_INV);
+        sel[1] = _emul(zh, _einv(sel[2]));
+
+        uint256 folded = _evalConstraints(local, next, pis, sel, alpha);
+        return _emul(folded, _einv(zh)) == _quotientAtZeta(proof, zeta);
+    }
+
+    /// Fold the AIR constraints with powers of alpha
+    /// @dev sel = [isFirstRow, isLastRow, isTransition]
+    function _evalConstraints(
+        uint256[] memory local,
+        uint256[] memory next,
+        uint256[] memory pis,
+        uint256[] memory sel,
+        uint256 alpha
+    ) internal pure returns (uint256 acc) {
+        if (AIR_PERMUTATION) acc = _permutationConstraints(local, alpha);
+
+        // Operand kinds of `ConstraintProgram`
+        uint256[][6] memory vals;
+        vals[0] = new uint256[](CONSTRAINT_TEMPS);
+        vals[1] = local;
+        vals[2] = next;
+        vals[3] = pis;
+        vals[4] = sel;
+        vals[5] = _unpackWords(CONSTRAINT_CONSTANTS);
+        return _runProgram(vals, alpha, acc);
+    }
+
+    /// Interpret CONSTRAINT_PROGRAM over the value arrays `vals`
+    /// @dev Each 4-byte instruction is a 2-bit opcode (add, sub or mul into
+    ///      the next temporary, or fold) and two 15-bit operands, whose top
+    ///      three bits pick one of `vals` and the rest index it.
+    function _runProgram(uint256[][6] memory vals, uint256 alpha, uint256 acc) internal pure returns (uint256) {
+        bytes memory program = CONSTRAINT_PROGRAM;
+        uint256 n = 0;
+        for (uint256 pc = 0; pc < program.length; pc += 4) {
+            uint256 ins;
+            assembly {
+                ins := shr(224, mload(add(add(program, 32), pc)))
+            }
+            uint256 op = ins >> 30;
+            uint256 a = vals[(ins >> 27) & 7][(ins >> 15) & 0xfff];
+            if (op == 3) {
+                acc = _fold(acc, alpha, a);
+                continue;
+            }
+            uint256 b = vals[(ins >> 12) & 7][ins & 0xfff];
+            vals[0][n++] = op == 0 ? _eadd(a, b) : op == 1 ? _esub(a, b) : _emul(a, b);
+        }
+        return acc;
+    }
+
+    /// Fold the constraints of the Poseidon2 permutation that starts every
+    /// row program, in the order `poseidon_air::permutation_residuals`
+    /// yields them
+    /// @dev The row holds the 16 input lanes, then per round one cube column
+    ///      per S-boxed lane (c = u^3 with u = lane + rc) and the 16 lanes
+    ///      after the round (MDS * s with s = c^2 * u on S-boxed lanes, u
+    ///      elsewhere). Lanes congruent mod 4 share their MDS coefficients,
+    ///      so each output lane mixes four sums.
+    function _permutationConstraints(uint256[] memory local, uint256 alpha) internal pure returns (uint256 acc) {
+        uint256[] memory rcs = _unpackWords(AIR_ROUND_CONSTANTS);
+        uint256[16] memory state;
+        for (uint256 i = 0; i < 16; i++) {
+            state[i] = local[i];
+        }
+
+        uint256 col = 16;
+        for (uint256 r = 0; r < AIR_ROUNDS; r++) {
+            uint256[4] memory sums;
+            {
+                uint256 lanes = (AIR_EXTERNAL_ROUNDS >> r) & 1 == 1 ? 16 : 1;
+                for (uint256 j = 0; j < 16; j++) {
+                    uint256 u = _eadd(state[j], rcs[16 * r + j]);
+                    if (j < lanes) {
+                        uint256 cube = local[col++];
+                        acc = _fold(acc, alpha, _esub(cube, _emul(_emul(u, u), u)));
+                        u = _emul(_emul(cube, cube), u);
+                    }
+                    sums[j % 4] = _eadd(sums[j % 4], u);
+                }
+            }
+            for (uint256 i = 0; i < 16; i++) {
+                uint256 mixed = 0;
+                for (uint256 j = 0; j < 4; j++) {
+                    mixed = _eadd(mixed, _escale(sums[j], (AIR_MDS >> (8 * (4 * (i % 4) + j))) & 0xff));
+                }
+                state[i] = local[col + i];
+                acc = _fold(acc, alpha, _esub(state[i], mixed));
+            }
+            col += 16;
+        }
+    }
+
+    /// acc * alpha + c
+    function _fold(uint256 acc, uint256 alpha, uint256 c) internal pure returns (uint256) {
+        return _eadd(_emul(acc, alpha), c);
+    }
+
+    /// Recompose the quotient polynomial at zeta from its chunks
+    function _quotientAtZeta(bytes calldata proof, uint256 zeta) internal pure returns (uint256 quotient) {
+        uint256 zps;
+        zps = 1006632960;
+        zps = _emul(zps, _esub(_epow2(_escale(zeta, 1125410352), DEGREE_BITS), 1));
+        quotient = _eadd(quotient, _emul(zps, _chunkAt(proof, OFF_QUOTIENT_OPENED + 0)));
+        zps = 1006632960;
+        zps = _emul(zps, _esub(_epow2(_escale(zeta, 64944062), DEGREE_BITS), 1));
+        quotient = _eadd(quotient, _emul(zps, _chunkAt(proof, OFF_QUOTIENT_OPENED + 16)));
+    }
+
+    function _chunkAt(bytes calldata proof, uint256 off) internal pure returns (uint256 value) {
+        // sum_e c_e * X^e; multiplying by X shifts lanes up and wraps with W
+        for (uint256 e = 4; e > 0; e--) {
+            uint256 top = value >> 192;
+            value = ((value << 64) & ~LANE) | ((top * W) % P);
+            value = _eadd(value, _readExt(proof, off + 4 * (e - 1)));
+        }
+    }
+
+    // ============ FRI ============
+
+    function _verifyQuery(
+        Context memory ctx,
+        bytes calldata proof,
+        uint256 q,
+        uint256 index
+    ) internal pure returns (bool) {
+        uint256 off = OFF_QUERIES + q * QUERY_WORDS;
+        if (!_verifyInputOpenings(ctx.rc, proof, off, index)) return false;
+
+        uint256 folded = _reducedOpening(ctx, proof, off, index);
+
+        off += TRACE_WIDTH + 4 * QUOTIENT_CHUNKS + 16 * LOG_MAX_HEIGHT;
+        for (uint256 r = 0; r < NUM_FRI_ROUNDS; r++) {
+            bool ok;
+            (ok, folded) = _foldStep(ctx, proof, off, r, index, folded);
+            if (!ok) return false;
+            index >>= 1;
+            off += 4 + 8 * (LOG_MAX_HEIGHT - 1 - r);
+        }
+
+        return folded == _readExt(proof, OFF_FINAL_POLY);
+    }
+
+    /// Check the trace and quotient rows against their commitments
+    function _verifyInputOpenings(
+        uint256[] memory rc,
+        bytes calldata proof,
+        uint256 off,
+        uint256 index
+    ) internal pure returns (bool) {
+        uint256[8] memory leaf = _hashWords(rc, proof, off, TRACE_WIDTH);
+        if (!_verifyPath(rc, proof, off + TRACE_WIDTH, LOG_MAX_HEIGHT, index, leaf, OFF_TRACE_COMMIT)) {
+            return false;
+        }
+        off += TRACE_WIDTH + 8 * LOG_MAX_HEIGHT;
+        leaf = _hashWords(rc, proof, off, 4 * QUOTIENT_CHUNKS);
+        return _verifyPath(rc, proof, off + 4 * QUOTIENT_CHUNKS, LOG_MAX_HEIGHT, index, leaf, OFF_QUOTIENT_COMMIT);
+    }
+
+    /// One FRI folding round: open the sibling pair, then fold with beta_r
+    function _foldStep(
+        Context memory ctx,
+        bytes calldata proof,
+        uint256 off,
+        uint256 r,
+        uint256 index,
+        uint256 folded
+    ) internal pure returns (bool, uint256) {
+        uint256 e0 = folded;
+        uint256 e1 = _readExt(proof, off);
+        if (index & 1 == 1) (e0, e1) = (e1, e0);
+        index >>= 1;
+
+        uint256[8] memory leaf = _hashExtPair(ctx.rc, e0, e1);
+        uint256 logFolded = LOG_MAX_HEIGHT - 1 - r;
+        if (!_verifyPath(ctx.rc, proof, off + 4, logFolded, index, leaf, OFF_FRI_COMMITS + 8 * r)) {
+            return (false, 0);
+        }
+        return (true, _foldPair(e0, e1, ctx.betas[r], index, logFolded));
+    }
+
+    /// sum alpha^i * (p_i(z) - p_i(x)) / (z - x) over all opened columns
+    function _reducedOpening(
+        Context memory ctx,
+        bytes calldata proof,
+        uint256 off,
+        uint256 index
+    ) internal pure returns (uint256) {
+        uint256 invZeta;
+        uint256 invZetaNext;
+        {
+            uint256 x = mulmod(GENERATOR, _pow(G_MAX, _reverseBits(index, LOG_MAX_HEIGHT)), P);
+            invZeta = _einv(_esub(ctx.zeta, x));
+            invZetaNext = _einv(_esub(ctx.zetaNext, x));
+        }
+
+        // acc = [ro, alphaPow]
+        uint256[2] memory acc = [uint256(0), uint256(1)];
+        _accumulate(proof, ctx.alpha, acc, OFF_TRACE_LOCAL, off, TRACE_WIDTH, invZeta);
+        _accumulate(proof, ctx.alpha, acc, OFF_TRACE_NEXT, off, TRACE_WIDTH, invZetaNext);
+        off += TRACE_WIDTH + 8 * LOG_MAX_HEIGHT;
+        _accumulate(proof, ctx.alpha, acc, OFF_QUOTIENT_OPENED, off, 4 * QUOTIENT_CHUNKS, invZeta);
+        return acc[0];
+    }
+
+    function _accumulate(
+        bytes calldata proof,
+        uint256 alpha,
+        uint256[2] memory acc,
+        uint256 openedOff,
+        uint256 rowOff,
+        uint256 n,
+        uint256 invDenom
+    ) internal pure {
+        for (uint256 i = 0; i < n; i++) {
+            uint256 diff = _esub(_readExt(proof, openedOff + 4 * i), _word(proof, rowOff + i));
+            acc[0] = _eadd(acc[0], _emul(acc[1], _emul(diff, invDenom)));
+            acc[1] = _emul(acc[1], alpha);
+        }
+    }
+
+    /// Evaluate the line through (s, e0), (-s, e1) at beta
+    function _foldPair(
+        uint256 e0,
+        uint256 e1,
+        uint256 beta,
+        uint256 index,
+        uint256 logFolded
+    ) internal pure returns (uint256) {
+        uint256 s = _pow(_twoAdicGenerator(logFolded + 1), _reverseBits(index, logFolded));
+        uint256 invDenom = _inv(P - mulmod(2, s, P));
+        return _eadd(e0, _escale(_emul(_esub(beta, s), _esub(e1, e0)), invDenom));
+    }
+
+    function _twoAdicGenerator(uint256 bits) internal pure returns (uint256) {
+        if (bits == 1) return 2013265920;
+        if (bits == 2) return 1728404513;
+        if (bits == 3) return 1592366214;
+        if (bits == 4) return 196396260;
+        if (bits == 5) return 760005850;
+        if (bits == 6) return 1721589904;
+        revert("two-adic generator out of range");
+    }
+
+    // ============ Merkle ============
+
+    function _verifyPath(
+        uint256[] memory rc,
+        bytes calldata proof,
+        uint256 off,
+        uint256 depth,
+        uint256 index,
+        uint256[8] memory node,
+        uint256 commitOff
+    ) internal pure returns (bool) {
+        for (uint256 l = 0; l < depth; l++) {
+            uint256[16] memory state;
+            bool isRight = (index >> l) & 1 == 1;
+            for (uint256 k = 0; k < 8; k++) {
+                uint256 sibling = _word(proof, off + 8 * l + k);
+                if (isRight) {
+                    state[k] = sibling;
+                    state[8 + k] = node[k];
+                } else {
+                    state[k] = node[k];
+                    state[8 + k] = sibling;
+                }
+            }
+            _permute(state, rc);
+            for (uint256 k = 0; k < 8; k++) {
+                node[k] = state[k];
+            }
+        }
+        for (uint256 k = 0; k < 8; k++) {
+            if (node[k] != _word(proof, commitOff + k)) return false;
+        }
+        return true;
+    }
+
+    /// Padding-free sponge over `n` proof words, overwrite mode
+    function _hashWords(
+        uint256[] memory rc,
+        bytes calldata proof,
+        uint256 off,
+        uint256 n
+    ) internal pure returns (uint256[8] memory digest) {
+        uint256[16] memory state;
+        uint256 i = 0;
+        while (true) {
+            uint256 j = 0;
+            for (; j < 8 && i < n; j++) {
+                state[j] = _word(proof, off + i);
+                i++;
+            }
+            if (j == 0) break;
+            _permute(state, rc);
+            if (j < 8) break;
+        }
+        for (uint256 k = 0; k < 8; k++) {
+            digest[k] = state[k];
+        }
+    }
+
+    function _hashExtPair(
+        uint256[] memory rc,
+        uint256 e0,
+        uint256 e1
+    ) internal pure returns (uint256[8] memory digest) {
+        uint256[16] memory state;
+        for (uint256 k = 0; k < 4; k++) {
+            state[k] = (e0 >> (64 * k)) & LANE;
+            state[4 + k] = (e1 >> (64 * k)) & LANE;
+        }
+        _permute(state, rc);
+        for (uint256 k = 0; k < 8; k++) {
+            digest[k] = state[k];
+        }
+    }
+
+    // ============ Challenger ============
+
+    function _observe(Challenger memory ch, uint256[] memory rc, uint256 value) internal pure {
+        ch.outputLen = 0;
+        ch.input[ch.inputLen] = value;
+        ch.inputLen++;
+        if (ch.inputLen == 8) _duplex(ch, rc);
+    }
+
+    function _observeWords(
+        Challenger memory ch,
+        uint256[] memory rc,
+        bytes calldata proof,
+        uint256 off,
+        uint256 n
+    ) internal pure {
+        for (uint256 i = 0; i < n; i++) {
+            _observe(ch, rc, _word(proof, off + i));
+        }
+    }
+
+    function _duplex(Challenger memory ch, uint256[] memory rc) internal pure {
+        for (uint256 i = 0; i < ch.inputLen; i++) {
+            ch.state[i] = ch.input[i];
+        }
+        ch.inputLen = 0;
+        _permute(ch.state, rc);
+        for (uint256 i = 0; i < 8; i++) {
+            ch.output[i] = ch.state[i];
+        }
+        ch.outputLen = 8;
+    }
+
+    function _sample(Challenger memory ch, uint256[] memory rc) internal pure returns (uint256) {
+        if (ch.inputLen != 0 || ch.outputLen == 0) _duplex(ch, rc);
+        ch.outputLen--;
+        return ch.output[ch.outputLen];
+    }
+
+    function _sampleExt(Challenger memory ch, uint256[] memory rc) internal pure returns (uint256 e) {
+        for (uint256 k = 0; k < 4; k++) {
+            e |= _sample(ch, rc) << (64 * k);
+        }
+    }
+
+    function _sampleBits(Challenger memory ch, uint256[] memory rc, uint256 bits) internal pure returns (uint256) {
+        return _sample(ch, rc) & ((1 << bits) - 1);
+    }
+
+    // ============ Poseidon2 (BabyBear, width 16) ============
+
+    function _permute(uint256[16] memory s, uint256[] memory rc) internal pure {
+        _externalLinear(s);
+        for (uint256 r = 0; r < 4; r++) {
+            for (uint256 i = 0; i < 16; i++) {
+                s[i] = _sbox(addmod(s[i], rc[16 * r + i], P));
+            }
+            _externalLinear(s);
+        }
+        for (uint256 r = 0; r < 13; r++) {
+            s[0] = _sbox(addmod(s[0], rc[64 + r], P));
+            uint256 sum = 0;
+            for (uint256 i = 0; i < 16; i++) {
+                sum += s[i];
+            }
+            sum %= P;
+            for (uint256 i = 0; i < 16; i++) {
+                s[i] = addmod(sum, mulmod(s[i], rc[141 + i], P), P);
+            }
+        }
+        for (uint256 r = 0; r < 4; r++) {
+            for (uint256 i = 0; i < 16; i++) {
+                s[i] = _sbox(addmod(s[i], rc[77 + 16 * r + i], P));
+            }
+            _externalLinear(s);
+        }
+    }
+
+    function _sbox(uint256 x) internal pure returns (uint256) {
+        uint256 x2 = mulmod(x, x, P);
+        uint256 x3 = mulmod(x2, x, P);
+        return mulmod(mulmod(x3, x3, P), x, P);
+    }
+
+    /// circ(2 * M4, M4, M4, M4) with M4 = [[2,3,1,1],[1,2,3,1],[1,1,2,3],[3,1,1,2]]
+    function _externalLinear(uint256[16] memory s) internal pure {
+        for (uint256 c = 0; c < 16; c += 4) {
+            uint256 a = s[c];
+            uint256 b = s[c + 1];
+            uint256 d = s[c + 2];
+            uint256 e = s[c + 3];
+            s[c] = (2 * a + 3 * b + d + e) % P;
+            s[c + 1] = (a + 2 * b + 3 * d + e) % P;
+            s[c + 2] = (a + b + 2 * d + 3 * e) % P;
+            s[c + 3] = (3 * a + b + d + 2 * e) % P;
+        }
+        for (uint256 j = 0; j < 4; j++) {
+            uint256 sum = (s[j] + s[4 + j] + s[8 + j] + s[12 + j]) % P;
+            for (uint256 c = 0; c < 16; c += 4) {
+                s[c + j] = addmod(s[c + j], sum, P);
+            }
+        }
+    }
+
+    // ============ Field arithmetic ============
+
+    function _word(bytes calldata proof, uint256 i) internal pure returns (uint256 v) {
+        assembly {
+            v := calldataload(add(proof.offset, mul(i, 32)))
+        }
+    }
+
+    /// Field elements packed four big-endian bytes each
+    function _unpackWords(bytes memory packed) internal pure returns (uint256[] memory words) {
+        words = new uint256[](packed.length / 4);
+        for (uint256 i = 0; i < words.length; i++) {
+            uint256 w;
+            assembly {
+                w := shr(224, mload(add(add(packed, 32), mul(i, 4))))
+            }
+            words[i] = w;
+        }
+    }
+
+    function _readExt(bytes calldata proof, uint256 off) internal pure returns (uint256) {
+        return _word(proof, off)
+            | (_word(proof, off + 1) << 64)
+            | (_word(proof, off + 2) << 128)
+            | (_word(proof, off + 3) << 192);
+    }
+
+    function _eadd(uint256 a, uint256 b) internal pure returns (uint256 r) {
+        for (uint256 k = 0; k < 256; k += 64) {
+            r |= addmod((a >> k) & LANE, (b >> k) & LANE, P) << k;
+        }
+    }
+
+    function _esub(uint256 a, uint256 b) internal pure returns (uint256 r) {
+        for (uint256 k = 0; k < 256; k += 64) {
+            r |= addmod((a >> k) & LANE, P - ((b >> k) & LANE), P) << k;
+        }
+    }
+
+    function _escale(uint256 a, uint256 s) internal pure returns (uint256 r) {
+        for (uint256 k = 0; k < 256; k += 64) {
+            r |= mulmod((a >> k) & LANE, s, P) << k;
+        }
+    }
+
+    function _emul(uint256 a, uint256 b) internal pure returns (uint256) {
+        unchecked {
+            uint256 a0 = a & LANE;
+            uint256 a1 = (a >> 64) & LANE;
+            uint256 a2 = (a >> 128) & LANE;
+            uint256 a3 = a >> 192;
+            uint256 b0 = b & LANE;
+            uint256 b1 = (b >> 64) & LANE;
+            uint256 b2 = (b >> 128) & LANE;
+            uint256 b3 = b >> 192;
+            return ((a0 * b0 + W * (a1 * b3 + a2 * b2 + a3 * b1)) % P)
+                | (((a0 * b1 + a1 * b0 + W * (a2 * b3 + a3 * b2)) % P) << 64)
+                | (((a0 * b2 + a1 * b1 + a2 * b0 + W * (a3 * b3)) % P) << 128)
+                | (((a0 * b3 + a1 * b2 + a2 * b1 + a3 * b0) % P) << 192);
+        }
+    }
+
+    function _epow2(uint256 a, uint256 k) internal pure returns (uint256) {
+        for (uint256 i = 0; i < k; i++) {
+            a = _emul(a, a);
+        }
+        return a;
+    }
+
+    /// a^-1 = conj(a) * (n0 - n2 X^2) / (n0^2 - W n2^2), with conj(a)(X) = a(-X)
+    function _einv(uint256 a) internal pure returns (uint256) {
+        uint256 conj = _esub(a & (LANE | (LANE << 128)), a & ((LANE << 64) | (LANE << 192)));
+        uint256 n = _emul(a, conj);
+        uint256 n0 = n & LANE;
+        uint256 n2 = (n >> 128) & LANE;
+        uint256 norm = addmod(mulmod(n0, n0, P), P - mulmod(W, mulmod(n2, n2, P), P), P);
+        uint256 half = n0 | (((P - n2) % P) << 128);
+        return _escale(_emul(conj, half), _inv(norm));
+    }
+
+    function _inv(uint256 x) internal pure returns (uint256) {
+        return _pow(x, P - 2);
+    }
+
+    function _pow(uint256 base, uint256 exp) internal pure returns (uint256 r) {
+        r = 1;
+        while (exp > 0) {
+            if (exp & 1 == 1) r = mulmod(r, base, P);
+            base = mulmod(base, base, P);
+            exp >>= 1;
+        }
+    }
+
+    function _reverseBits(uint256 x, uint256 bits) internal pure returns (uint256 r) {
+        for (uint256 i = 0; i < bits; i++) {
+            r = (r << 1) | ((x >> i) & 1);
+        }
+    }
+}